@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::AtomicU64;
 use bson::{Document, RawDocumentBuf};
@@ -20,7 +20,12 @@ use bson::uuid::Uuid;
 use polodb_core::{ClientCursor, Database, Transaction};
 use crate::handlers::Handler;
 use anyhow::Result;
+use log::debug;
 use crate::session_context::SessionContext;
+use crate::command_policy::CommandPolicy;
+
+/// The number of cursors a connection can keep open by default.
+pub(crate) const DEFAULT_MAX_CURSORS_PER_CONNECTION: usize = 1000;
 
 #[derive(Clone)]
 pub(crate) struct AppContext {
@@ -29,9 +34,9 @@ pub(crate) struct AppContext {
 
 impl AppContext {
 
-    pub(crate) fn new(db: Database) -> Self {
+    pub(crate) fn new(db: Database, command_policy: CommandPolicy, max_cursors_per_connection: usize) -> Self {
         AppContext {
-            inner: Arc::new(AppContextInner::new(db)),
+            inner: Arc::new(AppContextInner::new(db, command_policy, max_cursors_per_connection)),
         }
     }
 
@@ -40,6 +45,11 @@ impl AppContext {
         self.inner.db.clone()
     }
 
+    #[inline]
+    pub(crate) fn command_policy(&self) -> &CommandPolicy {
+        &self.inner.command_policy
+    }
+
     pub(crate) fn register_handlers(&self, handlers: Vec<Arc<dyn Handler>>) {
         let mut handlers_guard = self.inner.handlers.lock().unwrap();
         for handler in handlers {
@@ -62,23 +72,55 @@ impl AppContext {
         self.inner.conn_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
     }
 
-    pub (crate) fn save_cursor(&self, cursor: Arc<Mutex<ClientCursor<Document>>>) -> i64 {
+    /// Save the cursor opened by the connection and return its id.
+    ///
+    /// When the connection already has `max_cursors_per_connection` open cursors,
+    /// its oldest cursor is killed to make room, so a `getMore` on it fails
+    /// with "cursor not found". Zero means no limit.
+    pub(crate) fn save_cursor(&self, conn_id: u64, cursor: Arc<Mutex<ClientCursor<Document>>>) -> i64 {
         let mut cursors = self.inner.cursors.lock().unwrap();
-        let mut cursor_id = cursors.len() as i64;
-        cursor_id += 1; // cursor_id starts from 1
-        cursors.insert(cursor_id, cursor);
+        cursors.next_id += 1; // cursor_id starts from 1
+        let cursor_id = cursors.next_id;
+
+        let max_cursors = self.inner.max_cursors_per_connection;
+        let owned = cursors.by_conn.entry(conn_id).or_default();
+        let mut evicted = Vec::new();
+        while max_cursors > 0 && owned.len() >= max_cursors {
+            evicted.extend(owned.pop_front());
+        }
+        owned.push_back(cursor_id);
+
+        for oldest in evicted {
+            debug!("connection {} has too many cursors, kill cursor {}", conn_id, oldest);
+            cursors.by_id.remove(&oldest);
+        }
+        cursors.by_id.insert(cursor_id, (conn_id, cursor));
         cursor_id
     }
 
     pub(crate) fn get_cursor(&self, cursor_id: i64) -> Option<Arc<Mutex<ClientCursor<Document>>>> {
         let cursors = self.inner.cursors.lock().unwrap();
-        cursors.get(&cursor_id).cloned()
+        cursors.by_id.get(&cursor_id).map(|(_, cursor)| cursor.clone())
     }
 
     pub(crate) fn remove_cursor(&self, cursor_ids: &[i64]) {
         let mut cursors = self.inner.cursors.lock().unwrap();
         for cursor_id in cursor_ids {
-            cursors.remove(cursor_id);
+            if let Some((conn_id, _)) = cursors.by_id.remove(cursor_id) {
+                if let Some(owned) = cursors.by_conn.get_mut(&conn_id) {
+                    owned.retain(|id| id != cursor_id);
+                }
+            }
+        }
+    }
+
+    /// Kill the cursors left open by a closed connection.
+    pub(crate) fn remove_connection_cursors(&self, conn_id: u64) {
+        let mut cursors = self.inner.cursors.lock().unwrap();
+        if let Some(owned) = cursors.by_conn.remove(&conn_id) {
+            for cursor_id in owned {
+                cursors.by_id.remove(&cursor_id);
+            }
         }
     }
 
@@ -95,23 +137,36 @@ impl AppContext {
     }
 }
 
+#[derive(Default)]
+struct CursorTable {
+    next_id: i64,
+    /// the cursors by id, with the id of the connection which opened them
+    by_id: HashMap<i64, (u64, Arc<Mutex<ClientCursor<Document>>>)>,
+    /// the ids of the cursors opened by each connection, the oldest first
+    by_conn: HashMap<u64, VecDeque<i64>>,
+}
+
 struct AppContextInner {
     db: Arc<Database>,
     handlers: Mutex<Vec<Arc<dyn Handler>>>,
-    cursors: Mutex<HashMap<i64, Arc<Mutex<ClientCursor<Document>>>>>,
+    cursors: Mutex<CursorTable>,
+    max_cursors_per_connection: usize,
     conn_id: AtomicU64,
     session_ctx: Mutex<HashMap<Uuid, SessionContext>>,
+    command_policy: CommandPolicy,
 }
 
 impl AppContextInner {
 
-    fn new(db: Database) -> Self {
+    fn new(db: Database, command_policy: CommandPolicy, max_cursors_per_connection: usize) -> Self {
         AppContextInner {
             db: Arc::new(db),
             handlers: Mutex::new(Vec::with_capacity(32)),
-            cursors: Mutex::new(HashMap::new()),
+            cursors: Mutex::new(CursorTable::default()),
+            max_cursors_per_connection,
             conn_id: AtomicU64::new(0),
             session_ctx: Mutex::new(HashMap::new()),
+            command_policy,
         }
     }
 
@@ -128,7 +183,8 @@ impl Drop for AppContextInner {
     fn drop(&mut self) {
         {
             let mut cursors = self.cursors.lock().unwrap();
-            cursors.clear();
+            cursors.by_id.clear();
+            cursors.by_conn.clear();
         }
         {
             let mut session_map = self.session_ctx.lock().unwrap();
@@ -0,0 +1,88 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use bson::RawDocumentBuf;
+
+/// Restricts the commands accepted by the server, by the command names
+/// such as `insert` or `dropDatabase`.
+///
+/// The drivers send `hello` or `isMaster` on connecting,
+/// so an allowlist has to include them.
+#[derive(Debug, Clone, Default)]
+pub(crate) enum CommandPolicy {
+    #[default]
+    AllowAll,
+    /// Only accept the listed commands
+    Allow(HashSet<String>),
+    /// Accept the commands except the listed ones
+    Deny(HashSet<String>),
+}
+
+impl CommandPolicy {
+
+    /// Parse a comma-separated list of command names.
+    pub(crate) fn parse_names(names: &str) -> HashSet<String> {
+        names.split(',')
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    pub(crate) fn is_allowed(&self, command_name: &str) -> bool {
+        match self {
+            CommandPolicy::AllowAll => true,
+            CommandPolicy::Allow(names) => names.contains(command_name),
+            CommandPolicy::Deny(names) => !names.contains(command_name),
+        }
+    }
+
+}
+
+/// The name of a command is the first key of the command document.
+pub(crate) fn command_name(doc: &RawDocumentBuf) -> Option<String> {
+    match doc.iter().next() {
+        Some(Ok((key, _))) => Some(key.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::rawdoc;
+    use super::{command_name, CommandPolicy};
+
+    #[test]
+    fn test_command_policy() {
+        let policy = CommandPolicy::Deny(CommandPolicy::parse_names("dropDatabase, delete"));
+        assert!(!policy.is_allowed("dropDatabase"));
+        assert!(!policy.is_allowed("delete"));
+        assert!(policy.is_allowed("find"));
+
+        let policy = CommandPolicy::Allow(CommandPolicy::parse_names("hello,find,"));
+        assert!(policy.is_allowed("find"));
+        assert!(!policy.is_allowed("insert"));
+
+        assert!(CommandPolicy::AllowAll.is_allowed("dropDatabase"));
+    }
+
+    #[test]
+    fn test_command_name() {
+        let doc = rawdoc! { "insert": "movies", "$db": "test" };
+        assert_eq!(command_name(&doc).as_deref(), Some("insert"));
+        assert_eq!(command_name(&rawdoc! {}), None);
+    }
+
+}
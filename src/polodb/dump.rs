@@ -0,0 +1,205 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Write;
+use anyhow::{anyhow, Result};
+use bson::{doc, Bson, Document};
+use polodb_core::{CollectionT, Config, Database};
+use polodb_core::options::OpenOptions;
+
+/// The flavor of extended JSON used when dumping documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EjsonMode {
+    /// Canonical extended JSON, every BSON type survives a round trip.
+    Strict,
+    /// Relaxed extended JSON, numbers and dates are written in their natural form.
+    Relaxed,
+}
+
+impl EjsonMode {
+    pub(crate) fn parse(name: &str) -> Result<EjsonMode> {
+        match name {
+            "strict" => Ok(EjsonMode::Strict),
+            "relaxed" => Ok(EjsonMode::Relaxed),
+            _ => Err(anyhow!("unknown extended JSON mode '{}', expected 'strict' or 'relaxed'", name)),
+        }
+    }
+
+    fn encode(self, doc: Document) -> serde_json::Value {
+        match self {
+            EjsonMode::Strict => Bson::Document(doc).into_canonical_extjson(),
+            EjsonMode::Relaxed => Bson::Document(doc).into_relaxed_extjson(),
+        }
+    }
+}
+
+/// Open an existing database to dump it.
+/// It's opened read-only, and a mistyped path is an error instead of a new empty database.
+pub(crate) fn open_database(path: &str) -> Result<Database> {
+    // RocksDB creates the directory before it finds there is no database in it
+    if !std::path::Path::new(path).exists() {
+        return Err(anyhow!("database '{}' not found", path));
+    }
+    let options = OpenOptions::builder()
+        .create(false)
+        .read_only(true)
+        .build();
+    Ok(Database::open_path_with_options(path, Config::default(), options)?)
+}
+
+fn select_collections(db: &Database, collection: Option<&str>) -> Result<Vec<String>> {
+    let mut names = db.list_collection_names()?;
+    names.sort();
+
+    if let Some(name) = collection {
+        if !names.iter().any(|n| n == name) {
+            return Err(anyhow!("collection '{}' not found", name));
+        }
+        names.retain(|n| n == name);
+    }
+
+    Ok(names)
+}
+
+/// Write a human-readable summary of the collections and their document counts.
+/// When `collection` is passed, only that collection is listed.
+pub(crate) fn dump_database<W: Write>(db: &Database, collection: Option<&str>, output: &mut W) -> Result<()> {
+    let names = select_collections(db, collection)?;
+
+    writeln!(output, "collections: {}", names.len())?;
+
+    let mut total: u64 = 0;
+    for name in &names {
+        let count = db.collection::<Document>(name).count_documents()?;
+        total += count;
+        writeln!(output, "  {}: {} documents", name, count)?;
+    }
+
+    writeln!(output, "total documents: {}", total)?;
+
+    Ok(())
+}
+
+/// Write every document as one line of extended JSON:
+/// `{"collection": <name>, "document": <document>}`.
+/// When `collection` is passed, only that collection is dumped.
+pub(crate) fn dump_documents<W: Write>(db: &Database, collection: Option<&str>, mode: EjsonMode, output: &mut W) -> Result<()> {
+    let names = select_collections(db, collection)?;
+
+    for name in &names {
+        let mut cursor = db.collection::<Document>(name).find(doc! {}).run()?;
+        while cursor.advance()? {
+            let line = serde_json::json!({
+                "collection": name,
+                "document": mode.encode(cursor.deserialize_current()?),
+            });
+            writeln!(output, "{}", line)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Binary, DateTime, Document};
+    use bson::spec::BinarySubtype;
+    use polodb_core::{CollectionT, Database};
+    use crate::dump::{dump_database, dump_documents, open_database, EjsonMode};
+
+    fn prepare_db(name: &str) -> Database {
+        let mut db_path = std::env::temp_dir();
+        db_path.push(name);
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+        Database::open_path(db_path.as_path()).unwrap()
+    }
+
+    #[test]
+    fn test_dump_database() {
+        let db = prepare_db("test-dump-db-server");
+
+        db.collection::<Document>("books").insert_many(vec![
+            doc! { "title": "The Three-Body Problem" },
+            doc! { "title": "The Dark Forest" },
+        ]).unwrap();
+        db.collection::<Document>("authors").insert_one(doc! {
+            "name": "Liu Cixin",
+        }).unwrap();
+
+        let mut output = Vec::<u8>::new();
+        dump_database(&db, None, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "collections: 2\n  authors: 1 documents\n  books: 2 documents\ntotal documents: 3\n",
+        );
+
+        let mut output = Vec::<u8>::new();
+        dump_database(&db, Some("books"), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "collections: 1\n  books: 2 documents\ntotal documents: 2\n",
+        );
+
+        let mut output = Vec::<u8>::new();
+        assert!(dump_database(&db, Some("movies"), &mut output).is_err());
+    }
+
+    #[test]
+    fn test_open_missing_database() {
+        let mut db_path = std::env::temp_dir();
+        db_path.push("test-dump-missing-server");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        assert!(open_database(db_path.to_str().unwrap()).is_err());
+        assert!(!db_path.exists());
+    }
+
+    #[test]
+    fn test_dump_documents_ejson() {
+        let db = prepare_db("test-dump-ejson-server");
+
+        db.collection::<Document>("events").insert_one(doc! {
+            "_id": 1,
+            "count": 42i64,
+            "at": DateTime::from_millis(1_700_000_000_000),
+            "payload": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2, 3] },
+        }).unwrap();
+
+        let mut output = Vec::<u8>::new();
+        dump_documents(&db, None, EjsonMode::Strict, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"{"collection":"events","document":{"_id":{"$numberInt":"1"},"count":{"$numberLong":"42"},"#,
+                r#""at":{"$date":{"$numberLong":"1700000000000"}},"payload":{"$binary":{"base64":"AQID","subType":"00"}}}}"#,
+                "\n",
+            ),
+        );
+
+        let mut output = Vec::<u8>::new();
+        dump_documents(&db, None, EjsonMode::Relaxed, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"{"collection":"events","document":{"_id":1,"count":42,"#,
+                r#""at":{"$date":"2023-11-14T22:13:20Z"},"payload":{"$binary":{"base64":"AQID","subType":"00"}}}}"#,
+                "\n",
+            ),
+        );
+
+        assert!(EjsonMode::parse("loose").is_err());
+        let mut output = Vec::<u8>::new();
+        assert!(dump_documents(&db, Some("movies"), EjsonMode::Strict, &mut output).is_err());
+    }
+}
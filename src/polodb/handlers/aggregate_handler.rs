@@ -5,6 +5,7 @@ use bson::{rawdoc, Document, RawDocumentBuf};
 use polodb_core::CollectionT;
 use crate::handlers::{FindHandler, HandleContext, Handler};
 use crate::reply::Reply;
+use crate::utils;
 
 pub(crate) struct AggregateHandle;
 
@@ -52,19 +53,32 @@ impl Handler for AggregateHandle {
             pipeline_arr.push(d);
         }
 
+        let max_time = utils::max_time_for_bson_ref(doc.get("maxTimeMS")?);
+        let allow_disk_use = doc.get_bool("allowDiskUse").unwrap_or(false);
+
         let session_opt = ctx.session.clone();
         let cursor = if let Some(session) = session_opt {
             let txn = session.get_transaction().ok_or(anyhow!("transaction not started"))?;
             let collection = txn.collection::<Document>(col_name);
-            collection.aggregate(pipeline_arr).run()?
+            let mut aggregate = collection.aggregate(pipeline_arr)
+                .allow_disk_use(allow_disk_use);
+            if let Some(max_time) = max_time {
+                aggregate = aggregate.max_time(max_time);
+            }
+            aggregate.run()?
         } else {
             let db = ctx.app_context.db();
             let collection = db.collection::<Document>(col_name);
-            collection.aggregate(pipeline_arr).run()?
+            let mut aggregate = collection.aggregate(pipeline_arr)
+                .allow_disk_use(allow_disk_use);
+            if let Some(max_time) = max_time {
+                aggregate = aggregate.max_time(max_time);
+            }
+            aggregate.run()?
         };
 
         let cursor = Arc::new(Mutex::new(cursor));
-        let cursor_id = ctx.app_context.save_cursor(cursor.clone());
+        let cursor_id = ctx.app_context.save_cursor(ctx.conn_id, cursor.clone());
         let cursor_doc = {
             let mut cursor_guard = cursor.lock().unwrap();
             FindHandler::mk_cursor_doc(cursor_id, db_name, col_name, &mut cursor_guard, batch_size as isize)?
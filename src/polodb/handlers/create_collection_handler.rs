@@ -0,0 +1,96 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bson::{rawdoc, RawBsonRef, RawDocumentBuf};
+use log::debug;
+use polodb_core::options::CreateCollectionOptions;
+use tokio::task;
+use crate::bson_util;
+use crate::handlers::{HandleContext, Handler};
+use crate::reply::Reply;
+use crate::utils;
+
+/// Create a collection with the `capped`, `size`, `max` and `validator` options.
+pub(crate) struct CreateCollectionHandler {}
+
+impl CreateCollectionHandler {
+
+    pub(crate) fn new() -> Arc<dyn Handler> {
+        Arc::new(CreateCollectionHandler {})
+    }
+
+    fn parse_options(doc: &RawDocumentBuf) -> Result<CreateCollectionOptions> {
+        let mut builder = CreateCollectionOptions::builder();
+        if utils::truly_value_for_bson_ref(doc.get("capped")?, false) {
+            builder = builder.capped(true);
+        }
+        if let Some(size) = CreateCollectionHandler::get_limit(doc, "size")? {
+            builder = builder.size(size);
+        }
+        if let Some(max) = CreateCollectionHandler::get_limit(doc, "max")? {
+            builder = builder.max(max);
+        }
+        match doc.get("validator")? {
+            Some(RawBsonRef::Document(validator)) => {
+                builder = builder.validator(bson::from_slice(validator.as_bytes())?);
+            }
+            Some(_) => return Err(anyhow!("validator is not a document")),
+            None => (),
+        }
+        Ok(builder.build())
+    }
+
+    /// Read a non-negative number, `size` and `max` may be sent as an int or a double.
+    fn get_limit(doc: &RawDocumentBuf, key: &str) -> Result<Option<u64>> {
+        let value = match doc.get(key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match bson_util::get_int_raw(value) {
+            Some(n) if n >= 0 => Ok(Some(n as u64)),
+            _ => Err(anyhow!("{} must be a non-negative integer", key)),
+        }
+    }
+
+}
+
+#[async_trait]
+impl Handler for CreateCollectionHandler {
+
+    fn test(&self, doc: &RawDocumentBuf) -> Result<bool> {
+        let val = doc.get("create")?;
+        Ok(matches!(val, Some(RawBsonRef::String(_))))
+    }
+
+    async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
+        let req_id = ctx.message.request_id.unwrap();
+        let doc = &ctx.message.document_payload;
+        let name = doc.get_str("create").map_err(|_| anyhow!("create is not a string"))?.to_string();
+        let options = CreateCollectionHandler::parse_options(doc)?;
+        debug!("create collection {}, capped: {:?}", name, options.capped);
+
+        let db = ctx.app_context.db();
+        task::spawn_blocking(move || db.create_collection_with_options(&name, options)).await??;
+
+        let body = rawdoc! {
+            "ok": 1,
+        };
+        let reply = Reply::new(req_id, body);
+        Ok(reply)
+    }
+
+}
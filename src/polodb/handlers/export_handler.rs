@@ -0,0 +1,98 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Result};
+use bson::{rawdoc, Document, RawArrayBuf, RawBson, RawDocumentBuf};
+use crate::handlers::{FindHandler, HandleContext, Handler, DEFAULT_BATCH_SIZE};
+use crate::reply::Reply;
+use async_trait::async_trait;
+use log::debug;
+use polodb_core::CollectionT;
+
+/// Export the documents of every collection in the database.
+///
+/// The reply carries one cursor per collection. Collections that do not fit
+/// in the first batch are continued with the regular `getMore` command,
+/// passing the collection name as `collection`.
+pub(crate) struct ExportHandler {}
+
+impl ExportHandler {
+
+    pub(crate) fn new() -> Arc<dyn Handler> {
+        Arc::new(ExportHandler {})
+    }
+
+}
+
+#[async_trait]
+impl Handler for ExportHandler {
+
+    fn test(&self, doc: &RawDocumentBuf) -> Result<bool> {
+        let val = doc.get("exportDocuments")?;
+        Ok(val.is_some())
+    }
+
+    async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
+        let doc = &ctx.message.document_payload;
+        let db = ctx.app_context.db();
+
+        let db_name = match doc.get("$db")? {
+            Some(val) => {
+                val.as_str().ok_or(anyhow!("$db is not a string"))?
+            },
+            None => {
+                return Err(anyhow!("$db is missing"));
+            },
+        };
+
+        let batch_size = match doc.get("batchSize")? {
+            Some(val) => {
+                val.as_i32().unwrap_or(DEFAULT_BATCH_SIZE)
+            },
+            None => DEFAULT_BATCH_SIZE,
+        };
+
+        let mut collections = RawArrayBuf::new();
+        for col_name in db.list_collection_names()? {
+            let collection = db.collection::<Document>(&col_name);
+            let mut cursor = collection.find(Document::new()).run()?;
+            let (first_batch, has_more) = FindHandler::consume_first_batch(&mut cursor, batch_size as isize)?;
+
+            let cursor_id = if has_more {
+                ctx.app_context.save_cursor(ctx.conn_id, Arc::new(Mutex::new(cursor)))
+            } else {
+                0
+            };
+            debug!("export collection: {}, cursor id: {}", col_name, cursor_id);
+
+            let cursor_doc = rawdoc! {
+                "firstBatch": first_batch,
+                "id": cursor_id,
+                "ns": format!("{}.{}", db_name, col_name),
+            };
+            collections.push(RawBson::Document(rawdoc! {
+                "name": col_name,
+                "cursor": cursor_doc,
+            }));
+        }
+
+        let body = rawdoc! {
+            "ok": 1,
+            "collections": collections,
+        };
+        let reply = Reply::new(ctx.message.request_id.unwrap(), body);
+        Ok(reply)
+    }
+
+}
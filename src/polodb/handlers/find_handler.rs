@@ -17,6 +17,7 @@ use anyhow::{anyhow, Result};
 use bson::{rawdoc, Document, RawArrayBuf, RawBson, RawBsonRef, RawDocumentBuf};
 use crate::handlers::{HandleContext, Handler, DEFAULT_BATCH_SIZE};
 use crate::reply::Reply;
+use crate::utils;
 use async_trait::async_trait;
 use log::debug;
 use polodb_core::{ClientCursor, CollectionT};
@@ -55,7 +56,7 @@ impl FindHandler {
         Ok(doc)
     }
 
-    fn consume_first_batch(cursor: &mut ClientCursor<Document>, batch_size: isize) -> Result<(RawArrayBuf, bool)> {
+    pub(crate) fn consume_first_batch(cursor: &mut ClientCursor<Document>, batch_size: isize) -> Result<(RawArrayBuf, bool)> {
         let mut raw_arr = RawArrayBuf::new();
         let mut has_more = false;
         let mut count: isize = 0;
@@ -137,6 +138,8 @@ impl Handler for FindHandler {
             None => DEFAULT_BATCH_SIZE,
         };
 
+        let max_time = utils::max_time_for_bson_ref(doc.get("maxTimeMS")?);
+
         let session_opt = ctx.session.clone();
         debug!("find collection: {}, auto commit: {}", collection_name, ctx.auto_commit);
         let mut cursor = if let Some(session) = session_opt {
@@ -149,6 +152,9 @@ impl Handler for FindHandler {
             if let Some(skip) = skip {
                 find = find.skip(skip as u64)
             };
+            if let Some(max_time) = max_time {
+                find = find.max_time(max_time)
+            };
             find.run()?
         } else {
             let collection = db.collection::<Document>(collection_name);
@@ -159,6 +165,9 @@ impl Handler for FindHandler {
             if let Some(skip) = skip {
                 find = find.skip(skip as u64)
             };
+            if let Some(max_time) = max_time {
+                find = find.max_time(max_time)
+            };
             find.run()?
         };
         if single_batch {
@@ -166,7 +175,7 @@ impl Handler for FindHandler {
         }
         let cursor = Arc::new(Mutex::new(cursor));
 
-        let cursor_id = ctx.app_context.save_cursor(cursor.clone());
+        let cursor_id = ctx.app_context.save_cursor(ctx.conn_id, cursor.clone());
         let cursor_doc = {
             let mut cursor_guard = cursor.lock().unwrap();
             FindHandler::mk_cursor_doc(cursor_id, db_name, collection_name, &mut cursor_guard, batch_size as isize)?
@@ -0,0 +1,87 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use bson::{rawdoc, RawDocumentBuf};
+use crate::handlers::{HandleContext, Handler};
+use crate::reply::Reply;
+use async_trait::async_trait;
+use polodb_core::CollectionT;
+use tokio::task;
+use log::debug;
+
+/// Import a batch of exported documents into a collection.
+///
+/// The documents are taken from the `documents` array of the command and
+/// from any attached document sequences, and are inserted as they are,
+/// keeping their `_id`.
+pub(crate) struct ImportHandler {}
+
+impl ImportHandler {
+
+    pub(crate) fn new() -> Arc<dyn Handler> {
+        Arc::new(ImportHandler {})
+    }
+
+}
+
+#[async_trait]
+impl Handler for ImportHandler {
+
+    fn test(&self, doc: &RawDocumentBuf) -> Result<bool> {
+        let val = doc.get("importDocuments")?;
+        match val {
+            Some(r) => Ok(r.as_str().is_some()),
+            None => Ok(false),
+        }
+    }
+
+    async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
+        let doc = &ctx.message.document_payload;
+        let collection_name = doc.get("importDocuments")?.unwrap().as_str().ok_or(anyhow!("importDocuments field is not a string"))?.to_string();
+
+        let mut batch = Vec::<bson::Document>::new();
+        if let Some(val) = doc.get("documents")? {
+            let arr = val.as_array().ok_or(anyhow!("documents is not an array"))?;
+            for item in arr {
+                let item = item?.as_document().ok_or(anyhow!("document is not a document"))?;
+                batch.push(bson::from_slice::<bson::Document>(item.as_bytes())?);
+            }
+        }
+        for doc_seq in ctx.message.document_sequences.as_slice() {
+            for doc in doc_seq.documents.as_slice() {
+                batch.push(bson::from_slice::<bson::Document>(doc.as_bytes())?);
+            }
+        }
+
+        debug!("import {} documents into {}", batch.len(), collection_name);
+        let db = ctx.app_context.db();
+        let count = batch.len();
+        if count > 0 {
+            task::spawn_blocking(move || -> Result<()> {
+                let collection = db.collection::<bson::Document>(&collection_name);
+                collection.insert_many(batch.as_slice())?;
+                Ok(())
+            }).await??;
+        }
+
+        let body = rawdoc! {
+            "ok": 1,
+            "n": count as i64,
+        };
+        let reply = Reply::new(ctx.message.request_id.unwrap(), body);
+        Ok(reply)
+    }
+
+}
@@ -58,6 +58,14 @@ impl Handler for InsertHandler {
                 batch_insert.push(d);
             }
         }
+        // the documents may be in the command itself, e.g. sent by `runCommand`
+        if let Some(documents) = doc.get("documents")? {
+            let documents = documents.as_array().ok_or(anyhow!("documents field is not an array"))?;
+            for item in documents {
+                let item = item?.as_document().ok_or(anyhow!("the item of documents is not a document"))?;
+                batch_insert.push(bson::from_slice::<bson::Document>(item.as_bytes())?);
+            }
+        }
 
         // insert could be blocking, so we spawn a blocking task
         debug!("inserted {} documents, start_transaction: {}", batch_insert.len(), auto_commit);
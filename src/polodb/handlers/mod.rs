@@ -8,6 +8,11 @@ mod delete_handler;
 mod commit_transaction;
 mod abort_transaction;
 mod aggregate_handler;
+mod export_handler;
+mod import_handler;
+mod drop_database_handler;
+mod rename_collection_handler;
+mod create_collection_handler;
 
 use std::sync::Arc;
 use bson::RawDocumentBuf;
@@ -26,6 +31,11 @@ pub(crate) use delete_handler::DeleteHandler;
 pub(crate) use commit_transaction::CommitTransactionHandler;
 pub(crate) use abort_transaction::AbortTransactionHandler;
 pub(crate) use aggregate_handler::AggregateHandle;
+pub(crate) use export_handler::ExportHandler;
+pub(crate) use import_handler::ImportHandler;
+pub(crate) use drop_database_handler::DropDatabaseHandler;
+pub(crate) use rename_collection_handler::RenameCollectionHandler;
+pub(crate) use create_collection_handler::CreateCollectionHandler;
 use crate::app_context::AppContext;
 use crate::session_context::SessionContext;
 
@@ -60,5 +70,10 @@ pub(crate) fn make_handlers() -> Vec<Arc<dyn Handler>> {
         HelloHandler::new(),
         CommitTransactionHandler::new(),
         AbortTransactionHandler::new(),
+        ExportHandler::new(),
+        ImportHandler::new(),
+        DropDatabaseHandler::new(),
+        RenameCollectionHandler::new(),
+        CreateCollectionHandler::new(),
     ]
 }
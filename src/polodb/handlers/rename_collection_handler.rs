@@ -0,0 +1,71 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bson::{rawdoc, RawDocumentBuf};
+use log::debug;
+use tokio::task;
+use crate::handlers::{HandleContext, Handler};
+use crate::reply::Reply;
+use crate::utils;
+
+/// Rename a collection, the names are given as `<db>.<collection>`.
+pub(crate) struct RenameCollectionHandler {}
+
+impl RenameCollectionHandler {
+
+    pub(crate) fn new() -> Arc<dyn Handler> {
+        Arc::new(RenameCollectionHandler {})
+    }
+
+    /// The database is ignored, the server attaches one database.
+    fn collection_name(namespace: &str) -> Result<String> {
+        match namespace.split_once('.') {
+            Some((_, name)) if !name.is_empty() => Ok(name.to_string()),
+            _ => Err(anyhow!("invalid namespace: {}", namespace)),
+        }
+    }
+
+}
+
+#[async_trait]
+impl Handler for RenameCollectionHandler {
+
+    fn test(&self, doc: &RawDocumentBuf) -> Result<bool> {
+        let val = doc.get("renameCollection")?;
+        Ok(val.is_some())
+    }
+
+    async fn handle(&self, ctx: &HandleContext) -> Result<Reply> {
+        let req_id = ctx.message.request_id.unwrap();
+        let doc = &ctx.message.document_payload;
+        let from = doc.get_str("renameCollection").map_err(|_| anyhow!("renameCollection is not a string"))?;
+        let to = doc.get_str("to").map_err(|_| anyhow!("to is missing"))?;
+        let from = RenameCollectionHandler::collection_name(from)?;
+        let to = RenameCollectionHandler::collection_name(to)?;
+        let drop_target = utils::truly_value_for_bson_ref(doc.get("dropTarget")?, false);
+        debug!("rename collection {} to {}, drop target: {}", from, to, drop_target);
+
+        let db = ctx.app_context.db();
+        task::spawn_blocking(move || db.rename_collection(&from, &to, drop_target)).await??;
+
+        let body = rawdoc! {
+            "ok": 1,
+        };
+        let reply = Reply::new(req_id, body);
+        Ok(reply)
+    }
+
+}
@@ -22,6 +22,7 @@ use polodb_core::results::UpdateResult;
 use polodb_core::CollectionT;
 use crate::app_context::AppContext;
 use crate::reply::Reply;
+use crate::session_context::SessionContext;
 
 pub(crate) struct UpdateHandler {}
 
@@ -31,9 +32,11 @@ impl UpdateHandler {
         Arc::new(UpdateHandler {})
     }
 
-    fn handle_update(ctx: AppContext, col_name: &str, update: Document, result: &mut UpdateResult) -> Result<()> {
+    fn handle_update(ctx: AppContext, session_opt: &Option<SessionContext>, col_name: &str, update: Document, result: &mut UpdateResult) -> Result<()> {
         let db = ctx.db();
-        let collection = db.collection::<Document>(col_name);
+
+        // only the first matched document is updated, unless `multi` is true
+        let multi = update.get_bool("multi").unwrap_or(false);
 
         let filter = update.get("q").ok_or(anyhow!("update document missing q field"))?;
         let update = update.get("u").ok_or(anyhow!("update document missing u field"))?;
@@ -41,12 +44,29 @@ impl UpdateHandler {
         let filter_doc = filter.as_document().ok_or(anyhow!("q field is not a document"))?;
         let update_doc = update.as_document().ok_or(anyhow!("u field is not a document"))?;
 
-        let tmp_result = collection.update_many(filter_doc.clone(), update_doc.clone())?;
+        // the updates of a session run in its transaction to see its pending writes
+        let tmp_result = if let Some(session) = session_opt {
+            let txn = session.get_transaction().ok_or(anyhow!("transaction not found"))?;
+            let collection = txn.collection::<Document>(col_name);
+            UpdateHandler::update(&collection, multi, filter_doc.clone(), update_doc.clone())?
+        } else {
+            let collection = db.collection::<Document>(col_name);
+            UpdateHandler::update(&collection, multi, filter_doc.clone(), update_doc.clone())?
+        };
+        result.matched_count += tmp_result.matched_count;
         result.modified_count += tmp_result.modified_count;
 
         Ok(())
     }
 
+    fn update<C: CollectionT<Document>>(collection: &C, multi: bool, filter: Document, update: Document) -> polodb_core::Result<UpdateResult> {
+        if multi {
+            collection.update_many(filter, update)
+        } else {
+            collection.update_one(filter, update)
+        }
+    }
+
 }
 
 #[async_trait]
@@ -65,11 +85,12 @@ impl Handler for UpdateHandler {
 
         let mut update_result = UpdateResult::default();
 
+        let session_opt = ctx.session.clone();
         let updates = doc.get_array("updates")?;
         for update in updates.into_iter() {
             let update = update?.as_document().ok_or(anyhow!("update is not a document"))?;
             let d = bson::from_slice::<Document>(update.as_bytes())?;
-            UpdateHandler::handle_update(ctx.app_context.clone(), collection_name, d, &mut update_result)?;
+            UpdateHandler::handle_update(ctx.app_context.clone(), &session_opt, collection_name, d, &mut update_result)?;
         }
         debug!("update result: {:?}", update_result);
 
@@ -17,9 +17,15 @@
 //!
 //! You can start the server by running `cargo run -- serve --path /path/to/db`.
 //!
+//! You can also query a database interactively by running `cargo run -- shell /path/to/db`,
+//! or print a summary of it by running `cargo run -- dump /path/to/db`.
+//! Pass `--ejson strict` or `--ejson relaxed` to dump the documents as extended JSON.
+//!
 //! The server will listen on `localhost:27017` by default.
 //! You can also specify the host and port by passing `--host` and `--port` arguments.
 //! For example: `cargo run -- serve --host 0.0.0.0 --port 8080 --path /path/to/db`.
+//! The aggregations allowing the disk spill their `$sort` and `$group` stages to temporary files
+//! beyond `--sort-spill-threshold` documents and `--group-spill-threshold` groups.
 //!
 //! # Connect
 //!
@@ -38,9 +44,12 @@ mod handlers;
 mod app_context;
 mod utils;
 mod session_context;
+mod shell;
+mod dump;
+mod command_policy;
 
 use std::net::SocketAddr;
-use polodb_core::Database;
+use polodb_core::{Config, ConfigBuilder, Database};
 use bson::{rawdoc, Document, RawBsonRef};
 use clap::{Arg, Command as App};
 use anyhow::{Result, anyhow};
@@ -50,7 +59,8 @@ use tokio::select;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 use reply::Reply;
-use crate::app_context::AppContext;
+use crate::app_context::{AppContext, DEFAULT_MAX_CURSORS_PER_CONNECTION};
+use crate::command_policy::{command_name, CommandPolicy};
 use crate::handlers::{make_handlers, HandleContext};
 use crate::utils::uuid_from_bson;
 
@@ -86,6 +96,41 @@ async fn main() {
                     .num_args(0..=1)
             )
             .arg(Arg::new("memory"))
+            .arg(
+                Arg::new("allow-commands")
+                    .long("allow-commands")
+                    .help("only accept the comma-separated commands, e.g. hello,isMaster,find")
+                    .conflicts_with("deny-commands")
+                    .num_args(1)
+            )
+            .arg(
+                Arg::new("deny-commands")
+                    .long("deny-commands")
+                    .help("reject the comma-separated commands, e.g. dropDatabase,delete")
+                    .num_args(1)
+            )
+            .arg(
+                Arg::new("max-cursors-per-connection")
+                    .long("max-cursors-per-connection")
+                    .help("kill the oldest cursor of a connection opening more cursors than this, 0 for no limit")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("1000")
+                    .num_args(1)
+            )
+            .arg(
+                Arg::new("sort-spill-threshold")
+                    .long("sort-spill-threshold")
+                    .help("the documents a $sort stage holds in memory before spilling to the disk, when the aggregation allows it")
+                    .value_parser(clap::value_parser!(u64))
+                    .num_args(1)
+            )
+            .arg(
+                Arg::new("group-spill-threshold")
+                    .long("group-spill-threshold")
+                    .help("the groups a $group stage holds in memory before spilling to the disk, when the aggregation allows it")
+                    .value_parser(clap::value_parser!(u64))
+                    .num_args(1)
+            )
             .arg(
                 Arg::new("log")
                     .help("print log")
@@ -93,6 +138,36 @@ async fn main() {
                     .short('l')
             )
         )
+        .subcommand(App::new("dump")
+            .about("print the collections and their document counts")
+            .arg(
+                Arg::new("path")
+                    .value_name("PATH")
+                    .required(true)
+            )
+            .arg(
+                Arg::new("collection")
+                    .long("collection")
+                    .short('c')
+                    .help("only print this collection")
+                    .num_args(1)
+            )
+            .arg(
+                Arg::new("ejson")
+                    .long("ejson")
+                    .help("print the documents as extended JSON instead of a summary")
+                    .value_parser(["strict", "relaxed"])
+                    .num_args(1)
+            )
+        )
+        .subcommand(App::new("shell")
+            .about("open the database and query it interactively")
+            .arg(
+                Arg::new("path")
+                    .value_name("PATH")
+                    .required(true)
+            )
+        )
         .arg(
             Arg::new("log")
                 .help("print log")
@@ -109,10 +184,30 @@ async fn main() {
         let host = sub.get_one::<String>("host").unwrap();
         let port = sub.get_one::<String>("port").unwrap();
         let path = sub.get_one::<String>("path");
+        let command_policy = match (sub.get_one::<String>("allow-commands"), sub.get_one::<String>("deny-commands")) {
+            (Some(names), _) => CommandPolicy::Allow(CommandPolicy::parse_names(names)),
+            (None, Some(names)) => CommandPolicy::Deny(CommandPolicy::parse_names(names)),
+            (None, None) => CommandPolicy::AllowAll,
+        };
+        let max_cursors_per_connection = *sub.get_one::<usize>("max-cursors-per-connection").unwrap();
+        let mut config = ConfigBuilder::new();
+        if let Some(threshold) = sub.get_one::<u64>("sort-spill-threshold") {
+            config.set_sort_spill_threshold(*threshold);
+        }
+        if let Some(threshold) = sub.get_one::<u64>("group-spill-threshold") {
+            config.set_group_spill_threshold(*threshold);
+        }
         if let Some(path) = path {
             let socket = format!("{}:{}", host, port);
             let token = CancellationToken::new();
-            let result = start_socket_server(path.clone(), socket.to_string(), token).await;
+            let result = start_socket_server_with_policy(
+                path.clone(),
+                socket.to_string(),
+                command_policy,
+                max_cursors_per_connection,
+                config.take(),
+                token,
+            ).await;
             match result {
                 Ok((addr, fut)) => {
                     info!("listening on {}", addr);
@@ -128,12 +223,61 @@ async fn main() {
         return;
     }
 
+    if let Some(sub) = matches.subcommand_matches("dump") {
+        let path = sub.get_one::<String>("path").unwrap();
+        let collection = sub.get_one::<String>("collection");
+        let ejson = sub.get_one::<String>("ejson");
+        let result = dump::open_database(path)
+            .and_then(|db| {
+                let collection = collection.map(|c| c.as_str());
+                let mut stdout = std::io::stdout();
+                match ejson {
+                    Some(mode) => {
+                        let mode = dump::EjsonMode::parse(mode)?;
+                        dump::dump_documents(&db, collection, mode, &mut stdout)
+                    }
+                    None => dump::dump_database(&db, collection, &mut stdout),
+                }
+            });
+        if let Err(e) = result {
+            eprintln!("error: {:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("shell") {
+        let path = sub.get_one::<String>("path").unwrap();
+        let result = Database::open_path(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|db| {
+                let stdin = std::io::stdin();
+                shell::run_shell(&db, stdin.lock(), &mut std::io::stdout())
+            });
+        if let Err(e) = result {
+            eprintln!("error: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+
 }
 
+#[cfg(test)]
 pub(crate) async fn start_socket_server(path: String, socket: String, token: CancellationToken) -> Result<(SocketAddr, JoinHandle<()>)> {
-    let db = Database::open_path(&path)?;
+    start_socket_server_with_policy(path, socket, CommandPolicy::AllowAll, DEFAULT_MAX_CURSORS_PER_CONNECTION, Config::default(), token).await
+}
+
+pub(crate) async fn start_socket_server_with_policy(
+    path: String,
+    socket: String,
+    command_policy: CommandPolicy,
+    max_cursors_per_connection: usize,
+    config: Config,
+    token: CancellationToken,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let db = Database::open_path_with_config(&path, config)?;
 
-    let ctx = AppContext::new(db);
+    let ctx = AppContext::new(db, command_policy, max_cursors_per_connection);
 
     ctx.register_handlers(make_handlers());
 
@@ -156,6 +300,7 @@ pub(crate) async fn start_socket_server(path: String, socket: String, token: Can
                                 let conn_id = ctx.next_conn_id();
                                 info!("new connection: {} from {}", conn_id, addr);
                                 let result = handle_stream(ctx.clone(), conn_id, stream).await;
+                                ctx.remove_connection_cursors(conn_id);
                                 if let Err(e) = result {
                                     // if is unexpected end of file, ignore if
                                     if e.to_string().contains("unexpected end of file") {
@@ -192,6 +337,21 @@ async fn handle_stream<W: AsyncWrite + AsyncRead + Unpin + Send>(ctx: AppContext
 }
 
 async fn handle_message<W: AsyncWrite + Unpin>(ctx: AppContext, conn_id: u64, stream: &mut W, message: wire::Message) -> Result<()> {
+    let name = command_name(&message.document_payload).unwrap_or_default();
+    if !ctx.command_policy().is_allowed(&name) {
+        warn!("command {} is not allowed", name);
+        // 13 is Unauthorized
+        let doc = rawdoc! {
+            "ok": 0,
+            "errmsg": format!("command {} is not allowed by the server configuration", name),
+            "code": 13,
+            "codeName": "Unauthorized",
+        };
+        let reply = Reply::new(message.request_id.unwrap(), doc);
+        reply.write_to(stream).await?;
+        return Ok(());
+    }
+
     let handler = ctx.get_handlers(&message.document_payload)?;
     if let Some(handler) = handler {
         let start_transaction = utils::truly_value_for_bson_ref(message.document_payload.get("startTransaction")?, false);
@@ -233,10 +393,15 @@ async fn handle_message<W: AsyncWrite + Unpin>(ctx: AppContext, conn_id: u64, st
             }
             Err(e) => {
                 log::error!("handler error: {:?}", e);
+                // 50 is MaxTimeMSExpired, which the drivers recognize
+                let code = match e.downcast_ref::<polodb_core::Error>() {
+                    Some(polodb_core::Error::MaxTimeExpired(_)) => 50,
+                    _ => 1,
+                };
                 let doc = rawdoc! {
                     "ok": 0,
                     "errmsg": e.to_string(),
-                    "code": 1,
+                    "code": code,
                 };
                 let reply = Reply::new(message.request_id.unwrap(), doc);
                 reply.write_to(stream).await?;
@@ -259,7 +424,10 @@ mod tests {
     use async_trait::async_trait;
     use tokio_util::sync::CancellationToken;
     use anyhow::Result;
-    use crate::start_socket_server;
+    use crate::{start_socket_server, start_socket_server_with_policy};
+    use crate::app_context::DEFAULT_MAX_CURSORS_PER_CONNECTION;
+    use crate::command_policy::CommandPolicy;
+    use polodb_core::{Config, ConfigBuilder};
 
     #[async_trait]
     trait Runner {
@@ -267,6 +435,20 @@ mod tests {
     }
 
     async fn open_server_with_test(path: &std::path::Path, callback: Box<dyn Runner>) -> Result<()> {
+        open_server_with_policy(path, CommandPolicy::AllowAll, callback).await
+    }
+
+    async fn open_server_with_policy(path: &std::path::Path, command_policy: CommandPolicy, callback: Box<dyn Runner>) -> Result<()> {
+        open_server_with_options(path, command_policy, DEFAULT_MAX_CURSORS_PER_CONNECTION, Config::default(), callback).await
+    }
+
+    async fn open_server_with_options(
+        path: &std::path::Path,
+        command_policy: CommandPolicy,
+        max_cursors_per_connection: usize,
+        config: Config,
+        callback: Box<dyn Runner>,
+    ) -> Result<()> {
         use mongodb::Client;
 
         std::env::set_var("RUST_LOG", "polodb=debug,tokio=info, mongodb=debug");
@@ -274,9 +456,12 @@ mod tests {
 
         let token = CancellationToken::new();
 
-        let (addr, handle) = start_socket_server(
+        let (addr, handle) = start_socket_server_with_policy(
             path.to_str().unwrap().to_string(),
             "localhost:0".to_string(),
+            command_policy,
+            max_cursors_per_connection,
+            config,
             token.clone(),
         ).await.unwrap();
         assert!(addr.port() > 0);
@@ -430,6 +615,7 @@ mod tests {
             bson::{Document, doc},
             Collection
         };
+        use futures::TryStreamExt;
 
 
         let db_path = mk_db_path("test-update");
@@ -452,6 +638,19 @@ mod tests {
                 let insert_result = my_coll.insert_many(docs).await.unwrap();
                 assert_eq!(insert_result.inserted_ids.len(), 100);
 
+                // only the first matched document
+                let result = my_coll.update_one(doc! {
+                    "x": {
+                        "$gte": 50
+                    }
+                }, doc! { "$set": { "y": 1 } }).await.unwrap();
+
+                assert_eq!(1, result.matched_count);
+                assert_eq!(1, result.modified_count);
+                let updated = my_coll.find(doc! { "y": 1 }).await.unwrap()
+                    .try_collect::<Vec<Document>>().await.unwrap();
+                assert_eq!(1, updated.len());
+
                 let result = my_coll.update_many(doc! {
                     "x": {
                         "$lt": 50
@@ -459,6 +658,9 @@ mod tests {
                 }, doc! { "$set": { "x": "updated" } }).await.unwrap();
 
                 assert_eq!(50, result.modified_count);
+                let updated = my_coll.find(doc! { "x": "updated" }).await.unwrap()
+                    .try_collect::<Vec<Document>>().await.unwrap();
+                assert_eq!(50, updated.len());
                 Ok(())
             }
         }
@@ -547,6 +749,62 @@ mod tests {
         open_server_with_test(db_path.as_path(), Box::new(TestRunner)).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_aggregation_allow_disk_use() {
+        use mongodb::{
+            bson::{Document, doc},
+            Collection
+        };
+        use futures::TryStreamExt;
+
+        struct TestRunner;
+
+        #[async_trait::async_trait]
+        impl Runner for TestRunner {
+
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                const COUNT: i64 = 5000;
+                // insert the values out of order
+                let docs: Vec<Document> = (0..COUNT).map(|i| doc! {
+                    "_id": i,
+                    "x": (i * 7919) % COUNT,
+                }).collect();
+
+                let database = client.database("sample_mflix");
+                let my_coll: Collection<Document> = database.collection("movies");
+                my_coll.insert_many(docs).await?;
+
+                let cursor = my_coll
+                    .aggregate(vec![
+                        doc! { "$sort": { "x": -1 } },
+                    ])
+                    .allow_disk_use(true)
+                    .await?;
+                let result: Vec<Document> = cursor.try_collect().await?;
+
+                assert_eq!(COUNT as usize, result.len());
+                for (index, item) in result.iter().enumerate() {
+                    assert_eq!(COUNT - 1 - index as i64, item.get_i64("x")?);
+                }
+                Ok(())
+            }
+        }
+
+        let db_path = mk_db_path("test-aggregation-allow-disk-use");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        // spill the sort to many runs
+        let mut config = ConfigBuilder::new();
+        config.set_sort_spill_threshold(100);
+        open_server_with_options(
+            db_path.as_path(),
+            CommandPolicy::AllowAll,
+            DEFAULT_MAX_CURSORS_PER_CONNECTION,
+            config.take(),
+            Box::new(TestRunner),
+        ).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_session() {
         use mongodb::{
@@ -598,4 +856,355 @@ mod tests {
         open_server_with_test(db_path.as_path(), Box::new(TestRunner)).await.unwrap();
     }
 
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_session_read_your_writes() {
+        use mongodb::{
+            bson::{Document, doc},
+            Collection,
+        };
+
+        struct TestRunner;
+
+        #[async_trait::async_trait]
+        impl Runner for TestRunner {
+
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let coll: Collection<Document> = client.database("sample_mflix").collection("movies");
+
+                let mut session = client.start_session().await.unwrap();
+                session.start_transaction().await?;
+
+                coll.insert_one(doc! { "_id": 1, "x": 1 }).session(&mut session).await?;
+
+                // the update sees the pending insert of the session
+                let result = coll.update_one(doc! { "_id": 1 }, doc! { "$set": { "x": 2 } }).session(&mut session).await?;
+                assert_eq!(result.matched_count, 1);
+                assert_eq!(result.modified_count, 1);
+
+                let one = coll.find_one(doc! { "_id": 1 }).session(&mut session).await?;
+                assert_eq!(2, one.unwrap().get_i32("x").unwrap());
+
+                // invisible out of the session before the commit
+                assert_eq!(None, coll.find_one(doc! { "_id": 1 }).await?);
+
+                session.commit_transaction().await?;
+
+                let one = coll.find_one(doc! { "_id": 1 }).await?;
+                assert_eq!(2, one.unwrap().get_i32("x").unwrap());
+
+                Ok(())
+            }
+        }
+
+        let db_path = mk_db_path("test-session-read-your-writes");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+        open_server_with_test(db_path.as_path(), Box::new(TestRunner)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_import() {
+        use std::sync::{Arc, Mutex};
+        use mongodb::{
+            bson::{Document, doc},
+            Collection
+        };
+
+        type Dump = Arc<Mutex<Vec<(String, Vec<Document>)>>>;
+
+        struct ExportRunner {
+            dump: Dump,
+        }
+
+        #[async_trait::async_trait]
+        impl Runner for ExportRunner {
+
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let database = client.database("sample_mflix");
+                let movies: Collection<Document> = database.collection("movies");
+                let docs: Vec<Document> = (0..250).map(|i| doc! { "_id": i, "x": i }).collect();
+                movies.insert_many(docs).await?;
+                let users: Collection<Document> = database.collection("users");
+                users.insert_many(vec![doc! { "_id": 1 }, doc! { "_id": 2 }]).await?;
+
+                let reply = database.run_command(doc! {
+                    "exportDocuments": 1,
+                    "batchSize": 100,
+                }).await?;
+
+                for item in reply.get_array("collections")? {
+                    let item = item.as_document().unwrap();
+                    let name = item.get_str("name")?.to_string();
+                    let cursor = item.get_document("cursor")?;
+                    let mut docs: Vec<Document> = cursor.get_array("firstBatch")?
+                        .iter()
+                        .map(|d| d.as_document().unwrap().clone())
+                        .collect();
+
+                    let mut cursor_id = cursor.get_i64("id")?;
+                    while cursor_id != 0 {
+                        let more = database.run_command(doc! {
+                            "getMore": cursor_id,
+                            "collection": name.as_str(),
+                            "batchSize": 100,
+                        }).await?;
+                        let cursor = more.get_document("cursor")?;
+                        for d in cursor.get_array("nextBatch")? {
+                            docs.push(d.as_document().unwrap().clone());
+                        }
+                        cursor_id = cursor.get_i64("id")?;
+                    }
+
+                    self.dump.lock().unwrap().push((name, docs));
+                }
+                Ok(())
+            }
+        }
+
+        struct ImportRunner {
+            dump: Dump,
+        }
+
+        #[async_trait::async_trait]
+        impl Runner for ImportRunner {
+
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let database = client.database("sample_mflix");
+                let dump = self.dump.lock().unwrap().clone();
+                assert_eq!(2, dump.len());
+
+                for (name, docs) in dump {
+                    for chunk in docs.chunks(100) {
+                        let reply = database.run_command(doc! {
+                            "importDocuments": name.as_str(),
+                            "documents": chunk.to_vec(),
+                        }).await?;
+                        assert_eq!(chunk.len() as i64, reply.get_i64("n")?);
+                    }
+
+                    let collection: Collection<Document> = database.collection(&name);
+                    let count = collection.count_documents(doc! {}).await?;
+                    assert_eq!(docs.len() as u64, count);
+                }
+
+                let movies: Collection<Document> = database.collection("movies");
+                assert_eq!(250, movies.count_documents(doc! {}).await?);
+                Ok(())
+            }
+        }
+
+        let dump: Dump = Arc::new(Mutex::new(Vec::new()));
+
+        let export_path = mk_db_path("test-export");
+        let _ = std::fs::remove_dir_all(export_path.as_path());
+        open_server_with_test(export_path.as_path(), Box::new(ExportRunner {
+            dump: dump.clone(),
+        })).await.unwrap();
+
+        let import_path = mk_db_path("test-import");
+        let _ = std::fs::remove_dir_all(import_path.as_path());
+        open_server_with_test(import_path.as_path(), Box::new(ImportRunner {
+            dump,
+        })).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_denied_command() {
+        use mongodb::{
+            bson::{Document, doc},
+            error::ErrorKind,
+            Collection
+        };
+
+        let db_path = mk_db_path("test-denied-command");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        struct TestRunner;
+        #[async_trait::async_trait]
+        impl Runner for TestRunner {
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let database = client.database("sample_mflix");
+                let my_coll: Collection<Document> = database.collection("movies");
+
+                let err = my_coll.delete_many(doc! {}).await.unwrap_err();
+                match *err.kind {
+                    ErrorKind::Command(ref command_error) => {
+                        assert_eq!(13, command_error.code);
+                        assert!(command_error.message.contains("delete"));
+                    }
+                    _ => panic!("unexpected error: {:?}", err),
+                }
+
+                my_coll.insert_one(doc! { "x": 1 }).await.unwrap();
+                assert_eq!(1, my_coll.count_documents(doc! {}).await?);
+                Ok(())
+            }
+        }
+
+        let command_policy = CommandPolicy::Deny(CommandPolicy::parse_names("delete,dropDatabase"));
+        open_server_with_policy(db_path.as_path(), command_policy, Box::new(TestRunner)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drop_database() {
+        use mongodb::{
+            bson::{Document, doc},
+            Collection
+        };
+
+        let db_path = mk_db_path("test-drop-database");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        struct TestRunner;
+        #[async_trait::async_trait]
+        impl Runner for TestRunner {
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let database = client.database("sample_mflix");
+                for name in ["movies", "comments"] {
+                    let my_coll: Collection<Document> = database.collection(name);
+                    my_coll.insert_one(doc! { "x": 1 }).await.unwrap();
+                }
+
+                database.drop().await.unwrap();
+
+                for name in ["movies", "comments"] {
+                    let my_coll: Collection<Document> = database.collection(name);
+                    assert_eq!(0, my_coll.count_documents(doc! {}).await?);
+                }
+                Ok(())
+            }
+        }
+
+        open_server_with_test(db_path.as_path(), Box::new(TestRunner)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rename_collection() {
+        use mongodb::{
+            bson::{Document, doc},
+            Collection
+        };
+
+        let db_path = mk_db_path("test-rename-collection");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        struct TestRunner;
+        #[async_trait::async_trait]
+        impl Runner for TestRunner {
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let database = client.database("sample_mflix");
+                let movies: Collection<Document> = database.collection("movies");
+                movies.insert_many(vec![doc! { "_id": 1 }, doc! { "_id": 2 }]).await.unwrap();
+                let films: Collection<Document> = database.collection("films");
+                films.insert_one(doc! { "_id": 3 }).await.unwrap();
+
+                let rename = |drop_target: bool| doc! {
+                    "renameCollection": "sample_mflix.movies",
+                    "to": "sample_mflix.films",
+                    "dropTarget": drop_target,
+                };
+                let admin = client.database("admin");
+                assert!(admin.run_command(rename(false)).await.is_err());
+                admin.run_command(rename(true)).await.unwrap();
+
+                assert_eq!(0, movies.count_documents(doc! {}).await?);
+                assert_eq!(2, films.count_documents(doc! {}).await?);
+                assert!(films.find_one(doc! { "_id": 1 }).await?.is_some());
+                assert!(films.find_one(doc! { "_id": 3 }).await?.is_none());
+                Ok(())
+            }
+        }
+
+        open_server_with_test(db_path.as_path(), Box::new(TestRunner)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_collection() {
+        use futures::TryStreamExt;
+        use mongodb::{
+            bson::{Document, doc},
+            Collection
+        };
+
+        let db_path = mk_db_path("test-create-collection");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        struct TestRunner;
+        #[async_trait::async_trait]
+        impl Runner for TestRunner {
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let database = client.database("test");
+                database.create_collection("logs").capped(true).max(3).await?;
+                assert!(database.create_collection("logs").await.is_err());
+
+                let logs: Collection<Document> = database.collection("logs");
+                for i in 0..5 {
+                    logs.insert_one(doc! { "message": i }).await?;
+                }
+                let messages = logs.find(doc! {}).await?
+                    .try_collect::<Vec<Document>>().await?
+                    .iter()
+                    .map(|log| log.get_i32("message").unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(messages, vec![2, 3, 4]);
+
+                database.create_collection("users")
+                    .validator(doc! { "name": { "$exists": true } })
+                    .await?;
+                let users: Collection<Document> = database.collection("users");
+                assert!(users.insert_one(doc! { "age": 30 }).await.is_err());
+                users.insert_one(doc! { "name": "Alice" }).await?;
+                Ok(())
+            }
+        }
+
+        open_server_with_test(db_path.as_path(), Box::new(TestRunner)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_cursors_per_connection() {
+        use mongodb::{
+            bson::{Document, doc},
+            Collection
+        };
+
+        let db_path = mk_db_path("test-max-cursors-per-connection");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        struct TestRunner;
+        #[async_trait::async_trait]
+        impl Runner for TestRunner {
+            async fn run(&self, client: mongodb::Client) -> Result<()> {
+                let database = client.database("test");
+                let items: Collection<Document> = database.collection("items");
+                items.insert_many((0..5).map(|i| doc! { "_id": i })).await?;
+
+                let mut cursor_ids = Vec::new();
+                for _ in 0..3 {
+                    let reply = database.run_command(doc! {
+                        "find": "items",
+                        "batchSize": 1,
+                    }).await?;
+                    cursor_ids.push(reply.get_document("cursor")?.get_i64("id")?);
+                }
+
+                let get_more = |cursor_id: i64| doc! {
+                    "getMore": cursor_id,
+                    "collection": "items",
+                    "batchSize": 1,
+                };
+                // the oldest cursor is killed by the third one
+                let err = database.run_command(get_more(cursor_ids[0])).await.unwrap_err();
+                assert!(err.to_string().contains("cursor not found"));
+                for cursor_id in &cursor_ids[1..] {
+                    let reply = database.run_command(get_more(*cursor_id)).await?;
+                    let batch = reply.get_document("cursor")?.get_array("nextBatch")?;
+                    assert_eq!(batch.len(), 1);
+                }
+                Ok(())
+            }
+        }
+
+        open_server_with_options(db_path.as_path(), CommandPolicy::AllowAll, 2, Config::default(), Box::new(TestRunner)).await.unwrap();
+    }
+
+}
@@ -0,0 +1,257 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A minimal interactive shell working on a local database.
+//!
+//! Commands look like the mongo shell, but the arguments must be JSON:
+//!
+//! ```text
+//! db.books.insertOne({"title": "The Three-Body Problem"})
+//! db.books.find({"title": "The Three-Body Problem"})
+//! show collections
+//! ```
+
+use std::convert::TryFrom;
+use std::io::{BufRead, Write};
+use anyhow::{anyhow, Result};
+use bson::{Bson, Document};
+use polodb_core::{CollectionT, Database};
+
+const PROMPT: &str = "polodb> ";
+
+/// Read the commands line by line from `input` and write the results to `output`,
+/// until the input ends or `exit` is entered.
+pub(crate) fn run_shell<R: BufRead, W: Write>(db: &Database, input: R, output: &mut W) -> Result<()> {
+    write!(output, "{}", PROMPT)?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line == "exit" || line == "quit" {
+            break;
+        }
+
+        if !line.is_empty() {
+            if let Err(err) = eval_command(db, line, output) {
+                writeln!(output, "error: {}", err)?;
+            }
+        }
+
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+fn eval_command<W: Write>(db: &Database, line: &str, output: &mut W) -> Result<()> {
+    if line == "show collections" {
+        for name in db.list_collection_names()? {
+            writeln!(output, "{}", name)?;
+        }
+        return Ok(());
+    }
+
+    let command = parse_command(line)?;
+    let collection = db.collection::<Document>(&command.collection);
+    let mut args = command.args.into_iter();
+
+    match command.method.as_str() {
+        "find" => {
+            let filter = next_document(&mut args)?.unwrap_or_default();
+            for doc in collection.find(filter).run()? {
+                print_bson(output, Bson::Document(doc?))?;
+            }
+        }
+        "findOne" => {
+            let filter = next_document(&mut args)?.unwrap_or_default();
+            match collection.find_one(filter)? {
+                Some(doc) => print_bson(output, Bson::Document(doc))?,
+                None => writeln!(output, "null")?,
+            }
+        }
+        "countDocuments" => {
+            let count = match next_document(&mut args)? {
+                Some(filter) => collection.find(filter).count()?,
+                None => collection.count_documents()?,
+            };
+            writeln!(output, "{}", count)?;
+        }
+        "insertOne" => {
+            let doc = require_document(&mut args, "document")?;
+            let result = collection.insert_one(doc)?;
+            print_bson(output, Bson::Document(bson::doc! {
+                "insertedId": result.inserted_id,
+            }))?;
+        }
+        "insertMany" => {
+            let docs = match args.next() {
+                Some(Bson::Array(arr)) => arr
+                    .into_iter()
+                    .map(|item| match item {
+                        Bson::Document(doc) => Ok(doc),
+                        _ => Err(anyhow!("insertMany expects an array of documents")),
+                    })
+                    .collect::<Result<Vec<Document>>>()?,
+                _ => return Err(anyhow!("insertMany expects an array of documents")),
+            };
+            let result = collection.insert_many(docs)?;
+            writeln!(output, "inserted: {}", result.inserted_ids.len())?;
+        }
+        "updateOne" | "updateMany" => {
+            let filter = require_document(&mut args, "filter")?;
+            let update = require_document(&mut args, "update")?;
+            let result = if command.method == "updateOne" {
+                collection.update_one(filter, update)?
+            } else {
+                collection.update_many(filter, update)?
+            };
+            writeln!(output, "matched: {}, modified: {}", result.matched_count, result.modified_count)?;
+        }
+        "deleteOne" | "deleteMany" => {
+            let filter = require_document(&mut args, "filter")?;
+            let result = if command.method == "deleteOne" {
+                collection.delete_one(filter)?
+            } else {
+                collection.delete_many(filter)?
+            };
+            writeln!(output, "deleted: {}", result.deleted_count)?;
+        }
+        "drop" => {
+            collection.drop()?;
+            writeln!(output, "dropped")?;
+        }
+        method => return Err(anyhow!("unknown method: {}", method)),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct ShellCommand {
+    collection: String,
+    method: String,
+    args: Vec<Bson>,
+}
+
+/// Parse `db.<collection>.<method>(<json args>)`
+fn parse_command(line: &str) -> Result<ShellCommand> {
+    let line = line.trim_end_matches(';');
+    let rest = line.strip_prefix("db.").ok_or_else(|| anyhow!("command should start with 'db.'"))?;
+    let open = rest.find('(').ok_or_else(|| anyhow!("missing '('"))?;
+    if !rest.ends_with(')') {
+        return Err(anyhow!("missing ')'"));
+    }
+
+    let target = &rest[..open];
+    let (collection, method) = target.rsplit_once('.').ok_or_else(|| anyhow!("missing method name"))?;
+    if collection.is_empty() || method.is_empty() {
+        return Err(anyhow!("invalid command: {}", line));
+    }
+
+    let args_str = rest[open + 1..rest.len() - 1].trim();
+    let args = if args_str.is_empty() {
+        vec![]
+    } else {
+        let value: serde_json::Value = serde_json::from_str(&format!("[{}]", args_str))?;
+        match Bson::try_from(value)? {
+            Bson::Array(arr) => arr,
+            _ => unreachable!(),
+        }
+    };
+
+    Ok(ShellCommand {
+        collection: collection.to_string(),
+        method: method.to_string(),
+        args,
+    })
+}
+
+fn next_document(args: &mut impl Iterator<Item = Bson>) -> Result<Option<Document>> {
+    match args.next() {
+        Some(Bson::Document(doc)) => Ok(Some(doc)),
+        Some(other) => Err(anyhow!("expect a document, actual: {}", other)),
+        None => Ok(None),
+    }
+}
+
+fn require_document(args: &mut impl Iterator<Item = Bson>, name: &str) -> Result<Document> {
+    next_document(args)?.ok_or_else(|| anyhow!("missing argument: {}", name))
+}
+
+fn print_bson<W: Write>(output: &mut W, value: Bson) -> Result<()> {
+    writeln!(output, "{}", value.into_relaxed_extjson())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use polodb_core::Database;
+    use crate::shell::{parse_command, run_shell};
+
+    #[test]
+    fn test_parse_command() {
+        let command = parse_command(r#"db.books.updateOne({"x": 1}, {"$set": {"y": 2}})"#).unwrap();
+        assert_eq!(command.collection, "books");
+        assert_eq!(command.method, "updateOne");
+        assert_eq!(command.args.len(), 2);
+
+        let command = parse_command("db.books.find()").unwrap();
+        assert!(command.args.is_empty());
+
+        assert!(parse_command("books.find()").is_err());
+        assert!(parse_command("db.books.find(").is_err());
+        assert!(parse_command("db.find()").is_err());
+    }
+
+    #[test]
+    fn test_run_shell() {
+        let mut db_path = std::env::temp_dir();
+        db_path.push("test-shell-db-server");
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+        let db = Database::open_path(db_path.as_path()).unwrap();
+
+        let script = r#"
+db.books.insertOne({"_id": 1, "title": "The Three-Body Problem"})
+db.books.insertMany([{"_id": 2, "title": "The Dark Forest"}, {"_id": 3, "title": "Death's End"}])
+db.books.updateOne({"_id": 3}, {"$set": {"year": 2010}})
+db.books.deleteOne({"_id": 2})
+db.books.find({"_id": 3})
+db.books.countDocuments()
+db.books.countDocuments({"year": 2010})
+show collections
+db.books.unknown()
+exit
+db.books.countDocuments()
+"#;
+        let mut output = Vec::<u8>::new();
+        run_shell(&db, Cursor::new(script), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains(r#"{"insertedId":1}"#));
+        assert!(output.contains("inserted: 2"));
+        assert!(output.contains("matched: 1, modified: 1"));
+        assert!(output.contains("deleted: 1"));
+        assert!(output.contains(r#"{"_id":3,"title":"Death's End","year":2010}"#));
+        assert!(output.contains("polodb> 2\n"));
+        assert!(output.contains("polodb> 1\n"));
+        assert!(output.contains("books\n"));
+        assert!(output.contains("error: unknown method: unknown"));
+        // the commands after exit are ignored
+        assert_eq!(output.matches("polodb> 2\n").count(), 1);
+    }
+}
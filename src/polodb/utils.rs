@@ -1,3 +1,4 @@
+use std::time::Duration;
 use bson::{Bson, RawBsonRef, uuid};
 
 pub(crate) fn truly_value_for_bson_ref(r: Option<RawBsonRef>, default: bool) -> bool {
@@ -15,6 +16,21 @@ pub(crate) fn truly_value_for_bson_ref(r: Option<RawBsonRef>, default: bool) ->
     }
 }
 
+/// Parse the `maxTimeMS` of a command, zero means no limit.
+pub(crate) fn max_time_for_bson_ref(r: Option<RawBsonRef>) -> Option<Duration> {
+    let ms = match r? {
+        RawBsonRef::Int32(i) => i as i64,
+        RawBsonRef::Int64(i) => i,
+        RawBsonRef::Double(d) => d as i64,
+        _ => return None,
+    };
+    if ms > 0 {
+        Some(Duration::from_millis(ms as u64))
+    } else {
+        None
+    }
+}
+
 pub(crate) fn uuid_from_bson(r: &Bson) -> Option<uuid::Uuid> {
     match r {
         Bson::Binary(bin) => {
@@ -13,10 +13,11 @@
 // limitations under the License.
 
 use std::sync::Weak;
+use std::time::Duration;
 use bson::Document;
 use serde::de::DeserializeOwned;
 use crate::{ClientCursor, Error, Result};
-use crate::db::db_inner::DatabaseInner;
+use crate::db::db_inner::{AggregateOptions, DatabaseInner};
 use crate::transaction::TransactionInner;
 
 pub struct Aggregate<'a, 'b, T: DeserializeOwned + Send + Sync = Document> {
@@ -24,6 +25,8 @@ pub struct Aggregate<'a, 'b, T: DeserializeOwned + Send + Sync = Document> {
     name: &'a str,
     pipeline: Vec<Document>,
     txn: Option<&'b TransactionInner>,
+    max_time: Option<Duration>,
+    allow_disk_use: bool,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -34,19 +37,51 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Aggregate<'a, 'b, T> {
             name,
             pipeline,
             txn,
+            max_time: None,
+            allow_disk_use: false,
             _phantom: Default::default(),
         }
     }
 
+    /// Abort the aggregation with [`Error::MaxTimeExpired`] when it runs longer than `max_time`.
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// Let `$sort` and `$group` write the documents to temporary files instead of holding
+    /// all of them in memory, see [`Config::sort_spill_threshold`](crate::Config::sort_spill_threshold)
+    /// and [`Config::group_spill_threshold`](crate::Config::group_spill_threshold).
+    pub fn allow_disk_use(mut self, allow_disk_use: bool) -> Self {
+        self.allow_disk_use = allow_disk_use;
+        self
+    }
+
+    /// Run the pipeline and return a cursor of the results.
+    ///
+    /// The results are produced while the cursor is iterated, a batch of
+    /// [`Config::cursor_batch_size`](crate::Config::cursor_batch_size) at a time, so a pipeline
+    /// of stages like `$match`, `$project` or `$limit` reads the documents as they are needed.
+    /// A stage which needs all its input, such as `$sort` or `$group`,
+    /// holds the documents until the collection is scanned.
     pub fn run(self) -> Result<ClientCursor<T>> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
         let txn = match self.txn {
             Some(txn) => txn.clone(),
             None => {
+                db.flush_write_buffer()?;
                 db.start_transaction()?
             }
         };
-        db.aggregate_with_owned_session(self.name, self.pipeline, txn.clone())
+        let options = AggregateOptions {
+            allow_disk_use: self.allow_disk_use,
+            ..Default::default()
+        };
+        let mut cursor = db.aggregate_with_owned_session(self.name, self.pipeline, options, txn.clone())?;
+        if let Some(max_time) = self.max_time {
+            cursor.set_max_time(max_time);
+        }
+        Ok(cursor)
     }
 
     pub fn with_type<U>(self) -> Aggregate<'a, 'b, U>
@@ -56,6 +91,8 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Aggregate<'a, 'b, T> {
             name: self.name,
             pipeline: self.pipeline,
             txn: self.txn,
+            max_time: self.max_time,
+            allow_disk_use: self.allow_disk_use,
             _phantom: Default::default(),
         }
     }
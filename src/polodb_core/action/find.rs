@@ -13,10 +13,11 @@
 // limitations under the License.
 
 use std::sync::Weak;
-use bson::{Document, doc};
+use std::time::Duration;
+use bson::{Bson, Document, doc};
 use serde::de::DeserializeOwned;
-use crate::db::db_inner::DatabaseInner;
-use crate::{ClientCursor, Error, Result};
+use crate::db::db_inner::{AggregateOptions, DatabaseInner};
+use crate::{CancellationToken, ClientCursor, Error, Result};
 use crate::transaction::TransactionInner;
 
 pub struct Find<'a, 'b, T: DeserializeOwned + Send + Sync> {
@@ -27,6 +28,9 @@ pub struct Find<'a, 'b, T: DeserializeOwned + Send + Sync> {
     skip: Option<u64>,
     limit: Option<u64>,
     sort: Option<Document>,
+    projection: Option<Document>,
+    max_time: Option<Duration>,
+    cancellation_token: Option<CancellationToken>,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -40,6 +44,9 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Find<'a, 'b, T> {
             skip: None,
             limit: None,
             sort: None,
+            projection: None,
+            max_time: None,
+            cancellation_token: None,
             _phantom: Default::default(),
         }
     }
@@ -54,34 +61,100 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Find<'a, 'b, T> {
         self
     }
 
+    /// Sort the matched documents.
+    ///
+    /// `{ "$natural": 1 }` or `{ "$natural": -1 }` returns the documents in storage
+    /// order, forward or reverse, with a collection scan that bypasses the indexes.
+    /// `$natural` can't be combined with other sort keys.
     pub fn sort(mut self, sort: Document) -> Self {
         self.sort = Some(sort);
         self
     }
 
+    /// Only return the fields specified by the projection.
+    ///
+    /// Besides including or excluding fields, `{ "items": { "$elemMatch": <condition> } }`
+    /// returns only the first element of the array matching the condition.
+    pub fn projection(mut self, projection: Document) -> Self {
+        self.projection = Some(projection);
+        self
+    }
+
+    /// Abort the query with [`Error::MaxTimeExpired`] when it runs longer than `max_time`.
+    ///
+    /// The time is counted from [`Find::run`], including the time spent iterating the cursor.
+    pub fn max_time(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    /// Stop the query with [`Error::Cancelled`] when `token` is cancelled,
+    /// which can be done from another thread.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Run the query and return a cursor of the matched documents.
+    ///
+    /// Without an explicit [`Find::limit`], iterating the cursor yields
+    /// [`Error::MaterializeLimitExceeded`] after
+    /// [`Config::max_materialize_count`](crate::Config::max_materialize_count)
+    /// documents, so collecting a huge result doesn't run out of memory.
+    /// Use [`ClientCursor::advance`] to stream a large result instead.
     pub fn run(self) -> Result<ClientCursor<T>> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = match self.txn {
-            Some(txn) => txn.clone(),
+        let max_time = self.max_time;
+        let cancellation_token = self.cancellation_token.clone();
+        let materialize_limit = match self.limit {
+            Some(_) => 0,
             None => {
-                db.start_transaction()?
+                let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+                db.config().max_materialize_count
             }
         };
-        match (self.skip.as_ref(), self.limit.as_ref(), self.sort.as_ref()) {
-            (None, None, None) => {
+        let mut cursor = self.run_internal()?;
+        cursor.set_materialize_limit(materialize_limit);
+        if let Some(max_time) = max_time {
+            cursor.set_max_time(max_time);
+        }
+        if let Some(token) = cancellation_token {
+            cursor.set_cancellation_token(token);
+        }
+        Ok(cursor)
+    }
+
+    fn session(&self, db: &DatabaseInner) -> Result<TransactionInner> {
+        match self.txn {
+            Some(txn) => Ok(txn.clone()),
+            None => {
+                db.flush_write_buffer()?;
+                db.start_transaction()
+            }
+        }
+    }
+
+    fn run_internal(self) -> Result<ClientCursor<T>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = self.session(&db)?;
+        match (self.skip.as_ref(), self.limit.as_ref(), self.sort.as_ref(), self.projection.as_ref()) {
+            (None, None, None, None) => {
                 db.find_with_owned_session(self.name, self.filter, txn)
             }
             _ => {
-                let mut pipeline = vec![
-                    doc! {
-                        "$match": self.filter
-                    }
-                ];
+                let mut pipeline = vec![];
+                let mut options = AggregateOptions::default();
 
                 if let Some(sort) = self.sort {
-                    pipeline.push(doc! {
-                        "$sort": sort
-                    });
+                    match natural_direction(&sort)? {
+                        Some(reverse) => {
+                            options.natural_reverse = Some(reverse);
+                        }
+                        None => {
+                            pipeline.push(doc! {
+                                "$sort": sort
+                            });
+                        }
+                    }
                 }
 
                 if let Some(skip) = self.skip {
@@ -96,8 +169,86 @@ impl <'a, 'b , T: DeserializeOwned + Send + Sync> Find<'a, 'b, T> {
                     });
                 }
 
-                db.aggregate_with_owned_session(self.name, pipeline, txn)
+                if let Some(projection) = self.projection {
+                    pipeline.push(doc! {
+                        "$project": projection,
+                    });
+                }
+
+                db.find_pipeline_with_owned_session(self.name, self.filter, pipeline, options, txn)
             }
         }
     }
+
+    /// Count the matched documents, after the skip and the limit.
+    ///
+    /// The documents are counted by a `$count` stage while they are scanned,
+    /// none of them is returned by the cursor. The sort and the projection are ignored.
+    pub fn count(self) -> Result<u64> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = self.session(&db)?;
+
+        let mut pipeline = vec![];
+
+        if let Some(skip) = self.skip {
+            pipeline.push(doc! {
+                "$skip": skip as i64,
+            });
+        }
+
+        if let Some(limit) = self.limit {
+            pipeline.push(doc! {
+                "$limit": limit as i64,
+            });
+        }
+
+        pipeline.push(doc! {
+            "$count": "count",
+        });
+
+        let mut cursor = db.find_pipeline_with_owned_session::<Document>(
+            self.name,
+            self.filter,
+            pipeline,
+            AggregateOptions::default(),
+            txn,
+        )?;
+        if let Some(token) = self.cancellation_token {
+            cursor.set_cancellation_token(token);
+        }
+        if let Some(max_time) = self.max_time {
+            cursor.set_max_time(max_time);
+        }
+
+        if !cursor.advance()? {
+            return Ok(0);
+        }
+        let result = cursor.deserialize_current()?;
+        Ok(result.get_i64("count").unwrap_or(0) as u64)
+    }
+}
+
+/// Returns whether a `$natural` sort is in the reverse order,
+/// or `None` when the sort doesn't use `$natural`.
+fn natural_direction(sort: &Document) -> Result<Option<bool>> {
+    let value = match sort.get("$natural") {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    if sort.len() != 1 {
+        return Err(Error::ValidationError("$natural can't be combined with other sort keys".to_string()));
+    }
+    let direction = match value {
+        Bson::Int32(i) => *i as f64,
+        Bson::Int64(i) => *i as f64,
+        Bson::Double(d) => *d,
+        _ => 0.0,
+    };
+    if direction == 1.0 {
+        Ok(Some(false))
+    } else if direction == -1.0 {
+        Ok(Some(true))
+    } else {
+        Err(Error::ValidationError(format!("$natural must be 1 or -1, got {}", value)))
+    }
 }
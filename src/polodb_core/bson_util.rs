@@ -0,0 +1,102 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for the BSON documents.
+
+use bson::{Bson, Document};
+use crate::Result;
+
+/// Return a byte representation of the document that is the same for
+/// the documents equal by value, to be used as a hash key.
+///
+/// The keys of the document and the embedded documents are sorted, the order of
+/// the array elements is kept. The integral numbers are stored as `Int64`, whatever
+/// type they have, so `1`, `1_i64` and `1.0` are the same.
+pub fn canonical_bytes(doc: &Document) -> Result<Vec<u8>> {
+    let canonical = canonical_document(doc);
+    Ok(bson::to_vec(&canonical)?)
+}
+
+fn canonical_document(doc: &Document) -> Document {
+    let mut fields: Vec<_> = doc.iter().collect();
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut result = Document::new();
+    for (key, value) in fields {
+        result.insert(key.clone(), canonical_value(value));
+    }
+    result
+}
+
+fn canonical_value(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(doc) => Bson::Document(canonical_document(doc)),
+        Bson::Array(arr) => Bson::Array(arr.iter().map(canonical_value).collect()),
+        Bson::Int32(i) => Bson::Int64(*i as i64),
+        // -0.0 is 0 too
+        Bson::Double(d) if d.fract() == 0.0 && *d >= i64::MIN as f64 && *d < i64::MAX as f64 => {
+            Bson::Int64(*d as i64)
+        }
+        // the NaNs have different bits
+        Bson::Double(d) if d.is_nan() => Bson::Double(f64::NAN),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use bson::doc;
+    use super::canonical_bytes;
+
+    fn hash_of(doc: &bson::Document) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        canonical_bytes(doc).unwrap().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_canonical_bytes_key_order() {
+        let a = doc! {
+            "name": "Vincent",
+            "address": { "city": "Paris", "zip": "75001" },
+            "tags": [{ "k": 1, "v": 2 }],
+        };
+        let b = doc! {
+            "tags": [{ "v": 2, "k": 1 }],
+            "address": { "zip": "75001", "city": "Paris" },
+            "name": "Vincent",
+        };
+        assert_eq!(canonical_bytes(&a).unwrap(), canonical_bytes(&b).unwrap());
+        assert_eq!(hash_of(&a), hash_of(&b));
+    }
+
+    #[test]
+    fn test_canonical_bytes_numbers() {
+        let a = doc! { "x": 1, "y": [2_i64, 3.0], "z": -0.0 };
+        let b = doc! { "x": 1.0, "y": [2, 3_i64], "z": 0 };
+        assert_eq!(hash_of(&a), hash_of(&b));
+
+        assert_ne!(hash_of(&doc! { "x": 1.5 }), hash_of(&doc! { "x": 1 }));
+        assert_ne!(hash_of(&doc! { "x": "1" }), hash_of(&doc! { "x": 1 }));
+        assert_eq!(hash_of(&doc! { "x": f64::NAN }), hash_of(&doc! { "x": -f64::NAN }));
+    }
+
+    #[test]
+    fn test_canonical_bytes_array_order() {
+        assert_ne!(hash_of(&doc! { "x": [1, 2] }), hash_of(&doc! { "x": [2, 1] }));
+    }
+
+}
@@ -13,48 +13,33 @@
 // limitations under the License.
 
 use serde::Serialize;
-use bson::Document;
+use bson::{doc, Bson, Document};
 use std::borrow::Borrow;
-use std::sync::Weak;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
 use serde::de::DeserializeOwned;
-use crate::options::UpdateOptions;
-use crate::{Error, IndexModel, Result};
+use crate::options::{DeleteOptions, InsertManyOptions, UpdateOptions};
+use crate::{Error, IndexModel, RawScan, Result};
 use crate::db::db_inner::DatabaseInner;
 use crate::action::{Aggregate, Find};
-use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
-
-macro_rules! try_multiple {
-    ($err: expr, $action: expr) => {
-        match $action {
-            Ok(ret) => ret,
-            Err(expr_err) => {
-                return Err($err.add(expr_err))
-            },
-        }
-    }
-}
-
-macro_rules! try_db_op {
-    ($txn: expr, $action: expr) => {
-        match $action {
-            Ok(ret) => {
-                $txn.commit()?;
-                ret
-            }
-
-            Err(err) => {
-                try_multiple!(err, $txn.rollback());
-                return Err(err);
-            }
-        }
-    }
-}
+use crate::results::{CollectionStats, DeleteResult, InferredSchema, InsertManyResult, InsertOneResult, UpdateResult};
 
 pub trait CollectionT<T> {
     fn name(&self) -> &str;
     /// Return the size of all data in the collection.
     fn count_documents(&self) -> Result<u64>;
 
+    /// Return the document count, the data size and the index sizes of the collection.
+    fn stats(&self) -> Result<CollectionStats>;
+
+    /// Return how many times every index of the collection has been used
+    /// by the queries since the database was opened, by index name.
+    fn index_stats(&self) -> Result<HashMap<String, u64>>;
+
+    /// Sample up to `sample_size` documents in the order of `_id`
+    /// and return the types observed for every field path with their frequencies.
+    fn infer_schema(&self, sample_size: u64) -> Result<InferredSchema>;
+
     /// Updates up to one document matching `query` in the collection.
     /// [documentation](https://www.polodb.org/docs/curd/update) for more information on specifying updates.
     fn update_one(&self, query: Document, update: Document) -> Result<UpdateResult>;
@@ -67,6 +52,16 @@ pub trait CollectionT<T> {
 
     fn update_many_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> Result<UpdateResult>;
 
+    /// Returns the document `update_one` would modify with the update applied,
+    /// without writing anything.
+    fn update_one_dry_run(&self, query: Document, update: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned;
+
+    /// Returns the documents `update_many` would modify with the update applied,
+    /// without writing anything.
+    fn update_many_dry_run(&self, query: Document, update: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned;
+
     /// Deletes up to one document found matching `query`.
     fn delete_one(&self, query: Document) -> Result<DeleteResult>;
 
@@ -74,6 +69,21 @@ pub trait CollectionT<T> {
     ///
     /// The size of data deleted returns.
     fn delete_many(&self, query: Document) -> Result<DeleteResult>;
+
+    /// Deletes the documents matching `query`, at most [`DeleteOptions::limit`] of them.
+    fn delete_many_with_options(&self, query: Document, options: DeleteOptions) -> Result<DeleteResult>;
+
+    /// Deletes the documents with the `_id`s in `ids` in one transaction,
+    /// each of them is looked up by the primary key. The missing ones are ignored.
+    fn delete_by_ids(&self, ids: &[Bson]) -> Result<DeleteResult>;
+
+    /// Returns the document `delete_one` would delete, without writing anything.
+    fn delete_one_dry_run(&self, query: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned;
+
+    /// Returns the documents `delete_many` would delete, without writing anything.
+    fn delete_many_dry_run(&self, query: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned;
     fn create_index(&self, index: IndexModel) -> Result<()>;
 
     /// Drops the index specified by `name` from this collection.
@@ -84,10 +94,21 @@ pub trait CollectionT<T> {
     fn insert_one(&self, doc: impl Borrow<T>) -> Result<InsertOneResult>
     where T: Serialize;
 
+    /// Inserts `doc` into the collection and returns it as stored,
+    /// including the generated `_id` and the default values of the collection.
+    fn insert_one_and_fetch(&self, doc: impl Borrow<T>) -> Result<T>
+    where T: Serialize + DeserializeOwned;
+
     /// Inserts the data in `docs` into the collection.
     fn insert_many(&self, docs: impl IntoIterator<Item = impl Borrow<T>>) -> Result<InsertManyResult>
     where T: Serialize;
 
+    /// Inserts the data in `docs` into the collection.
+    /// An unordered insert commits the documents inserted successfully
+    /// and returns the failures in [`InsertManyResult::write_errors`].
+    fn insert_many_with_options(&self, docs: impl IntoIterator<Item = impl Borrow<T>>, options: InsertManyOptions) -> Result<InsertManyResult>
+    where T: Serialize;
+
     /// When query document is passed to the function. The result satisfies
     /// the query document.
     fn find(&self, filter: Document) -> Find<'_, '_, T>
@@ -97,6 +118,21 @@ pub trait CollectionT<T> {
     fn find_one(&self, filter: Document) -> Result<Option<T>>
     where T: DeserializeOwned + Send + Sync;
 
+    /// Gets the value of the field at `path`, such as `"address.zip"`, of the first
+    /// document matching `filter`. Only that value is returned by the query, the document
+    /// isn't deserialized. `None` if no document matches or the field is missing.
+    fn get_field(&self, filter: Document, path: &str) -> Result<Option<Bson>>
+    where T: DeserializeOwned + Send + Sync;
+
+    /// Gets the documents with the `_id`s in `ids` by the primary key lookups.
+    /// The results are in the order of `ids`, with `None` for the missing documents.
+    fn find_by_ids(&self, ids: &[Bson]) -> Result<Vec<Option<T>>>
+    where T: DeserializeOwned;
+
+    /// Iterates the stored BSON bytes of all the documents in the order of `_id`,
+    /// without deserializing them.
+    fn scan_raw(&self) -> Result<RawScan>;
+
     /// Runs an aggregation operation.
     fn aggregate(&self, pipeline: impl IntoIterator<Item = Document>) -> Aggregate<'_, '_>;
 }
@@ -122,6 +158,22 @@ impl<T>  Collection<T>
             _phantom: std::default::Default::default(),
         }
     }
+
+    /// Write the documents buffered by [`Config::write_buffer_size`](crate::Config::write_buffer_size).
+    ///
+    /// The buffer is shared by all the collections of the database, so they are all flushed.
+    /// The errors of the buffered documents written since the last flush are returned,
+    /// e.g. a duplicate key, the other documents are still written.
+    pub fn flush(&self) -> Result<()> {
+        self.flushed_db()?.take_write_buffer_errors()
+    }
+
+    /// Every operation except the buffered insert sees the buffered documents.
+    fn flushed_db(&self) -> Result<Arc<DatabaseInner>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.flush_write_buffer()?;
+        Ok(db)
+    }
 }
 
 impl<T> CollectionT<T> for Collection<T> {
@@ -131,117 +183,162 @@ impl<T> CollectionT<T> for Collection<T> {
     }
 
     fn count_documents(&self) -> Result<u64> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let db = self.flushed_db()?;
         let txn = db.start_transaction()?;
         let count = db.count_documents(&self.name, &txn)?;
         Ok(count)
     }
 
-    fn update_one(&self, query: Document, update: Document) -> Result<UpdateResult> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+    fn stats(&self) -> Result<CollectionStats> {
+        let db = self.flushed_db()?;
         let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.update_one(
-            &self.name,
-            query,
-            update,
-            UpdateOptions::default(),
-            &txn,
-        ));
-        Ok(result)
+        db.collection_stats(&self.name, &txn)
     }
 
-    fn update_one_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> Result<UpdateResult> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+    fn index_stats(&self) -> Result<HashMap<String, u64>> {
+        let db = self.flushed_db()?;
         let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.update_one(
-            &self.name,
-            query,
-            update,
-            options,
-            &txn,
-        ));
-        Ok(result)
+        db.index_stats(&self.name, &txn)
     }
 
-    fn update_many(&self, query: Document, update: Document) -> Result<UpdateResult> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+    fn infer_schema(&self, sample_size: u64) -> Result<InferredSchema> {
+        let db = self.flushed_db()?;
         let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.update_many(
+        db.infer_schema(&self.name, sample_size, &txn)
+    }
+
+    fn update_one(&self, query: Document, update: Document) -> Result<UpdateResult> {
+        self.update_one_with_options(query, update, UpdateOptions::default())
+    }
+
+    fn update_one_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> Result<UpdateResult> {
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.update_one(
             &self.name,
-            query,
-            update,
-            UpdateOptions::default(),
-            &txn,
-        ));
-        Ok(result)
+            query.clone(),
+            update.clone(),
+            options.clone(),
+            txn,
+        ))
+    }
+
+    fn update_many(&self, query: Document, update: Document) -> Result<UpdateResult> {
+        self.update_many_with_options(query, update, UpdateOptions::default())
     }
 
     fn update_many_with_options(&self, query: Document, update: Document, options: UpdateOptions) -> Result<UpdateResult> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.update_many(
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.update_many(
             &self.name,
-            query,
-            update,
-            options,
-            &txn,
-        ));
-        Ok(result)
+            query.clone(),
+            update.clone(),
+            options.clone(),
+            txn,
+        ))
     }
 
-    fn delete_one(&self, query: Document) -> Result<DeleteResult> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+    fn update_one_dry_run(&self, query: Document, update: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.flushed_db()?;
+        let txn = db.start_transaction()?;
+        let docs = db.dry_run_update(&self.name, query, update, false, &txn)?;
+        deserialize_documents(docs)
+    }
+
+    fn update_many_dry_run(&self, query: Document, update: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.flushed_db()?;
         let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.delete_one(&self.name, query, &txn));
-        Ok(result)
+        let docs = db.dry_run_update(&self.name, query, update, true, &txn)?;
+        deserialize_documents(docs)
+    }
+
+    fn delete_one(&self, query: Document) -> Result<DeleteResult> {
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.delete_one(&self.name, query.clone(), txn))
     }
 
     fn delete_many(&self, query: Document) -> Result<DeleteResult> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        self.delete_many_with_options(query, DeleteOptions::default())
+    }
+
+    fn delete_many_with_options(&self, query: Document, options: DeleteOptions) -> Result<DeleteResult> {
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.delete_many(&self.name, query.clone(), options.clone(), txn))
+    }
+
+    fn delete_by_ids(&self, ids: &[Bson]) -> Result<DeleteResult> {
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.delete_by_ids(&self.name, ids, txn))
+    }
+
+    fn delete_one_dry_run(&self, query: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.flushed_db()?;
         let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.delete_many(&self.name, query, &txn));
-        Ok(result)
+        let docs = db.dry_run_delete(&self.name, query, false, &txn)?;
+        deserialize_documents(docs)
     }
 
-    fn create_index(&self, index: IndexModel) -> Result<()> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+    fn delete_many_dry_run(&self, query: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.flushed_db()?;
         let txn = db.start_transaction()?;
-        try_db_op!(txn, db.create_index(&self.name, index, &txn));
-        Ok(())
+        let docs = db.dry_run_delete(&self.name, query, true, &txn)?;
+        deserialize_documents(docs)
+    }
+
+    fn create_index(&self, index: IndexModel) -> Result<()> {
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.create_index(&self.name, index.clone(), txn))
     }
 
     fn drop_index(&self, name: impl AsRef<str>) -> Result<()> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
-        try_db_op!(txn, db.drop_index(&self.name, name.as_ref(), &txn));
-        Ok(())
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.drop_index(&self.name, name.as_ref(), txn))
     }
 
     fn drop(&self) -> Result<()> {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
-        try_db_op!(txn, db.drop_collection(&self.name, &txn));
-        Ok(())
+        let db = self.flushed_db()?;
+        db.run_in_auto_transaction(|txn| db.drop_collection(&self.name, txn))
     }
 
     fn insert_one(&self, doc: impl Borrow<T>) -> Result<InsertOneResult>
     where T: Serialize {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.insert_one(
-            &self.name,
-            bson::to_document(doc.borrow())?,
-            &txn,
-        ));
-        Ok(result)
+        let doc = bson::to_document(doc.borrow())?;
+        if db.is_write_buffer_enabled() {
+            return db.buffered_insert_one(&self.name, doc);
+        }
+        db.run_in_auto_transaction(|txn| db.insert_one(&self.name, doc.clone(), txn))
+    }
+
+    fn insert_one_and_fetch(&self, doc: impl Borrow<T>) -> Result<T>
+    where T: Serialize + DeserializeOwned {
+        let db = self.flushed_db()?;
+        let doc = bson::to_document(doc.borrow())?;
+        let stored = db.run_in_auto_transaction(|txn| db.insert_one_and_fetch(&self.name, doc.clone(), txn))?;
+        Ok(bson::from_document(stored)?)
     }
 
     fn insert_many(&self, docs: impl IntoIterator<Item = impl Borrow<T>>) -> Result<InsertManyResult>
     where T: Serialize {
-        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let txn = db.start_transaction()?;
-        let result = try_db_op!(txn, db.insert_many(&self.name, docs, &txn));
-        Ok(result)
+        let db = self.flushed_db()?;
+        let docs = docs
+            .into_iter()
+            .map(|doc| bson::to_document(doc.borrow()))
+            .collect::<std::result::Result<Vec<Document>, _>>()?;
+        db.run_in_auto_transaction(|txn| db.insert_many::<Document>(&self.name, &docs, txn))
+    }
+
+    fn insert_many_with_options(&self, docs: impl IntoIterator<Item = impl Borrow<T>>, options: InsertManyOptions) -> Result<InsertManyResult>
+    where T: Serialize {
+        let db = self.flushed_db()?;
+        let docs = docs
+            .into_iter()
+            .map(|doc| bson::to_document(doc.borrow()))
+            .collect::<std::result::Result<Vec<Document>, _>>()?;
+        db.run_in_auto_transaction(|txn| db.insert_many_with_options::<Document>(&self.name, &docs, &options, txn))
     }
 
     fn find(&self, filter: Document) -> Find<T>
@@ -259,6 +356,25 @@ impl<T> CollectionT<T> for Collection<T> {
         Ok(Some(cursor.deserialize_current()?))
     }
 
+    fn get_field(&self, filter: Document, path: &str) -> Result<Option<Bson>>
+    where T: DeserializeOwned + Send + Sync {
+        first_field_value(self.find(filter), path)
+    }
+
+    fn find_by_ids(&self, ids: &[Bson]) -> Result<Vec<Option<T>>>
+    where T: DeserializeOwned {
+        let db = self.flushed_db()?;
+        let txn = db.start_transaction()?;
+        let docs = db.find_by_ids(&self.name, ids, &txn)?;
+        deserialize_optional_documents(docs)
+    }
+
+    fn scan_raw(&self) -> Result<RawScan> {
+        let db = self.flushed_db()?;
+        let txn = db.start_transaction()?;
+        db.scan_raw(&self.name, txn)
+    }
+
     fn aggregate(&self, pipeline: impl IntoIterator<Item = Document>) -> Aggregate<'_, '_> {
         Aggregate::new(
             self.db.clone(),
@@ -268,3 +384,41 @@ impl<T> CollectionT<T> for Collection<T> {
         )
     }
 }
+
+/// Run the query projected to the value of `path` and return the value of the first match.
+pub(super) fn first_field_value<T: DeserializeOwned + Send + Sync>(find: Find<'_, '_, T>, path: &str) -> Result<Option<Bson>> {
+    let mut cursor = find
+        .projection(doc! {
+            "_id": 0,
+            "value": format!("${}", path),
+        })
+        .limit(1)
+        .run()?;
+    if !cursor.advance()? {
+        return Ok(None);
+    }
+    let value = match cursor.get() {
+        Bson::Document(doc) => doc.get("value").cloned(),
+        _ => None,
+    };
+    Ok(value)
+}
+
+pub(super) fn deserialize_optional_documents<T: DeserializeOwned>(docs: Vec<Option<Document>>) -> Result<Vec<Option<T>>> {
+    let mut result = Vec::with_capacity(docs.len());
+    for doc in docs {
+        result.push(match doc {
+            Some(doc) => Some(bson::from_document(doc)?),
+            None => None,
+        });
+    }
+    Ok(result)
+}
+
+pub(super) fn deserialize_documents<T: DeserializeOwned>(docs: Vec<Document>) -> Result<Vec<T>> {
+    let mut result = Vec::with_capacity(docs.len());
+    for doc in docs {
+        result.push(bson::from_document(doc)?);
+    }
+    Ok(result)
+}
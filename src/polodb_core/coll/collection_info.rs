@@ -12,12 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bson::{Binary, DateTime};
+use bson::{Binary, DateTime, Document};
 use bson::spec::BinarySubtype;
 use serde::{Deserialize, Serialize};
 use indexmap::IndexMap;
 use uuid::Uuid;
-use crate::IndexOptions;
+use crate::{Collation, IndexOptions};
 use crate::utils::bson::bson_datetime_now;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -49,6 +49,13 @@ impl IndexInfo {
             .unwrap_or(false)
     }
 
+    #[inline]
+    pub fn collation(&self) -> Option<&Collation> {
+        self.options
+            .as_ref()
+            .and_then(|options| options.collation.as_ref())
+    }
+
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -81,6 +88,54 @@ pub struct CollectionSpecification {
     /// The name is converted to the underline format.
     /// For examples, `author.age` is converted to `author_age`
     pub indexes: IndexMap<String, IndexInfo>,
+
+    /// The documents written to the collection must satisfy the validator.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator: Option<Document>,
+
+    /// The values of the fields filled when they are missing in inserted documents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<Document>,
+
+    /// Increased every time the indexes of the collection change,
+    /// the compiled queries of older versions are outdated.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub meta_version: u64,
+
+    /// Assign an incrementing Int64 `_id` to the inserted documents without one.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub auto_increment_id: bool,
+
+    /// The limits of a capped collection, the oldest documents are evicted
+    /// when an insertion exceeds them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capped: Option<CappedInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CappedInfo {
+    /// The max total size in bytes of the documents, 0 for no limit.
+    pub size: u64,
+
+    /// The max count of the documents, 0 for no limit.
+    pub max: u64,
+}
+
+impl CappedInfo {
+
+    pub(crate) fn is_exceeded(&self, count: u64, size: u64) -> bool {
+        (self.max > 0 && count > self.max) || (self.size > 0 && size > self.size)
+    }
+
+}
+
+fn is_zero(v: &u64) -> bool {
+    *v == 0
+}
+
+fn is_false(v: &bool) -> bool {
+    !*v
 }
 
 impl CollectionSpecification {
@@ -105,6 +160,11 @@ impl CollectionSpecification {
             },
 
             indexes: IndexMap::new(),
+            validator: None,
+            defaults: None,
+            meta_version: 0,
+            auto_increment_id: false,
+            capped: None,
         }
     }
 
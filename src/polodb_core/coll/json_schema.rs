@@ -0,0 +1,238 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The subset of `$jsonSchema` shared by the collection validators and the queries:
+//! - `bsonType`: the BSON type of the value, by alias, or an array of aliases.
+//! - `required`: the fields the document must contain.
+//! - `properties`: the schemas of the fields of the document. Missing fields are not checked.
+//! - `minimum`/`maximum`: the inclusive bounds of a number. Other values are not checked.
+//! - `title`/`description`: annotations, ignored.
+
+use std::cmp::Ordering;
+use bson::{Bson, Document};
+use crate::{Error, Result};
+use crate::coll::validator::type_matches;
+
+/// Check that `schema` is well-formed, before it's used to validate a document.
+pub(crate) fn check_schema(schema: &Document) -> Result<()> {
+    check_schema_at(schema, "")
+}
+
+/// Validate `value` against `schema`, the error tells the first constraint not satisfied.
+pub(crate) fn validate(schema: &Document, value: &Bson) -> Result<()> {
+    match find_violation(schema, value, "")? {
+        Some(reason) => Err(Error::ValidationError(format!("$jsonSchema: {}", reason))),
+        None => Ok(()),
+    }
+}
+
+/// Whether `value` satisfies `schema`, used by the `$jsonSchema` query operator.
+pub(crate) fn matches(schema: &Document, value: &Bson) -> Result<bool> {
+    Ok(find_violation(schema, value, "")?.is_none())
+}
+
+fn check_schema_at(schema: &Document, path: &str) -> Result<()> {
+    for (keyword, arg) in schema {
+        match keyword.as_str() {
+            "bsonType" => {
+                type_matches(&Bson::Null, arg)?;
+            }
+            "required" => {
+                required_fields(path, arg)?;
+            }
+            "properties" => {
+                let properties = expect_document(path, keyword, arg)?;
+                for (field, sub_schema) in properties {
+                    let sub_path = join_path(path, field);
+                    let sub_schema = expect_document(&sub_path, "schema", sub_schema)?;
+                    check_schema_at(sub_schema, &sub_path)?;
+                }
+            }
+            "minimum" | "maximum" => {
+                expect_number(path, keyword, arg)?;
+            }
+            "title" | "description" => (),
+            _ => {
+                return Err(Error::ValidationError(format!(
+                    "$jsonSchema keyword '{}' is not supported{}", keyword, at_path(path),
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the reason why `value` doesn't satisfy `schema`, or `None` if it does.
+/// An error is returned only when the schema is malformed.
+fn find_violation(schema: &Document, value: &Bson, path: &str) -> Result<Option<String>> {
+    for (keyword, arg) in schema {
+        match keyword.as_str() {
+            "bsonType" => {
+                if !type_matches(value, arg)? {
+                    return Ok(Some(format!("expected type: {}, actual: {:?}{}", arg, value.element_type(), at_path(path))));
+                }
+            }
+            "required" => {
+                let fields = required_fields(path, arg)?;
+                if let Bson::Document(doc) = value {
+                    for field in fields {
+                        if !doc.contains_key(field) {
+                            return Ok(Some(format!("field '{}' is required", join_path(path, field))));
+                        }
+                    }
+                }
+            }
+            "properties" => {
+                let properties = expect_document(path, keyword, arg)?;
+                if let Bson::Document(doc) = value {
+                    for (field, sub_schema) in properties {
+                        let sub_value = match doc.get(field) {
+                            Some(sub_value) => sub_value,
+                            None => continue,
+                        };
+                        let sub_path = join_path(path, field);
+                        let sub_schema = expect_document(&sub_path, "schema", sub_schema)?;
+                        if let Some(reason) = find_violation(sub_schema, sub_value, &sub_path)? {
+                            return Ok(Some(reason));
+                        }
+                    }
+                }
+            }
+            "minimum" => {
+                let bound = expect_number(path, keyword, arg)?;
+                if is_number(value) && crate::utils::bson::value_cmp(value, bound)? == Ordering::Less {
+                    return Ok(Some(format!("{} is less than the minimum {}{}", value, bound, at_path(path))));
+                }
+            }
+            "maximum" => {
+                let bound = expect_number(path, keyword, arg)?;
+                if is_number(value) && crate::utils::bson::value_cmp(value, bound)? == Ordering::Greater {
+                    return Ok(Some(format!("{} is greater than the maximum {}{}", value, bound, at_path(path))));
+                }
+            }
+            "title" | "description" => (),
+            _ => {
+                return Err(Error::ValidationError(format!(
+                    "$jsonSchema keyword '{}' is not supported{}", keyword, at_path(path),
+                )));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn required_fields<'a>(path: &str, arg: &'a Bson) -> Result<Vec<&'a str>> {
+    let invalid = || Error::ValidationError(format!(
+        "$jsonSchema keyword 'required' must be an array of strings{}", at_path(path),
+    ));
+    let arr = match arg {
+        Bson::Array(arr) => arr,
+        _ => return Err(invalid()),
+    };
+    arr.iter()
+        .map(|item| item.as_str().ok_or_else(invalid))
+        .collect()
+}
+
+fn expect_document<'a>(path: &str, keyword: &str, arg: &'a Bson) -> Result<&'a Document> {
+    match arg {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(Error::ValidationError(format!(
+            "$jsonSchema keyword '{}' must be a document{}", keyword, at_path(path),
+        ))),
+    }
+}
+
+fn expect_number<'a>(path: &str, keyword: &str, arg: &'a Bson) -> Result<&'a Bson> {
+    if is_number(arg) {
+        Ok(arg)
+    } else {
+        Err(Error::ValidationError(format!(
+            "$jsonSchema keyword '{}' must be a number{}", keyword, at_path(path),
+        )))
+    }
+}
+
+fn is_number(value: &Bson) -> bool {
+    matches!(value, Bson::Double(_) | Bson::Int32(_) | Bson::Int64(_) | Bson::Decimal128(_))
+}
+
+fn join_path(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+fn at_path(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" at '{}'", path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Bson};
+    use crate::coll::json_schema::{check_schema, matches, validate};
+
+    #[test]
+    fn test_json_schema() {
+        let schema = doc! {
+            "bsonType": "object",
+            "required": ["name", "address"],
+            "properties": {
+                "name": { "bsonType": "string" },
+                "age": { "bsonType": ["int", "long"], "minimum": 0, "maximum": 150 },
+                "address": {
+                    "bsonType": "object",
+                    "required": ["city"],
+                    "properties": {
+                        "city": { "bsonType": "string", "description": "the city name" },
+                    },
+                },
+            },
+        };
+        check_schema(&schema).unwrap();
+
+        let valid = doc! { "name": "Vincent", "age": 30, "address": { "city": "Shanghai" } };
+        validate(&schema, &Bson::Document(valid)).unwrap();
+
+        let invalid = vec![
+            doc! { "name": "Vincent" },
+            doc! { "name": "Vincent", "address": {} },
+            doc! { "name": "Vincent", "address": { "city": 1 } },
+            doc! { "name": "Vincent", "age": -1, "address": { "city": "Shanghai" } },
+            doc! { "name": "Vincent", "age": 151, "address": { "city": "Shanghai" } },
+            doc! { "name": "Vincent", "age": 30.5, "address": { "city": "Shanghai" } },
+        ];
+        for doc in invalid {
+            assert!(!matches(&schema, &Bson::Document(doc.clone())).unwrap(), "{}", doc);
+            assert!(validate(&schema, &Bson::Document(doc)).is_err());
+        }
+
+        let err = validate(&schema, &Bson::Document(doc! { "name": "Vincent", "address": {} })).unwrap_err();
+        assert!(err.to_string().contains("address.city"), "{}", err);
+    }
+
+    #[test]
+    fn test_check_schema() {
+        assert!(check_schema(&doc! { "bsonType": "str" }).is_err());
+        assert!(check_schema(&doc! { "required": "name" }).is_err());
+        assert!(check_schema(&doc! { "properties": { "age": { "minimum": "0" } } }).is_err());
+        assert!(check_schema(&doc! { "pattern": "^a" }).is_err());
+    }
+}
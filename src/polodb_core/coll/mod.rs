@@ -15,6 +15,10 @@
 mod collection;
 pub(crate) mod collection_info;
 mod txn_collection;
+mod snapshot_collection;
+pub(crate) mod validator;
+pub(crate) mod json_schema;
 
 pub use collection::{Collection, CollectionT};
 pub use txn_collection::TransactionalCollection;
+pub use snapshot_collection::SnapshotCollection;
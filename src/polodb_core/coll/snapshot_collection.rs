@@ -0,0 +1,76 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Weak;
+use bson::Document;
+use serde::de::DeserializeOwned;
+use crate::db::db_inner::DatabaseInner;
+use crate::{Error, Result};
+use crate::action::{Aggregate, Find};
+use crate::transaction::TransactionInner;
+
+/// A read-only collection bound to a [`Snapshot`](crate::Snapshot).
+pub struct SnapshotCollection<T> {
+    db: Weak<DatabaseInner>,
+    name: String,
+    txn: TransactionInner,
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T> SnapshotCollection<T> {
+
+    pub(crate) fn new(db: Weak<DatabaseInner>, name: &str, txn: TransactionInner) -> SnapshotCollection<T> {
+        SnapshotCollection {
+            db,
+            name: name.into(),
+            txn,
+            _phantom: std::default::Default::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the size of all data in the collection at the time of the snapshot.
+    pub fn count_documents(&self) -> Result<u64> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.count_documents(&self.name, &self.txn)
+    }
+
+    pub fn find(&self, filter: Document) -> Find<'_, '_, T>
+    where T: DeserializeOwned + Send + Sync {
+        Find::new(self.db.clone(), &self.name, Some(&self.txn), filter)
+    }
+
+    pub fn find_one(&self, filter: Document) -> Result<Option<T>>
+    where T: DeserializeOwned + Send + Sync {
+        let mut cursor = self.find(filter).run()?;
+        let test = cursor.advance()?;
+        if !test {
+            return Ok(None);
+        }
+        Ok(Some(cursor.deserialize_current()?))
+    }
+
+    pub fn aggregate(&self, pipeline: impl IntoIterator<Item = Document>) -> Aggregate<'_, '_> {
+        Aggregate::new(
+            self.db.clone(),
+            &self.name,
+            pipeline.into_iter().collect(),
+            Some(&self.txn),
+        )
+    }
+
+}
@@ -13,16 +13,18 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::sync::Weak;
-use bson::Document;
+use bson::{Bson, Document};
 use serde::Serialize;
 use crate::db::db_inner::DatabaseInner;
-use crate::options::UpdateOptions;
+use crate::options::{DeleteOptions, InsertManyOptions, UpdateOptions};
 use serde::de::DeserializeOwned;
-use crate::{CollectionT, Error, IndexModel, Result};
+use crate::{CollectionT, Error, IndexModel, RawScan, Result};
 use crate::action::{Aggregate, Find};
-use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use crate::results::{CollectionStats, DeleteResult, InferredSchema, InsertManyResult, InsertOneResult, UpdateResult};
 use crate::transaction::TransactionInner;
+use super::collection::{deserialize_documents, deserialize_optional_documents, first_field_value};
 
 pub struct TransactionalCollection<T> {
     db: Weak<DatabaseInner>,
@@ -55,6 +57,21 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         db.count_documents(&self.name, &self.txn)
     }
 
+    fn stats(&self) -> Result<CollectionStats> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.collection_stats(&self.name, &self.txn)
+    }
+
+    fn index_stats(&self) -> Result<HashMap<String, u64>> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.index_stats(&self.name, &self.txn)
+    }
+
+    fn infer_schema(&self, sample_size: u64) -> Result<InferredSchema> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.infer_schema(&self.name, sample_size, &self.txn)
+    }
+
     fn update_one(&self, query: Document, update: Document) -> crate::Result<UpdateResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
         let result = db.update_one(
@@ -103,6 +120,20 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         Ok(result)
     }
 
+    fn update_one_dry_run(&self, query: Document, update: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let docs = db.dry_run_update(&self.name, query, update, false, &self.txn)?;
+        deserialize_documents(docs)
+    }
+
+    fn update_many_dry_run(&self, query: Document, update: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let docs = db.dry_run_update(&self.name, query, update, true, &self.txn)?;
+        deserialize_documents(docs)
+    }
+
     fn delete_one(&self, query: Document) -> crate::Result<DeleteResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
         let result = db.delete_one(&self.name, query, &self.txn)?;
@@ -110,11 +141,34 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
     }
 
     fn delete_many(&self, query: Document) -> crate::Result<DeleteResult> {
+        self.delete_many_with_options(query, DeleteOptions::default())
+    }
+
+    fn delete_many_with_options(&self, query: Document, options: DeleteOptions) -> Result<DeleteResult> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
-        let result = db.delete_many(&self.name, query, &self.txn)?;
+        let result = db.delete_many(&self.name, query, options, &self.txn)?;
         Ok(result)
     }
 
+    fn delete_by_ids(&self, ids: &[Bson]) -> Result<DeleteResult> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.delete_by_ids(&self.name, ids, &self.txn)
+    }
+
+    fn delete_one_dry_run(&self, query: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let docs = db.dry_run_delete(&self.name, query, false, &self.txn)?;
+        deserialize_documents(docs)
+    }
+
+    fn delete_many_dry_run(&self, query: Document) -> Result<Vec<T>>
+    where T: DeserializeOwned {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let docs = db.dry_run_delete(&self.name, query, true, &self.txn)?;
+        deserialize_documents(docs)
+    }
+
     fn create_index(&self, index: IndexModel) -> crate::Result<()> {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
         db.create_index(&self.name, index, &self.txn)?;
@@ -144,6 +198,17 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         Ok(result)
     }
 
+    fn insert_one_and_fetch(&self, doc: impl Borrow<T>) -> Result<T>
+    where T: Serialize + DeserializeOwned {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let stored = db.insert_one_and_fetch(
+            &self.name,
+            bson::to_document(doc.borrow())?,
+            &self.txn,
+        )?;
+        Ok(bson::from_document(stored)?)
+    }
+
     fn insert_many(&self, docs: impl IntoIterator<Item = impl Borrow<T>>) -> crate::Result<InsertManyResult>
     where T: Serialize {
         let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
@@ -151,6 +216,13 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         Ok(result)
     }
 
+    fn insert_many_with_options(&self, docs: impl IntoIterator<Item = impl Borrow<T>>, options: InsertManyOptions) -> crate::Result<InsertManyResult>
+    where T: Serialize {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let result = db.insert_many_with_options(&self.name, docs, &options, &self.txn)?;
+        Ok(result)
+    }
+
     fn find(&self, filter: Document) -> Find<'_, '_, T>
     where T: DeserializeOwned + Send + Sync {
         Find::new(self.db.clone(), &self.name, Some(&self.txn), filter)
@@ -166,6 +238,23 @@ impl<T> CollectionT<T> for TransactionalCollection<T> {
         Ok(Some(cursor.deserialize_current()?))
     }
 
+    fn get_field(&self, filter: Document, path: &str) -> Result<Option<Bson>>
+    where T: DeserializeOwned + Send + Sync {
+        first_field_value(self.find(filter), path)
+    }
+
+    fn find_by_ids(&self, ids: &[Bson]) -> Result<Vec<Option<T>>>
+    where T: DeserializeOwned {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let docs = db.find_by_ids(&self.name, ids, &self.txn)?;
+        deserialize_optional_documents(docs)
+    }
+
+    fn scan_raw(&self) -> Result<RawScan> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        db.scan_raw(&self.name, self.txn.clone())
+    }
+
     fn aggregate(&self, pipeline: impl IntoIterator<Item = Document>) -> Aggregate<'_, '_> {
         Aggregate::new(
             self.db.clone(),
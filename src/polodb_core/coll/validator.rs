@@ -0,0 +1,225 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::{Bson, Document};
+use bson::spec::ElementType;
+use crate::{Error, Result};
+use crate::utils::bson::try_get_document_value;
+use crate::coll::json_schema;
+
+const JSON_SCHEMA: &str = "$jsonSchema";
+
+/// Check that `validator` is well-formed, so that the errors are reported
+/// when the collection is created instead of the first write.
+pub(crate) fn check_validator(validator: &Document) -> Result<()> {
+    for (path, constraints) in validator {
+        let constraints = get_constraints(path, constraints)?;
+        if path == JSON_SCHEMA {
+            json_schema::check_schema(constraints)?;
+            continue;
+        }
+        for (op, expected) in constraints {
+            match op.as_str() {
+                "$exists" => {
+                    expect_bool(path, expected)?;
+                }
+                "$type" => {
+                    type_matches(&Bson::Null, expected)?;
+                }
+                _ => return Err(unknown_operator(path, op)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate `doc` against the `validator` of a collection.
+///
+/// The validator maps field paths to the constraints of the fields:
+/// - `$exists`: whether the field must be present or absent.
+/// - `$type`: the BSON type of the field, by alias or number,
+///   or an array of them. Missing fields are not checked.
+///
+/// The `$jsonSchema` key validates the whole document against a schema instead,
+/// see [`json_schema`](crate::coll::json_schema).
+pub(crate) fn validate_document(validator: &Document, doc: &Document) -> Result<()> {
+    for (path, constraints) in validator {
+        let constraints = get_constraints(path, constraints)?;
+        if path == JSON_SCHEMA {
+            json_schema::validate(constraints, &Bson::Document(doc.clone()))?;
+            continue;
+        }
+        let value = try_get_document_value(doc, path);
+
+        for (op, expected) in constraints {
+            match op.as_str() {
+                "$exists" => {
+                    let expected = expect_bool(path, expected)?;
+                    if value.is_some() != expected {
+                        let reason = if expected { "is required" } else { "is not allowed" };
+                        return Err(Error::ValidationError(format!("field '{}' {}", path, reason)));
+                    }
+                }
+                "$type" => {
+                    if let Some(value) = &value {
+                        if !type_matches(value, expected)? {
+                            return Err(Error::ValidationError(format!(
+                                "field '{}' expected type: {}, actual: {:?}",
+                                path, expected, value.element_type(),
+                            )));
+                        }
+                    }
+                }
+                _ => return Err(unknown_operator(path, op)),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_constraints<'a>(path: &str, constraints: &'a Bson) -> Result<&'a Document> {
+    match constraints {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(Error::ValidationError(format!("constraints of field '{}' must be a document", path))),
+    }
+}
+
+fn expect_bool(path: &str, value: &Bson) -> Result<bool> {
+    match value {
+        Bson::Boolean(b) => Ok(*b),
+        Bson::Int32(i) => Ok(*i != 0),
+        Bson::Int64(i) => Ok(*i != 0),
+        _ => Err(Error::ValidationError(format!("$exists of field '{}' must be a boolean", path))),
+    }
+}
+
+fn unknown_operator(path: &str, op: &str) -> Error {
+    Error::ValidationError(format!("unknown operator '{}' for field '{}'", op, path))
+}
+
+fn type_code_of_alias(alias: &str) -> Option<i32> {
+    let code = match alias {
+        "double" => 1,
+        "string" => 2,
+        "object" => 3,
+        "array" => 4,
+        "binData" => 5,
+        "undefined" => 6,
+        "objectId" => 7,
+        "bool" => 8,
+        "date" => 9,
+        "null" => 10,
+        "regex" => 11,
+        "javascript" => 13,
+        "int" => 16,
+        "timestamp" => 17,
+        "long" => 18,
+        "decimal" => 19,
+        "minKey" => -1,
+        "maxKey" => 127,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// The alias of the type of the value, as accepted by `$type`.
+pub(crate) fn type_alias_of_value(value: &Bson) -> &'static str {
+    match value {
+        Bson::Double(_) => "double",
+        Bson::String(_) | Bson::Symbol(_) => "string",
+        Bson::Document(_) => "object",
+        Bson::Array(_) => "array",
+        Bson::Binary(_) => "binData",
+        Bson::Undefined => "undefined",
+        Bson::ObjectId(_) => "objectId",
+        Bson::Boolean(_) => "bool",
+        Bson::DateTime(_) => "date",
+        Bson::Null => "null",
+        Bson::RegularExpression(_) => "regex",
+        Bson::JavaScriptCode(_) | Bson::JavaScriptCodeWithScope(_) => "javascript",
+        Bson::Int32(_) => "int",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::Int64(_) => "long",
+        Bson::Decimal128(_) => "decimal",
+        Bson::MinKey => "minKey",
+        Bson::MaxKey => "maxKey",
+        Bson::DbPointer(_) => "dbPointer",
+    }
+}
+
+fn type_code_of_value(value: &Bson) -> i32 {
+    match value.element_type() {
+        ElementType::MinKey => -1,
+        ty => ty as u8 as i32,
+    }
+}
+
+pub(crate) fn type_matches(value: &Bson, expected: &Bson) -> Result<bool> {
+    let code = match expected {
+        Bson::String(alias) if alias == "number" => {
+            return Ok(matches!(value, Bson::Double(_) | Bson::Int32(_) | Bson::Int64(_) | Bson::Decimal128(_)));
+        }
+        Bson::String(alias) => type_code_of_alias(alias)
+            .ok_or_else(|| Error::ValidationError(format!("unknown type alias '{}'", alias)))?,
+        Bson::Int32(code) => *code,
+        Bson::Int64(code) => *code as i32,
+        Bson::Array(arr) => {
+            let mut matched = false;
+            for item in arr {
+                matched |= type_matches(value, item)?;
+            }
+            return Ok(matched);
+        }
+        _ => return Err(Error::ValidationError(format!("invalid type: {}", expected))),
+    };
+    Ok(type_code_of_value(value) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use crate::coll::validator::{check_validator, validate_document};
+
+    #[test]
+    fn test_validate_document() {
+        let validator = doc! {
+            "name": {
+                "$exists": true,
+                "$type": "string",
+            },
+            "age": {
+                "$type": ["int", "long"],
+            },
+            "info.email": {
+                "$type": "string",
+            },
+        };
+        check_validator(&validator).unwrap();
+
+        validate_document(&validator, &doc! { "name": "Vincent", "age": 10 }).unwrap();
+        validate_document(&validator, &doc! { "name": "Vincent", "info": { "email": "a@b.c" } }).unwrap();
+        assert!(validate_document(&validator, &doc! { "age": 10 }).is_err());
+        assert!(validate_document(&validator, &doc! { "name": 1 }).is_err());
+        assert!(validate_document(&validator, &doc! { "name": "Vincent", "age": "10" }).is_err());
+        assert!(validate_document(&validator, &doc! { "name": "Vincent", "info": { "email": 1 } }).is_err());
+    }
+
+    #[test]
+    fn test_check_validator() {
+        assert!(check_validator(&doc! { "name": { "$type": "str" } }).is_err());
+        assert!(check_validator(&doc! { "name": { "$gt": 1 } }).is_err());
+        assert!(check_validator(&doc! { "name": "string" }).is_err());
+        assert!(check_validator(&doc! { "$jsonSchema": { "bsonType": "str" } }).is_err());
+    }
+}
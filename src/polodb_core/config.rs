@@ -80,6 +80,291 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn get_max_materialize_count(&self) -> u64 {
+        self.inner.max_materialize_count
+    }
+
+    /// Set the maximum count of documents returned by iterating the cursor of
+    /// [`Find::run`](crate::action::Find::run) without an explicit limit.
+    /// `0` means unlimited.
+    pub fn set_max_materialize_count(&mut self, v: u64) -> &mut Self {
+        self.inner.max_materialize_count = v;
+        self
+    }
+
+    pub fn get_busy_retry_count(&self) -> u32 {
+        self.inner.busy_retry_count
+    }
+
+    /// Set how many times an auto-committed operation is retried
+    /// when it fails with [`Error::Busy`](crate::Error::Busy).
+    /// `0` disables retrying.
+    pub fn set_busy_retry_count(&mut self, v: u32) -> &mut Self {
+        self.inner.busy_retry_count = v;
+        self
+    }
+
+    pub fn get_busy_retry_backoff_ms(&self) -> u64 {
+        self.inner.busy_retry_backoff_ms
+    }
+
+    /// Set the delay before the first retry. The delay is doubled on
+    /// every following retry, up to 500 milliseconds.
+    pub fn set_busy_retry_backoff_ms(&mut self, v: u64) -> &mut Self {
+        self.inner.busy_retry_backoff_ms = v;
+        self
+    }
+
+    pub fn get_cursor_batch_size(&self) -> u64 {
+        self.inner.cursor_batch_size
+    }
+
+    /// Set how many documents a cursor fetches from the query at once.
+    ///
+    /// The default `1` runs the query on demand, one document per
+    /// [`ClientCursor::advance`](crate::ClientCursor::advance). A larger batch reads ahead:
+    /// the documents of a batch are read when the batch is fetched, so the writes made
+    /// in the meantime are not seen by the documents already fetched.
+    pub fn set_cursor_batch_size(&mut self, v: u64) -> &mut Self {
+        self.inner.cursor_batch_size = v;
+        self
+    }
+
+    pub fn get_max_query_depth(&self) -> u32 {
+        self.inner.max_query_depth
+    }
+
+    /// Set the maximum nesting depth of `$and`/`$or` in a query.
+    /// A deeper query is rejected with [`Error::InvalidField`](crate::Error::InvalidField).
+    pub fn set_max_query_depth(&mut self, v: u32) -> &mut Self {
+        self.inner.max_query_depth = v;
+        self
+    }
+
+    pub fn get_max_document_depth(&self) -> u32 {
+        self.inner.max_document_depth
+    }
+
+    /// Set the maximum nesting depth of the embedded documents and arrays in the stored
+    /// documents, the top-level document being the first level. A deeper document is
+    /// rejected on insert and update with [`Error::ValidationError`](crate::Error::ValidationError).
+    /// `0` for no limit.
+    pub fn set_max_document_depth(&mut self, v: u32) -> &mut Self {
+        self.inner.max_document_depth = v;
+        self
+    }
+
+    pub fn get_sync_on_drop(&self) -> bool {
+        self.inner.sync_on_drop
+    }
+
+    /// Set whether the log is synced to the disk with fsync when the database is dropped.
+    /// The written data is always flushed to the files on drop.
+    pub fn set_sync_on_drop(&mut self, v: bool) -> &mut Self {
+        self.inner.sync_on_drop = v;
+        self
+    }
+
+    pub fn get_sync_policy(&self) -> SyncPolicy {
+        self.inner.sync_policy
+    }
+
+    /// Set when the log of the committed transactions is synced to the disk with fsync.
+    /// Syncing less often gives a higher write throughput,
+    /// the transactions committed after the last sync may be lost on a crash.
+    pub fn set_sync_policy(&mut self, v: SyncPolicy) -> &mut Self {
+        self.inner.sync_policy = v;
+        self
+    }
+
+    pub fn get_query_cache_size(&self) -> u64 {
+        self.inner.query_cache_size
+    }
+
+    /// Set how many compiled queries are kept for reuse. `0` disables the cache.
+    pub fn set_query_cache_size(&mut self, v: u64) -> &mut Self {
+        self.inner.query_cache_size = v;
+        self
+    }
+
+    pub fn get_isolation_level(&self) -> IsolationLevel {
+        self.inner.isolation_level
+    }
+
+    /// Set the isolation of the transactions started by the database,
+    /// including the ones started automatically for every operation.
+    pub fn set_isolation_level(&mut self, v: IsolationLevel) -> &mut Self {
+        self.inner.isolation_level = v;
+        self
+    }
+
+    pub fn get_oplog_enabled(&self) -> bool {
+        self.inner.oplog_enabled
+    }
+
+    /// Set whether every write is recorded in the operation log,
+    /// which can be read with [`Database::read_oplog`](crate::Database::read_oplog).
+    /// The entries are written in the transaction of the write. The `ts` of an entry
+    /// stays locked until the transaction ends, so the concurrent writing transactions
+    /// wait for each other and their entries are committed in the order of `ts`.
+    pub fn set_oplog_enabled(&mut self, v: bool) -> &mut Self {
+        self.inner.oplog_enabled = v;
+        self
+    }
+
+    pub fn get_oplog_max_entries(&self) -> u64 {
+        self.inner.oplog_max_entries
+    }
+
+    /// Set how many of the latest entries the operation log keeps,
+    /// the older ones are removed. `0` keeps all the entries.
+    pub fn set_oplog_max_entries(&mut self, v: u64) -> &mut Self {
+        self.inner.oplog_max_entries = v;
+        self
+    }
+
+    pub fn get_auto_create_collections(&self) -> bool {
+        self.inner.auto_create_collections
+    }
+
+    /// Set whether writing to a missing collection creates it. When it's off,
+    /// the collections must be created with [`Database::create_collection`](crate::Database::create_collection)
+    /// and the reads and the writes of a missing one return [`Error::CollectionNotFound`](crate::Error::CollectionNotFound),
+    /// so a misspelled name is not silently turned into a new collection.
+    pub fn set_auto_create_collections(&mut self, v: bool) -> &mut Self {
+        self.inner.auto_create_collections = v;
+        self
+    }
+
+    pub fn get_slow_query_log_enabled(&self) -> bool {
+        self.inner.slow_query_log_enabled
+    }
+
+    /// Set whether the finds, updates and deletes running at least
+    /// [`Config::slow_query_ms`] are kept in memory, they can be read
+    /// with [`Database::slow_queries`](crate::Database::slow_queries).
+    pub fn set_slow_query_log_enabled(&mut self, v: bool) -> &mut Self {
+        self.inner.slow_query_log_enabled = v;
+        self
+    }
+
+    pub fn get_slow_query_ms(&self) -> u64 {
+        self.inner.slow_query_ms
+    }
+
+    /// Set how many milliseconds a query runs before it's logged as slow.
+    /// The time of a find is counted until its cursor is dropped.
+    pub fn set_slow_query_ms(&mut self, v: u64) -> &mut Self {
+        self.inner.slow_query_ms = v;
+        self
+    }
+
+    pub fn get_sort_spill_threshold(&self) -> u64 {
+        self.inner.sort_spill_threshold
+    }
+
+    /// Set how many documents a `$sort` stage holds in memory before writing them
+    /// to a temporary file, when the aggregation allows using the disk with
+    /// [`Aggregate::allow_disk_use`](crate::action::Aggregate::allow_disk_use).
+    pub fn set_sort_spill_threshold(&mut self, v: u64) -> &mut Self {
+        self.inner.sort_spill_threshold = v;
+        self
+    }
+
+    pub fn get_group_spill_threshold(&self) -> u64 {
+        self.inner.group_spill_threshold
+    }
+
+    /// Set how many groups a `$group` stage holds in memory, the documents of the
+    /// other groups are written to temporary files, when the aggregation allows using
+    /// the disk with [`Aggregate::allow_disk_use`](crate::action::Aggregate::allow_disk_use).
+    pub fn set_group_spill_threshold(&mut self, v: u64) -> &mut Self {
+        self.inner.group_spill_threshold = v;
+        self
+    }
+
+    pub fn get_max_index_key_length(&self) -> u64 {
+        self.inner.max_index_key_length
+    }
+
+    /// Set the maximum length in bytes of the strings and binaries stored in the indexes.
+    /// A longer value is rejected with [`Error::DataSizeTooLarge`](crate::Error::DataSizeTooLarge),
+    /// unless [`ConfigBuilder::set_truncate_index_keys`] is set.
+    /// `0` means no limit, which is the default.
+    pub fn set_max_index_key_length(&mut self, v: u64) -> &mut Self {
+        self.inner.max_index_key_length = v;
+        self
+    }
+
+    pub fn get_truncate_index_keys(&self) -> bool {
+        self.inner.truncate_index_keys
+    }
+
+    /// Set whether the values longer than the maximum index key length are truncated
+    /// in the indexes instead of rejected. The queries still return the exact matches.
+    /// A unique index rejects the values sharing the truncated prefix as duplicates.
+    /// The length must not be changed afterwards, or the truncated keys can't be found.
+    pub fn set_truncate_index_keys(&mut self, v: bool) -> &mut Self {
+        self.inner.truncate_index_keys = v;
+        self
+    }
+
+    pub fn get_object_id_process_id(&self) -> Option<[u8; 5]> {
+        self.inner.object_id_process_id
+    }
+
+    /// Set the 5-byte process id in the ObjectIds generated for the inserted documents,
+    /// instead of the random one picked when the database is opened.
+    /// Give a distinct id to every process writing to a file that is merged with the others later.
+    pub fn set_object_id_process_id(&mut self, v: [u8; 5]) -> &mut Self {
+        self.inner.object_id_process_id = Some(v);
+        self
+    }
+
+    pub fn get_coerce_object_id_strings(&self) -> bool {
+        self.inner.coerce_object_id_strings
+    }
+
+    /// Set whether a 24-char hex string compared with `_id` in a filter matches the ObjectId
+    /// with the same hex form. Off by default, so a string only matches the same string,
+    /// which is the only way to find the documents whose `_id` is such a string.
+    pub fn set_coerce_object_id_strings(&mut self, v: bool) -> &mut Self {
+        self.inner.coerce_object_id_strings = v;
+        self
+    }
+
+    pub fn get_write_buffer_size(&self) -> u64 {
+        self.inner.write_buffer_size
+    }
+
+    /// Set the number of documents [`Collection::insert_one`](crate::Collection) buffers
+    /// in memory before writing them to the storage in one transaction. `0` disables the buffer.
+    ///
+    /// The buffer is flushed before the other operations of a collection, when a transaction
+    /// or a snapshot is started, and by [`Database::flush`](crate::Database::flush), so the reads
+    /// see the buffered documents. The `_id`s are assigned when the documents are buffered.
+    /// An insert error, e.g. a duplicate key, is returned by the insert writing the buffer
+    /// if it's the error of its own document, the ones of the other documents are returned
+    /// by the next [`Collection::flush`](crate::Collection::flush) or `Database::flush`,
+    /// the other documents are still written. The documents not flushed are lost on a crash.
+    pub fn set_write_buffer_size(&mut self, v: u64) -> &mut Self {
+        self.inner.write_buffer_size = v;
+        self
+    }
+
+    pub fn get_random_seed(&self) -> Option<u64> {
+        self.inner.random_seed
+    }
+
+    /// Seed the random behaviors of the database, so they are reproduced by every run
+    /// doing the same operations in the same order: the documents picked by `$sample`,
+    /// and the random bytes of the generated ObjectIds. The timestamps of the ObjectIds
+    /// still come from the clock. Meant for tests, don't rely on it for unpredictable values.
+    pub fn set_random_seed(&mut self, v: u64) -> &mut Self {
+        self.inner.random_seed = Some(v);
+        self
+    }
+
     pub fn take(self) -> Config {
         self.inner
     }
@@ -92,15 +377,75 @@ impl Default for ConfigBuilder {
     }
 }
 
+/// What a transaction sees of the writes committed by the others while it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Every read sees the data committed at the moment it runs.
+    ReadCommitted,
+    /// All the reads see the data committed when the transaction started.
+    Snapshot,
+}
+
+/// When the log of the committed transactions is synced to the disk with fsync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// The log is never synced on commit, only when the database is dropped
+    /// with [`ConfigBuilder::set_sync_on_drop`].
+    Never,
+    /// The log is synced on every commit, the default.
+    EveryCommit,
+    /// The log is synced on the commit after `commits` commits since the last sync,
+    /// or at least `interval_ms` milliseconds after the last sync. `0` disables either bound.
+    Deferred {
+        commits:     u64,
+        interval_ms: u64,
+    },
+}
+
 pub struct Config {
     pub init_block_count:  u64,
     pub journal_full_size: u64,
     pub lsm_page_size:     u32,
     pub lsm_block_size:    u32,
     pub sync_log_count:    u64,
+    pub max_materialize_count: u64,
+    pub busy_retry_count:      u32,
+    pub busy_retry_backoff_ms: u64,
+    pub cursor_batch_size:     u64,
+    pub max_query_depth:       u32,
+    pub max_document_depth:    u32,
+    pub sync_on_drop:          bool,
+    pub sync_policy:           SyncPolicy,
+    pub query_cache_size:      u64,
+    pub isolation_level:       IsolationLevel,
+    pub oplog_enabled:         bool,
+    pub oplog_max_entries:     u64,
+    pub slow_query_log_enabled: bool,
+    pub auto_create_collections: bool,
+    pub slow_query_ms:         u64,
+    pub sort_spill_threshold:  u64,
+    pub group_spill_threshold: u64,
+    pub max_index_key_length:  u64,
+    pub truncate_index_keys:   bool,
+    pub object_id_process_id:  Option<[u8; 5]>,
+    pub coerce_object_id_strings: bool,
+    pub write_buffer_size:     u64,
+    pub random_seed:           Option<u64>,
 }
 
 const SYNC_LOG_COUNT: u64 = 1000;
+const MAX_MATERIALIZE_COUNT: u64 = 1_000_000;
+const BUSY_RETRY_COUNT: u32 = 5;
+const BUSY_RETRY_BACKOFF_MS: u64 = 2;
+pub(crate) const MAX_BUSY_RETRY_BACKOFF_MS: u64 = 500;
+const CURSOR_BATCH_SIZE: u64 = 1;
+pub(crate) const MAX_QUERY_DEPTH: u32 = 100;
+const MAX_DOCUMENT_DEPTH: u32 = 100;
+const QUERY_CACHE_SIZE: u64 = 128;
+const OPLOG_MAX_ENTRIES: u64 = 100_000;
+const SLOW_QUERY_MS: u64 = 100;
+const SORT_SPILL_THRESHOLD: u64 = 100_000;
+const GROUP_SPILL_THRESHOLD: u64 = 100_000;
 
 impl Default for Config {
 
@@ -111,6 +456,29 @@ impl Default for Config {
             lsm_page_size: 4096,
             lsm_block_size: 4 * 1024 * 1024,
             sync_log_count: SYNC_LOG_COUNT,
+            max_materialize_count: MAX_MATERIALIZE_COUNT,
+            busy_retry_count: BUSY_RETRY_COUNT,
+            busy_retry_backoff_ms: BUSY_RETRY_BACKOFF_MS,
+            cursor_batch_size: CURSOR_BATCH_SIZE,
+            max_query_depth: MAX_QUERY_DEPTH,
+            max_document_depth: MAX_DOCUMENT_DEPTH,
+            sync_on_drop: true,
+            sync_policy: SyncPolicy::EveryCommit,
+            query_cache_size: QUERY_CACHE_SIZE,
+            isolation_level: IsolationLevel::ReadCommitted,
+            oplog_enabled: false,
+            oplog_max_entries: OPLOG_MAX_ENTRIES,
+            slow_query_log_enabled: false,
+            slow_query_ms: SLOW_QUERY_MS,
+            auto_create_collections: true,
+            sort_spill_threshold: SORT_SPILL_THRESHOLD,
+            group_spill_threshold: GROUP_SPILL_THRESHOLD,
+            max_index_key_length: 0,
+            truncate_index_keys: false,
+            object_id_process_id: None,
+            coerce_object_id_strings: false,
+            write_buffer_size: 0,
+            random_seed: None,
         }
     }
 
@@ -16,9 +16,8 @@ use std::cmp::Ordering;
 use std::sync::Arc;
 use bson::Bson;
 use crate::db::RocksDBIterator;
-use crate::Result;
+use crate::{Error, Result};
 use crate::transaction::TransactionInner;
-use crate::index::make_index_key_with_query_key;
 
 /// Cursor is struct pointing on
 /// a value on the kv engine
@@ -50,8 +49,18 @@ impl Cursor {
         self.kv_cursor.copy_data()
     }
 
+    /// Replace the value of the current key.
+    ///
+    /// The key is locked for the transaction first. When another transaction has
+    /// changed the value after the cursor read it, [`Error::Busy`] is returned
+    /// instead of overwriting the other write, so the update can be retried.
     pub fn update_current(&mut self, txn: &TransactionInner, value: &[u8]) -> Result<bool> {
         if let Some(key) = &self.current_key {
+            let read = self.kv_cursor.copy_data()?;
+            let latest = txn.get_for_update(key.as_ref())?;
+            if latest.as_deref() != Some(read.as_slice()) {
+                return Err(Error::Busy);
+            }
             txn.rocksdb_txn.set(key.as_ref(), value)?;
             return Ok(true);
         }
@@ -69,6 +78,29 @@ impl Cursor {
         Ok(())
     }
 
+    /// Move to the last key with the prefix, to scan backwards with [`Cursor::prev`].
+    pub fn reset_to_last(&mut self) -> Result<()> {
+        match prefix_successor(self.prefix_bytes.as_slice()) {
+            Some(upper_bound) => {
+                self.kv_cursor.seek(upper_bound.as_slice());
+                if self.kv_cursor.valid() {
+                    self.kv_cursor.prev();
+                } else {
+                    self.kv_cursor.seek_to_last();
+                }
+            }
+            None => self.kv_cursor.seek_to_last(),
+        }
+
+        self.current_key = if self.kv_cursor.valid() {
+            Some(self.kv_cursor.copy_key_arc()?)
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+
     pub fn reset_by_pkey(&mut self, pkey: &Bson) -> Result<bool> {
         let mut key_buffer = self.prefix_bytes.clone();
 
@@ -105,18 +137,15 @@ impl Cursor {
         Ok(false)
     }
 
-    pub fn reset_by_index_value(&mut self, index_value: &Bson) -> Result<bool> {
-        let key_buffer = make_index_key_with_query_key(
-            self.prefix_bytes.as_slice(),
-            index_value,
-        )?;
-
-        self.kv_cursor.seek(key_buffer.as_slice());
+    /// Move to the first key starting with `key_prefix`,
+    /// return false if there is no such key.
+    pub fn reset_by_key_prefix(&mut self, key_prefix: &[u8]) -> Result<bool> {
+        self.kv_cursor.seek(key_prefix);
 
         if self.kv_cursor.valid() {
             self.current_key = Some(self.kv_cursor.copy_key_arc()?);
             if let Some(found) = &self.current_key {
-                let starts_with = found.as_ref().starts_with(key_buffer.as_slice());
+                let starts_with = found.as_ref().starts_with(key_prefix);
                 return Ok(starts_with);
             }
         }
@@ -153,4 +182,98 @@ impl Cursor {
         Ok(())
     }
 
+    pub fn prev(&mut self) -> Result<()> {
+        self.kv_cursor.prev();
+        if !self.kv_cursor.valid() {
+            self.current_key = None;
+            return Ok(());
+        }
+        self.current_key = Some(self.kv_cursor.copy_key_arc()?);
+        Ok(())
+    }
+
+}
+
+/// The smallest key greater than all the keys starting with `prefix`,
+/// `None` if there is no such key.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut result = prefix.to_vec();
+    while let Some(last) = result.pop() {
+        if last < u8::MAX {
+            result.push(last + 1);
+            return Some(result);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Bson, Document};
+    use crate::cursor::{prefix_successor, Cursor};
+    use crate::db::db_inner::DatabaseInner;
+    use crate::options::{CreateCollectionOptions, OpenOptions};
+    use crate::Config;
+
+    #[test]
+    fn test_prefix_successor() {
+        assert_eq!(prefix_successor(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_successor(&[1, 0xff, 0xff]), Some(vec![2]));
+        assert_eq!(prefix_successor(&[0xff, 0xff]), None);
+        assert_eq!(prefix_successor(&[]), None);
+    }
+
+    fn collect_ids(cursor: &mut Cursor, reverse: bool) -> Vec<Bson> {
+        let mut result = Vec::new();
+        if reverse {
+            cursor.reset_to_last().unwrap();
+        } else {
+            cursor.reset().unwrap();
+        }
+        while cursor.has_next() {
+            let doc: Document = bson::from_slice(cursor.copy_data().unwrap().as_ref()).unwrap();
+            result.push(doc.get("_id").unwrap().clone());
+            if reverse {
+                cursor.prev().unwrap();
+            } else {
+                cursor.next().unwrap();
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_reverse_scan() {
+        let path = std::env::temp_dir().join("test-cursor-reverse-scan");
+        let _ = std::fs::remove_dir_all(&path);
+        let db = DatabaseInner::open_file(&path, Config::default(), &OpenOptions::default()).unwrap();
+
+        // the neighbouring collections must not leak into the scan
+        db.run_in_auto_transaction(|txn| {
+            for name in ["a", "b", "c"] {
+                for i in 0..100 {
+                    db.insert_one(name, doc! { "_id": format!("{}-{:03}", name, i) }, txn)?;
+                }
+            }
+            db.create_collection_internal("empty", &CreateCollectionOptions::default(), txn)?;
+            Ok(())
+        }).unwrap();
+
+        let txn = db.start_transaction().unwrap();
+        for name in ["a", "b", "c"] {
+            let mut cursor = Cursor::new_with_str_prefix(name, txn.rocksdb_txn.new_iterator()).unwrap();
+            let forward = collect_ids(&mut cursor, false);
+            let mut reverse = collect_ids(&mut cursor, true);
+            assert_eq!(forward.len(), 100);
+            reverse.reverse();
+            assert_eq!(forward, reverse);
+        }
+
+        let mut cursor = Cursor::new_with_str_prefix("empty", txn.rocksdb_txn.new_iterator()).unwrap();
+        assert!(collect_ids(&mut cursor, true).is_empty());
+
+        drop(txn);
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+    }
 }
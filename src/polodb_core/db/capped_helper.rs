@@ -0,0 +1,173 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::{doc, Bson, Document};
+use crate::coll::collection_info::CappedInfo;
+use crate::cursor::Cursor;
+use crate::transaction::TransactionInner;
+use crate::Result;
+
+/// The count and the total size of the documents of each capped collection,
+/// and the sequence number of its next inserted document.
+const CAPPED_STATE_PREFIX: &str = "$CAPPED_STATE";
+/// The `_id` and the size of the inserted documents by their sequence numbers,
+/// the oldest come first.
+const CAPPED_SEQ_PREFIX: &str = "$CAPPED_SEQ";
+/// The sequence numbers of the inserted documents by their `_id`s.
+const CAPPED_ID_PREFIX: &str = "$CAPPED_ID";
+
+#[derive(Default)]
+struct CappedState {
+    next_seq: i64,
+    count: i64,
+    size: i64,
+}
+
+fn collection_key(prefix: &str, col_name: &str) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key([
+        &Bson::String(prefix.to_string()),
+        &Bson::String(col_name.to_string()),
+    ])
+}
+
+fn seq_key(col_name: &str, seq: i64) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key([
+        &Bson::String(CAPPED_SEQ_PREFIX.to_string()),
+        &Bson::String(col_name.to_string()),
+        &Bson::Int64(seq),
+    ])
+}
+
+fn id_key(col_name: &str, pkey: &Bson) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key([
+        &Bson::String(CAPPED_ID_PREFIX.to_string()),
+        &Bson::String(col_name.to_string()),
+        pkey,
+    ])
+}
+
+/// The state is locked until the transaction ends,
+/// so the concurrent inserts are counted one after another.
+fn load_state(txn: &TransactionInner, col_name: &str) -> Result<CappedState> {
+    let key = collection_key(CAPPED_STATE_PREFIX, col_name)?;
+    let state = match txn.get_for_update(key.as_slice())? {
+        Some(buf) => {
+            let doc = bson::from_slice::<Document>(&buf)?;
+            CappedState {
+                next_seq: doc.get_i64("next").unwrap_or(0),
+                count: doc.get_i64("count").unwrap_or(0),
+                size: doc.get_i64("size").unwrap_or(0),
+            }
+        }
+        None => CappedState::default(),
+    };
+    Ok(state)
+}
+
+fn save_state(txn: &TransactionInner, col_name: &str, state: &CappedState) -> Result<()> {
+    let key = collection_key(CAPPED_STATE_PREFIX, col_name)?;
+    let buf = bson::to_vec(&doc! {
+        "next": state.next_seq,
+        "count": state.count,
+        "size": state.size,
+    })?;
+    txn.put(key.as_slice(), &buf)
+}
+
+/// Record an inserted document of the capped collection, return the `_id`s of
+/// the oldest documents to delete to get the collection within its limits.
+///
+/// Only the oldest entries are read, and only when the collection is over the limits.
+/// A document replacing the one with the same `_id` becomes the newest.
+pub(crate) fn append(
+    txn: &TransactionInner,
+    col_name: &str,
+    capped: &CappedInfo,
+    pkey: &Bson,
+    size: u64,
+) -> Result<Vec<Bson>> {
+    remove(txn, col_name, pkey)?;
+
+    let mut state = load_state(txn, col_name)?;
+    let seq = state.next_seq;
+
+    let entry = bson::to_vec(&doc! {
+        "_id": pkey.clone(),
+        "size": size as i64,
+    })?;
+    txn.put(seq_key(col_name, seq)?.as_slice(), &entry)?;
+    txn.put(id_key(col_name, pkey)?.as_slice(), &seq.to_be_bytes())?;
+
+    state.next_seq += 1;
+    state.count += 1;
+    state.size += size as i64;
+    save_state(txn, col_name, &state)?;
+
+    let mut count = state.count as u64;
+    let mut total_size = state.size as u64;
+    let mut evicted = Vec::new();
+    if !capped.is_exceeded(count, total_size) {
+        return Ok(evicted);
+    }
+
+    let prefix = collection_key(CAPPED_SEQ_PREFIX, col_name)?;
+    let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+    cursor.reset()?;
+    // the newest document is kept even if it's over the limits alone
+    while count > 1 && capped.is_exceeded(count, total_size) && cursor.has_next() {
+        let entry = bson::from_slice::<Document>(&cursor.copy_data()?)?;
+        if let Some(id) = entry.get("_id") {
+            evicted.push(id.clone());
+        }
+        count -= 1;
+        total_size = total_size.saturating_sub(entry.get_i64("size").unwrap_or(0) as u64);
+        cursor.next()?;
+    }
+
+    Ok(evicted)
+}
+
+/// Forget a deleted document of the capped collection.
+pub(crate) fn remove(txn: &TransactionInner, col_name: &str, pkey: &Bson) -> Result<()> {
+    let id_key = id_key(col_name, pkey)?;
+    let seq = match txn.get(id_key.as_slice())? {
+        Some(bytes) if bytes.len() == 8 => {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(&bytes);
+            i64::from_be_bytes(buf)
+        }
+        _ => return Ok(()),
+    };
+
+    let seq_key = seq_key(col_name, seq)?;
+    let size = match txn.get(seq_key.as_slice())? {
+        Some(buf) => bson::from_slice::<Document>(&buf)?.get_i64("size").unwrap_or(0),
+        None => 0,
+    };
+    txn.delete(seq_key.as_slice())?;
+    txn.delete(id_key.as_slice())?;
+
+    let mut state = load_state(txn, col_name)?;
+    state.count = (state.count - 1).max(0);
+    state.size = (state.size - size).max(0);
+    save_state(txn, col_name, &state)
+}
+
+/// The prefixes of the keys kept for the capped collection,
+/// moved and deleted with the collection.
+pub(crate) fn key_prefixes(col_name: &str) -> Result<Vec<Vec<u8>>> {
+    [CAPPED_STATE_PREFIX, CAPPED_SEQ_PREFIX, CAPPED_ID_PREFIX].iter()
+        .map(|prefix| collection_key(prefix, col_name))
+        .collect()
+}
@@ -12,11 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::VecDeque;
 use std::fmt;
 use std::marker::PhantomData;
+use std::time::Duration;
 use bson::Bson;
 use serde::de::DeserializeOwned;
-use crate::{Result};
+use crate::{CancellationToken, Error, Result};
+use crate::db::slow_query_log::SlowQueryTimer;
 use crate::vm::{VM, VmState};
 
 /// A `ClientCursor` is used get the result of a query.
@@ -24,36 +27,93 @@ use crate::vm::{VM, VmState};
 ///
 /// Additionally, you can use deserialize_current() method to
 /// deserialize the documents returned by advance()
+///
+/// The documents are fetched from the VM in batches of
+/// [`Config::cursor_batch_size`](crate::Config::cursor_batch_size),
+/// one at a time by default.
 pub struct ClientCursor<T: DeserializeOwned + Send + Sync> {
     vm: VM,
+    batch_size: usize,
+    buffer: VecDeque<Bson>,
+    current: Option<Bson>,
+    // the error is returned after the documents fetched before it
+    pending_error: Option<Error>,
+    // the query is recorded in the slow query log when the cursor is dropped
+    slow_query_timer: Option<SlowQueryTimer>,
+    // the count of documents the iterator may return, 0 means unlimited
+    materialize_limit: u64,
+    materialized_count: u64,
     _phantom: PhantomData<T>,
 }
 
 impl<T: DeserializeOwned + Send + Sync> ClientCursor<T> {
 
-    pub(crate) fn new(vm: VM) -> ClientCursor<T> {
+    pub(crate) fn new(vm: VM, batch_size: usize) -> ClientCursor<T> {
+        let batch_size = batch_size.max(1);
         ClientCursor{
             vm,
+            batch_size,
+            buffer: VecDeque::with_capacity(batch_size),
+            current: None,
+            pending_error: None,
+            slow_query_timer: None,
+            materialize_limit: 0,
+            materialized_count: 0,
             _phantom: Default::default(),
         }
     }
 
-    #[inline]
-    fn has_row(&self) -> bool {
-        self.vm.state == VmState::HasRow
+    pub(crate) fn set_max_time(&mut self, max_time: Duration) {
+        self.vm.set_max_time(max_time);
+    }
+
+    pub(crate) fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.vm.set_cancellation_token(token);
+    }
+
+    pub(crate) fn set_slow_query_timer(&mut self, timer: SlowQueryTimer) {
+        self.slow_query_timer = Some(timer);
+    }
+
+    pub(crate) fn set_materialize_limit(&mut self, limit: u64) {
+        self.materialize_limit = limit;
     }
 
     #[inline]
     pub(crate) fn get(&self) -> &Bson {
-        self.vm.stack_top()
+        self.current.as_ref().expect("the cursor has no current document")
+    }
+
+    fn fetch_batch(&mut self) {
+        self.vm.metrics.add_cursor_fetch_count();
+
+        while self.buffer.len() < self.batch_size && self.vm.state != VmState::Halt {
+            if let Err(err) = self.vm.execute() {
+                self.pending_error = Some(err);
+                return;
+            }
+            if self.vm.state != VmState::HasRow {
+                return;
+            }
+            self.buffer.push_back(self.vm.stack_top().clone());
+            self.vm.metrics.add_returned_count();
+        }
     }
 
     pub fn advance(&mut self) -> Result<bool> {
-        if self.vm.state == VmState::Halt {
-            return Ok(false);
+        if self.buffer.is_empty() && self.pending_error.is_none() && self.vm.state != VmState::Halt {
+            self.fetch_batch();
+        }
+
+        self.current = self.buffer.pop_front();
+        if self.current.is_some() {
+            return Ok(true);
+        }
+
+        match self.pending_error.take() {
+            Some(err) => Err(err),
+            None => Ok(false),
         }
-        self.vm.execute()?;
-        Ok(self.has_row())
     }
 
     pub fn deserialize_current(&self) -> Result<T> {
@@ -71,6 +131,22 @@ impl<T: DeserializeOwned + Send + Sync> fmt::Display for ClientCursor<T> {
 
 }
 
+impl<T: DeserializeOwned + Send + Sync> Drop for ClientCursor<T> {
+
+    fn drop(&mut self) {
+        if let Some(timer) = self.slow_query_timer.take() {
+            timer.finish();
+        }
+    }
+
+}
+
+/// Every document is deserialized on its own, a document which can't be
+/// deserialized into `T` yields an error and the iteration goes on with the next one.
+///
+/// When the cursor has a materialize limit, the iteration ends with
+/// [`Error::MaterializeLimitExceeded`] if there are more documents than the limit.
+/// `advance()` is not limited.
 impl<T> Iterator for ClientCursor<T>
     where
         T: DeserializeOwned + Unpin + Send + Sync,
@@ -78,11 +154,20 @@ impl<T> Iterator for ClientCursor<T>
     type Item = Result<T>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.materialize_limit > 0 && self.materialized_count > self.materialize_limit {
+            return None;
+        }
         let test = self.advance();
         match test {
             Ok(false) => None,
             Ok(true) => {
-                Some(Ok(bson::from_bson(self.get().clone()).unwrap()))
+                if self.materialize_limit > 0 {
+                    self.materialized_count += 1;
+                    if self.materialized_count > self.materialize_limit {
+                        return Some(Err(Error::MaterializeLimitExceeded(self.materialize_limit)));
+                    }
+                }
+                Some(self.deserialize_current())
             }
             Err(err) =>{
                 Some(Err(err))
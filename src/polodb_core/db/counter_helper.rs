@@ -0,0 +1,68 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::Bson;
+use crate::transaction::TransactionInner;
+use crate::Result;
+
+/// The counters are stored under this prefix, one for each collection.
+pub(crate) const COUNTER_PREFIX: &str = "$COUNTER";
+
+fn counter_key(col_name: &str) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key([
+        &Bson::String(COUNTER_PREFIX.to_string()),
+        &Bson::String(col_name.to_string()),
+    ])
+}
+
+/// Increase the counter of the collection and return the new value.
+/// The first value is `1`.
+///
+/// The counter is locked until the transaction ends, so the concurrent
+/// transactions can't take the same value.
+pub(crate) fn next_value(txn: &TransactionInner, col_name: &str) -> Result<i64> {
+    let key = counter_key(col_name)?;
+
+    let current = match txn.get_for_update(key.as_slice())? {
+        Some(bytes) if bytes.len() == 8 => {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(&bytes);
+            i64::from_be_bytes(buf)
+        }
+        _ => 0,
+    };
+
+    let next = current + 1;
+    txn.put(key.as_slice(), &next.to_be_bytes())?;
+
+    Ok(next)
+}
+
+pub(crate) fn delete_counter(txn: &TransactionInner, col_name: &str) -> Result<()> {
+    let key = counter_key(col_name)?;
+    txn.delete(key.as_slice())
+}
+
+/// Move the counter of the collection to its new name.
+pub(crate) fn rename_counter(txn: &TransactionInner, from: &str, to: &str) -> Result<()> {
+    let from_key = counter_key(from)?;
+    let to_key = counter_key(to)?;
+    match txn.get(from_key.as_slice())? {
+        Some(bytes) => {
+            txn.put(to_key.as_slice(), &bytes)?;
+            txn.delete(from_key.as_slice())
+        }
+        None => txn.delete(to_key.as_slice()),
+    }
+}
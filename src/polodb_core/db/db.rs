@@ -13,14 +13,20 @@
 // limitations under the License.
 
 use std::path::Path;
+use bson::Document;
 use serde::Serialize;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 use crate::errors::Error;
-use crate::{Config, Transaction};
+use crate::{Config, Snapshot, Transaction};
 use super::db_inner::DatabaseInner;
+use super::{OplogCursor, SlowQuery};
+#[cfg(feature = "debug")]
+use super::DebugScan;
 use crate::coll::Collection;
-use crate::metrics::Metrics;
+use crate::metrics::{LsmMetrics, Metrics, QueryProfile};
+use crate::options::{CreateCollectionOptions, OpenOptions};
 
 pub(crate) static SHOULD_LOG: AtomicBool = AtomicBool::new(false);
 
@@ -39,6 +45,13 @@ pub(crate) static SHOULD_LOG: AtomicBool = AtomicBool::new(false);
 /// You can use [`Database::create_collection`] to create a data collection.
 /// To obtain an exist collection, use [`Database::collection`],
 ///
+/// # Sharing
+/// A file can only be opened by one `Database` at a time, [`Error::DatabaseOccupied`]
+/// is returned otherwise, even in the same process. To use the database from several
+/// components or threads, clone the `Database`: the clones share the opened database,
+/// which is closed when the last of them is dropped.
+///
+#[derive(Clone)]
 pub struct Database {
     inner: Arc<DatabaseInner>,
 }
@@ -72,7 +85,13 @@ impl Database {
     }
 
     pub fn open_path_with_config<P: AsRef<Path>>(path: P, config: Config) -> Result<Database>  {
-        let inner = DatabaseInner::open_file(path.as_ref(), config)?;
+        Database::open_path_with_options(path, config, OpenOptions::default())
+    }
+
+    /// Open the database with [`OpenOptions`] to control whether the database
+    /// is created, must be newly created, or is read-only.
+    pub fn open_path_with_options<P: AsRef<Path>>(path: P, config: Config, options: OpenOptions) -> Result<Database>  {
+        let inner = DatabaseInner::open_file(path.as_ref(), config, &options)?;
 
         Ok(Database {
             inner: Arc::new(inner),
@@ -84,9 +103,81 @@ impl Database {
         self.inner.metrics()
     }
 
+    /// Run the queries in `f` and return the counters accumulated by them.
+    ///
+    /// The [`Metrics`] count while `f` runs, even if they are not enabled.
+    /// They are shared by the whole database, so the queries run by other threads
+    /// meanwhile are counted too.
+    pub fn profile<F>(&self, f: F) -> Result<QueryProfile>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let metrics = self.metrics();
+        let _scope = metrics.begin_profile();
+        let before = metrics.snapshot();
+        let start = Instant::now();
+
+        f()?;
+
+        let elapsed = start.elapsed();
+        Ok(QueryProfile::between(&before, &metrics.snapshot(), elapsed))
+    }
+
+    /// Return the statistics of the underlying LSM tree,
+    /// or `None` when the storage backend is not an LSM tree.
+    pub fn lsm_metrics(&self) -> Option<LsmMetrics> {
+        self.inner.lsm_metrics()
+    }
+
+    /// Iterate the raw key/value pairs whose keys start with `prefix`,
+    /// to inspect how the documents and the indexes are stored.
+    ///
+    /// The pairs are read from a snapshot taken when the method is called.
+    #[cfg(feature = "debug")]
+    pub fn debug_scan(&self, prefix: &[u8]) -> Result<DebugScan> {
+        let txn = self.inner.start_snapshot_transaction()?;
+        Ok(DebugScan::new(txn, prefix))
+    }
+
+    /// Flush the written data in memory to the disk,
+    /// including the documents in the write buffer.
+    pub fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    /// Flush the pending writes and close the database.
+    ///
+    /// The files are released when this function returns, so the same path
+    /// can be opened again immediately. The collections obtained from this
+    /// database return [`Error::DbIsClosed`] afterwards.
+    ///
+    /// [`Error::Busy`] is returned if another thread is still running an
+    /// operation on the database, or another clone of the `Database` is alive.
+    /// In that case, the database is closed when the operation finishes
+    /// or the last clone is dropped.
+    pub fn close(self) -> Result<()> {
+        self.inner.flush()?;
+        let inner = Arc::try_unwrap(self.inner).map_err(|_| Error::Busy)?;
+        drop(inner);
+        Ok(())
+    }
+
+    /// Rebuild all the indexes of the collection from its documents.
+    ///
+    /// It repairs the indexes which are out of sync with the data,
+    /// e.g. the data imported bypassing the indexes.
+    pub fn rebuild_indexes(&self, col_name: &str) -> Result<()> {
+        self.inner.run_in_auto_transaction(|txn| self.inner.rebuild_indexes(col_name, txn))
+    }
+
     /// Creates a new collection in the database with the given `name`.
     pub fn create_collection(&self, name: &str) -> Result<()> {
-        let _ = self.inner.create_collection(name)?;
+        self.create_collection_with_options(name, CreateCollectionOptions::default())
+    }
+
+    /// Creates a new collection in the database with the given `name` and `options`.
+    pub fn create_collection_with_options(&self, name: &str, options: CreateCollectionOptions) -> Result<()> {
+        let _ = self.inner.create_collection(name, &options)?;
         Ok(())
     }
 
@@ -94,22 +185,111 @@ impl Database {
     /// [error]: ../enum.DbErr.html
     ///
     /// Return an exist collection. If the collection is not exists,
-    /// a new collection will be created.
+    /// a new collection will be created by the first write.
+    ///
+    /// When [`Config::auto_create_collections`](crate::Config::auto_create_collections) is off,
+    /// the reads and the writes of a collection which is not created by [`Database::create_collection`]
+    /// return [`Error::CollectionNotFound`] instead.
     ///
     pub fn collection<T: Serialize>(&self, col_name: &str) -> Collection<T> {
         Collection::new(Arc::downgrade(&self.inner), col_name)
     }
 
     pub fn start_transaction(&self) -> Result<Transaction> {
+        self.inner.flush_write_buffer()?;
         let mut inner = self.inner.start_transaction()?;
         inner.set_auto_commit(false);
         Ok(Transaction::new(Arc::downgrade(&self.inner), inner))
     }
 
+    /// Take a snapshot of the database.
+    ///
+    /// Queries running on the snapshot see the data at the time
+    /// the snapshot is taken, regardless of the writes committed after it.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        self.inner.flush_write_buffer()?;
+        let inner = self.inner.start_snapshot_transaction()?;
+        Ok(Snapshot::new(Arc::downgrade(&self.inner), inner))
+    }
+
+    /// Rename the collection `from` to `to`, keeping its documents, indexes and options.
+    ///
+    /// If a collection named `to` exists, it's dropped when `drop_target` is set,
+    /// otherwise [`Error::CollectionAlreadyExits`] is returned.
+    pub fn rename_collection(&self, from: &str, to: &str, drop_target: bool) -> Result<()> {
+        self.inner.flush_write_buffer()?;
+        self.inner.run_in_auto_transaction(|txn| self.inner.rename_collection(from, to, drop_target, txn))
+    }
+
+    /// Drop all the collections of the database with their documents and indexes,
+    /// and the materialized views, in a transaction.
+    /// The database stays valid and can be written or reopened afterwards.
+    pub fn drop_database(&self) -> Result<()> {
+        self.inner.flush_write_buffer()?;
+        self.inner.run_in_auto_transaction(|txn| self.inner.drop_database(txn))
+    }
+
     /// Gets the names of the collections in the database.
     pub fn list_collection_names(&self) -> Result<Vec<String>> {
         let txn = self.inner.start_transaction()?;
         self.inner.list_collection_names_with_session(&txn)
     }
 
+    /// Define a materialized view named `name`: the results of `pipeline` run on the
+    /// collection `source` are written to the collection `target` by [`Database::refresh_view`].
+    /// A view with the same name is replaced.
+    pub fn create_materialized_view(&self, name: &str, source: &str, pipeline: Vec<Document>, target: &str) -> Result<()> {
+        self.inner.run_in_auto_transaction(|txn| {
+            self.inner.create_materialized_view(name, source, pipeline.clone(), target, txn)
+        })
+    }
+
+    /// Recompute the materialized view, replacing all the documents of its target collection
+    /// with the results of its pipeline. Returns the count of the documents written.
+    ///
+    /// The refresh runs in one transaction, the readers see the old or the new results.
+    pub fn refresh_view(&self, name: &str) -> Result<u64> {
+        self.inner.run_in_auto_transaction(|txn| self.inner.refresh_view(name, txn))
+    }
+
+    /// Remove the definition of the materialized view, the target collection is kept.
+    pub fn drop_materialized_view(&self, name: &str) -> Result<()> {
+        self.inner.run_in_auto_transaction(|txn| self.inner.drop_materialized_view(name, txn))
+    }
+
+    /// Read the entries of the operation log with a `ts` greater than `since_ts`, in order.
+    /// Pass `0` to read all the kept entries.
+    ///
+    /// The log is only written when [`Config::oplog_enabled`](crate::Config::oplog_enabled) is set.
+    /// Every entry has the fields:
+    ///
+    /// - `ts`: the sequence number of the entry
+    /// - `op`: `"i"` for an insert, `"u"` for an update, `"d"` for a delete, `"c"` for a command
+    /// - `ns`: the name of the collection
+    /// - `wall`: the time of the write
+    /// - `o`: the inserted or updated document, the `_id` of the deleted document, or the command
+    /// - `o2`: the `_id` of the updated document
+    /// - `comment`: the `$comment` of the filter of an update or a delete, if it has one
+    pub fn read_oplog(&self, since_ts: i64) -> Result<Vec<Document>> {
+        let txn = self.inner.start_transaction()?;
+        self.inner.read_oplog(since_ts, &txn)
+    }
+
+    /// The latest finds, updates and deletes which ran at least
+    /// [`Config::slow_query_ms`](crate::Config::slow_query_ms), the oldest first.
+    /// Empty unless [`Config::slow_query_log_enabled`](crate::Config::slow_query_log_enabled) is set.
+    pub fn slow_queries(&self) -> Vec<SlowQuery> {
+        self.inner.slow_queries()
+    }
+
+    /// Follow the operation log from the entries with a `ts` greater than `since_ts`,
+    /// the cursor waits for the entries appended later.
+    /// See [`Database::read_oplog`] for the fields of the entries.
+    ///
+    /// Entries removed by [`Config::oplog_max_entries`](crate::Config::oplog_max_entries)
+    /// before they are read are skipped.
+    pub fn tail_oplog(&self, since_ts: i64) -> OplogCursor {
+        OplogCursor::new(Arc::downgrade(&self.inner), since_ts)
+    }
+
 }
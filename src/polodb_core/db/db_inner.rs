@@ -13,33 +13,62 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
-use std::collections::HashMap;
-use bson::{Bson, Document};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use bson::{doc, Bson, Document};
 use serde::Serialize;
 use super::db::Result;
-use crate::errors::Error;
-use crate::options::UpdateOptions;
-use crate::Config;
-use crate::vm::SubProgram;
+use crate::errors::{mk_invalid_query_field, DuplicateKeyError, Error, VersionMismatchError};
+use crate::options::{CreateCollectionOptions, DeleteOptions, InsertManyOptions, OpenOptions, UpdateOptions};
+use crate::{Config, IsolationLevel};
+use crate::config::MAX_BUSY_RETRY_BACKOFF_MS;
+use crate::vm::{AggregateCompileOptions, SubProgram};
 use crate::meta_doc_helper::meta_doc_key;
 use crate::index::{IndexBuilder, IndexModel, IndexOptions};
 use crate::db::client_cursor::ClientCursor;
-use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use crate::db::RawScan;
+use crate::results::{CollectionStats, DeleteResult, FieldTypes, InferredSchema, InsertManyResult, InsertOneResult, UpdateResult};
 use std::path::Path;
-use bson::oid::ObjectId;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::utils::object_id::{coerce_object_id_strings, ObjectIdMaker};
+use crate::utils::rand::SmallRng;
 use serde::de::DeserializeOwned;
 use crate::coll::collection_info::{
+    CappedInfo,
     CollectionSpecification,
     IndexInfo,
 };
+use crate::coll::validator;
 use crate::cursor::Cursor;
-use crate::index::{IndexHelper, IndexHelperOperation};
-use crate::metrics::Metrics;
+use crate::index::{IndexHelper, IndexHelperOperation, IndexKeyLimit, IndexStats, INDEX_PREFIX};
+use crate::db::materialized_view::{self, MaterializedView};
+use crate::metrics::{LsmMetrics, Metrics};
 use crate::db::rocksdb_wrapper::RocksDBWrapper;
+use crate::db::query_cache::{QueryCache, QueryCacheKey};
+use crate::db::counter_helper;
+use crate::db::capped_helper;
+use crate::db::write_buffer::WriteBuffer;
+use crate::db::oplog::{self, OplogTarget};
+use crate::db::slow_query_log::{SlowQuery, SlowQueryLog, SlowQueryTimer};
 use crate::transaction::TransactionInner;
 use crate::vm::VM;
 
 const TABLE_META_PREFIX: &str = "$TABLE_META";
+// a raw key which never collides with the stacked keys of the collections
+const FORMAT_VERSION_KEY: &[u8] = b"$FORMAT_VERSION";
+// bump it when the on-disk format is changed incompatibly
+const FORMAT_VERSION: [u8; 4] = [0, 0, 0, 1];
+// the default count of levels of RocksDB
+const LSM_LEVEL_COUNT: usize = 7;
+
+/// Options of [`DatabaseInner::aggregate_with_owned_session`].
+#[derive(Default)]
+pub(crate) struct AggregateOptions {
+    /// Let `$sort` write to temporary files
+    pub allow_disk_use: bool,
+    /// Scan the collection in storage order, set by a `$natural` sort
+    pub natural_reverse: Option<bool>,
+}
 
 /**
  * API for all platforms
@@ -48,18 +77,25 @@ pub(crate) struct DatabaseInner {
     rocksdb:      RocksDBWrapper,
     node_id:      [u8; 6],
     metrics:      Metrics,
-    #[allow(dead_code)]
     config:       Config,
+    query_cache:  QueryCache,
+    object_id_maker: ObjectIdMaker,
+    index_stats:  IndexStats,
+    write_buffer: WriteBuffer,
+    slow_query_log: SlowQueryLog,
+    // the generator seeded by the config, `None` for the random behaviors from entropy
+    seeded_rng:   Option<Mutex<SmallRng>>,
 }
 
 impl DatabaseInner {
 
-    pub fn open_file(path: &Path, config: Config) -> Result<DatabaseInner> {
+    pub fn open_file(path: &Path, config: Config, open_options: &OpenOptions) -> Result<DatabaseInner> {
         let metrics = Metrics::new();
 
         DatabaseInner::open_with_backend(
             path,
             config,
+            open_options,
             metrics,
         )
     }
@@ -67,12 +103,26 @@ impl DatabaseInner {
     fn open_with_backend(
         path: &Path,
         config: Config,
+        open_options: &OpenOptions,
         metrics: Metrics,
     ) -> Result<DatabaseInner> {
         let mut node_id: [u8; 6] = [0; 6];
         getrandom::getrandom(&mut node_id).unwrap();
 
-        let rocksdb = RocksDBWrapper::open(path)?;
+        let rocksdb = RocksDBWrapper::open_with_options(path, open_options)?;
+        rocksdb.set_sync_on_drop(config.sync_on_drop)?;
+        rocksdb.set_sync_policy(config.sync_policy, metrics.clone())?;
+        DatabaseInner::check_format_version(&rocksdb, open_options)?;
+
+        let query_cache = QueryCache::new(config.query_cache_size as usize);
+        let mut rng = match config.random_seed {
+            Some(seed) => SmallRng::new(seed),
+            None => SmallRng::from_entropy(),
+        };
+        let object_id_maker = ObjectIdMaker::new(config.object_id_process_id, &mut rng);
+        let seeded_rng = config.random_seed.map(|_| Mutex::new(rng));
+        let write_buffer = WriteBuffer::new(config.write_buffer_size as usize);
+        let slow_query_log = SlowQueryLog::new(config.slow_query_log_enabled, config.slow_query_ms);
 
         let ctx = DatabaseInner {
             rocksdb,
@@ -80,17 +130,240 @@ impl DatabaseInner {
             node_id,
             metrics,
             config,
+            query_cache,
+            object_id_maker,
+            index_stats: IndexStats::new(),
+            write_buffer,
+            slow_query_log,
+            seeded_rng,
         };
 
         Ok(ctx)
     }
 
+    /// Check the format version of the database, a new database is stamped with the current version.
+    ///
+    /// Returns [`Error::VersionMismatch`] if the database is written in another format,
+    /// e.g. a file created by a newer version.
+    fn check_format_version(rocksdb: &RocksDBWrapper, open_options: &OpenOptions) -> Result<()> {
+        let txn = rocksdb.begin_transaction()?;
+        match txn.get(FORMAT_VERSION_KEY)? {
+            Some(bytes) => {
+                let mut actual_version = [0; 4];
+                if bytes.len() == actual_version.len() {
+                    actual_version.copy_from_slice(&bytes);
+                }
+                if actual_version != FORMAT_VERSION {
+                    return Err(Error::VersionMismatch(Box::new(VersionMismatchError {
+                        actual_version,
+                        expect_version: FORMAT_VERSION,
+                    })));
+                }
+            }
+            None if !open_options.read_only => {
+                txn.set(FORMAT_VERSION_KEY, &FORMAT_VERSION)?;
+                txn.commit()?;
+            }
+            None => (),
+        }
+        Ok(())
+    }
+
+    /// The seed of the next random behavior, e.g. a `$sample` stage,
+    /// `None` when the config has no random seed.
+    fn next_random_seed(&self) -> Option<u64> {
+        self.seeded_rng
+            .as_ref()
+            .map(|rng| rng.lock().unwrap().next_u64())
+    }
+
     pub fn metrics(&self) -> Metrics {
         self.metrics.clone()
     }
 
+    /// Return `None` when the storage can't report the statistics of an LSM tree.
+    pub fn lsm_metrics(&self) -> Option<LsmMetrics> {
+        let property = |name: &str| self.rocksdb.property_int(name).ok();
+
+        let mut segment_count = 0;
+        for level in 0..LSM_LEVEL_COUNT {
+            segment_count += property(&format!("rocksdb.num-files-at-level{}", level))?;
+        }
+
+        Some(LsmMetrics {
+            segment_count,
+            segment_bytes: property("rocksdb.total-sst-files-size")?,
+            mem_table_bytes: property("rocksdb.cur-size-all-mem-tables")?,
+            compaction_pending: property("rocksdb.compaction-pending")? != 0,
+            running_compactions: property("rocksdb.num-running-compactions")?,
+        })
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.flush_write_buffer()?;
+        self.rocksdb.flush()?;
+        self.take_write_buffer_errors()
+    }
+
+    #[inline]
+    pub(crate) fn is_write_buffer_enabled(&self) -> bool {
+        self.write_buffer.is_enabled()
+    }
+
+    /// Add the document to the write buffer. The buffered documents of the collection
+    /// are written when there are [`Config::write_buffer_size`] of them.
+    ///
+    /// When the buffer is written, the error of this document is returned,
+    /// the errors of the documents buffered before are kept for [`DatabaseInner::flush`].
+    pub(crate) fn buffered_insert_one(&self, col_name: &str, doc: Document) -> Result<InsertOneResult> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let doc = self.assign_buffered_id(col_name, doc)?;
+        let inserted_id = doc.get(meta_doc_key::ID).cloned().unwrap_or(Bson::Null);
+        if let Some(batch) = self.write_buffer.push(col_name, doc)? {
+            let index = batch.len() - 1;
+            let (own_errors, earlier_errors): (Vec<Error>, Vec<Error>) = self.write_buffered_batch(col_name, batch)?
+                .into_iter()
+                .partition(|err| matches!(err, Error::WriteDocument(err) if err.index == Some(index)));
+            self.write_buffer.add_errors(earlier_errors)?;
+            if let Some(Error::WriteDocument(err)) = own_errors.into_iter().next() {
+                return Err(err.source);
+            }
+        }
+
+        Ok(InsertOneResult { inserted_id })
+    }
+
+    /// Give the document the `_id` the collection would give it when it's written,
+    /// so it's known before the buffer is flushed. The `_id` of an auto-increment
+    /// collection is taken from its counter right away.
+    fn assign_buffered_id(&self, col_name: &str, mut doc: Document) -> Result<Document> {
+        if !DatabaseInner::lacks_id(&doc) {
+            return Ok(doc);
+        }
+        let id = self.run_in_auto_transaction(|txn| {
+            match self.get_collection_meta_by_name_advanced_auto(col_name, false, txn)? {
+                Some(col_spec) if col_spec.auto_increment_id => {
+                    Ok(Bson::Int64(counter_helper::next_value(txn, col_name)?))
+                }
+                _ => Ok(Bson::ObjectId(self.object_id_maker.make())),
+            }
+        })?;
+        doc.insert(meta_doc_key::ID, id);
+        Ok(doc)
+    }
+
+    /// Write all the buffered documents, each collection in a transaction.
+    ///
+    /// A failing document doesn't stop the others, the errors of the documents are kept
+    /// to be reported by [`DatabaseInner::flush`] instead of the operation reading the
+    /// buffered documents. If a transaction fails, its documents and the ones of the rest
+    /// of the collections stay in the buffer, and the error is returned.
+    pub(crate) fn flush_write_buffer(&self) -> Result<()> {
+        if !self.write_buffer.is_enabled() {
+            return Ok(());
+        }
+        let mut pending = self.write_buffer.take_all()?.into_iter();
+        while let Some((col_name, docs)) = pending.next() {
+            match self.write_buffered_batch(&col_name, docs) {
+                Ok(errors) => self.write_buffer.add_errors(errors)?,
+                Err(err) => {
+                    for (col_name, docs) in pending {
+                        self.write_buffer.restore(&col_name, docs)?;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the errors of the buffered documents written since the last time.
+    pub(crate) fn take_write_buffer_errors(&self) -> Result<()> {
+        DatabaseInner::merge_write_errors(self.write_buffer.take_errors()?)
+    }
+
+    /// Write the buffered documents of the collection unordered in a transaction,
+    /// return the errors of the documents which are not written.
+    /// The documents are buffered again if the transaction fails.
+    fn write_buffered_batch(&self, col_name: &str, docs: Vec<Document>) -> Result<Vec<Error>> {
+        let result = self.run_in_auto_transaction(|txn| {
+            self.insert_many_internal::<Document>(txn, col_name, &docs, false, &self.node_id)
+        });
+        match result {
+            Ok(result) => Ok(result.write_errors),
+            Err(err) => {
+                self.write_buffer.restore(col_name, docs)?;
+                Err(err)
+            }
+        }
+    }
+
+    fn merge_write_errors(write_errors: Vec<Error>) -> Result<()> {
+        let mut write_errors = write_errors.into_iter();
+        let first = match write_errors.next() {
+            Some(err) => err,
+            None => return Ok(()),
+        };
+        Err(write_errors.fold(first, Error::add))
+    }
+
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Start a transaction with the [`Config::isolation_level`] of the database.
     pub fn start_transaction(&self) -> Result<TransactionInner> {
-        Ok(TransactionInner::new(self.rocksdb.begin_transaction()?))
+        match self.config.isolation_level {
+            IsolationLevel::ReadCommitted => Ok(TransactionInner::new(self.rocksdb.begin_transaction()?)),
+            IsolationLevel::Snapshot => self.start_snapshot_transaction(),
+        }
+    }
+
+    pub fn start_snapshot_transaction(&self) -> Result<TransactionInner> {
+        Ok(TransactionInner::new(self.rocksdb.begin_snapshot_transaction()?))
+    }
+
+    /// Run `op` in a new transaction and commit it.
+    ///
+    /// If the operation fails with [`Error::Busy`] because of other writers,
+    /// the transaction is rolled back and `op` is retried with an exponential
+    /// backoff, at most [`Config::busy_retry_count`] times.
+    pub(crate) fn run_in_auto_transaction<R, F>(&self, mut op: F) -> Result<R>
+    where
+        F: FnMut(&TransactionInner) -> Result<R>,
+    {
+        let mut retry_count: u32 = 0;
+        loop {
+            let txn = self.start_transaction()?;
+            let err = match op(&txn) {
+                Ok(ret) => match txn.commit() {
+                    Ok(()) => return Ok(ret),
+                    Err(err) => err,
+                },
+                Err(err) => {
+                    if let Err(rollback_err) = txn.rollback() {
+                        return Err(err.add(rollback_err));
+                    }
+                    err
+                }
+            };
+
+            if !matches!(err, Error::Busy) || retry_count >= self.config.busy_retry_count {
+                return Err(err);
+            }
+
+            std::thread::sleep(self.busy_retry_backoff(retry_count));
+            retry_count += 1;
+        }
+    }
+
+    fn busy_retry_backoff(&self, retry_count: u32) -> Duration {
+        let ms = self.config.busy_retry_backoff_ms
+            .saturating_mul(1u64.checked_shl(retry_count).unwrap_or(u64::MAX))
+            .min(MAX_BUSY_RETRY_BACKOFF_MS);
+        Duration::from_millis(ms)
     }
 
     fn internal_get_collection_id_by_name(&self, txn: &TransactionInner, name: &str) -> Result<CollectionSpecification> {
@@ -127,9 +400,13 @@ impl DatabaseInner {
     pub fn get_collection_meta_by_name_advanced(&self, txn: &TransactionInner, name: &str, create_if_not_exist: bool, node_id: &[u8; 6]) -> Result<Option<CollectionSpecification>> {
         match self.internal_get_collection_id_by_name(txn, name) {
             Ok(meta) => Ok(Some(meta)),
-            Err(Error::CollectionNotFound(_)) => {
-                if create_if_not_exist {
-                    let meta = self.internal_create_collection(txn, name, node_id)?;
+            Err(Error::CollectionNotFound(col_name)) => {
+                if !self.config.auto_create_collections {
+                    // the collection must be created with `create_collection` first,
+                    // both the reads and the writes of a missing one fail
+                    Err(Error::CollectionNotFound(col_name))
+                } else if create_if_not_exist {
+                    let meta = self.internal_create_collection(txn, name, &CreateCollectionOptions::default(), node_id)?;
                     Ok(Some(meta))
                 } else {
                     Ok(None)
@@ -139,19 +416,19 @@ impl DatabaseInner {
         }
     }
 
-    pub fn create_collection(&self, name: &str) -> Result<CollectionSpecification> {
+    pub fn create_collection(&self, name: &str, options: &CreateCollectionOptions) -> Result<CollectionSpecification> {
         DatabaseInner::validate_col_name(name)?;
 
         let txn = self.start_transaction()?;
-        let result = self.create_collection_internal(name, &txn)?;
+        let result = self.create_collection_internal(name, options, &txn)?;
         txn.commit()?;
 
         Ok(result)
     }
 
     #[inline]
-    pub fn create_collection_internal(&self, name: &str, txn: &TransactionInner) -> Result<CollectionSpecification> {
-        let meta = self.internal_create_collection(txn, name, &self.node_id)?;
+    pub fn create_collection_internal(&self, name: &str, options: &CreateCollectionOptions, txn: &TransactionInner) -> Result<CollectionSpecification> {
+        let meta = self.internal_create_collection(txn, name, options, &self.node_id)?;
         Ok(meta)
     }
 
@@ -164,7 +441,13 @@ impl DatabaseInner {
         }
     }
 
-    fn internal_create_collection(&self, txn: &TransactionInner, name: &str, node_id: &[u8; 6]) -> Result<CollectionSpecification> {
+    fn internal_create_collection(
+        &self,
+        txn: &TransactionInner,
+        name: &str,
+        options: &CreateCollectionOptions,
+        node_id: &[u8; 6],
+    ) -> Result<CollectionSpecification> {
         if name.is_empty() {
             return Err(Error::IllegalCollectionName(name.into()));
         }
@@ -173,8 +456,20 @@ impl DatabaseInner {
             return Err(Error::CollectionAlreadyExits(name.into()));
         }
 
+        if let Some(validator) = &options.validator {
+            validator::check_validator(validator)?;
+        }
+
         let uuid = uuid::Uuid::now_v1(node_id);
-        let spec = CollectionSpecification::new(name.to_string(), uuid);
+        let mut spec = CollectionSpecification::new(name.to_string(), uuid);
+        spec.validator = options.validator.clone();
+        spec.defaults = options.defaults.clone();
+        spec.auto_increment_id = options.auto_increment_id.unwrap_or(false);
+        spec.capped = DatabaseInner::capped_info(options)?;
+        if spec.capped.is_some() {
+            // the incrementing ids keep the documents in insertion order
+            spec.auto_increment_id = true;
+        }
 
         let stacked_key = crate::utils::bson::stacked_key(&[
             Bson::String(TABLE_META_PREFIX.to_string()),
@@ -188,13 +483,32 @@ impl DatabaseInner {
         Ok(spec)
     }
 
+    fn capped_info(options: &CreateCollectionOptions) -> Result<Option<CappedInfo>> {
+        if !options.capped.unwrap_or(false) {
+            if options.size.is_some() || options.max.is_some() {
+                return Err(Error::ValidationError("size and max are only allowed for capped collections".to_string()));
+            }
+            return Ok(None);
+        }
+        let size = options.size.unwrap_or(0);
+        let max = options.max.unwrap_or(0);
+        if size == 0 && max == 0 {
+            return Err(Error::ValidationError("a capped collection needs a size or a max".to_string()));
+        }
+        Ok(Some(CappedInfo { size, max }))
+    }
+
+    fn new_vm(&self, txn: TransactionInner, program: SubProgram) -> VM {
+        let mut vm = VM::new(txn, program, self.metrics.clone());
+        vm.set_index_key_limit(IndexKeyLimit::from_config(&self.config));
+        vm.set_max_document_depth(self.config.max_document_depth);
+        vm.set_index_stats(self.index_stats.clone());
+        vm
+    }
+
     pub(crate) fn make_handle<T: DeserializeOwned + Send + Sync>(&self, program: SubProgram, txn: TransactionInner) -> Result<ClientCursor<T>> {
-        let vm = VM::new(
-            txn,
-            program,
-            self.metrics.clone(),
-        );
-        Ok(ClientCursor::new(vm))
+        let vm = self.new_vm(txn, program);
+        Ok(ClientCursor::new(vm, self.config.cursor_batch_size as usize))
     }
 
     pub fn create_index(&self, col_name: &str, index: IndexModel, txn: &TransactionInner) -> Result<()> {
@@ -256,6 +570,7 @@ impl DatabaseInner {
             options.cloned(),
         );
         collection_spec.indexes.insert(index_name.clone(), index_info.clone());
+        collection_spec.meta_version += 1;
 
         DatabaseInner::update_collection_spec(
             col_name,
@@ -283,15 +598,70 @@ impl DatabaseInner {
             col_name,
             index_name,
             index_info,
+            IndexKeyLimit::from_config(&self.config),
         );
 
         builder.execute(IndexHelperOperation::Insert)
     }
 
+    /// Delete all the index entries of the collection and build them again from the documents.
+    pub fn rebuild_indexes(&self, col_name: &str, txn: &TransactionInner) -> Result<()> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let collection_spec = match self.internal_get_collection_id_by_name(txn, col_name) {
+            Ok(spec) => spec,
+            Err(Error::CollectionNotFound(_)) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        self.delete_index_entries(col_name, txn)?;
+
+        for (index_name, index_info) in collection_spec.indexes.iter() {
+            self.build_index(
+                txn,
+                col_name,
+                index_name.as_str(),
+                index_info,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn delete_index_entries(&self, col_name: &str, txn: &TransactionInner) -> Result<()> {
+        let prefix_bytes = crate::utils::bson::stacked_key([
+            &Bson::String(INDEX_PREFIX.to_string()),
+            &Bson::String(col_name.to_string()),
+        ])?;
+        DatabaseInner::delete_keys(txn, prefix_bytes)
+    }
+
+    fn delete_keys(txn: &TransactionInner, prefix: Vec<u8>) -> Result<()> {
+        // collect the keys first, don't write when the iterator is alive
+        let mut keys = Vec::new();
+        {
+            let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+            cursor.reset()?;
+            while cursor.has_next() {
+                if let Some(key) = cursor.peek_key() {
+                    keys.push(key);
+                }
+                cursor.next()?;
+            }
+        }
+
+        for key in keys {
+            txn.delete(key.as_ref())?;
+        }
+
+        Ok(())
+    }
+
     pub fn drop_index(&self, col_name: &str, index_name: &str, txn: &TransactionInner) -> Result<()> {
         DatabaseInner::validate_col_name(col_name)?;
 
         self.internal_drop_index(col_name, index_name, txn)?;
+        self.index_stats.remove_index(col_name, index_name);
 
         Ok(())
     }
@@ -320,11 +690,13 @@ impl DatabaseInner {
             col_name,
             index_name,
             index_info,
+            IndexKeyLimit::from_config(&self.config),
         );
 
         builder.execute(IndexHelperOperation::Delete)?;
 
         collection_spec.indexes.shift_remove(index_name);
+        collection_spec.meta_version += 1;
 
         DatabaseInner::update_collection_spec(
             col_name,
@@ -371,19 +743,33 @@ impl DatabaseInner {
     }
 
     #[inline]
-    fn fix_doc(mut doc: Document) -> Document {
-        if let Some(id) = doc.get(meta_doc_key::ID) {
-            // If the id type is not null, the document is ok
-            if id.as_null().is_none() {
-                return doc;
-            }
+    fn lacks_id(doc: &Document) -> bool {
+        match doc.get(meta_doc_key::ID) {
+            Some(id) => id.as_null().is_some(),
+            None => true,
+        }
+    }
+
+    #[inline]
+    fn fix_doc(&self, mut doc: Document) -> Document {
+        // If the id type is not null, the document is ok
+        if !DatabaseInner::lacks_id(&doc) {
+            return doc;
         }
 
-        let new_oid = ObjectId::new();
+        let new_oid = self.object_id_maker.make();
         doc.insert::<String, Bson>(meta_doc_key::ID.into(), new_oid.into());
         doc
     }
 
+    fn fill_defaults(doc: &mut Document, defaults: &Document) {
+        for (key, value) in defaults {
+            if !doc.contains_key(key) {
+                doc.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
     fn validate_col_name(col_name: &str) -> Result<()> {
         for ch in col_name.chars() {
             if ch == '$' || ch == '\n' || ch == '\t' || ch == '\r' || ch == '.' {
@@ -412,6 +798,16 @@ impl DatabaseInner {
         Ok(changed)
     }
 
+    /// Insert one document and return it as stored,
+    /// with the generated `_id` and the default values.
+    pub fn insert_one_and_fetch(&self, col_name: &str, doc: Document, txn: &TransactionInner) -> Result<Document> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let col_meta = self.get_collection_meta_by_name_advanced(txn, col_name, true, &self.node_id)?
+            .expect("internal: meta must exist");
+        self.store_document(txn, &col_meta, doc)
+    }
+
     fn insert_one_internal(&self, txn: &TransactionInner, col_name: &str, doc: Document, node_id: &[u8; 6]) -> Result<InsertOneResult> {
         let col_meta = self.get_collection_meta_by_name_advanced(txn, col_name, true, node_id)?
             .expect("internal: meta must exist");
@@ -422,7 +818,33 @@ impl DatabaseInner {
     /// Insert one item with the collection spec
     /// return the new spec for the outside to do the following operation
     fn insert_one_with_meta(&self, txn: &TransactionInner, col_spec: CollectionSpecification, doc: Document) -> Result<(InsertOneResult, CollectionSpecification)> {
-        let doc  = DatabaseInner::fix_doc(doc);
+        let mut doc = self.store_document(txn, &col_spec, doc)?;
+        let inserted_id = doc.remove("_id").unwrap();
+
+        Ok((
+            InsertOneResult { inserted_id },
+            col_spec
+        ))
+    }
+
+    /// Write the document and its index entries, return the stored document
+    fn store_document(&self, txn: &TransactionInner, col_spec: &CollectionSpecification, mut doc: Document) -> Result<Document> {
+        if col_spec.auto_increment_id && DatabaseInner::lacks_id(&doc) {
+            let id = counter_helper::next_value(txn, col_spec.name())?;
+            doc.insert::<String, Bson>(meta_doc_key::ID.into(), Bson::Int64(id));
+        }
+
+        let mut doc  = self.fix_doc(doc);
+
+        if let Some(defaults) = &col_spec.defaults {
+            DatabaseInner::fill_defaults(&mut doc, defaults);
+        }
+
+        crate::utils::bson::check_document_depth(&doc, self.config.max_document_depth)?;
+
+        if let Some(validator) = &col_spec.validator {
+            validator::validate_document(validator, &doc)?;
+        }
 
         let pkey = doc.get("_id").unwrap();
 
@@ -438,12 +860,109 @@ impl DatabaseInner {
             &doc_buf,
         )?;
 
-        self.try_insert_index(txn, &col_spec, &doc, pkey)?;
+        self.try_insert_index(txn, col_spec, &doc, pkey)?;
 
-        Ok((
-            InsertOneResult { inserted_id: pkey.clone() },
-            col_spec
-        ))
+        if let Some(target) = self.oplog_target(col_spec.name()) {
+            oplog::append(txn, &target, oplog::OP_INSERT, doc.clone(), None)?;
+        }
+
+        // the oldest documents by insertion are evicted, whatever their _ids are
+        if let Some(capped) = &col_spec.capped {
+            let evicted = capped_helper::append(txn, col_spec.name(), capped, pkey, doc_buf.len() as u64)?;
+            if !evicted.is_empty() {
+                self.delete_by_ids(col_spec.name(), &evicted, txn)?;
+            }
+        }
+
+        Ok(doc)
+    }
+
+    /// Where the writes to the collection are logged, `None` if the oplog is disabled.
+    fn oplog_target(&self, col_name: &str) -> Option<OplogTarget> {
+        if !self.config.oplog_enabled {
+            return None;
+        }
+        Some(OplogTarget {
+            ns: col_name.to_string(),
+            max_entries: self.config.oplog_max_entries,
+            comment: None,
+        })
+    }
+
+    /// Like [`DatabaseInner::oplog_target`], the entries carry the `$comment` of the filter of the write.
+    fn oplog_target_with_comment(&self, col_name: &str, comment: &Option<Bson>) -> Option<OplogTarget> {
+        let mut target = self.oplog_target(col_name)?;
+        target.comment = comment.clone();
+        Some(target)
+    }
+
+    /// The latest queries which ran at least [`Config::slow_query_ms`](crate::Config::slow_query_ms).
+    pub(crate) fn slow_queries(&self) -> Vec<SlowQuery> {
+        self.slow_query_log.entries()
+    }
+
+    /// Read the entries of the operation log with a `ts` greater than `since_ts`.
+    pub(crate) fn read_oplog(&self, since_ts: i64, txn: &TransactionInner) -> Result<Vec<Document>> {
+        oplog::read_since(txn, since_ts)
+    }
+
+    pub(crate) fn create_materialized_view(
+        &self,
+        name: &str,
+        source: &str,
+        pipeline: Vec<Document>,
+        target: &str,
+        txn: &TransactionInner,
+    ) -> Result<()> {
+        DatabaseInner::validate_col_name(source)?;
+        DatabaseInner::validate_col_name(target)?;
+        if source == target {
+            return Err(Error::ValidationError("the target of a materialized view can't be its source".to_string()));
+        }
+
+        let view = MaterializedView {
+            source: source.to_string(),
+            pipeline,
+            target: target.to_string(),
+        };
+        materialized_view::save(txn, name, &view)
+    }
+
+    /// Replace the documents of the target with the results of the pipeline,
+    /// return the count of the documents written.
+    pub(crate) fn refresh_view(&self, name: &str, txn: &TransactionInner) -> Result<u64> {
+        let view = materialized_view::load(txn, name)?
+            .ok_or_else(|| Error::ViewNotFound(name.to_string()))?;
+
+        // the cursor must not commit the transaction when it's closed
+        let mut read_txn = txn.clone();
+        read_txn.set_auto_commit(false);
+        let mut handle = self.aggregate_with_owned_session::<Document>(
+            &view.source,
+            view.pipeline,
+            AggregateOptions::default(),
+            read_txn,
+        )?;
+        let mut docs = Vec::new();
+        while handle.advance()? {
+            docs.push(handle.deserialize_current()?);
+        }
+
+        self.delete_all(&view.target, txn)?;
+        if docs.is_empty() {
+            return Ok(0);
+        }
+        let result = self.insert_many_internal::<Document>(txn, &view.target, docs, true, &self.node_id)?;
+
+        Ok(result.inserted_ids.len() as u64)
+    }
+
+    /// Remove the definition of the view, the target collection is kept.
+    pub(crate) fn drop_materialized_view(&self, name: &str, txn: &TransactionInner) -> Result<()> {
+        if !materialized_view::remove(txn, name)? {
+            return Err(Error::ViewNotFound(name.to_string()));
+        }
+        Ok(())
     }
 
     fn try_insert_index(&self, txn: &TransactionInner, col_spec: &CollectionSpecification, doc: &Document, pkey: &Bson) -> Result<()> {
@@ -452,6 +971,7 @@ impl DatabaseInner {
             col_spec,
             doc,
             pkey,
+            IndexKeyLimit::from_config(&self.config),
         );
         index_helper.execute(IndexHelperOperation::Insert)
     }
@@ -461,10 +981,20 @@ impl DatabaseInner {
         col_name: &str,
         docs: impl IntoIterator<Item = impl Borrow<T>>,
         txn: &TransactionInner,
+    ) -> Result<InsertManyResult> {
+        self.insert_many_with_options(col_name, docs, &InsertManyOptions::default(), txn)
+    }
+
+    pub fn insert_many_with_options<T: Serialize>(
+        &self,
+        col_name: &str,
+        docs: impl IntoIterator<Item = impl Borrow<T>>,
+        options: &InsertManyOptions,
+        txn: &TransactionInner,
     ) -> Result<InsertManyResult> {
         DatabaseInner::validate_col_name(col_name)?;
 
-        let result = self.insert_many_internal(txn, col_name, docs, &self.node_id)?;
+        let result = self.insert_many_internal(txn, col_name, docs, options.is_ordered(), &self.node_id)?;
 
         Ok(result)
     }
@@ -474,25 +1004,115 @@ impl DatabaseInner {
         txn: &TransactionInner,
         col_name: &str,
         docs: impl IntoIterator<Item = impl Borrow<T>>,
+        ordered: bool,
         node_id: &[u8; 6],
     ) -> Result<InsertManyResult> {
-        let mut col_spec = self.get_collection_meta_by_name_advanced(txn, col_name, true, node_id)?
+        let col_spec = self.get_collection_meta_by_name_advanced(txn, col_name, true, node_id)?
             .expect("internal: meta must exist");
         let mut inserted_ids: HashMap<usize, Bson> = HashMap::new();
+        let mut write_errors = Vec::new();
+
+        let docs = docs.into_iter()
+            .enumerate()
+            .map(|(counter, item)| {
+                bson::to_document(item.borrow())
+                    .map_err(|err| Error::from(err).with_document(Some(counter), None))
+            })
+            .collect::<Result<Vec<Document>>>()?;
+        if ordered {
+            DatabaseInner::check_batch_duplicate_ids(col_name, &docs)?;
+        }
 
-        for (counter, item) in docs.into_iter().enumerate() {
-            let doc = bson::to_document(item.borrow())?;
-            let (insert_one_result, new_col_spec) = self.insert_one_with_meta(txn, col_spec, doc)?;
-            inserted_ids.insert(counter, insert_one_result.inserted_id);
+        // the keys of the explicit _ids inserted by the batch
+        let mut inserted_keys: HashSet<Vec<u8>> = HashSet::new();
 
-            col_spec = new_col_spec;
+        for (counter, doc) in docs.into_iter().enumerate() {
+            let id = doc.get(meta_doc_key::ID).cloned();
+            if ordered {
+                let mut stored = self.store_document(txn, &col_spec, doc)
+                    .map_err(|err| err.with_document(Some(counter), id))?;
+                inserted_ids.insert(counter, stored.remove(meta_doc_key::ID).unwrap());
+                continue;
+            }
+
+            match self.insert_unordered_document(txn, &col_spec, doc, &mut inserted_keys) {
+                Ok(inserted_id) => {
+                    inserted_ids.insert(counter, inserted_id);
+                }
+                // the whole transaction is retried
+                Err(Error::Busy) => return Err(Error::Busy),
+                Err(err) => write_errors.push(err.with_document(Some(counter), id)),
+            }
         }
 
         Ok(InsertManyResult {
             inserted_ids,
+            write_errors,
         })
     }
 
+    /// Insert a document of an unordered batch, only its own writes are
+    /// rolled back when it fails. Return the `_id` of the document.
+    fn insert_unordered_document(
+        &self,
+        txn: &TransactionInner,
+        col_spec: &CollectionSpecification,
+        doc: Document,
+        inserted_keys: &mut HashSet<Vec<u8>>,
+    ) -> Result<Bson> {
+        let id_key = match doc.get(meta_doc_key::ID) {
+            Some(id) if !DatabaseInner::lacks_id(&doc) => {
+                let key = crate::utils::bson::stacked_key([id])?;
+                if inserted_keys.contains(&key) {
+                    return Err(DatabaseInner::duplicate_id_error(col_spec.name(), id));
+                }
+                Some(key)
+            }
+            _ => None,
+        };
+
+        txn.set_savepoint();
+        match self.store_document(txn, col_spec, doc) {
+            Ok(mut stored) => {
+                if let Some(key) = id_key {
+                    inserted_keys.insert(key);
+                }
+                Ok(stored.remove(meta_doc_key::ID).unwrap())
+            }
+            Err(err) => {
+                txn.rollback_to_savepoint()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Reject a batch containing the same explicit `_id` more than once before anything is written.
+    /// The error points to the first document repeating an `_id` of an earlier one.
+    fn check_batch_duplicate_ids(col_name: &str, docs: &[Document]) -> Result<()> {
+        let mut seen_ids: HashSet<Vec<u8>> = HashSet::new();
+        for (counter, doc) in docs.iter().enumerate() {
+            if DatabaseInner::lacks_id(doc) {
+                continue;
+            }
+            let id = doc.get(meta_doc_key::ID).unwrap();
+            let key = crate::utils::bson::stacked_key([id])
+                .map_err(|err| err.with_document(Some(counter), Some(id.clone())))?;
+            if !seen_ids.insert(key) {
+                let err = DatabaseInner::duplicate_id_error(col_name, id);
+                return Err(err.with_document(Some(counter), Some(id.clone())));
+            }
+        }
+        Ok(())
+    }
+
+    fn duplicate_id_error(col_name: &str, id: &Bson) -> Error {
+        DuplicateKeyError {
+            name: "_id_".to_string(),
+            key: id.to_string(),
+            ns: col_name.to_string(),
+        }.into()
+    }
+
     fn find_internal<T: DeserializeOwned + Send + Sync>(
         &self,
         col_spec: &CollectionSpecification,
@@ -500,11 +1120,7 @@ impl DatabaseInner {
         txn: TransactionInner,
     ) -> Result<ClientCursor<T>> {
         let subprogram = match query {
-            Some(query) => SubProgram::compile_query(
-                col_spec,
-                &query,
-                true
-            ),
+            Some(query) => self.compile_query_cached(col_spec, &query),
             None => SubProgram::compile_query_all(col_spec, true),
         }?;
 
@@ -512,6 +1128,51 @@ impl DatabaseInner {
         Ok(handle)
     }
 
+    /// Compile the query, or take the compiled program of the same query from the cache.
+    fn compile_query_cached(&self, col_spec: &CollectionSpecification, query: &Document) -> Result<SubProgram> {
+        let coerced;
+        let query = if self.config.coerce_object_id_strings {
+            coerced = coerce_object_id_strings(query.clone());
+            &coerced
+        } else {
+            query
+        };
+        let key = QueryCacheKey::new(col_spec, query)?;
+        if let Some(subprogram) = self.query_cache.get(&key) {
+            return Ok(subprogram);
+        }
+
+        self.metrics.add_query_compile_count();
+        let subprogram = SubProgram::compile_query(
+            col_spec,
+            query,
+            true,
+            self.config.max_query_depth,
+        )?;
+        self.query_cache.put(key, &subprogram);
+
+        Ok(subprogram)
+    }
+
+    /// Apply the config-dependent rewrites to a filter before it's compiled.
+    fn prepare_filter(&self, filter: Document) -> Document {
+        if self.config.coerce_object_id_strings {
+            coerce_object_id_strings(filter)
+        } else {
+            filter
+        }
+    }
+
+    fn prepare_match_stage(&self, mut stage: Document) -> Document {
+        if !self.config.coerce_object_id_strings {
+            return stage;
+        }
+        if let Some(Bson::Document(filter)) = stage.get_mut("$match") {
+            *filter = coerce_object_id_strings(std::mem::take(filter));
+        }
+        stage
+    }
+
     pub fn update_one(
         &self,
         col_name: &str,
@@ -556,7 +1217,7 @@ impl DatabaseInner {
     fn internal_update(
         &self,
         col_name: &str,
-        query: Document,
+        mut query: Document,
         update: Document,
         is_many: bool,
         options: UpdateOptions,
@@ -564,7 +1225,38 @@ impl DatabaseInner {
     ) -> Result<UpdateResult> {
         let meta_opt = self.get_collection_meta_by_name_advanced_auto(col_name, false, txn)?;
 
-        let result = match &meta_opt {
+        let update = if options.is_versioned() {
+            DatabaseInner::add_version_increment(update)?
+        } else {
+            update
+        };
+
+        let comment = query.remove("$comment");
+        let query = self.prepare_filter(query);
+        let timer = SlowQueryTimer::start(&self.slow_query_log, "update", col_name, &query, comment.clone());
+
+        // the failed updates are logged too
+        let result = self.execute_update(col_name, meta_opt.as_ref(), query, update, is_many, options, &comment, txn);
+        if let Some(timer) = timer {
+            timer.finish();
+        }
+
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_update(
+        &self,
+        col_name: &str,
+        meta_opt: Option<&CollectionSpecification>,
+        query: Document,
+        update: Document,
+        is_many: bool,
+        options: UpdateOptions,
+        comment: &Option<Bson>,
+        txn: &TransactionInner,
+    ) -> Result<UpdateResult> {
+        let result = match meta_opt {
             Some(col_spec) => {
                 let subprogram = SubProgram::compile_update(
                     col_spec,
@@ -572,13 +1264,14 @@ impl DatabaseInner {
                     &update,
                     true,
                     is_many,
+                    options.get_limit(),
+                    self.config.max_query_depth,
                 )?;
 
-                let mut vm = VM::new(
-                    txn.clone(),
-                    subprogram,
-                    self.metrics.clone(),
-                );
+                let mut vm = self.new_vm(txn.clone(), subprogram);
+                if let Some(target) = self.oplog_target_with_comment(col_name, comment) {
+                    vm.set_oplog_target(target);
+                }
                 vm.execute()?;
 
                 // vm.r2 as u64
@@ -589,6 +1282,11 @@ impl DatabaseInner {
             },
             None => UpdateResult::default(),
         };
+        if options.is_versioned() && result.matched_count == 0 {
+            if let Some(col_spec) = meta_opt {
+                self.check_version_conflict(col_spec, &query, txn)?;
+            }
+        }
         if options.is_upsert() && result.modified_count == 0 {
             self.upsert(col_name, query, update, txn)?;
         }
@@ -596,6 +1294,107 @@ impl DatabaseInner {
         Ok(result)
     }
 
+    /// Run the query of an update without writing anything,
+    /// returns the matched documents with the update applied.
+    pub(crate) fn dry_run_update(
+        &self,
+        col_name: &str,
+        query: Document,
+        update: Document,
+        is_many: bool,
+        txn: &TransactionInner,
+    ) -> Result<Vec<Document>> {
+        self.dry_run(col_name, query, Some(update), is_many, txn)
+    }
+
+    /// Run the query of a delete without writing anything, returns the matched documents.
+    pub(crate) fn dry_run_delete(
+        &self,
+        col_name: &str,
+        query: Document,
+        is_many: bool,
+        txn: &TransactionInner,
+    ) -> Result<Vec<Document>> {
+        self.dry_run(col_name, query, None, is_many, txn)
+    }
+
+    fn dry_run(
+        &self,
+        col_name: &str,
+        query: Document,
+        update: Option<Document>,
+        is_many: bool,
+        txn: &TransactionInner,
+    ) -> Result<Vec<Document>> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let col_spec = match self.get_collection_meta_by_name_advanced_auto(col_name, false, txn)? {
+            Some(col_spec) => col_spec,
+            None => return Ok(vec![]),
+        };
+        let query = self.prepare_filter(query);
+
+        let subprogram = SubProgram::compile_dry_run(
+            &col_spec,
+            &query,
+            update.as_ref(),
+            true,
+            is_many,
+            self.config.max_query_depth,
+        )?;
+
+        let mut handle: ClientCursor<Document> = self.make_handle(subprogram, txn.clone())?;
+        let mut result = Vec::new();
+        while handle.advance()? {
+            result.push(handle.deserialize_current()?);
+        }
+
+        Ok(result)
+    }
+
+    /// Append `$inc: { __v: 1 }` to the update
+    fn add_version_increment(mut update: Document) -> Result<Document> {
+        let version_key = meta_doc_key::VERSION;
+        for (op, fields) in &update {
+            if let Some(fields) = fields.as_document() {
+                if fields.contains_key(version_key) {
+                    return Err(Error::InvalidField(mk_invalid_query_field(op.clone(), version_key.to_string())));
+                }
+            }
+        }
+
+        match update.get_mut("$inc") {
+            Some(Bson::Document(inc)) => {
+                inc.insert(version_key, 1);
+            }
+            Some(_) => return Err(Error::InvalidField(mk_invalid_query_field("$inc".to_string(), "$inc".to_string()))),
+            None => {
+                update.insert("$inc", doc! { version_key: 1 });
+            }
+        }
+
+        Ok(update)
+    }
+
+    /// Nothing is matched by a versioned update. If the document is still
+    /// there without the version condition, another writer has updated it.
+    fn check_version_conflict(&self, col_spec: &CollectionSpecification, query: &Document, txn: &TransactionInner) -> Result<()> {
+        let expected_version = match query.get(meta_doc_key::VERSION) {
+            Some(version) => version.clone(),
+            None => return Ok(()),
+        };
+
+        let mut query = query.clone();
+        query.remove(meta_doc_key::VERSION);
+
+        let mut handle = self.find_internal::<Document>(col_spec, Some(query), txn.clone())?;
+        if handle.advance()? {
+            return Err(Error::VersionConflict(Box::new(expected_version)));
+        }
+
+        Ok(())
+    }
+
     fn merge_query_and_update(query: &Document, update: &Document) -> Result<Document> {
         let mut doc = query.clone();
         for (key, value) in update {
@@ -630,6 +1429,102 @@ impl DatabaseInner {
         DatabaseInner::validate_col_name(col_name)?;
 
         self.drop_collection_internal(col_name, txn)?;
+        self.index_stats.remove_collection(col_name);
+
+        Ok(())
+    }
+
+    /// Rename the collection `from` to `to` with its documents, indexes and counter.
+    /// An existing collection `to` is dropped first with `drop_target`,
+    /// otherwise [`Error::CollectionAlreadyExits`] is returned.
+    pub(crate) fn rename_collection(&self, from: &str, to: &str, drop_target: bool, txn: &TransactionInner) -> Result<()> {
+        DatabaseInner::validate_col_name(from)?;
+        DatabaseInner::validate_col_name(to)?;
+        if to.is_empty() {
+            return Err(Error::IllegalCollectionName(to.into()));
+        }
+        if from == to {
+            return Err(Error::ValidationError("can't rename a collection to itself".to_string()));
+        }
+
+        let mut col_spec = self.internal_get_collection_id_by_name(txn, from)?;
+        if self.check_collection_exist(txn, to)? {
+            if !drop_target {
+                return Err(Error::CollectionAlreadyExits(to.into()));
+            }
+            self.drop_collection_internal(to, txn)?;
+            self.delete_index_entries(to, txn)?;
+        }
+
+        // the keys are stacked from the collection name and the rest stays the same
+        DatabaseInner::move_keys(
+            txn,
+            &crate::utils::bson::stacked_key([&Bson::String(from.to_string())])?,
+            &crate::utils::bson::stacked_key([&Bson::String(to.to_string())])?,
+        )?;
+        let index_prefix = Bson::String(INDEX_PREFIX.to_string());
+        DatabaseInner::move_keys(
+            txn,
+            &crate::utils::bson::stacked_key([&index_prefix, &Bson::String(from.to_string())])?,
+            &crate::utils::bson::stacked_key([&index_prefix, &Bson::String(to.to_string())])?,
+        )?;
+
+        self.delete_collection_meta(from, txn)?;
+        col_spec._id = to.to_string();
+        col_spec.meta_version += 1;
+        DatabaseInner::update_collection_spec(to, &col_spec, txn)?;
+        counter_helper::rename_counter(txn, from, to)?;
+        let capped_prefixes = capped_helper::key_prefixes(from)?.into_iter()
+            .zip(capped_helper::key_prefixes(to)?);
+        for (from_prefix, to_prefix) in capped_prefixes {
+            DatabaseInner::move_keys(txn, &from_prefix, &to_prefix)?;
+        }
+
+        if let Some(target) = self.oplog_target(from) {
+            oplog::append(txn, &target, oplog::OP_COMMAND, doc! { "renameCollection": from, "to": to }, None)?;
+        }
+        self.index_stats.remove_collection(from);
+        self.index_stats.remove_collection(to);
+
+        Ok(())
+    }
+
+    /// Move the keys starting with `from_prefix` under `to_prefix`.
+    fn move_keys(txn: &TransactionInner, from_prefix: &[u8], to_prefix: &[u8]) -> Result<()> {
+        // collect the entries first, don't write when the iterator is alive
+        let mut entries = Vec::new();
+        {
+            let mut cursor = Cursor::new(from_prefix.to_vec(), txn.rocksdb_txn.new_iterator());
+            cursor.reset()?;
+            while cursor.has_next() {
+                if let Some(key) = cursor.peek_key() {
+                    entries.push((key, cursor.copy_data()?));
+                }
+                cursor.next()?;
+            }
+        }
+
+        for (key, value) in entries {
+            let mut new_key = to_prefix.to_vec();
+            new_key.extend_from_slice(&key[from_prefix.len()..]);
+            txn.put(new_key.as_slice(), value.as_slice())?;
+            txn.delete(key.as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    /// Drop all the collections with their indexes, and the definitions of the materialized views.
+    /// The format version is kept, so the database stays valid.
+    pub(crate) fn drop_database(&self, txn: &TransactionInner) -> Result<()> {
+        let col_names = self.list_collection_names_with_session(txn)?;
+        for col_name in &col_names {
+            self.drop_collection_internal(col_name, txn)?;
+            // the entries out of sync with the documents are removed too
+            self.delete_index_entries(col_name, txn)?;
+            self.index_stats.remove_collection(col_name);
+        }
+        materialized_view::remove_all(txn)?;
 
         Ok(())
     }
@@ -652,15 +1547,19 @@ impl DatabaseInner {
         {
             let mut txn = txn.clone();
             txn.set_auto_commit(false);
-            let mut vm = VM::new(
-                txn,
-                subprogram,
-                self.metrics.clone(),
-            );
+            let mut vm = self.new_vm(txn, subprogram);
             vm.execute()?;
         } // Delete content end
 
         self.delete_collection_meta(col_name, txn)?;
+        counter_helper::delete_counter(txn, col_name)?;
+        for prefix in capped_helper::key_prefixes(col_name)? {
+            DatabaseInner::delete_keys(txn, prefix)?;
+        }
+
+        if let Some(target) = self.oplog_target(col_name) {
+            oplog::append(txn, &target, oplog::OP_COMMAND, doc! { "drop": col_name }, None)?;
+        }
 
         Ok(())
     }
@@ -683,44 +1582,81 @@ impl DatabaseInner {
         Ok(())
     }
 
-    pub fn delete(&self, col_name: &str, query: Document, is_many: bool, txn: &TransactionInner) -> Result<usize> {
-        DatabaseInner::validate_col_name(col_name)?;
+    fn delete(&self, col_name: &str, query: Document, is_many: bool, limit: Option<u64>, txn: &TransactionInner) -> Result<DeleteResult> {
         let mut txn = txn.clone();
         txn.set_auto_commit(false);
-        let result = self.internal_delete_by_query(&txn, col_name, query, is_many)?;
+        let result = self.internal_delete_by_query(&txn, col_name, query, is_many, limit)?;
         Ok(result)
     }
 
-    fn internal_delete_by_query(&self, txn: &TransactionInner, col_name: &str, query: Document, is_many: bool) -> Result<usize> {
+    fn internal_delete_by_query(
+        &self,
+        txn: &TransactionInner,
+        col_name: &str,
+        mut query: Document,
+        is_many: bool,
+        limit: Option<u64>,
+    ) -> Result<DeleteResult> {
         let col_spec = self.get_collection_meta_by_name_advanced(txn, col_name, true, &self.node_id)?;
         if col_spec.is_none() {
-            return Ok(0);
+            return Ok(DeleteResult::default());
         }
         let col_spec = col_spec.unwrap();
+        let comment = query.remove("$comment");
+        let query = self.prepare_filter(query);
+        let timer = SlowQueryTimer::start(&self.slow_query_log, "delete", col_name, &query, comment.clone());
+
+        // the failed deletes are logged too
+        let result = self.execute_delete(txn, col_name, &col_spec, &query, is_many, limit, &comment);
+        if let Some(timer) = timer {
+            timer.finish();
+        }
+
+        result
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn execute_delete(
+        &self,
+        txn: &TransactionInner,
+        col_name: &str,
+        col_spec: &CollectionSpecification,
+        query: &Document,
+        is_many: bool,
+        limit: Option<u64>,
+        comment: &Option<Bson>,
+    ) -> Result<DeleteResult> {
         let subprogram = SubProgram::compile_delete(
-            &col_spec,
+            col_spec,
             col_name,
-            Some(&query),
+            Some(query),
             true,
             is_many,
+            limit,
+            self.config.max_query_depth,
         )?;
 
-        let mut vm = VM::new(
-            txn.clone(),
-            subprogram,
-            self.metrics.clone(),
-        );
+        let mut vm = self.new_vm(txn.clone(), subprogram);
+        if let Some(target) = self.oplog_target_with_comment(col_name, comment) {
+            vm.set_oplog_target(target);
+        }
+        if col_spec.capped.is_some() {
+            vm.set_capped_collection(col_name);
+        }
         vm.execute()?;
 
-        Ok(vm.r2 as usize)
+        Ok(DeleteResult {
+            deleted_count: vm.r2 as u64,
+        })
     }
 
-    fn internal_delete_all(&self, txn: &TransactionInner, col_name: &str) -> Result<usize> {
+    fn internal_delete_all(&self, txn: &TransactionInner, col_name: &str) -> Result<DeleteResult> {
         let test_collection_spec = self.internal_get_collection_id_by_name(txn, col_name);
         let collection_spec = match test_collection_spec {
             Ok(collection_spec) => collection_spec,
-            Err(Error::CollectionNotFound(_)) => return Ok(0),
+            Err(Error::CollectionNotFound(_)) if self.config.auto_create_collections => {
+                return Ok(DeleteResult::default());
+            }
             Err(err) => return Err(err),
         };
 
@@ -731,22 +1667,25 @@ impl DatabaseInner {
             true,
         )?;
 
-        let delete_count = {
-            let mut vm = VM::new(
-                txn.clone(),
-                subprogram,
-                self.metrics.clone(),
-            );
+        let deleted_count = {
+            let mut vm = self.new_vm(txn.clone(), subprogram);
+            if let Some(target) = self.oplog_target(col_name) {
+                vm.set_oplog_target(target);
+            }
+            if collection_spec.capped.is_some() {
+                vm.set_capped_collection(col_name);
+            }
             vm.execute()?;
 
-            vm.r2 as usize
+            vm.r2 as u64
         }; // Delete content end
 
-        Ok(delete_count)
+        Ok(DeleteResult {
+            deleted_count,
+        })
     }
 
-    pub fn delete_all(&self, col_name: &str, txn: &TransactionInner) -> Result<usize> {
-        DatabaseInner::validate_col_name(col_name)?;
+    fn delete_all(&self, col_name: &str, txn: &TransactionInner) -> Result<DeleteResult> {
         let mut txn= txn.clone();
         txn.set_auto_commit(false);
         let result = self.internal_delete_all(&txn, col_name)?;
@@ -801,6 +1740,121 @@ impl DatabaseInner {
         Ok(count)
     }
 
+    /// The usage counts of the indexes of the collection, by index name.
+    pub(crate) fn index_stats(&self, col_name: &str, txn: &TransactionInner) -> Result<HashMap<String, u64>> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let col_spec = match self.internal_get_collection_id_by_name(txn, col_name) {
+            Ok(spec) => spec,
+            Err(Error::CollectionNotFound(_)) => return Ok(HashMap::new()),
+            Err(err) => return Err(err),
+        };
+
+        let result = col_spec.indexes.keys()
+            .map(|index_name| (index_name.clone(), self.index_stats.usage_count(col_name, index_name)))
+            .collect();
+        Ok(result)
+    }
+
+    /// Scan the documents and the index entries of the collection to measure them.
+    pub(crate) fn collection_stats(&self, col_name: &str, txn: &TransactionInner) -> Result<CollectionStats> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let col_spec = match self.internal_get_collection_id_by_name(txn, col_name) {
+            Ok(spec) => spec,
+            Err(Error::CollectionNotFound(_)) => return Ok(CollectionStats::default()),
+            Err(err) => return Err(err),
+        };
+
+        let doc_prefix = crate::utils::bson::stacked_key([
+            &Bson::String(col_name.to_string()),
+        ])?;
+        let (count, size) = DatabaseInner::measure_prefix(doc_prefix, false, txn)?;
+
+        let mut index_sizes = HashMap::new();
+        let mut total_index_size = 0;
+        for index_name in col_spec.indexes.keys() {
+            let index_prefix = crate::utils::bson::stacked_key([
+                &Bson::String(INDEX_PREFIX.to_string()),
+                &Bson::String(col_name.to_string()),
+                &Bson::String(index_name.clone()),
+            ])?;
+            let (_, index_size) = DatabaseInner::measure_prefix(index_prefix, true, txn)?;
+            total_index_size += index_size;
+            index_sizes.insert(index_name.clone(), index_size);
+        }
+
+        Ok(CollectionStats {
+            count,
+            size,
+            avg_obj_size: if count == 0 { 0 } else { size / count },
+            nindexes: col_spec.indexes.len() as u64,
+            total_index_size,
+            index_sizes,
+        })
+    }
+
+    /// Take the first `sample_size` documents in the order of `_id`
+    /// and count the types observed for every field path.
+    pub(crate) fn infer_schema(&self, col_name: &str, sample_size: u64, txn: &TransactionInner) -> Result<InferredSchema> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let col = match self.get_collection_meta_by_name_advanced_auto(col_name, false, txn) {
+            Ok(Some(col)) => col,
+            Ok(None) => return Ok(InferredSchema::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut schema = InferredSchema::default();
+        let mut handle = self.find_internal::<Document>(&col, None, txn.clone())?;
+
+        while schema.sample_count < sample_size && handle.advance()? {
+            let doc: Document = handle.deserialize_current()?;
+            DatabaseInner::collect_field_types(&mut schema.fields, None, &doc);
+            schema.sample_count += 1;
+        }
+
+        Ok(schema)
+    }
+
+    fn collect_field_types(fields: &mut BTreeMap<String, FieldTypes>, prefix: Option<&str>, doc: &Document) {
+        for (key, value) in doc {
+            let path = match prefix {
+                Some(prefix) => format!("{}.{}", prefix, key),
+                None => key.clone(),
+            };
+            let field = fields.entry(path.clone()).or_default();
+            field.count += 1;
+            *field.types.entry(validator::type_alias_of_value(value).to_string()).or_insert(0) += 1;
+
+            if let Bson::Document(sub_doc) = value {
+                DatabaseInner::collect_field_types(fields, Some(&path), sub_doc);
+            }
+        }
+    }
+
+    /// Return the count of the entries under the prefix and the total length of their values,
+    /// the keys are counted too if `with_keys` is true.
+    fn measure_prefix(prefix: Vec<u8>, with_keys: bool, txn: &TransactionInner) -> Result<(u64, u64)> {
+        let mut count = 0;
+        let mut size = 0;
+
+        let mut cursor = Cursor::new(prefix, txn.rocksdb_txn.new_iterator());
+        cursor.reset()?;
+        while cursor.has_next() {
+            count += 1;
+            size += cursor.copy_data()?.len() as u64;
+            if with_keys {
+                if let Some(key) = cursor.peek_key() {
+                    size += key.len() as u64;
+                }
+            }
+            cursor.next()?;
+        }
+
+        Ok((count, size))
+    }
+
     pub(crate) fn list_collection_names_with_session(&self, txn: &TransactionInner) -> Result<Vec<String>> {
         let docs = self.query_all_meta(txn)?;
         Ok(collection_metas_to_names(docs))
@@ -834,7 +1888,15 @@ impl DatabaseInner {
         txn: TransactionInner,
     ) -> Result<ClientCursor<T>> {
         DatabaseInner::validate_col_name(col_name)?;
-        let filter_query = filter.into();
+        let mut filter_query: Option<Document> = filter.into();
+        let comment = filter_query.as_mut().and_then(|query| query.remove("$comment"));
+        let timer = SlowQueryTimer::start(
+            &self.slow_query_log,
+            "find",
+            col_name,
+            filter_query.as_ref().unwrap_or(&Document::new()),
+            comment,
+        );
         let meta_opt = self.get_collection_meta_by_name_advanced_auto(
             col_name,
             false,
@@ -843,36 +1905,48 @@ impl DatabaseInner {
         let subprogram = match meta_opt {
             Some(col_spec) => {
                 match filter_query {
-                    Some(query) => SubProgram::compile_query(
-                        &col_spec,
-                        &query,
-                        true
-                    ),
+                    Some(query) => self.compile_query_cached(&col_spec, &query),
                     None => SubProgram::compile_query_all(&col_spec, true),
                 }?
             }
             None => SubProgram::compile_empty_query(),
         };
 
-        let vm = VM::new(
-            txn,
-            subprogram,
-            self.metrics.clone(),
-        );
+        let vm = self.new_vm(txn, subprogram);
+
+        let mut handle = ClientCursor::new(vm, self.config.cursor_batch_size as usize);
+        if let Some(timer) = timer {
+            handle.set_slow_query_timer(timer);
+        }
+
+        Ok(handle)
+    }
+
+    /// Run a find with a skip, a limit, a sort or a projection as an aggregation,
+    /// the filter becomes the leading `$match` stage followed by the `stages`.
+    pub(crate) fn find_pipeline_with_owned_session<T: DeserializeOwned + Send + Sync>(
+        &self,
+        col_name: &str,
+        mut filter: Document,
+        stages: Vec<Document>,
+        options: AggregateOptions,
+        txn: TransactionInner,
+    ) -> Result<ClientCursor<T>> {
+        let comment = filter.remove("$comment");
+        let timer = SlowQueryTimer::start(&self.slow_query_log, "find", col_name, &filter, comment);
+        let pipeline = std::iter::once(doc! { "$match": filter }).chain(stages);
 
-        let handle = ClientCursor::new(vm);
+        let mut handle = self.aggregate_with_owned_session(col_name, pipeline, options, txn)?;
+        if let Some(timer) = timer {
+            handle.set_slow_query_timer(timer);
+        }
 
         Ok(handle)
     }
 
     pub(crate) fn count_documents(&self, col_name: &str, txn: &TransactionInner) -> Result<u64> {
         DatabaseInner::validate_col_name(col_name)?;
-        let test_result = self.count(col_name, txn);
-        match test_result {
-            Ok(result) => Ok(result),
-            Err(Error::CollectionNotFound(_)) => Ok(0),
-            Err(err) => Err(err),
-        }
+        self.count(col_name, txn)
     }
 
     pub(crate) fn delete_one(
@@ -882,41 +1956,112 @@ impl DatabaseInner {
         txn: &TransactionInner,
     ) -> Result<DeleteResult> {
         DatabaseInner::validate_col_name(col_name)?;
+        self.delete(col_name, query, false, None, txn)
+    }
 
-        let test_count = self.delete(
-            col_name,
-            query,
-            false,
-            txn,
-        );
+    /// Iterate the stored bytes of all the documents of the collection,
+    /// the transaction is kept by the iterator.
+    pub(crate) fn scan_raw(&self, col_name: &str, txn: TransactionInner) -> Result<RawScan> {
+        DatabaseInner::validate_col_name(col_name)?;
+        RawScan::new(txn, col_name)
+    }
 
-        match test_count {
-            Ok(count) => Ok(DeleteResult {
-                deleted_count: count as u64,
-            }),
-            Err(Error::CollectionNotFound(_)) => Ok(DeleteResult {
-                deleted_count: 0,
-            }),
-            Err(err) => Err(err),
+    /// Get the documents by their `_id`s with the primary key lookups,
+    /// `None` for the missing ones. The results are in the order of `ids`.
+    pub(crate) fn find_by_ids(&self, col_name: &str, ids: &[Bson], txn: &TransactionInner) -> Result<Vec<Option<Document>>> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let col_spec = match self.get_collection_meta_by_name_advanced_auto(col_name, false, txn) {
+            Ok(Some(col_spec)) => col_spec,
+            Ok(None) => return Ok(vec![None; ids.len()]),
+            Err(err) => return Err(err),
+        };
+        let b_col_id = Bson::String(col_spec._id.clone());
+
+        let mut result = Vec::with_capacity(ids.len());
+        for id in ids {
+            let mut doc = None;
+            // the numbers are equal by value, whatever type the primary key is stored with
+            let candidates = std::iter::once(id.clone())
+                .chain(crate::utils::bson::numeric_equivalents(id));
+            for candidate in candidates {
+                let key = crate::utils::bson::stacked_key([&b_col_id, &candidate])?;
+                if let Some(buf) = txn.get(key.as_slice())? {
+                    doc = Some(bson::from_slice::<Document>(&buf)?);
+                    break;
+                }
+            }
+            result.push(doc);
+        }
+
+        Ok(result)
+    }
+
+    /// Delete the documents by their `_id`s with the primary key lookups,
+    /// the missing ones are skipped.
+    pub(crate) fn delete_by_ids(&self, col_name: &str, ids: &[Bson], txn: &TransactionInner) -> Result<DeleteResult> {
+        DatabaseInner::validate_col_name(col_name)?;
+
+        let col_spec = match self.get_collection_meta_by_name_advanced_auto(col_name, false, txn) {
+            Ok(Some(col_spec)) => col_spec,
+            Ok(None) => return Ok(DeleteResult::default()),
+            Err(err) => return Err(err),
+        };
+        let b_col_id = Bson::String(col_spec._id.clone());
+        let oplog_target = self.oplog_target(col_name);
+
+        let mut result = DeleteResult::default();
+        for id in ids {
+            // the numbers are equal by value, whatever type the primary key is stored with
+            let candidates = std::iter::once(id.clone())
+                .chain(crate::utils::bson::numeric_equivalents(id));
+            for pkey in candidates {
+                let key = crate::utils::bson::stacked_key([&b_col_id, &pkey])?;
+                // locked, so the index entries removed are the ones of the stored document
+                let buf = match txn.get_for_update(key.as_slice())? {
+                    Some(buf) => buf,
+                    None => continue,
+                };
+                let doc = bson::from_slice::<Document>(&buf)?;
+
+                let mut index_helper = IndexHelper::new(
+                    txn,
+                    &col_spec,
+                    &doc,
+                    &pkey,
+                    IndexKeyLimit::from_config(&self.config),
+                );
+                index_helper.execute(IndexHelperOperation::Delete)?;
+                txn.delete(key.as_slice())?;
+                if col_spec.capped.is_some() {
+                    capped_helper::remove(txn, col_name, &pkey)?;
+                }
+
+                if let Some(target) = &oplog_target {
+                    oplog::append(txn, target, oplog::OP_DELETE, doc! { "_id": pkey.clone() }, None)?;
+                }
+                result.deleted_count += 1;
+                break;
+            }
         }
+
+        Ok(result)
     }
 
-    pub(crate) fn delete_many(&self, col_name: &str, query: Document, txn: &TransactionInner) -> Result<DeleteResult> {
+    pub(crate) fn delete_many(
+        &self,
+        col_name: &str,
+        query: Document,
+        options: DeleteOptions,
+        txn: &TransactionInner,
+    ) -> Result<DeleteResult> {
         DatabaseInner::validate_col_name(col_name)?;
 
-        let test_deleted_count = if query.is_empty() {
+        let limit = options.get_limit();
+        if query.is_empty() && limit.is_none() {
             self.delete_all(col_name, txn)
         } else {
-            self.delete(col_name, query, true, txn)
-        };
-        match test_deleted_count {
-            Ok(deleted_count) => Ok(DeleteResult {
-                deleted_count: deleted_count as u64,
-            }),
-            Err(Error::CollectionNotFound(_)) => Ok(DeleteResult {
-                deleted_count: 0
-            }),
-            Err(err) => Err(err),
+            self.delete(col_name, query, true, limit, txn)
         }
     }
 
@@ -924,34 +2069,61 @@ impl DatabaseInner {
         &self,
         col_name: &str,
         pipeline: impl IntoIterator<Item = Document>,
+        options: AggregateOptions,
         txn: TransactionInner,
     ) -> Result<ClientCursor<T>> {
         DatabaseInner::validate_col_name(col_name)?;
         let meta_opt = self.get_collection_meta_by_name_advanced_auto(col_name, false, &txn)?;
+        let compile_options = AggregateCompileOptions {
+            max_query_depth: self.config.max_query_depth,
+            sort_spill_threshold: if options.allow_disk_use {
+                Some(self.config.sort_spill_threshold)
+            } else {
+                None
+            },
+            group_spill_threshold: if options.allow_disk_use {
+                Some(self.config.group_spill_threshold)
+            } else {
+                None
+            },
+            natural_reverse: options.natural_reverse,
+            random_seed: self.next_random_seed(),
+        };
+        let pipeline = pipeline.into_iter().map(|stage| self.prepare_match_stage(stage));
         let subprogram = match meta_opt {
             Some(col_spec) => {
                 SubProgram::compile_aggregate(
                     &col_spec,
                     pipeline,
-                    true
+                    true,
+                    &compile_options,
                 )?
             }
             None => SubProgram::compile_empty_query(),
         };
 
-        let vm = VM::new(
-            txn,
-            subprogram,
-            self.metrics.clone(),
-        );
+        let vm = self.new_vm(txn, subprogram);
 
-        let handle = ClientCursor::new(vm);
+        let handle = ClientCursor::new(vm, self.config.cursor_batch_size as usize);
 
         Ok(handle)
     }
 
 }
 
+impl Drop for DatabaseInner {
+
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_write_buffer() {
+            crate::polo_log!("failed to flush the write buffer: {}", err);
+        }
+        if let Err(err) = self.take_write_buffer_errors() {
+            crate::polo_log!("failed to write the buffered documents: {}", err);
+        }
+    }
+
+}
+
 fn collection_metas_to_names(doc_meta: Vec<Document>) -> Vec<String> {
     doc_meta
         .iter()
@@ -964,8 +2136,12 @@ fn collection_metas_to_names(doc_meta: Vec<Document>) -> Vec<String> {
 
 #[cfg(test)]
 mod tests {
-    use crate::db::db_inner::DatabaseInner;
-    use bson::Bson;
+    use crate::db::db_inner::{DatabaseInner, FORMAT_VERSION, FORMAT_VERSION_KEY};
+    use crate::db::rocksdb_wrapper::RocksDBWrapper;
+    use crate::options::OpenOptions;
+    use crate::index::IndexModel;
+    use crate::{Config, Error};
+    use bson::{doc, Bson, Document};
 
     #[test]
     fn test_validate_col_name() {
@@ -996,4 +2172,77 @@ mod tests {
         assert!(!DatabaseInner::is_num_1(&Bson::String("a".to_string())))
     }
 
+    #[test]
+    fn test_rebuild_indexes() {
+        let path = std::env::temp_dir().join("test-rebuild-indexes");
+        let _ = std::fs::remove_dir_all(&path);
+        let db = DatabaseInner::open_file(&path, Config::default(), &OpenOptions::default()).unwrap();
+        let metrics = db.metrics();
+        metrics.enable();
+
+        db.run_in_auto_transaction(|txn| {
+            db.create_index("test", IndexModel {
+                keys: doc! { "age": 1 },
+                options: None,
+            }, txn)?;
+            for i in 0..10 {
+                db.insert_one("test", doc! { "age": i }, txn)?;
+            }
+            Ok(())
+        }).unwrap();
+
+        let find_by_age = |age: i32| -> usize {
+            let txn = db.start_transaction().unwrap();
+            let cursor = db.find_with_owned_session::<Document>("test", doc! { "age": age }, txn).unwrap();
+            cursor.count()
+        };
+        assert_eq!(find_by_age(3), 1);
+
+        // lose the index entries
+        db.run_in_auto_transaction(|txn| db.delete_index_entries("test", txn)).unwrap();
+        assert_eq!(find_by_age(3), 0);
+
+        db.run_in_auto_transaction(|txn| db.rebuild_indexes("test", txn)).unwrap();
+
+        metrics.reset();
+        for i in 0..10 {
+            assert_eq!(find_by_age(i), 1);
+        }
+        assert_eq!(metrics.find_by_index_count(), 10);
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_format_version_mismatch() {
+        let path = std::env::temp_dir().join("test-format-version-mismatch");
+        let _ = std::fs::remove_dir_all(&path);
+        let open_options = OpenOptions::default();
+
+        // a new database is stamped with the current version
+        DatabaseInner::open_file(&path, Config::default(), &open_options).unwrap();
+
+        // simulate a file written by a newer version
+        {
+            let rocksdb = RocksDBWrapper::open_with_options(&path, &open_options).unwrap();
+            let txn = rocksdb.begin_transaction().unwrap();
+            let mut newer_version = FORMAT_VERSION;
+            newer_version[3] += 1;
+            txn.set(FORMAT_VERSION_KEY, &newer_version).unwrap();
+            txn.commit().unwrap();
+        }
+
+        let result = DatabaseInner::open_file(&path, Config::default(), &open_options);
+        match result {
+            Err(Error::VersionMismatch(err)) => {
+                assert_eq!(err.expect_version, FORMAT_VERSION);
+                assert_eq!(err.actual_version[3], FORMAT_VERSION[3] + 1);
+            }
+            _ => panic!("the format version is not checked"),
+        }
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
 }
@@ -0,0 +1,71 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::db::RocksDBIterator;
+use crate::transaction::TransactionInner;
+use crate::Result;
+
+/// An iterator over the raw key/value pairs stored in the database,
+/// returned by [`Database::debug_scan`](crate::Database::debug_scan).
+///
+/// The keys are encoded as the stacked keys of PoloDB, e.g. the key of a document
+/// is the name of the collection followed by its `_id`, the values are the stored bytes.
+pub struct DebugScan {
+    // dropped before the transaction owning it
+    iter: RocksDBIterator,
+    _txn: TransactionInner,
+    prefix: Vec<u8>,
+    started: bool,
+}
+
+impl DebugScan {
+
+    pub(crate) fn new(txn: TransactionInner, prefix: &[u8]) -> DebugScan {
+        let iter = txn.rocksdb_txn.new_iterator();
+        DebugScan {
+            iter,
+            _txn: txn,
+            prefix: prefix.to_vec(),
+            started: false,
+        }
+    }
+
+}
+
+impl Iterator for DebugScan {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.started {
+            self.iter.next();
+        } else {
+            self.iter.seek(self.prefix.as_slice());
+            self.started = true;
+        }
+
+        if !self.iter.valid() {
+            return None;
+        }
+
+        let key = match self.iter.copy_key() {
+            Ok(key) => key,
+            Err(err) => return Some(Err(err)),
+        };
+        if !key.starts_with(self.prefix.as_slice()) {
+            return None;
+        }
+
+        Some(self.iter.copy_data().map(|value| (key, value)))
+    }
+}
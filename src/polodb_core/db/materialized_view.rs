@@ -0,0 +1,115 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::{doc, Bson, Document};
+use crate::cursor::Cursor;
+use crate::transaction::TransactionInner;
+use crate::{Error, Result};
+
+pub(crate) const VIEW_PREFIX: &str = "$VIEW";
+
+/// A saved pipeline whose results are written to the target collection
+/// when the view is refreshed.
+pub(crate) struct MaterializedView {
+    pub(crate) source: String,
+    pub(crate) pipeline: Vec<Document>,
+    pub(crate) target: String,
+}
+
+impl MaterializedView {
+
+    fn to_document(&self, name: &str) -> Document {
+        let pipeline: Vec<Bson> = self.pipeline.iter().cloned().map(Bson::Document).collect();
+        doc! {
+            "_id": name,
+            "source": self.source.as_str(),
+            "pipeline": pipeline,
+            "target": self.target.as_str(),
+        }
+    }
+
+    fn from_document(doc: &Document) -> Result<MaterializedView> {
+        let invalid = || Error::ValidationError("invalid definition of the materialized view".to_string());
+        let source = doc.get_str("source").map_err(|_| invalid())?;
+        let target = doc.get_str("target").map_err(|_| invalid())?;
+        let pipeline = doc.get_array("pipeline")
+            .map_err(|_| invalid())?
+            .iter()
+            .map(|stage| stage.as_document().cloned().ok_or_else(invalid))
+            .collect::<Result<Vec<Document>>>()?;
+        Ok(MaterializedView {
+            source: source.to_string(),
+            pipeline,
+            target: target.to_string(),
+        })
+    }
+
+}
+
+fn view_key(name: &str) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key([
+        &Bson::String(VIEW_PREFIX.to_string()),
+        &Bson::String(name.to_string()),
+    ])
+}
+
+/// Save the definition of the view, replacing the one with the same name.
+pub(crate) fn save(txn: &TransactionInner, name: &str, view: &MaterializedView) -> Result<()> {
+    let key = view_key(name)?;
+    let buf = bson::to_vec(&view.to_document(name))?;
+    txn.put(key.as_slice(), &buf)
+}
+
+pub(crate) fn load(txn: &TransactionInner, name: &str) -> Result<Option<MaterializedView>> {
+    let key = view_key(name)?;
+    match txn.get(key.as_slice())? {
+        Some(buf) => {
+            let doc = bson::from_slice::<Document>(&buf)?;
+            Ok(Some(MaterializedView::from_document(&doc)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Remove the definition of the view, return false if it doesn't exist.
+pub(crate) fn remove(txn: &TransactionInner, name: &str) -> Result<bool> {
+    let key = view_key(name)?;
+    if txn.get(key.as_slice())?.is_none() {
+        return Ok(false);
+    }
+    txn.delete(key.as_slice())?;
+    Ok(true)
+}
+
+/// Remove the definitions of all the views.
+pub(crate) fn remove_all(txn: &TransactionInner) -> Result<()> {
+    // collect the keys first, don't write when the iterator is alive
+    let mut keys = Vec::new();
+    {
+        let mut cursor = Cursor::new_with_str_prefix(VIEW_PREFIX, txn.rocksdb_txn.new_iterator())?;
+        cursor.reset()?;
+        while cursor.has_next() {
+            if let Some(key) = cursor.peek_key() {
+                keys.push(key);
+            }
+            cursor.next()?;
+        }
+    }
+
+    for key in keys {
+        txn.delete(key.as_ref())?;
+    }
+
+    Ok(())
+}
@@ -19,7 +19,25 @@ mod rocksdb_wrapper;
 mod rocksdb_transaction;
 mod rocksdb_iterator;
 mod rocksdb_options;
+mod query_cache;
+mod counter_helper;
+pub(crate) mod capped_helper;
+pub(crate) mod oplog;
+mod oplog_cursor;
+mod materialized_view;
+mod cancellation_token;
+mod raw_scan;
+mod write_buffer;
+pub(crate) mod slow_query_log;
+#[cfg(feature = "debug")]
+mod debug_scan;
 
 pub use db::{Database, Result};
+pub use cancellation_token::CancellationToken;
+pub use oplog_cursor::OplogCursor;
+pub use raw_scan::RawScan;
+pub use slow_query_log::SlowQuery;
+#[cfg(feature = "debug")]
+pub use debug_scan::DebugScan;
 pub(crate) use rocksdb_transaction::RocksDBTransaction;
 pub(crate) use rocksdb_iterator::RocksDBIterator;
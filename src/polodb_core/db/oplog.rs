@@ -0,0 +1,102 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::{doc, Bson, DateTime, Document};
+use crate::cursor::Cursor;
+use crate::db::counter_helper;
+use crate::transaction::TransactionInner;
+use crate::Result;
+
+pub(crate) const OPLOG_PREFIX: &str = "$OPLOG";
+
+pub(crate) const OP_INSERT: &str = "i";
+pub(crate) const OP_UPDATE: &str = "u";
+pub(crate) const OP_DELETE: &str = "d";
+pub(crate) const OP_COMMAND: &str = "c";
+
+/// Where the entries of the writes of a collection are appended.
+#[derive(Clone)]
+pub(crate) struct OplogTarget {
+    pub(crate) ns: String,
+    pub(crate) max_entries: u64,
+    /// The `$comment` of the filter of the write, copied to the entries
+    pub(crate) comment: Option<Bson>,
+}
+
+fn oplog_key(ts: i64) -> Result<Vec<u8>> {
+    crate::utils::bson::stacked_key([
+        &Bson::String(OPLOG_PREFIX.to_string()),
+        &Bson::Int64(ts),
+    ])
+}
+
+/// Append an entry in the transaction of the write, so it's committed
+/// or rolled back with the write.
+///
+/// The entries are numbered by `ts` from 1, the oldest entry is removed
+/// once there are more than `max_entries`. Taking `ts` locks the counter until
+/// the transaction ends, so a transaction can't commit an entry after a later one,
+/// and a rolled back `ts` is taken again by the next write.
+pub(crate) fn append(
+    txn: &TransactionInner,
+    target: &OplogTarget,
+    op: &str,
+    o: Document,
+    o2: Option<Document>,
+) -> Result<()> {
+    let ts = counter_helper::next_value(txn, OPLOG_PREFIX)?;
+
+    let mut entry = doc! {
+        "ts": ts,
+        "op": op,
+        "ns": target.ns.as_str(),
+        "wall": DateTime::now(),
+        "o": o,
+    };
+    if let Some(o2) = o2 {
+        entry.insert("o2", o2);
+    }
+    if let Some(comment) = &target.comment {
+        entry.insert("comment", comment.clone());
+    }
+
+    let key = oplog_key(ts)?;
+    let buf = bson::to_vec(&entry)?;
+    txn.put(key.as_slice(), &buf)?;
+
+    if target.max_entries > 0 && ts > target.max_entries as i64 {
+        let expired_key = oplog_key(ts - target.max_entries as i64)?;
+        txn.delete(expired_key.as_slice())?;
+    }
+
+    Ok(())
+}
+
+/// Read the entries with a `ts` greater than `since_ts` in order.
+pub(crate) fn read_since(txn: &TransactionInner, since_ts: i64) -> Result<Vec<Document>> {
+    let mut cursor = Cursor::new_with_str_prefix(OPLOG_PREFIX, txn.rocksdb_txn.new_iterator())?;
+    // the keys are ordered by the big-endian bytes of ts, which must not be negative
+    let start_ts = since_ts.max(0).saturating_add(1);
+    cursor.reset_by_pkey(&Bson::Int64(start_ts))?;
+
+    let mut result = Vec::new();
+    while cursor.has_next() {
+        let buf = cursor.copy_data()?;
+        let entry = bson::from_slice::<Document>(&buf)?;
+        result.push(entry);
+        cursor.next()?;
+    }
+
+    Ok(result)
+}
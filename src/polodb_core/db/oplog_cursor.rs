@@ -0,0 +1,111 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::sync::Weak;
+use std::time::{Duration, Instant};
+use bson::Document;
+use crate::db::db_inner::DatabaseInner;
+use crate::{Error, Result};
+
+// how long to wait before reading the log again when there is no new entry
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A cursor following the operation log, returned by
+/// [`Database::tail_oplog`](crate::Database::tail_oplog).
+///
+/// Iterating the cursor blocks until a new entry is appended,
+/// the iteration ends when the database is closed.
+///
+/// The cursor reads the entries after the last returned `ts`. A writing transaction
+/// keeps the next `ts` locked until it ends, so no entry with a lower `ts` can be
+/// committed after the cursor has passed it.
+pub struct OplogCursor {
+    db: Weak<DatabaseInner>,
+    last_ts: i64,
+    pending: VecDeque<Document>,
+}
+
+impl OplogCursor {
+
+    pub(crate) fn new(db: Weak<DatabaseInner>, since_ts: i64) -> OplogCursor {
+        OplogCursor {
+            db,
+            last_ts: since_ts,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The `ts` of the last returned entry, or the one the tailing started from.
+    pub fn last_ts(&self) -> i64 {
+        self.last_ts
+    }
+
+    /// Return the next entry if there is one, without waiting.
+    pub fn try_next(&mut self) -> Result<Option<Document>> {
+        if self.pending.is_empty() {
+            self.fetch()?;
+        }
+
+        let entry = match self.pending.pop_front() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if let Ok(ts) = entry.get_i64("ts") {
+            self.last_ts = ts;
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// Wait up to `timeout` for the next entry,
+    /// return `None` if nothing is appended in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Option<Document>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(entry) = self.try_next()? {
+                return Ok(Some(entry));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    fn fetch(&mut self) -> Result<()> {
+        let db = self.db.upgrade().ok_or(Error::DbIsClosed)?;
+        let txn = db.start_transaction()?;
+        let entries = db.read_oplog(self.last_ts, &txn)?;
+        self.pending.extend(entries);
+        Ok(())
+    }
+
+}
+
+impl Iterator for OplogCursor {
+    type Item = Result<Document>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.try_next() {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => std::thread::sleep(POLL_INTERVAL),
+                Err(Error::DbIsClosed) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
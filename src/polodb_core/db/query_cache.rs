@@ -0,0 +1,96 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Mutex;
+use bson::Document;
+use indexmap::IndexMap;
+use crate::bson_util::canonical_bytes;
+use crate::coll::collection_info::CollectionSpecification;
+use crate::vm::{PlainSubProgram, SubProgram};
+use crate::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct QueryCacheKey {
+    col_name: String,
+    uuid: Option<Vec<u8>>,
+    indexes: Vec<u8>,
+    query: Vec<u8>,
+}
+
+impl QueryCacheKey {
+
+    /// The order of the fields and the types of the equal numbers don't change
+    /// the result of a query, so the queries differing by them share the program.
+    /// The program depends on the indexes, so they are a part of the key, in their order.
+    pub(crate) fn new(col_spec: &CollectionSpecification, query: &Document) -> Result<QueryCacheKey> {
+        Ok(QueryCacheKey {
+            col_name: col_spec.name().to_string(),
+            uuid: col_spec.info.uuid.as_ref().map(|uuid| uuid.bytes.clone()),
+            indexes: bson::to_vec(&col_spec.indexes)?,
+            query: canonical_bytes(query)?,
+        })
+    }
+
+}
+
+/// A LRU cache of the compiled queries.
+pub(crate) struct QueryCache {
+    capacity: usize,
+    entries: Mutex<IndexMap<QueryCacheKey, PlainSubProgram>>,
+}
+
+impl QueryCache {
+
+    pub(crate) fn new(capacity: usize) -> QueryCache {
+        QueryCache {
+            capacity,
+            entries: Mutex::new(IndexMap::new()),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &QueryCacheKey) -> Option<SubProgram> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.get_index_of(key)?;
+
+        // the most recently used entry is at the back
+        let last = entries.len() - 1;
+        entries.move_index(index, last);
+
+        let (_, cached) = entries.get_index(last)?;
+        Some(cached.to_program())
+    }
+
+    pub(crate) fn put(&self, key: QueryCacheKey, program: &SubProgram) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let program = match program.to_plain() {
+            Some(program) => program,
+            None => return,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.shift_remove(&key);
+        while entries.len() >= self.capacity {
+            entries.shift_remove_index(0);
+        }
+        entries.insert(key, program);
+    }
+
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::RawDocumentBuf;
+use crate::cursor::Cursor;
+use crate::transaction::TransactionInner;
+use crate::{Error, Result};
+
+/// An iterator over the stored bytes of all the documents of a collection in the order of `_id`,
+/// returned by [`CollectionT::scan_raw`](crate::CollectionT::scan_raw).
+///
+/// The documents are not deserialized, which is faster when they are passed on as BSON.
+pub struct RawScan {
+    // dropped before the transaction owning it
+    cursor: Cursor,
+    _txn: TransactionInner,
+    started: bool,
+}
+
+impl RawScan {
+
+    pub(crate) fn new(txn: TransactionInner, col_name: &str) -> Result<RawScan> {
+        let iter = txn.rocksdb_txn.new_iterator();
+        let cursor = Cursor::new_with_str_prefix(col_name, iter)?;
+        Ok(RawScan {
+            cursor,
+            _txn: txn,
+            started: false,
+        })
+    }
+
+    fn next_raw(&mut self) -> Result<Option<RawDocumentBuf>> {
+        if self.started {
+            self.cursor.next()?;
+        } else {
+            self.cursor.reset()?;
+            self.started = true;
+        }
+
+        if !self.cursor.has_next() {
+            return Ok(None);
+        }
+
+        let bytes = self.cursor.copy_data()?;
+        let doc = RawDocumentBuf::from_bytes(bytes)
+            .map_err(|err| Error::ParseError(err.to_string()))?;
+        Ok(Some(doc))
+    }
+
+}
+
+impl Iterator for RawScan {
+    type Item = Result<RawDocumentBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_raw().transpose()
+    }
+}
@@ -37,6 +37,10 @@ impl RocksDBIterator {
         self.inner.seek_to_first()
     }
 
+    pub fn seek_to_last(&self) {
+        self.inner.seek_to_last()
+    }
+
     pub fn seek(&self, key: &[u8]) {
         self.inner.seek(key)
     }
@@ -49,7 +53,6 @@ impl RocksDBIterator {
         self.inner.next()
     }
 
-    #[allow(dead_code)]
     pub fn prev(&self) {
         self.inner.prev()
     }
@@ -101,6 +104,12 @@ impl RocksDBIteratorInner {
         }
     }
 
+    pub fn seek_to_last(&self) {
+        unsafe {
+            ffi::rocksdb_iter_seek_to_last(self.inner);
+        }
+    }
+
     pub fn seek(&self, key: &[u8]) {
         unsafe {
             ffi::rocksdb_iter_seek(self.inner, key.as_ptr() as *const i8, key.len());
@@ -98,6 +98,12 @@ impl RocksDBReadOptions {
         self.inner
     }
 
+    pub(crate) fn set_snapshot(&self, snapshot: *const ffi::rocksdb_snapshot_t) {
+        unsafe {
+            ffi::rocksdb_readoptions_set_snapshot(self.inner, snapshot)
+        }
+    }
+
 }
 
 impl Drop for RocksDBReadOptions {
@@ -106,6 +112,40 @@ impl Drop for RocksDBReadOptions {
     }
 }
 
+pub(crate) struct RocksDBFlushOptions {
+    inner: *mut ffi::rocksdb_flushoptions_t,
+}
+
+impl RocksDBFlushOptions {
+
+    pub(crate) fn new() -> RocksDBFlushOptions {
+        let inner = unsafe { ffi::rocksdb_flushoptions_create() };
+        assert!(!inner.is_null(), "rocksdb_flushoptions_create failed");
+        RocksDBFlushOptions { inner }
+    }
+
+    pub(crate) fn get(&self) -> *mut ffi::rocksdb_flushoptions_t {
+        self.inner
+    }
+
+    pub(crate) fn set_wait(&self, wait: bool) {
+        unsafe {
+            ffi::rocksdb_flushoptions_set_wait(self.inner, if wait {
+                1
+            } else {
+                0
+            })
+        }
+    }
+
+}
+
+impl Drop for RocksDBFlushOptions {
+    fn drop(&mut self) {
+        unsafe { ffi::rocksdb_flushoptions_destroy(self.inner) }
+    }
+}
+
 pub(crate) struct RocksDBTransactionOptions {
     inner: *mut ffi::rocksdb_transaction_options_t,
 }
@@ -122,6 +162,16 @@ impl RocksDBTransactionOptions {
         self.inner
     }
 
+    pub(crate) fn set_set_snapshot(&self, set_snapshot: bool) {
+        unsafe {
+            ffi::rocksdb_transaction_options_set_set_snapshot(self.inner, if set_snapshot {
+                1
+            } else {
+                0
+            })
+        }
+    }
+
 }
 
 impl Drop for RocksDBTransactionOptions {
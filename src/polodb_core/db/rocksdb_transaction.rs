@@ -32,6 +32,12 @@ macro_rules! check_err {
             // Convert the &CStr to a &str
             let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
 
+            // Lock conflicts with other transactions are transient,
+            // report them as busy so the caller is able to retry.
+            if str_slice.starts_with("Resource busy") || str_slice.starts_with("Operation timed out") {
+                return Err(crate::Error::Busy)
+            }
+
             // Convert the &str to a String and return
             return Err(crate::Error::RocksDbErr(str_slice.to_owned()))
         }
@@ -45,8 +51,8 @@ pub(crate) struct RocksDBTransaction {
 
 impl RocksDBTransaction {
 
-    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner) -> Result<RocksDBTransaction>  {
-        let inner = RocksDBTransactionInner::new(db_inner)?;
+    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner, snapshot: bool) -> Result<RocksDBTransaction>  {
+        let inner = RocksDBTransactionInner::new(db_inner, snapshot)?;
         Ok(RocksDBTransaction {
             inner: Arc::new(Mutex::new(inner)),
         })
@@ -62,12 +68,16 @@ impl RocksDBTransaction {
         inner.set(key, value)
     }
 
-    #[allow(dead_code)]
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let inner = self.inner.lock().unwrap();
         inner.get(key)
     }
 
+    pub fn get_for_update(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let inner = self.inner.lock().unwrap();
+        inner.get_for_update(key)
+    }
+
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         let inner = self.inner.lock().unwrap();
         inner.delete(key)
@@ -78,6 +88,16 @@ impl RocksDBTransaction {
         inner.rollback()
     }
 
+    pub fn set_savepoint(&self) {
+        let inner = self.inner.lock().unwrap();
+        inner.set_savepoint()
+    }
+
+    pub fn rollback_to_savepoint(&self) -> Result<()> {
+        let inner = self.inner.lock().unwrap();
+        inner.rollback_to_savepoint()
+    }
+
     pub fn commit(&self) -> Result<()> {
         let inner = self.inner.lock().unwrap();
         inner.commit()
@@ -99,12 +119,15 @@ unsafe impl Sync for RocksDBTransactionInner {}
 
 impl RocksDBTransactionInner {
 
-    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner) -> Result<RocksDBTransactionInner>  {
+    /// When `snapshot` is true, all the reads of the transaction see the
+    /// database at the point in time when the transaction begins.
+    pub(crate) fn new(db_inner: *mut RocksDBWrapperInner, snapshot: bool) -> Result<RocksDBTransactionInner>  {
         unsafe {
             let read_options = RocksDBReadOptions::new();
             let write_options = RocksDBWriteOptions::new();
-            write_options.set_sync(true);
+            write_options.set_sync((*db_inner).sync_on_commit());
             let txn_options = RocksDBTransactionOptions::new();
+            txn_options.set_set_snapshot(snapshot);
             _ = (*db_inner).txn_count.fetch_add(1, Ordering::SeqCst);
             let inner = ffi::rocksdb_transaction_begin(
                 (*db_inner).inner,
@@ -112,6 +135,10 @@ impl RocksDBTransactionInner {
                 txn_options.get(),
                 null_mut(),
             );
+            if snapshot {
+                // the snapshot is owned by the transaction
+                read_options.set_snapshot(ffi::rocksdb_transaction_get_snapshot(inner));
+            }
 
             Ok(RocksDBTransactionInner {
                 read_options,
@@ -126,6 +153,10 @@ impl RocksDBTransactionInner {
 
     pub fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
         unsafe {
+            if (*self.db_inner).read_only {
+                return Err(crate::Error::ReadOnly);
+            }
+
             let mut err: *mut c_char = ptr::null_mut();
 
             ffi::rocksdb_transaction_put(
@@ -166,8 +197,43 @@ impl RocksDBTransactionInner {
         }
     }
 
+    /// Read the key and lock it until the transaction ends.
+    ///
+    /// [`crate::Error::Busy`] is returned when the key is locked by another transaction
+    /// for too long, or, with a snapshot, when it's written by another transaction
+    /// after the snapshot.
+    pub fn get_for_update(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        unsafe {
+            let mut err: *mut c_char = ptr::null_mut();
+            let mut value_len: usize = 0;
+            let value = ffi::rocksdb_transaction_get_for_update(
+                self.inner,
+                self.read_options.get(),
+                key.as_ptr() as *const i8,
+                key.len(),
+                &mut value_len,
+                1,
+                &mut err,
+            );
+
+            check_err!(err);
+
+            if value.is_null() {
+                return Ok(None);
+            }
+
+            let result = std::slice::from_raw_parts(value as *const u8, value_len).to_vec();
+            ffi::rocksdb_free(value as *mut libc::c_void);
+            Ok(Some(result))
+        }
+    }
+
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         unsafe {
+            if (*self.db_inner).read_only {
+                return Err(crate::Error::ReadOnly);
+            }
+
             let mut err: *mut c_char = ptr::null_mut();
 
             ffi::rocksdb_transaction_delete(
@@ -192,6 +258,23 @@ impl RocksDBTransactionInner {
         }
     }
 
+    pub fn set_savepoint(&self) {
+        unsafe {
+            ffi::rocksdb_transaction_set_savepoint(self.inner);
+        }
+    }
+
+    /// Discard the writes since the last savepoint, which is removed.
+    pub fn rollback_to_savepoint(&self) -> Result<()> {
+        unsafe {
+            let mut err: *mut c_char = ptr::null_mut();
+            ffi::rocksdb_transaction_rollback_to_savepoint(self.inner, &mut err);
+
+            check_err!(err);
+            Ok(())
+        }
+    }
+
     pub(crate) fn commit(&self) -> Result<()> {
         unsafe {
             let mut err: *mut c_char = ptr::null_mut();
@@ -199,7 +282,8 @@ impl RocksDBTransactionInner {
             ffi::rocksdb_transaction_commit(self.inner, &mut err);
 
             check_err!(err);
-            Ok(())
+
+            self.db_inner.as_ref().unwrap().sync_after_commit()
         }
     }
 
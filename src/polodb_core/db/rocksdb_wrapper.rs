@@ -21,8 +21,12 @@ use polodb_librocksdb_sys as ffi;
 use super::db::Result;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use crate::db::rocksdb_options::RocksDBWaitForCompactOptions;
+use std::time::{Duration, Instant};
+use crate::db::rocksdb_options::{RocksDBFlushOptions, RocksDBWaitForCompactOptions};
 use crate::db::rocksdb_transaction::RocksDBTransaction;
+use crate::metrics::Metrics;
+use crate::options::OpenOptions;
+use crate::SyncPolicy;
 
 macro_rules! check_err {
     ($err:expr) => {
@@ -45,16 +49,66 @@ pub(crate) struct RocksDBWrapper {
 
 impl RocksDBWrapper {
 
+    #[allow(dead_code)]
     pub fn open(path: &Path) -> Result<RocksDBWrapper> {
-        let inner = RocksDBWrapperInner::open(path)?;
+        RocksDBWrapper::open_with_options(path, &OpenOptions::default())
+    }
+
+    pub fn open_with_options(path: &Path, open_options: &OpenOptions) -> Result<RocksDBWrapper> {
+        let inner = RocksDBWrapperInner::open(path, open_options)?;
         Ok(RocksDBWrapper {
             inner: Arc::new(Mutex::new(inner)),
         })
     }
 
+    pub fn set_sync_on_drop(&self, sync_on_drop: bool) -> Result<()> {
+        let mut db_inner = self.inner.lock()?;
+        db_inner.sync_on_drop = sync_on_drop;
+        Ok(())
+    }
+
+    /// Set when the log is synced at the commits, the syncs are counted in `metrics`.
+    pub fn set_sync_policy(&self, sync_policy: SyncPolicy, metrics: Metrics) -> Result<()> {
+        let mut db_inner = self.inner.lock()?;
+        db_inner.sync_policy = sync_policy;
+        db_inner.metrics = Some(metrics);
+        Ok(())
+    }
+
     pub fn begin_transaction(&self) -> Result<RocksDBTransaction> {
         let mut db_inner = self.inner.lock()?;
-        RocksDBTransaction::new(db_inner.deref_mut() as *mut _)
+        RocksDBTransaction::new(db_inner.deref_mut() as *mut _, false)
+    }
+
+    pub fn begin_snapshot_transaction(&self) -> Result<RocksDBTransaction> {
+        let mut db_inner = self.inner.lock()?;
+        RocksDBTransaction::new(db_inner.deref_mut() as *mut _, true)
+    }
+
+    /// Flush the memtables into SST files and wait until it's finished.
+    pub fn flush(&self) -> Result<()> {
+        let db_inner = self.inner.lock()?;
+        unsafe {
+            let mut err: *mut c_char = ptr::null_mut();
+            let flush_options = RocksDBFlushOptions::new();
+            flush_options.set_wait(true);
+            ffi::rocksdb_transactiondb_flush(db_inner.inner, flush_options.get(), &mut err);
+            check_err!(err);
+            Ok(())
+        }
+    }
+
+    pub fn property_int(&self, name: &str) -> Result<u64> {
+        let db_inner = self.inner.lock()?;
+        let name_c = CString::new(name).unwrap();
+        let mut value: u64 = 0;
+        let ret = unsafe {
+            ffi::rocksdb_transactiondb_property_int(db_inner.inner, name_c.as_ptr(), &mut value)
+        };
+        if ret != 0 {
+            return Err(crate::Error::RocksDbErr(format!("failed to read property: {}", name)));
+        }
+        Ok(value)
     }
 
 }
@@ -66,6 +120,18 @@ pub(crate) struct RocksDBWrapperInner {
     pub(crate) txn_db_options: *mut ffi::rocksdb_transactiondb_options_t,
     pub(crate) inner: *mut ffi::rocksdb_transactiondb_t,
     pub(crate) txn_count: AtomicU64,
+    pub(crate) read_only: bool,
+    // fsync the log when the database is dropped
+    pub(crate) sync_on_drop: bool,
+    sync_policy: SyncPolicy,
+    // the commits since the log is synced last time
+    sync_state: Mutex<SyncState>,
+    metrics: Option<Metrics>,
+}
+
+struct SyncState {
+    pending_commits: u64,
+    last_sync: Instant,
 }
 
 unsafe impl Send for RocksDBWrapperInner {}
@@ -73,12 +139,14 @@ unsafe impl Sync for RocksDBWrapperInner {}
 
 impl RocksDBWrapperInner {
 
-    pub fn open(path: &Path) -> Result<RocksDBWrapperInner> {
+    pub fn open(path: &Path, open_options: &OpenOptions) -> Result<RocksDBWrapperInner> {
         let path: String = path.to_str().unwrap().into();
+        let create_if_missing = (open_options.create || open_options.create_new) && !open_options.read_only;
         unsafe {
             let txn_db_opts = ffi::rocksdb_transactiondb_options_create();
             let options = ffi::rocksdb_options_create();
-            ffi::rocksdb_options_set_create_if_missing(options, 1);
+            ffi::rocksdb_options_set_create_if_missing(options, if create_if_missing { 1 } else { 0 });
+            ffi::rocksdb_options_set_error_if_exists(options, if open_options.create_new { 1 } else { 0 });
             let mut err: *mut c_char = ptr::null_mut();
             let path_c = CString::new(path.clone()).unwrap();
             let db = ffi::rocksdb_transactiondb_open(options, txn_db_opts, path_c.as_ptr(), &mut err);
@@ -89,10 +157,55 @@ impl RocksDBWrapperInner {
                 txn_db_options: txn_db_opts,
                 inner: db,
                 txn_count: AtomicU64::new(0),
+                read_only: open_options.read_only,
+                sync_on_drop: true,
+                sync_policy: SyncPolicy::EveryCommit,
+                sync_state: Mutex::new(SyncState {
+                    pending_commits: 0,
+                    last_sync: Instant::now(),
+                }),
+                metrics: None,
             })
         }
     }
 
+    /// Whether the transactions sync the log when they are committed,
+    /// set on their write options.
+    #[inline]
+    pub(crate) fn sync_on_commit(&self) -> bool {
+        self.sync_policy == SyncPolicy::EveryCommit
+    }
+
+    /// Sync the log after a transaction is committed, if it's due by the sync policy.
+    pub(crate) fn sync_after_commit(&self) -> Result<()> {
+        match self.sync_policy {
+            SyncPolicy::Never => return Ok(()),
+            // the commit is synced by the write options of the transaction
+            SyncPolicy::EveryCommit => (),
+            SyncPolicy::Deferred { commits, interval_ms } => {
+                let mut state = self.sync_state.lock()?;
+                state.pending_commits += 1;
+                let due_by_count = commits > 0 && state.pending_commits >= commits;
+                let due_by_time = interval_ms > 0 && state.last_sync.elapsed() >= Duration::from_millis(interval_ms);
+                if !due_by_count && !due_by_time {
+                    return Ok(());
+                }
+                state.pending_commits = 0;
+                state.last_sync = Instant::now();
+
+                unsafe {
+                    let mut err: *mut c_char = ptr::null_mut();
+                    ffi::rocksdb_transactiondb_flush_wal(self.inner, 1, &mut err);
+                    check_err!(err);
+                }
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.add_sync_count();
+        }
+        Ok(())
+    }
+
 }
 
 impl Drop for RocksDBWrapperInner {
@@ -114,7 +227,8 @@ impl Drop for RocksDBWrapperInner {
                 }
             }
 
-            ffi::rocksdb_transactiondb_flush_wal(self.inner, 1, &mut err);
+            let sync = if self.sync_on_drop { 1 } else { 0 };
+            ffi::rocksdb_transactiondb_flush_wal(self.inner, sync, &mut err);
             if !err.is_null() {
                 let c_str = std::ffi::CStr::from_ptr(err);
                 let str_slice = c_str.to_str().expect("C string is not valid UTF-8");
@@ -0,0 +1,128 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use bson::{Bson, Document};
+
+/// How many of the latest slow queries are kept.
+const SLOW_QUERY_LOG_CAPACITY: usize = 100;
+
+/// An operation which ran at least [`Config::slow_query_ms`](crate::Config::slow_query_ms).
+#[derive(Debug, Clone)]
+pub struct SlowQuery {
+    /// `"find"`, `"update"` or `"delete"`
+    pub op: &'static str,
+    /// The name of the collection
+    pub ns: String,
+    /// The filter of the operation, without the `$comment`
+    pub filter: Document,
+    /// The `$comment` of the filter
+    pub comment: Option<Bson>,
+    /// How long the operation ran
+    pub duration: Duration,
+}
+
+/// Keeps the latest slow queries in memory since the database was opened.
+#[derive(Clone)]
+pub(crate) struct SlowQueryLog {
+    // `None` when the log is disabled
+    threshold: Option<Duration>,
+    entries: Arc<Mutex<VecDeque<SlowQuery>>>,
+}
+
+impl SlowQueryLog {
+
+    pub fn new(enabled: bool, slow_query_ms: u64) -> SlowQueryLog {
+        SlowQueryLog {
+            threshold: if enabled { Some(Duration::from_millis(slow_query_ms)) } else { None },
+            entries: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.threshold.is_some()
+    }
+
+    pub fn record(&self, query: SlowQuery) {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        if query.duration < threshold {
+            return;
+        }
+        crate::polo_log!("slow query: {} {} {} {:?} {}ms", query.op, query.ns, query.filter, query.comment, query.duration.as_millis());
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= SLOW_QUERY_LOG_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(query);
+    }
+
+    pub fn entries(&self) -> Vec<SlowQuery> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().cloned().collect()
+    }
+
+}
+
+/// Times a query from its start, the query is recorded by [`SlowQueryTimer::finish`].
+pub(crate) struct SlowQueryTimer {
+    log: SlowQueryLog,
+    op: &'static str,
+    ns: String,
+    filter: Document,
+    comment: Option<Bson>,
+    start: Instant,
+}
+
+impl SlowQueryTimer {
+
+    /// Returns `None` when the log is disabled.
+    pub fn start(
+        log: &SlowQueryLog,
+        op: &'static str,
+        ns: &str,
+        filter: &Document,
+        comment: Option<Bson>,
+    ) -> Option<SlowQueryTimer> {
+        if !log.is_enabled() {
+            return None;
+        }
+        Some(SlowQueryTimer {
+            log: log.clone(),
+            op,
+            ns: ns.to_string(),
+            filter: filter.clone(),
+            comment,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn finish(self) {
+        let duration = self.start.elapsed();
+        self.log.record(SlowQuery {
+            op: self.op,
+            ns: self.ns,
+            filter: self.filter,
+            comment: self.comment,
+            duration,
+        });
+    }
+
+}
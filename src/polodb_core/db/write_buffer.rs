@@ -0,0 +1,139 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Mutex;
+use bson::Document;
+use indexmap::IndexMap;
+use crate::{Error, Result};
+
+/// Holds the documents inserted in the buffered mode until they are written in a batch.
+///
+/// The documents of every collection are kept in insertion order,
+/// and the collections are flushed in the order they were first written.
+pub(crate) struct WriteBuffer {
+    capacity: usize,
+    pending:  Mutex<IndexMap<String, Vec<Document>>>,
+    // the errors of the written documents, until they are reported by a flush
+    errors:   Mutex<Vec<Error>>,
+}
+
+impl WriteBuffer {
+
+    /// `capacity` is the number of buffered documents of a collection
+    /// which triggers a flush, `0` disables the buffer.
+    pub fn new(capacity: usize) -> WriteBuffer {
+        WriteBuffer {
+            capacity,
+            pending: Mutex::new(IndexMap::new()),
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Buffer a document, returns the batch of the collection to write
+    /// when it reaches the capacity.
+    pub fn push(&self, col_name: &str, doc: Document) -> Result<Option<Vec<Document>>> {
+        let mut pending = self.pending.lock()?;
+        let docs = pending.entry(col_name.to_string()).or_default();
+        docs.push(doc);
+        if docs.len() < self.capacity {
+            return Ok(None);
+        }
+        Ok(pending.shift_remove(col_name))
+    }
+
+    /// Take the buffered documents of all the collections.
+    pub fn take_all(&self) -> Result<Vec<(String, Vec<Document>)>> {
+        let mut pending = self.pending.lock()?;
+        Ok(pending.drain(..).collect())
+    }
+
+    /// Put back the documents which failed to be written,
+    /// ahead of the ones buffered in the meantime.
+    pub fn restore(&self, col_name: &str, docs: Vec<Document>) -> Result<()> {
+        let mut pending = self.pending.lock()?;
+        let buffered = pending.entry(col_name.to_string()).or_default();
+        buffered.splice(0..0, docs);
+        Ok(())
+    }
+
+    /// Keep the errors of the written documents to report them later.
+    pub fn add_errors(&self, errors: Vec<Error>) -> Result<()> {
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let mut kept = self.errors.lock()?;
+        kept.extend(errors);
+        Ok(())
+    }
+
+    /// Take the errors kept since the last time.
+    pub fn take_errors(&self) -> Result<Vec<Error>> {
+        let mut kept = self.errors.lock()?;
+        Ok(std::mem::take(&mut *kept))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use crate::Error;
+    use super::WriteBuffer;
+
+    #[test]
+    fn test_push_until_capacity() {
+        let buffer = WriteBuffer::new(2);
+        assert!(buffer.push("a", doc! { "x": 1 }).unwrap().is_none());
+        assert!(buffer.push("b", doc! { "x": 2 }).unwrap().is_none());
+
+        let batch = buffer.push("a", doc! { "x": 3 }).unwrap().unwrap();
+        assert_eq!(batch, vec![doc! { "x": 1 }, doc! { "x": 3 }]);
+
+        let rest = buffer.take_all().unwrap();
+        assert_eq!(rest, vec![("b".to_string(), vec![doc! { "x": 2 }])]);
+        assert!(buffer.take_all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restore() {
+        let buffer = WriteBuffer::new(4);
+        buffer.push("a", doc! { "x": 1 }).unwrap();
+        let batch = buffer.take_all().unwrap();
+
+        buffer.push("a", doc! { "x": 2 }).unwrap();
+        for (col_name, docs) in batch {
+            buffer.restore(&col_name, docs).unwrap();
+        }
+
+        let rest = buffer.take_all().unwrap();
+        assert_eq!(rest, vec![("a".to_string(), vec![doc! { "x": 1 }, doc! { "x": 2 }])]);
+    }
+
+    #[test]
+    fn test_take_errors() {
+        let buffer = WriteBuffer::new(4);
+        buffer.add_errors(vec![Error::Busy, Error::DbIsClosed]).unwrap();
+        buffer.add_errors(vec![Error::ReadOnly]).unwrap();
+
+        let errors = buffer.take_errors().unwrap();
+        assert_eq!(errors.iter().map(Error::code).collect::<Vec<i32>>(), vec![43, 51, 65]);
+        assert!(buffer.take_errors().unwrap().is_empty());
+    }
+
+}
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use bson::ser::Error as BsonErr;
-use bson::Document;
+use bson::{Bson, Document};
 use std::fmt;
 use std::io;
 use std::string::FromUtf8Error;
@@ -142,6 +142,16 @@ pub struct DuplicateKeyError {
     pub ns: String,   // collection name
 }
 
+/// The document which failed to be written.
+#[derive(Debug)]
+pub struct WriteDocumentError {
+    /// The position of the document in the batch of `insert_many`.
+    pub index: Option<usize>,
+    /// The `_id` of the document, `None` if it was going to be generated.
+    pub id: Option<Bson>,
+    pub source: Error,
+}
+
 #[derive(Debug)]
 pub struct RegexError {
     pub error: String,
@@ -155,6 +165,8 @@ pub enum Error {
     UnexpectedIdType(u8, u8),
     #[error("type '{0}' is not a valid key type")]
     NotAValidKeyType(String),
+    #[error("invalid string in a key: {0}")]
+    InvalidKeyString(String),
     #[error("the {} field name: '{}' is invalid, path: {:?}", .0.field_type, .0.field_name, .0.path)]
     InvalidField(Box<InvalidFieldStruct>),
     #[error("validation error: {0}")]
@@ -242,6 +254,8 @@ pub enum Error {
     DatabaseOccupied,
     #[error("multiple errors")]
     Multiple(Vec<Error>),
+    /// The database is written in a format of another version,
+    /// e.g. a file created by a newer version of PoloDB.
     #[error("db version mismatched, please upgrade")]
     VersionMismatch(Box<VersionMismatchError>),
     #[error("the mutex is poisoned")]
@@ -278,9 +292,120 @@ pub enum Error {
     SetIsNotADocument,
     #[error("the field '{0}' is not a valid field name")]
     UpsertError(String),
+    #[error("the query returns more than {0} documents, use limit() or stream the cursor with advance() instead")]
+    MaterializeLimitExceeded(u64),
+    #[error("the database is opened in read-only mode")]
+    ReadOnly,
+    #[error("the document has been modified by another writer, expected version: {0}")]
+    VersionConflict(Box<Bson>),
+    #[error("failed to write the document, index: {:?}, _id: {:?}: {}", .0.index, .0.id, .0.source)]
+    WriteDocument(Box<WriteDocumentError>),
+    #[error("operation exceeded time limit of {0} ms")]
+    MaxTimeExpired(u64),
+    #[error("the operation is cancelled")]
+    Cancelled,
+    #[error("materialized view '{0}' not found")]
+    ViewNotFound(String),
 }
 
 impl Error {
+    /// A stable number of the kind of the error, to tell the errors apart
+    /// without matching their messages.
+    ///
+    /// The codes are never changed or reused, a new kind of error gets a new code.
+    ///
+    /// [`Error::WriteDocument`] has the code of the error it wraps, e.g. a duplicate key
+    /// of the n-th document of a batch is still `56`. [`Error::Multiple`] is always `45`,
+    /// the codes of the collected errors are read from the errors themselves.
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::UnexpectedIdType(..) => 1,
+            Error::NotAValidKeyType(..) => 2,
+            Error::InvalidKeyString(..) => 3,
+            Error::InvalidField(..) => 4,
+            Error::ValidationError(..) => 5,
+            Error::InvalidOrderOfIndex(..) => 6,
+            Error::IndexAlreadyExists(..) => 7,
+            Error::FieldTypeUnexpected(..) => 8,
+            Error::UnexpectedTypeForOp(..) => 9,
+            Error::ParseError(..) => 10,
+            Error::IOErr(..) => 11,
+            Error::UTF8Err { .. } => 12,
+            Error::BsonErr(..) => 13,
+            Error::BsonDeErr(..) => 14,
+            Error::DataSizeTooLarge(..) => 15,
+            Error::DecodeEOF => 16,
+            Error::DataOverflow => 17,
+            Error::DataExist(..) => 18,
+            Error::PageSpaceNotEnough => 19,
+            Error::DataHasNoPrimaryKey => 20,
+            Error::ChecksumMismatch => 21,
+            Error::JournalPageSizeMismatch(..) => 22,
+            Error::SaltMismatch => 23,
+            Error::PageMagicMismatch(..) => 24,
+            Error::ItemSizeGreaterThanExpected => 25,
+            Error::CollectionNotFound(..) => 26,
+            Error::MetaPageIdError => 27,
+            Error::CannotWriteDbWithoutTransaction => 28,
+            Error::StartTransactionInAnotherTransaction => 29,
+            Error::RollbackNotInTransaction => 30,
+            Error::IllegalCollectionName(..) => 31,
+            Error::IllegalIndexName(..) => 32,
+            Error::UnexpectedPageHeader => 33,
+            Error::UnexpectedPageType => 34,
+            Error::UnknownTransactionType => 35,
+            Error::BufferNotEnough(..) => 36,
+            Error::UnknownUpdateOperation(..) => 37,
+            Error::IncrementNullField => 38,
+            Error::VmIsHalt => 39,
+            Error::CollectionAlreadyExits(..) => 40,
+            Error::UnableToUpdatePrimaryKey => 41,
+            Error::NotAValidDatabase => 42,
+            Error::Busy => 43,
+            Error::DatabaseOccupied => 44,
+            Error::Multiple(..) => 45,
+            Error::VersionMismatch(..) => 46,
+            Error::LockError => 47,
+            Error::CannotApplyOperation(..) => 48,
+            Error::NoTransactionStarted => 49,
+            Error::SessionOutdated => 50,
+            Error::DbIsClosed => 51,
+            Error::FromUtf8Error(..) => 52,
+            Error::DbNotReady => 53,
+            Error::OnlySupportSingleFieldIndexes(..) => 54,
+            Error::OnlySupportsAscendingOrder(..) => 55,
+            Error::DuplicateKey(..) => 56,
+            Error::UnknownBsonElementType(..) => 57,
+            Error::RegexError(..) => 58,
+            Error::UnknownAggregationOperation(..) => 59,
+            Error::InvalidAggregationStage(..) => 60,
+            Error::RocksDbErr(..) => 61,
+            Error::SetIsNotADocument => 62,
+            Error::UpsertError(..) => 63,
+            Error::MaterializeLimitExceeded(..) => 64,
+            Error::ReadOnly => 65,
+            Error::VersionConflict(..) => 66,
+            // 67 was the code of the wrapper itself, it's not reused
+            Error::WriteDocument(err) => err.source.code(),
+            Error::MaxTimeExpired(..) => 68,
+            Error::Cancelled => 69,
+            Error::ViewNotFound(..) => 70,
+        }
+    }
+
+    /// Attach the document which caused the error.
+    /// [`Error::Busy`] is kept as it is to be retried.
+    pub(crate) fn with_document(self, index: Option<usize>, id: Option<Bson>) -> Error {
+        match self {
+            Error::Busy | Error::WriteDocument(_) => self,
+            _ => Error::WriteDocument(Box::new(WriteDocumentError {
+                index,
+                id,
+                source: self,
+            })),
+        }
+    }
+
     pub(crate) fn add(self, next: Error) -> Error {
         match self {
             Error::Multiple(mut result) => {
@@ -352,4 +477,20 @@ mod tests {
         let size = std::mem::size_of::<Error>();
         assert_eq!(size, 32);
     }
+
+    #[test]
+    fn test_error_code() {
+        assert_eq!(Error::UnexpectedIdType(1, 2).code(), 1);
+        assert_eq!(Error::ValidationError("invalid".to_string()).code(), 5);
+        assert_eq!(Error::CollectionNotFound("books".to_string()).code(), 26);
+        assert_eq!(Error::Busy.code(), 43);
+        assert_eq!(Error::DbIsClosed.code(), 51);
+        assert_eq!(Error::ViewNotFound("view".to_string()).code(), 70);
+
+        let err = Error::Busy.add(Error::DbIsClosed);
+        assert_eq!(err.code(), 45);
+
+        let err = Error::DbIsClosed.with_document(Some(1), None);
+        assert_eq!(err.code(), 51);
+    }
 }
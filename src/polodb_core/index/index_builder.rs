@@ -16,7 +16,7 @@ use bson::Document;
 use crate::Result;
 use crate::coll::collection_info::IndexInfo;
 use crate::cursor::Cursor;
-use crate::index::{IndexHelper, IndexHelperOperation};
+use crate::index::{IndexHelper, IndexHelperOperation, IndexKeyLimit};
 use crate::transaction::TransactionInner;
 
 pub(crate) struct IndexBuilder<'b, 'c, 'd, 'e> {
@@ -24,6 +24,7 @@ pub(crate) struct IndexBuilder<'b, 'c, 'd, 'e> {
     col_name: &'c str,
     index_name: &'d str,
     index_info: &'e IndexInfo,
+    limit: IndexKeyLimit,
 }
 
 impl<'b, 'c, 'd, 'e> IndexBuilder<'b, 'c, 'd, 'e> {
@@ -34,12 +35,14 @@ impl<'b, 'c, 'd, 'e> IndexBuilder<'b, 'c, 'd, 'e> {
         col_name: &'c str,
         index_name: &'d str,
         index_info: &'e IndexInfo,
+        limit: IndexKeyLimit,
     ) -> IndexBuilder<'b, 'c, 'd, 'e> {
         IndexBuilder {
             txn,
             col_name,
             index_name,
             index_info,
+            limit,
         }
     }
 
@@ -75,6 +78,7 @@ impl<'b, 'c, 'd, 'e> IndexBuilder<'b, 'c, 'd, 'e> {
             pkey,
             self.index_name,
             self.index_info,
+            self.limit,
             self.txn,
         )
     }
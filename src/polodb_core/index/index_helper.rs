@@ -12,9 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bson::{Bson, Document};
+use bson::{Binary, Bson, Document};
 use bson::spec::ElementType;
-use crate::Result;
+use crate::{Config, Error, Result};
 use crate::coll::collection_info::{
     CollectionSpecification,
     IndexInfo,
@@ -31,11 +31,87 @@ pub(crate) enum IndexHelperOperation {
     Delete,
 }
 
+/// The limit of the length of the strings and binaries stored in the index keys,
+/// see [`Config::max_index_key_length`].
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct IndexKeyLimit {
+    // 0 for no limit
+    max_length: usize,
+    truncate: bool,
+}
+
+impl IndexKeyLimit {
+
+    pub fn from_config(config: &Config) -> IndexKeyLimit {
+        IndexKeyLimit {
+            max_length: config.max_index_key_length as usize,
+            truncate: config.truncate_index_keys,
+        }
+    }
+
+    #[inline]
+    fn is_exceeded(&self, len: usize) -> bool {
+        self.max_length > 0 && len > self.max_length
+    }
+
+    /// The value stored in the index key for `value`.
+    fn apply(&self, op: IndexHelperOperation, value: Bson) -> Result<Bson> {
+        let len = match &value {
+            Bson::String(s) => s.len(),
+            Bson::Binary(bin) => bin.bytes.len(),
+            _ => return Ok(value),
+        };
+        if !self.is_exceeded(len) {
+            return Ok(value);
+        }
+        if self.truncate {
+            return Ok(self.truncate_value(value));
+        }
+        // the keys written before the limit are still removed as they are
+        if op == IndexHelperOperation::Delete {
+            return Ok(value);
+        }
+        Err(Error::DataSizeTooLarge(
+            u32::try_from(self.max_length).unwrap_or(u32::MAX),
+            u32::try_from(len).unwrap_or(u32::MAX),
+        ))
+    }
+
+    /// Truncate the value looked up in the index as the stored keys are truncated.
+    pub fn truncate_value(&self, value: Bson) -> Bson {
+        if !self.truncate {
+            return value;
+        }
+        match value {
+            Bson::String(s) => Bson::String(self.truncate_str(&s).to_string()),
+            Bson::Binary(bin) if self.is_exceeded(bin.bytes.len()) => Bson::Binary(Binary {
+                subtype: bin.subtype,
+                bytes: bin.bytes[..self.max_length].to_vec(),
+            }),
+            _ => value,
+        }
+    }
+
+    /// The prefix of the string stored in the index, cut at a char boundary.
+    pub fn truncate_str<'a>(&self, s: &'a str) -> &'a str {
+        if !self.truncate || !self.is_exceeded(s.len()) {
+            return s;
+        }
+        let mut end = self.max_length;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        &s[..end]
+    }
+
+}
+
 pub(crate) struct IndexHelper<'b, 'c, 'd, 'e> {
     txn: &'b TransactionInner,
     col_spec: &'c CollectionSpecification,
     doc: &'d Document,
     pkey: &'e Bson,
+    limit: IndexKeyLimit,
 }
 
 pub(crate) fn make_index_key_with_query_key(prefix_bytes: &[u8], query_value: &Bson) -> Result<Vec<u8>> {
@@ -57,12 +133,14 @@ impl<'b, 'c, 'd, 'e> IndexHelper<'b, 'c, 'd, 'e> {
         col_spec: &'c CollectionSpecification,
         doc: &'d Document,
         pkey: &'e Bson,
+        limit: IndexKeyLimit,
     ) -> IndexHelper<'b, 'c, 'd, 'e> {
         IndexHelper {
             txn,
             col_spec,
             doc,
             pkey,
+            limit,
         }
     }
 
@@ -77,6 +155,7 @@ impl<'b, 'c, 'd, 'e> IndexHelper<'b, 'c, 'd, 'e> {
                 self.pkey,
                 index_name.as_str(),
                 index_info,
+                self.limit,
                 self.txn,
             )?;
         }
@@ -86,6 +165,7 @@ impl<'b, 'c, 'd, 'e> IndexHelper<'b, 'c, 'd, 'e> {
 
     // The key of the collection value: collection_id + '\t' + primary_key
     // The key of the index in the table: '$I' + '\t' + collection_id + '\t' + index_name + '\t' + primary_key
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn try_execute_with_index_info(
         op: IndexHelperOperation,
         data_doc: &Document,
@@ -93,38 +173,62 @@ impl<'b, 'c, 'd, 'e> IndexHelper<'b, 'c, 'd, 'e> {
         pkey: &Bson,
         index_name: &str,
         index_info: &IndexInfo,
+        limit: IndexKeyLimit,
         txn: &TransactionInner,
     ) -> Result<()> {
         let tuples = index_info.keys.iter().collect::<Vec<(&String, &i8)>>();
         let first_tuple = tuples.first().unwrap();
         let (keys, _order) = first_tuple;
 
-        let value = crate::utils::bson::try_get_document_value(data_doc, keys);
-        if value.is_none() {
-            return Ok(())
-        }
-
-        if index_info.is_unique() {
-            IndexHelper::check_unique_key(
+        let value = match crate::utils::bson::try_get_document_value(data_doc, keys) {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        // an array has an entry for each element, so it's found by any of them,
+        // the elements repeated in the array share the entry
+        let values = match value {
+            Bson::Array(values) => values,
+            value => vec![value],
+        };
+
+        let mut index_keys = Vec::<Vec<u8>>::with_capacity(values.len());
+        for value in values {
+            // the keys are stored folded, so the values equal under the collation collide
+            let value = match index_info.collation() {
+                Some(collation) => collation.fold(value),
+                None => value,
+            };
+            let value = limit.apply(op, value)?;
+
+            let index_key = IndexHelper::make_index_key(
                 col_name,
                 index_name,
-                value.as_ref().unwrap(),
-                txn,
+                &value,
+                Some(pkey),
             )?;
+            if index_keys.contains(&index_key) {
+                continue;
+            }
+
+            if op == IndexHelperOperation::Insert && index_info.is_unique() {
+                IndexHelper::check_unique_key(
+                    col_name,
+                    index_name,
+                    &value,
+                    txn,
+                )?;
+            }
+
+            index_keys.push(index_key);
         }
 
-        let index_key = IndexHelper::make_index_key(
-            col_name,
-            index_name,
-            value.as_ref().unwrap(),
-            Some(pkey),
-        )?;
-
-        if op == IndexHelperOperation::Insert {
-            let value_buf = [ElementType::Null as u8];
-            txn.put(index_key.as_slice(), &value_buf)?;
-        } else {
-            txn.delete(index_key.as_slice())?;
+        for index_key in &index_keys {
+            if op == IndexHelperOperation::Insert {
+                let value_buf = [ElementType::Null as u8];
+                txn.put(index_key.as_slice(), &value_buf)?;
+            } else {
+                txn.delete(index_key.as_slice())?;
+            }
         }
 
         Ok(())
@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bson::Document;
+use bson::{Bson, Document};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct IndexModel {
     #[serde(rename = "key")]
@@ -37,4 +37,40 @@ pub struct IndexOptions {
     /// key value matches an existing value in the index. The default value is false.
     pub unique: Option<bool>,
 
+    /// The rules to compare the strings of the index, which apply to the unique check too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collation: Option<Collation>,
+
+}
+
+/// The rules to compare strings.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collation {
+
+    /// The locale, such as "en". Only the simple binary order of the strings is supported currently.
+    pub locale: String,
+
+    /// The level of comparison. `1` and `2` ignore the case of the letters,
+    /// the default value `3` is case-sensitive.
+    pub strength: Option<i32>,
+
+}
+
+impl Collation {
+
+    #[inline]
+    pub fn is_case_insensitive(&self) -> bool {
+        matches!(self.strength, Some(1) | Some(2))
+    }
+
+    /// Convert the value to the form the collation compares,
+    /// the strings equal under the collation have the same form.
+    pub(crate) fn fold(&self, value: Bson) -> Bson {
+        match value {
+            Bson::String(s) if self.is_case_insensitive() => Bson::String(s.to_lowercase()),
+            _ => value,
+        }
+    }
+
 }
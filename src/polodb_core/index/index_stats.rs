@@ -0,0 +1,66 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Counts how many times every index is used to look up the documents
+/// since the database was opened. The counts are not persisted.
+#[derive(Clone, Default)]
+pub(crate) struct IndexStats {
+    // collection name -> index name -> usage count
+    inner: Arc<Mutex<HashMap<String, HashMap<String, u64>>>>,
+}
+
+impl IndexStats {
+
+    pub fn new() -> IndexStats {
+        IndexStats::default()
+    }
+
+    pub fn add_usage(&self, col_name: &str, index_name: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let counts = match inner.get_mut(col_name) {
+            Some(counts) => counts,
+            None => inner.entry(col_name.to_string()).or_default(),
+        };
+        match counts.get_mut(index_name) {
+            Some(count) => *count += 1,
+            None => {
+                counts.insert(index_name.to_string(), 1);
+            }
+        }
+    }
+
+    pub fn usage_count(&self, col_name: &str, index_name: &str) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        inner.get(col_name)
+            .and_then(|counts| counts.get(index_name))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn remove_index(&self, col_name: &str, index_name: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(counts) = inner.get_mut(col_name) {
+            counts.remove(index_name);
+        }
+    }
+
+    pub fn remove_collection(&self, col_name: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(col_name);
+    }
+
+}
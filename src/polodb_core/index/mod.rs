@@ -15,7 +15,9 @@
 mod index_helper;
 mod index_model;
 mod index_builder;
+mod index_stats;
 
-pub(crate) use index_helper::{IndexHelper, IndexHelperOperation, INDEX_PREFIX, make_index_key_with_query_key};
+pub(crate) use index_helper::{IndexHelper, IndexHelperOperation, IndexKeyLimit, INDEX_PREFIX, make_index_key_with_query_key};
 pub(crate) use index_builder::IndexBuilder;
-pub use index_model::{IndexModel, IndexOptions};
+pub(crate) use index_stats::IndexStats;
+pub use index_model::{Collation, IndexModel, IndexOptions};
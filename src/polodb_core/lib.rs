@@ -113,6 +113,9 @@
 //! If you don't start it manually, a transaction will be automatically started
 //! in your every operation.
 //!
+//! The collections of the transaction are returned by [`Transaction::collection`].
+//! A transaction dropped without [`Transaction::commit`] is rolled back.
+//!
 //! ## Example
 //!
 //! ```rust
@@ -149,6 +152,7 @@ mod macros;
 mod transaction;
 pub mod options;
 pub mod results;
+pub mod bson_util;
 
 pub mod test_utils;
 mod metrics;
@@ -157,13 +161,15 @@ mod index;
 mod coll;
 pub mod action;
 
-pub use db::{Database, Result};
-pub use coll::{Collection, CollectionT, TransactionalCollection};
-pub use config::{Config, ConfigBuilder};
-pub use transaction::Transaction;
+pub use db::{CancellationToken, Database, OplogCursor, RawScan, Result, SlowQuery};
+pub use coll::{Collection, CollectionT, SnapshotCollection, TransactionalCollection};
+pub use config::{Config, ConfigBuilder, IsolationLevel, SyncPolicy};
+pub use transaction::{Snapshot, Transaction};
 pub use db::client_cursor::ClientCursor;
+#[cfg(feature = "debug")]
+pub use db::DebugScan;
 pub use errors::Error;
-pub use metrics::Metrics;
-pub use index::{IndexModel, IndexOptions};
+pub use metrics::{LsmMetrics, Metrics, MetricsSnapshot, QueryProfile};
+pub use index::{Collation, IndexModel, IndexOptions};
 
 pub extern crate bson;
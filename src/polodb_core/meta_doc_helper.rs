@@ -14,5 +14,6 @@
 
 pub(crate) mod meta_doc_key {
     pub(crate) static ID: &str       = "_id";
+    pub(crate) static VERSION: &str  = "__v";
 }
 
@@ -0,0 +1,31 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Statistics of the LSM tree of the storage engine.
+///
+/// Read them with [`Database::lsm_metrics`](crate::Database::lsm_metrics)
+/// when tuning the flush and compaction behaviour.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LsmMetrics {
+    /// The count of SST files (segments) over all levels.
+    pub segment_count: u64,
+    /// The total size in bytes of all SST files.
+    pub segment_bytes: u64,
+    /// The size in bytes of the data in memtables not flushed yet.
+    pub mem_table_bytes: u64,
+    /// Whether at least one compaction is pending.
+    pub compaction_pending: bool,
+    /// The count of compactions running currently.
+    pub running_compactions: u64,
+}
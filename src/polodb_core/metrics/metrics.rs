@@ -14,6 +14,7 @@
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct Metrics {
@@ -43,16 +44,145 @@ impl Metrics {
         self.inner.find_by_index_count.load(Ordering::SeqCst)
     }
 
+    #[inline]
+    pub(crate) fn add_cursor_fetch_count(&self) {
+        self.inner.add_cursor_fetch_count();
+    }
+
+    /// The count of batches fetched by the cursors
+    pub fn cursor_fetch_count(&self) -> usize {
+        self.inner.cursor_fetch_count.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn add_query_compile_count(&self) {
+        self.inner.add_query_compile_count();
+    }
+
+    /// The count of queries compiled, the queries found in the cache are not counted
+    pub fn query_compile_count(&self) -> usize {
+        self.inner.query_compile_count.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn add_examined_count(&self) {
+        self.inner.add_examined_count();
+    }
+
+    /// The count of documents read from the collections by the queries,
+    /// by a collection scan or an index
+    pub fn examined_count(&self) -> usize {
+        self.inner.examined_count.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn add_returned_count(&self) {
+        self.inner.add_returned_count();
+    }
+
+    /// The count of documents returned by the cursors
+    pub fn returned_count(&self) -> usize {
+        self.inner.returned_count.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub(crate) fn add_sync_count(&self) {
+        self.inner.add_sync_count();
+    }
+
+    /// The count of the log syncs at the commits, by the [`SyncPolicy`](crate::SyncPolicy)
+    pub fn sync_count(&self) -> usize {
+        self.inner.sync_count.load(Ordering::SeqCst)
+    }
+
+    /// Set all the counters to zero.
+    pub fn reset(&self) {
+        self.inner.reset()
+    }
+
+    /// Return the current values of all the counters.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.inner.snapshot()
+    }
+
+    /// Count to the counters until the scope is dropped, even if the metrics are not enabled.
+    pub(crate) fn begin_profile(&self) -> ProfileScope {
+        self.inner.profiling.fetch_add(1, Ordering::SeqCst);
+        ProfileScope {
+            inner: self.inner.clone(),
+        }
+    }
+
+}
+
+pub(crate) struct ProfileScope {
+    inner: Arc<MetricsInner>,
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        self.inner.profiling.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The values of all the counters of [`Metrics`] at a point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub find_by_index_count: usize,
+    pub cursor_fetch_count: usize,
+    pub query_compile_count: usize,
+    pub sync_count: usize,
+    pub examined_count: usize,
+    pub returned_count: usize,
+
+}
+
+/// The counters accumulated by the queries run in
+/// [`Database::profile`](crate::Database::profile).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryProfile {
+    /// The documents read from the collections, by a collection scan or an index.
+    pub examined_count: usize,
+    /// The documents returned by the cursors.
+    pub returned_count: usize,
+    /// The lookups by an index finding any entry.
+    pub find_by_index_count: usize,
+    /// The time spent running the queries.
+    pub elapsed: Duration,
+}
+
+impl QueryProfile {
+
+    /// The counts between the two snapshots, the counters reset meanwhile count from zero.
+    pub(crate) fn between(before: &MetricsSnapshot, after: &MetricsSnapshot, elapsed: Duration) -> QueryProfile {
+        let delta = |before: usize, after: usize| {
+            if after >= before { after - before } else { after }
+        };
+        QueryProfile {
+            examined_count: delta(before.examined_count, after.examined_count),
+            returned_count: delta(before.returned_count, after.returned_count),
+            find_by_index_count: delta(before.find_by_index_count, after.find_by_index_count),
+            elapsed,
+        }
+    }
+
 }
 
 struct MetricsInner {
     enable: AtomicBool,
     find_by_index_count: AtomicUsize,
+    cursor_fetch_count: AtomicUsize,
+    query_compile_count: AtomicUsize,
+    sync_count: AtomicUsize,
+    examined_count: AtomicUsize,
+    returned_count: AtomicUsize,
+    // the count of the profile scopes counting while the metrics are not enabled
+    profiling: AtomicUsize,
 }
 
 macro_rules! test_enable {
     ($self:ident) => {
-        if !$self.enable.load(Ordering::Relaxed) {
+        if !$self.enable.load(Ordering::Relaxed) && $self.profiling.load(Ordering::Relaxed) == 0 {
             return;
         }
     }
@@ -65,6 +195,12 @@ impl MetricsInner {
         MetricsInner {
             enable: AtomicBool::new(false),
             find_by_index_count: AtomicUsize::new(0),
+            cursor_fetch_count: AtomicUsize::new(0),
+            query_compile_count: AtomicUsize::new(0),
+            sync_count: AtomicUsize::new(0),
+            examined_count: AtomicUsize::new(0),
+            returned_count: AtomicUsize::new(0),
+            profiling: AtomicUsize::new(0),
         }
     }
 
@@ -79,5 +215,55 @@ impl MetricsInner {
         self.find_by_index_count.fetch_add(1, Ordering::SeqCst);
     }
 
+    pub(crate) fn add_cursor_fetch_count(&self) {
+        test_enable!(self);
+
+        self.cursor_fetch_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn add_query_compile_count(&self) {
+        test_enable!(self);
+
+        self.query_compile_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn add_sync_count(&self) {
+        test_enable!(self);
+
+        self.sync_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn add_examined_count(&self) {
+        test_enable!(self);
+
+        self.examined_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn add_returned_count(&self) {
+        test_enable!(self);
+
+        self.returned_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn reset(&self) {
+        self.find_by_index_count.store(0, Ordering::SeqCst);
+        self.cursor_fetch_count.store(0, Ordering::SeqCst);
+        self.query_compile_count.store(0, Ordering::SeqCst);
+        self.sync_count.store(0, Ordering::SeqCst);
+        self.examined_count.store(0, Ordering::SeqCst);
+        self.returned_count.store(0, Ordering::SeqCst);
+    }
+
+    fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            find_by_index_count: self.find_by_index_count.load(Ordering::SeqCst),
+            cursor_fetch_count: self.cursor_fetch_count.load(Ordering::SeqCst),
+            query_compile_count: self.query_compile_count.load(Ordering::SeqCst),
+            sync_count: self.sync_count.load(Ordering::SeqCst),
+            examined_count: self.examined_count.load(Ordering::SeqCst),
+            returned_count: self.returned_count.load(Ordering::SeqCst),
+        }
+    }
+
 }
 
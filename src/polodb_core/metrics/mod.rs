@@ -14,5 +14,7 @@
 
 
 mod metrics;
+mod lsm_metrics;
 
-pub use metrics::{Metrics};
+pub use metrics::{Metrics, MetricsSnapshot, QueryProfile};
+pub use lsm_metrics::LsmMetrics;
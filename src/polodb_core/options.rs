@@ -12,9 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bson::Document;
+
 #[derive(Debug, Clone, Default)]
 pub struct UpdateOptions {
     pub upsert: Option<bool>,
+    /// Enable optimistic concurrency control with the `__v` field.
+    ///
+    /// The `__v` field of the query is the version the caller read.
+    /// The version is incremented by every update, and the update fails with
+    /// [`Error::VersionConflict`](crate::Error::VersionConflict) if the document
+    /// has been updated by another writer in the meantime.
+    /// The document is locked while its version is checked and it's written,
+    /// so two concurrent writers can't both update the same version.
+    pub versioned: Option<bool>,
+    /// Update at most this many matching documents with `update_many`. `0` means no limit.
+    pub limit: Option<u64>,
 }
 
 impl UpdateOptions {
@@ -25,11 +38,21 @@ impl UpdateOptions {
     pub(crate) fn is_upsert(&self) -> bool {
         self.upsert.unwrap_or(false)
     }
+
+    pub(crate) fn is_versioned(&self) -> bool {
+        self.versioned.unwrap_or(false)
+    }
+
+    pub(crate) fn get_limit(&self) -> Option<u64> {
+        self.limit.filter(|limit| *limit > 0)
+    }
 }
 
 #[derive(Default)]
 pub struct UpdateOptionsBuilder {
     upsert: Option<bool>,
+    versioned: Option<bool>,
+    limit: Option<u64>,
 }
 
 impl UpdateOptionsBuilder {
@@ -38,9 +61,215 @@ impl UpdateOptionsBuilder {
         self
     }
 
+    pub fn versioned(mut self, versioned: bool) -> Self {
+        self.versioned = Some(versioned);
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
     pub fn build(self) -> UpdateOptions {
         UpdateOptions {
             upsert: self.upsert,
+            versioned: self.versioned,
+            limit: self.limit,
         }
     }
 }
+
+/// Options used to delete documents.
+#[derive(Debug, Clone, Default)]
+pub struct DeleteOptions {
+    /// Delete at most this many matching documents. `0` means no limit.
+    pub limit: Option<u64>,
+}
+
+impl DeleteOptions {
+    pub fn builder() -> DeleteOptionsBuilder {
+        DeleteOptionsBuilder::default()
+    }
+
+    pub(crate) fn get_limit(&self) -> Option<u64> {
+        self.limit.filter(|limit| *limit > 0)
+    }
+}
+
+#[derive(Default)]
+pub struct DeleteOptionsBuilder {
+    inner: DeleteOptions,
+}
+
+impl DeleteOptionsBuilder {
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.inner.limit = Some(limit);
+        self
+    }
+
+    pub fn build(self) -> DeleteOptions {
+        self.inner
+    }
+}
+
+/// Options used to insert many documents.
+#[derive(Debug, Clone, Default)]
+pub struct InsertManyOptions {
+    /// Stop at the first document failing to be inserted and roll back the whole batch.
+    /// When `false`, the other documents are still inserted and committed, and the failures
+    /// are returned in [`InsertManyResult::write_errors`](crate::results::InsertManyResult::write_errors).
+    /// Default: `true`.
+    pub ordered: Option<bool>,
+}
+
+impl InsertManyOptions {
+    pub fn builder() -> InsertManyOptionsBuilder {
+        InsertManyOptionsBuilder::default()
+    }
+
+    pub(crate) fn is_ordered(&self) -> bool {
+        self.ordered.unwrap_or(true)
+    }
+}
+
+#[derive(Default)]
+pub struct InsertManyOptionsBuilder {
+    inner: InsertManyOptions,
+}
+
+impl InsertManyOptionsBuilder {
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.inner.ordered = Some(ordered);
+        self
+    }
+
+    pub fn build(self) -> InsertManyOptions {
+        self.inner
+    }
+}
+
+/// Options to control how a database is opened.
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    /// Create the database if it doesn't exist. Default: `true`.
+    pub create: bool,
+    /// Fail if the database already exists. Default: `false`.
+    pub create_new: bool,
+    /// Reject all the writes to the database. Default: `false`.
+    pub read_only: bool,
+}
+
+impl OpenOptions {
+    pub fn builder() -> OpenOptionsBuilder {
+        OpenOptionsBuilder::default()
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        OpenOptions {
+            create: true,
+            create_new: false,
+            read_only: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct OpenOptionsBuilder {
+    inner: OpenOptions,
+}
+
+impl OpenOptionsBuilder {
+    pub fn create(mut self, create: bool) -> Self {
+        self.inner.create = create;
+        self
+    }
+
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.inner.create_new = create_new;
+        self
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.inner.read_only = read_only;
+        self
+    }
+
+    pub fn build(self) -> OpenOptions {
+        self.inner
+    }
+}
+
+/// Options used to create a collection.
+#[derive(Debug, Clone, Default)]
+pub struct CreateCollectionOptions {
+    /// Documents inserted or updated must satisfy the validator.
+    /// It maps field paths to constraints with `$exists` and `$type`.
+    pub validator: Option<Document>,
+    /// The values of the top-level fields filled when they are missing
+    /// in inserted documents. Present fields are never overwritten.
+    pub defaults: Option<Document>,
+    /// Assign `_id`s 1, 2, 3... (Int64) to the inserted documents without an `_id`,
+    /// instead of ObjectIds. The sequence is persisted with the collection.
+    pub auto_increment_id: Option<bool>,
+    /// Create a capped collection, which evicts its oldest documents when an insertion
+    /// exceeds [`size`](CreateCollectionOptions::size) or [`max`](CreateCollectionOptions::max).
+    /// The documents are evicted in insertion order, whatever their `_id`s are.
+    /// The missing `_id`s are assigned as with `auto_increment_id`, so those documents
+    /// are also scanned in insertion order.
+    pub capped: Option<bool>,
+    /// The max total size in bytes of the documents of a capped collection,
+    /// counted at their inserted sizes.
+    pub size: Option<u64>,
+    /// The max count of the documents of a capped collection.
+    pub max: Option<u64>,
+}
+
+impl CreateCollectionOptions {
+    pub fn builder() -> CreateCollectionOptionsBuilder {
+        CreateCollectionOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct CreateCollectionOptionsBuilder {
+    inner: CreateCollectionOptions,
+}
+
+impl CreateCollectionOptionsBuilder {
+    pub fn validator(mut self, validator: Document) -> Self {
+        self.inner.validator = Some(validator);
+        self
+    }
+
+    pub fn defaults(mut self, defaults: Document) -> Self {
+        self.inner.defaults = Some(defaults);
+        self
+    }
+
+    pub fn auto_increment_id(mut self, auto_increment_id: bool) -> Self {
+        self.inner.auto_increment_id = Some(auto_increment_id);
+        self
+    }
+
+    pub fn capped(mut self, capped: bool) -> Self {
+        self.inner.capped = Some(capped);
+        self
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.inner.size = Some(size);
+        self
+    }
+
+    pub fn max(mut self, max: u64) -> Self {
+        self.inner.max = Some(max);
+        self
+    }
+
+    pub fn build(self) -> CreateCollectionOptions {
+        self.inner
+    }
+}
@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{HashMap};
+use std::collections::{BTreeMap, HashMap};
 use crate::bson::Bson;
+use crate::Error;
 use serde::{Serialize, Serializer};
 use serde::ser::SerializeMap;
 
@@ -30,6 +31,11 @@ pub struct InsertManyResult {
     /// The `_id` field of the documents inserted.
     #[serde(serialize_with = "map_serialize")]
     pub inserted_ids: HashMap<usize, Bson>,
+    /// The documents an unordered `insert_many` failed to insert, every error is an
+    /// [`Error::WriteDocument`] with the position and the `_id` of the document.
+    /// Always empty for an ordered `insert_many`, which fails on the first error.
+    #[serde(skip)]
+    pub write_errors: Vec<Error>,
 }
 
 fn map_serialize<S>(data: &HashMap<usize, Bson>, serializer: S) -> Result<S::Ok, S::Error>
@@ -70,6 +76,29 @@ pub struct CountDocumentsResult {
     pub count: u64,
 }
 
+/// The storage statistics of a collection, the sizes are in bytes.
+#[derive(Debug, Clone, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionStats {
+    /// The number of documents in the collection.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub count: u64,
+    /// The total size of the stored documents.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub size: u64,
+    /// The average size of the stored documents, `0` for an empty collection.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub avg_obj_size: u64,
+    /// The number of indexes of the collection.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub nindexes: u64,
+    /// The total size of the index entries.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub total_index_size: u64,
+    /// The size of the entries of every index, by index name.
+    pub index_sizes: HashMap<String, u64>,
+}
+
 #[cfg(test)]
 mod tests {
     use bson::doc;
@@ -82,10 +111,31 @@ mod tests {
         let mut inserted_ids: HashMap<usize, Bson> = HashMap::new() ;
         inserted_ids.insert(0, doc! {}.into());
 
-        let result = InsertManyResult { inserted_ids };
+        let result = InsertManyResult { inserted_ids, write_errors: Vec::new() };
         let _bson_doc = bson::to_document(&result).unwrap();
         let bson_str = format!("{:?}", _bson_doc);
         assert_eq!(r#"Document({"insertedIds": Document({"0": Document({})})})"#, bson_str);
     }
 
 }
+
+/// The types observed in a sample of the documents of a collection.
+#[derive(Debug, Clone, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct InferredSchema {
+    /// The number of documents sampled.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub sample_count: u64,
+    /// The observed types of every field, by path, e.g. `"address.city"`.
+    pub fields: BTreeMap<String, FieldTypes>,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldTypes {
+    /// The number of sampled documents containing the field.
+    #[serde(serialize_with = "crate::bson::serde_helpers::serialize_u64_as_i64")]
+    pub count: u64,
+    /// The number of occurrences of every type, by the alias used by `$type`, e.g. `"string"`.
+    pub types: BTreeMap<String, u64>,
+}
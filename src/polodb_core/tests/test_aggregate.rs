@@ -245,6 +245,83 @@ fn test_aggregate_limit() {
     assert_eq!(result[1].get("name").unwrap().as_str().unwrap(), "banana");
 }
 
+#[test]
+fn test_aggregate_sample() {
+    let db = prepare_db("test-aggregate-sample").unwrap();
+    let fruits = db.collection::<Document>("fruits");
+
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$sample": { "size": 3 },
+            }
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 3);
+    let mut names: Vec<&str> = result.iter().map(|doc| doc.get_str("name").unwrap()).collect();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), 3);
+
+    // all the documents are returned when there are fewer than the size
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$sample": { "size": 10 },
+            },
+            doc! {
+                "$count": "count",
+            },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result[0].get_i64("count").unwrap(), 5);
+
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$sample": 3,
+            }
+        ])
+        .run();
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_aggregate_sample_seeded() {
+    use polodb_core::ConfigBuilder;
+    use polodb_core::test_utils::prepare_db_with_config;
+
+    let sample_values = |db_name: &str| -> Vec<i64> {
+        let mut config = ConfigBuilder::new();
+        config.set_random_seed(42);
+        let db = prepare_db_with_config(db_name, config.take()).unwrap();
+        let items = db.collection::<Document>("items");
+        items.insert_many((0..100i64).map(|i| doc! { "n": i })).unwrap();
+
+        items
+            .aggregate(vec![
+                doc! {
+                    "$sample": { "size": 10 },
+                }
+            ])
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i64("n").unwrap())
+            .collect()
+    };
+
+    let first = sample_values("test-aggregate-sample-seeded-1");
+    let second = sample_values("test-aggregate-sample-seeded-2");
+    assert_eq!(first.len(), 10);
+    assert_eq!(first, second);
+}
+
 #[test]
 fn test_aggregate_sort() {
     let db = prepare_db("test-aggregate-sort").unwrap();
@@ -372,3 +449,347 @@ fn test_aggregate_abs() {
         assert_eq!(abs_weight, weight.abs());
     }
 }
+
+#[test]
+fn test_aggregate_project_root() {
+    let db = prepare_db("test-aggregate-project-root").unwrap();
+    let fruits = db.collection::<Document>("fruits");
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$match": {
+                    "name": "apple",
+                },
+            },
+            doc! {
+                "$project": {
+                    "_id": 0,
+                    "name": 1,
+                    "doc": "$$ROOT",
+                },
+            },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 1);
+    let item = &result[0];
+    assert_eq!(item.keys().collect::<Vec<_>>(), vec!["name", "doc"]);
+    let embedded = item.get_document("doc").unwrap();
+    assert!(embedded.get_object_id("_id").is_ok());
+    assert_eq!(embedded.get_str("name").unwrap(), "apple");
+    assert_eq!(embedded.get_str("color").unwrap(), "red");
+    assert_eq!(embedded.get_i32("weight").unwrap(), 100);
+}
+
+#[test]
+fn test_aggregate_remove() {
+    let db = prepare_db("test-aggregate-remove").unwrap();
+    let fruits = db.collection::<Document>("fruits");
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$project": {
+                    "name": 1,
+                    "heavy_weight": {
+                        "$cond": [{ "$gte": ["$weight", 150] }, "$weight", "$$REMOVE"],
+                    },
+                },
+            },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 5);
+    for item in &result {
+        let name = item.get_str("name").unwrap();
+        match name {
+            "banana" => assert_eq!(item.get_i32("heavy_weight").unwrap(), 200),
+            "orange" => assert_eq!(item.get_i32("heavy_weight").unwrap(), 150),
+            _ => assert!(item.get("heavy_weight").is_none()),
+        }
+    }
+
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$addFields": {
+                    "color": {
+                        "$cond": {
+                            "if": { "$eq": ["$shape", "round"] },
+                            "then": "$$REMOVE",
+                            "else": "$color",
+                        },
+                    },
+                },
+            },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 5);
+    for item in &result {
+        if item.get_str("shape").unwrap() == "round" {
+            assert!(item.get("color").is_none());
+        } else {
+            assert_eq!(item.get_str("color").unwrap(), "yellow");
+        }
+    }
+}
+
+#[test]
+fn test_aggregate_sort_allow_disk_use() {
+    use polodb_core::ConfigBuilder;
+    use polodb_core::test_utils::prepare_db_with_config;
+
+    let mut config = ConfigBuilder::new();
+    // spill every 16 documents
+    config.set_sort_spill_threshold(16);
+    let db = prepare_db_with_config("test-aggregate-sort-allow-disk-use", config.take()).unwrap();
+    let items = db.collection::<Document>("items");
+
+    const COUNT: i64 = 500;
+    let docs: Vec<Document> = (0..COUNT).map(|i| doc! {
+        "_id": i,
+        "group": (i * 37) % 7,
+        "x": (i * 101) % COUNT,
+    }).collect();
+    items.insert_many(docs).unwrap();
+
+    let pipeline = vec![
+        doc! {
+            "$sort": {
+                "group": 1,
+                "x": -1,
+            },
+        },
+    ];
+
+    let in_memory = items
+        .aggregate(pipeline.clone())
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    let on_disk = items
+        .aggregate(pipeline)
+        .allow_disk_use(true)
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+
+    assert_eq!(on_disk.len(), COUNT as usize);
+    assert_eq!(on_disk, in_memory);
+    for pair in on_disk.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let a_key = (a.get_i64("group").unwrap(), -a.get_i64("x").unwrap());
+        let b_key = (b.get_i64("group").unwrap(), -b.get_i64("x").unwrap());
+        assert!(a_key < b_key);
+    }
+}
+
+#[test]
+fn test_aggregate_group_std_dev() {
+    use bson::Bson;
+
+    let db = project_prepare_db("test-aggregate-group-std-dev").unwrap();
+    let scores = db.collection::<Document>("scores");
+    let mut docs: Vec<Document> = [2, 4, 4, 4, 5, 5, 7, 9]
+        .iter()
+        .map(|score| doc! { "class": "a", "score": *score })
+        .collect();
+    docs.push(doc! { "class": "a", "score": "absent" });
+    docs.push(doc! { "class": "a" });
+    docs.push(doc! { "class": "b", "score": 1.5 });
+    docs.push(doc! { "class": "b", "score": 3.5 });
+    docs.push(doc! { "class": "c", "score": 10_i64 });
+    scores.insert_many(docs).unwrap();
+
+    let result = scores
+        .aggregate(vec![
+            doc! {
+                "$group": {
+                    "_id": "$class",
+                    "pop": { "$stdDevPop": "$score" },
+                    "samp": { "$stdDevSamp": "$score" },
+                    "count": { "$sum": 1 },
+                },
+            },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 3);
+
+    let assert_close = |value: &Bson, expected: f64| {
+        let actual = value.as_f64().unwrap();
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    };
+
+    // the groups are output in the order of their first documents
+    let a = &result[0];
+    assert_eq!(a.get_str("_id").unwrap(), "a");
+    // the non-numeric and missing scores are ignored
+    assert_close(a.get("pop").unwrap(), 2.0);
+    assert_close(a.get("samp").unwrap(), (32.0_f64 / 7.0).sqrt());
+    assert_eq!(a.get_i64("count").unwrap(), 10);
+
+    let b = &result[1];
+    assert_eq!(b.get_str("_id").unwrap(), "b");
+    assert_close(b.get("pop").unwrap(), 1.0);
+    assert_close(b.get("samp").unwrap(), 2.0_f64.sqrt());
+
+    // the sample standard deviation of a single value is undefined
+    let c = &result[2];
+    assert_eq!(c.get_str("_id").unwrap(), "c");
+    assert_close(c.get("pop").unwrap(), 0.0);
+    assert_eq!(c.get("samp"), Some(&Bson::Null));
+}
+
+#[test]
+fn test_aggregate_group_by_fields() {
+    let db = prepare_db("test-aggregate-group-by-fields").unwrap();
+    let fruits = db.collection::<Document>("fruits");
+
+    let result = fruits
+        .aggregate(vec![
+            doc! {
+                "$group": {
+                    "_id": { "color": "$color", "shape": "$shape" },
+                    "count": { "$sum": 1 },
+                },
+            },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+
+    let groups: Vec<(Document, i64)> = result
+        .iter()
+        .map(|doc| (doc.get_document("_id").unwrap().clone(), doc.get_i64("count").unwrap()))
+        .collect();
+    assert_eq!(groups, vec![
+        (doc! { "color": "red", "shape": "round" }, 1),
+        (doc! { "color": "yellow", "shape": "long" }, 1),
+        (doc! { "color": "orange", "shape": "round" }, 2),
+        (doc! { "color": "yellow", "shape": "round" }, 1),
+    ]);
+
+    // no groups for no documents
+    let result = fruits
+        .aggregate(vec![
+            doc! { "$match": { "name": "durian" } },
+            doc! { "$group": { "_id": "$color", "count": { "$sum": 1 } } },
+        ])
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn test_aggregate_streaming() {
+    use polodb_core::ConfigBuilder;
+    use polodb_core::test_utils::prepare_db_with_config;
+
+    let mut config = ConfigBuilder::new();
+    config.set_cursor_batch_size(10);
+    let db = prepare_db_with_config("test-aggregate-streaming", config.take()).unwrap();
+    let items = db.collection::<Document>("items");
+
+    const COUNT: i64 = 1000;
+    let docs: Vec<Document> = (0..COUNT).map(|i| doc! {
+        "_id": i,
+        "even": i % 2 == 0,
+    }).collect();
+    items.insert_many(docs).unwrap();
+
+    let metrics = db.metrics();
+    metrics.enable();
+
+    // only the documents of the first batch are read
+    let mut cursor = items
+        .aggregate(vec![doc! { "$match": { "even": true } }])
+        .run()
+        .unwrap();
+    assert!(cursor.advance().unwrap());
+    assert_eq!(metrics.returned_count(), 10);
+    assert!(metrics.examined_count() <= 20);
+
+    let mut count = 1;
+    while cursor.advance().unwrap() {
+        count += 1;
+    }
+    assert_eq!(count, COUNT / 2);
+    assert_eq!(metrics.examined_count(), COUNT as usize);
+    drop(cursor);
+
+    // `$sort` reads all the documents before it returns the first one
+    metrics.reset();
+    let mut cursor = items
+        .aggregate(vec![
+            doc! { "$match": { "even": true } },
+            doc! { "$sort": { "_id": -1 } },
+        ])
+        .run()
+        .unwrap();
+    assert!(cursor.advance().unwrap());
+    assert_eq!(cursor.deserialize_current().unwrap().get_i64("_id").unwrap(), COUNT - 2);
+    assert_eq!(metrics.examined_count(), COUNT as usize);
+}
+
+#[test]
+fn test_aggregate_group_allow_disk_use() {
+    use polodb_core::ConfigBuilder;
+    use polodb_core::test_utils::prepare_db_with_config;
+
+    let mut config = ConfigBuilder::new();
+    // hold 8 groups in memory, the partitions of the other groups
+    // have more than 8 each, so they are partitioned again
+    config.set_group_spill_threshold(8);
+    let db = prepare_db_with_config("test-aggregate-group-allow-disk-use", config.take()).unwrap();
+    let items = db.collection::<Document>("items");
+
+    const COUNT: i64 = 2000;
+    let docs: Vec<Document> = (0..COUNT).map(|i| doc! {
+        "_id": i,
+        "key": (i * 37) % 301,
+        "x": (i * 101) % COUNT,
+    }).collect();
+    items.insert_many(docs).unwrap();
+
+    let pipeline = vec![
+        doc! {
+            "$group": {
+                "_id": { "key": "$key" },
+                "count": { "$sum": 1 },
+                "total": { "$sum": "$x" },
+                "dev": { "$stdDevPop": "$x" },
+            },
+        },
+    ];
+
+    let in_memory = items
+        .aggregate(pipeline.clone())
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    let on_disk = items
+        .aggregate(pipeline)
+        .allow_disk_use(true)
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+
+    assert_eq!(in_memory.len(), 301);
+    assert_eq!(on_disk, in_memory);
+}
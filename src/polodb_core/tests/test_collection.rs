@@ -13,11 +13,12 @@
 // limitations under the License.
 
 use polodb_core::bson::{Document, doc};
-use polodb_core::{CollectionT, Result};
+use polodb_core::{CollectionT, ConfigBuilder, IndexModel, Result};
 mod common;
 
 use common::{
     prepare_db,
+    prepare_db_with_config,
     create_file_and_return_db_with_items,
 };
 
@@ -190,3 +191,267 @@ fn test_query_embedded_document() {
     });
 
 }
+
+#[test]
+fn test_collection_validator() {
+    use polodb_core::options::CreateCollectionOptions;
+
+    let db = prepare_db("test-collection-validator").unwrap();
+    db.create_collection_with_options("users", CreateCollectionOptions::builder()
+        .validator(doc! {
+            "name": {
+                "$exists": true,
+                "$type": "string",
+            },
+            "age": {
+                "$type": "number",
+            },
+        })
+        .build()
+    ).unwrap();
+
+    let collection = db.collection::<Document>("users");
+    collection.insert_one(doc! {
+        "_id": 1,
+        "name": "Vincent",
+        "age": 20,
+    }).unwrap();
+
+    let err = collection.insert_one(doc! {
+        "_id": 2,
+        "age": 20,
+    }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::ValidationError(_)));
+
+    let err = collection.insert_one(doc! {
+        "_id": 3,
+        "name": "Vincent",
+        "age": "20",
+    }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::ValidationError(_)));
+
+    let err = collection.update_one(doc! {
+        "_id": 1,
+    }, doc! {
+        "$set": {
+            "name": 1,
+        },
+    }).unwrap_err();
+    match err {
+        polodb_core::Error::WriteDocument(ctx) => {
+            assert_eq!(ctx.id, Some(polodb_core::bson::Bson::Int32(1)));
+            assert!(matches!(ctx.source, polodb_core::Error::ValidationError(_)));
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    collection.update_one(doc! {
+        "_id": 1,
+    }, doc! {
+        "$set": {
+            "age": 21,
+        },
+    }).unwrap();
+
+    let docs = collection
+        .find(doc! {})
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].get_str("name").unwrap(), "Vincent");
+    assert_eq!(docs[0].get_i32("age").unwrap(), 21);
+}
+
+#[test]
+fn test_collection_json_schema_validator() {
+    use polodb_core::options::CreateCollectionOptions;
+
+    let db = prepare_db("test-collection-json-schema-validator").unwrap();
+    db.create_collection_with_options("users", CreateCollectionOptions::builder()
+        .validator(doc! {
+            "$jsonSchema": {
+                "bsonType": "object",
+                "required": ["name", "contact"],
+                "properties": {
+                    "name": { "bsonType": "string" },
+                    "age": { "bsonType": "int", "minimum": 0, "maximum": 150 },
+                    "contact": {
+                        "bsonType": "object",
+                        "required": ["email"],
+                        "properties": {
+                            "email": { "bsonType": "string" },
+                        },
+                    },
+                },
+            },
+        })
+        .build()
+    ).unwrap();
+
+    let collection = db.collection::<Document>("users");
+    collection.insert_one(doc! {
+        "_id": 1,
+        "name": "Vincent",
+        "age": 20,
+        "contact": { "email": "vincent@example.com" },
+    }).unwrap();
+
+    let invalid = vec![
+        // nested required field is missing
+        doc! { "_id": 2, "name": "Vincent", "contact": {} },
+        doc! { "_id": 3, "name": "Vincent", "contact": { "email": 1 } },
+        doc! { "_id": 4, "name": "Vincent", "age": 200, "contact": { "email": "a@b.c" } },
+        doc! { "_id": 5, "name": "Vincent", "age": "20", "contact": { "email": "a@b.c" } },
+    ];
+    for doc in invalid {
+        let err = collection.insert_one(doc).unwrap_err();
+        assert!(matches!(err, polodb_core::Error::ValidationError(_)), "{}", err);
+    }
+
+    let err = collection.update_one(doc! {
+        "_id": 1,
+    }, doc! {
+        "$set": {
+            "age": -1,
+        },
+    }).unwrap_err();
+    match err {
+        polodb_core::Error::WriteDocument(ctx) => {
+            assert!(matches!(ctx.source, polodb_core::Error::ValidationError(_)));
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    assert_eq!(collection.count_documents().unwrap(), 1);
+
+    let err = db.create_collection_with_options("bad", CreateCollectionOptions::builder()
+        .validator(doc! {
+            "$jsonSchema": { "properties": { "age": { "minimum": "0" } } },
+        })
+        .build()
+    ).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::ValidationError(_)));
+}
+
+#[test]
+fn test_collection_stats() {
+    let db = prepare_db("test-collection-stats").unwrap();
+    let collection = db.collection::<Document>("test");
+
+    let stats = collection.stats().unwrap();
+    assert_eq!(stats.count, 0);
+    assert_eq!(stats.size, 0);
+    assert_eq!(stats.avg_obj_size, 0);
+
+    let docs: Vec<Document> = (0..100).map(|i| doc! {
+        "_id": i,
+        "n": i,
+        "payload": "x".repeat(100),
+    }).collect();
+    let doc_size = polodb_core::bson::to_vec(&docs[0]).unwrap().len() as u64;
+    collection.insert_many(docs).unwrap();
+
+    collection.create_index(IndexModel {
+        keys: doc! { "n": 1 },
+        options: None,
+    }).unwrap();
+
+    let stats = collection.stats().unwrap();
+    assert_eq!(stats.count, 100);
+    assert_eq!(stats.size, 100 * doc_size);
+    assert_eq!(stats.avg_obj_size, doc_size);
+    assert_eq!(stats.nindexes, 1);
+    assert_eq!(stats.index_sizes.len(), 1);
+
+    // every index entry holds at least the index value and the primary key
+    let index_size = *stats.index_sizes.values().next().unwrap();
+    assert_eq!(stats.total_index_size, index_size);
+    assert!(index_size >= 100 * 10);
+    assert!(index_size < stats.size);
+}
+
+#[test]
+fn test_infer_schema() {
+    let db = prepare_db("test-infer-schema").unwrap();
+    let collection = db.collection::<Document>("test");
+
+    let schema = collection.infer_schema(100).unwrap();
+    assert_eq!(schema.sample_count, 0);
+    assert!(schema.fields.is_empty());
+
+    let docs: Vec<Document> = (0..20).map(|i| {
+        let mut doc = doc! {
+            "_id": i,
+            "address": {
+                "city": "Shanghai",
+            },
+        };
+        // a mixed-type field: 10 ints, 5 strings, 5 nulls
+        if i < 10 {
+            doc.insert("value", i);
+        } else if i < 15 {
+            doc.insert("value", i.to_string());
+        } else {
+            doc.insert("value", polodb_core::bson::Bson::Null);
+        }
+        // a sparse field
+        if i % 4 == 0 {
+            doc.insert("tag", "x");
+        }
+        doc
+    }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let schema = collection.infer_schema(100).unwrap();
+    assert_eq!(schema.sample_count, 20);
+
+    let value = &schema.fields["value"];
+    assert_eq!(value.count, 20);
+    assert_eq!(value.types.len(), 3);
+    assert_eq!(value.types["int"], 10);
+    assert_eq!(value.types["string"], 5);
+    assert_eq!(value.types["null"], 5);
+
+    assert_eq!(schema.fields["tag"].count, 5);
+    assert_eq!(schema.fields["address"].types["object"], 20);
+    assert_eq!(schema.fields["address.city"].types["string"], 20);
+
+    // only the first documents are sampled
+    let schema = collection.infer_schema(10).unwrap();
+    assert_eq!(schema.sample_count, 10);
+    assert_eq!(schema.fields["value"].types.len(), 1);
+    assert_eq!(schema.fields["value"].types["int"], 10);
+}
+
+#[test]
+fn test_auto_create_collections() {
+    let db = prepare_db("test-auto-create-collections").unwrap();
+    db.collection::<Document>("books").insert_one(doc! { "_id": 1 }).unwrap();
+    assert_eq!(db.list_collection_names().unwrap(), vec!["books".to_string()]);
+
+    let mut config = ConfigBuilder::new();
+    config.set_auto_create_collections(false);
+    let db = prepare_db_with_config("test-auto-create-collections-off", config.take()).unwrap();
+
+    let result = db.collection::<Document>("boks").insert_one(doc! { "_id": 1 });
+    assert!(matches!(result, Err(polodb_core::Error::CollectionNotFound(name)) if name == "boks"));
+    let result = db.collection::<Document>("boks").insert_many(vec![doc! { "_id": 1 }]);
+    assert!(matches!(result, Err(polodb_core::Error::CollectionNotFound(_))));
+    assert!(db.list_collection_names().unwrap().is_empty());
+
+    // the reads of a missing collection fail too
+    let result = db.collection::<Document>("boks").find_one(doc! {});
+    assert!(matches!(result, Err(polodb_core::Error::CollectionNotFound(name)) if name == "boks"));
+    let result = db.collection::<Document>("boks").find(doc! {}).limit(1).run();
+    assert!(matches!(result, Err(polodb_core::Error::CollectionNotFound(_))));
+    let result = db.collection::<Document>("boks").count_documents();
+    assert!(matches!(result, Err(polodb_core::Error::CollectionNotFound(_))));
+    let result = db.collection::<Document>("boks").delete_many(doc! {});
+    assert!(matches!(result, Err(polodb_core::Error::CollectionNotFound(_))));
+
+    db.create_collection("books").unwrap();
+    db.collection::<Document>("books").insert_one(doc! { "_id": 1 }).unwrap();
+    assert_eq!(db.collection::<Document>("books").count_documents().unwrap(), 1);
+}
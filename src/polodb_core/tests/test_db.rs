@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use polodb_core::Database;
+use polodb_core::{ConfigBuilder, Database, IsolationLevel, SyncPolicy};
 use polodb_core::bson::{doc, Document};
 use polodb_core::CollectionT;
 
@@ -21,6 +21,8 @@ mod common;
 use common::{
     create_file_and_return_db_with_items,
     mk_db_path,
+    prepare_db,
+    prepare_db_with_config,
 };
 
 static TEST_SIZE: usize = 1000;
@@ -74,3 +76,496 @@ fn test_multi_threads() {
     assert_eq!(one.get("content").unwrap().as_str().unwrap(), "Hello");
 }
 
+#[test]
+fn test_multi_threads_busy_retry() {
+    use std::thread;
+    use std::sync::Arc;
+
+    const THREAD_COUNT: usize = 8;
+    const UPDATE_COUNT: usize = 50;
+
+    let mut config = ConfigBuilder::new();
+    config.set_busy_retry_count(100);
+    let db = Arc::new(prepare_db_with_config("test-busy-retry", config.take()).unwrap());
+
+    let collection = db.collection::<Document>("counter");
+    collection.insert_one(doc! {
+        "_id": 0,
+        "count": 0,
+    }).unwrap();
+
+    let handles = (0..THREAD_COUNT).map(|_| {
+        let db = db.clone();
+        thread::spawn(move || {
+            let collection = db.collection::<Document>("counter");
+            for _ in 0..UPDATE_COUNT {
+                collection.update_one(doc! {
+                    "_id": 0,
+                }, doc! {
+                    "$inc": {
+                        "count": 1,
+                    },
+                }).unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let counter = collection.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    assert_eq!(counter.get_i32("count").unwrap(), (THREAD_COUNT * UPDATE_COUNT) as i32);
+}
+
+#[test]
+fn test_update_conflict_is_busy() {
+    let mut config = ConfigBuilder::new();
+    config.set_isolation_level(IsolationLevel::Snapshot);
+    let db = prepare_db_with_config("test-update-conflict-is-busy", config.take()).unwrap();
+
+    let collection = db.collection::<Document>("counter");
+    collection.insert_one(doc! {
+        "_id": 0,
+        "count": 0,
+    }).unwrap();
+
+    // the counter is changed after the transaction begins
+    let txn = db.start_transaction().unwrap();
+    collection.update_one(doc! { "_id": 0 }, doc! { "$inc": { "count": 1 } }).unwrap();
+
+    // the transaction doesn't overwrite the other write with its stale value
+    let err = txn.collection::<Document>("counter")
+        .update_one(doc! { "_id": 0 }, doc! { "$inc": { "count": 1 } })
+        .unwrap_err();
+    assert!(matches!(err, polodb_core::Error::Busy));
+    txn.rollback().unwrap();
+
+    let counter = collection.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    assert_eq!(counter.get_i32("count").unwrap(), 1);
+}
+
+#[test]
+fn test_lsm_metrics_after_flush() {
+    let db = create_file_and_return_db_with_items("test-lsm-metrics", TEST_SIZE);
+
+    let before = db.lsm_metrics().expect("the RocksDB backend reports LSM metrics");
+    assert!(before.mem_table_bytes > 0);
+
+    db.flush().unwrap();
+
+    let after = db.lsm_metrics().unwrap();
+    assert!(after.segment_count > before.segment_count);
+    assert!(after.segment_bytes > before.segment_bytes);
+    assert!(after.mem_table_bytes < before.mem_table_bytes);
+}
+
+#[test]
+fn test_close_and_reopen() {
+    let db_path = mk_db_path("test-close-and-reopen");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("books");
+    collection.insert_one(doc! {
+        "title": "The Three-Body Problem",
+    }).unwrap();
+
+    db.close().unwrap();
+
+    let err = collection.insert_one(doc! {
+        "title": "The Dark Forest",
+    }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::DbIsClosed));
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("books");
+    assert_eq!(collection.count_documents().unwrap(), 1);
+}
+
+#[test]
+fn test_drop_and_reopen() {
+    for sync_on_drop in [true, false] {
+        let db_path = mk_db_path(&format!("test-drop-and-reopen-{}", sync_on_drop));
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        let mut config = ConfigBuilder::new();
+        config.set_sync_on_drop(sync_on_drop);
+
+        {
+            let db = Database::open_path_with_config(db_path.as_path(), config.take()).unwrap();
+            let collection = db.collection::<Document>("books");
+            collection.insert_one(doc! {
+                "title": "The Three-Body Problem",
+            }).unwrap();
+        } // the data is flushed when the database is dropped
+
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        let collection = db.collection::<Document>("books");
+        assert_eq!(collection.count_documents().unwrap(), 1);
+    }
+}
+
+#[test]
+fn test_deferred_sync() {
+    let mut sync_counts = Vec::new();
+    for (name, sync_policy) in [
+        ("every-commit", SyncPolicy::EveryCommit),
+        ("deferred", SyncPolicy::Deferred { commits: 10, interval_ms: 0 }),
+        // nothing is synced before the database is closed
+        ("deferred-on-close", SyncPolicy::Deferred { commits: 1000, interval_ms: 0 }),
+    ] {
+        let db_path = mk_db_path(&format!("test-deferred-sync-{}", name));
+        let _ = std::fs::remove_dir_all(db_path.as_path());
+
+        let mut config = ConfigBuilder::new();
+        config.set_sync_policy(sync_policy);
+
+        {
+            let db = Database::open_path_with_config(db_path.as_path(), config.take()).unwrap();
+            let metrics = db.metrics();
+            metrics.enable();
+
+            let collection = db.collection::<Document>("books");
+            for i in 0..25 {
+                collection.insert_one(doc! { "_id": i }).unwrap();
+            }
+            sync_counts.push(metrics.sync_count());
+        } // the pending commits are synced when the database is closed
+
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        let collection = db.collection::<Document>("books");
+        assert_eq!(collection.count_documents().unwrap(), 25);
+        assert_eq!(collection.find_one(doc! { "_id": 24 }).unwrap(), Some(doc! { "_id": 24 }));
+    }
+
+    // every commit is synced, one of every ten, or none of them
+    assert!(sync_counts[0] >= 25);
+    assert_eq!(sync_counts[1], sync_counts[0] / 10);
+    assert_eq!(sync_counts[2], 0);
+}
+
+#[test]
+fn test_open_options() {
+    use polodb_core::Config;
+    use polodb_core::options::OpenOptions;
+
+    let db_path = mk_db_path("test-open-options");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    let options = OpenOptions::builder().create(false).build();
+    let result = Database::open_path_with_options(db_path.as_path(), Config::default(), options);
+    assert!(result.is_err());
+    assert!(!db_path.exists());
+
+    let options = OpenOptions::builder().create_new(true).build();
+    let db = Database::open_path_with_options(db_path.as_path(), Config::default(), options.clone()).unwrap();
+    db.collection::<Document>("books").insert_one(doc! {
+        "title": "The Three-Body Problem",
+    }).unwrap();
+    db.close().unwrap();
+
+    let result = Database::open_path_with_options(db_path.as_path(), Config::default(), options);
+    assert!(result.is_err());
+
+    let options = OpenOptions::builder().read_only(true).build();
+    let db = Database::open_path_with_options(db_path.as_path(), Config::default(), options).unwrap();
+    let collection = db.collection::<Document>("books");
+    assert_eq!(collection.count_documents().unwrap(), 1);
+    let err = collection.insert_one(doc! {
+        "title": "The Dark Forest",
+    }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::ReadOnly));
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn test_debug_scan() {
+    use polodb_core::{Config, IndexModel, Result};
+
+    let db = prepare_db_with_config("test-debug-scan", Config::default()).unwrap();
+    let collection = db.collection::<Document>("users");
+    collection.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: None,
+    }).unwrap();
+    collection.insert_one(doc! {
+        "_id": 1,
+        "email": "a@x.com",
+    }).unwrap();
+
+    // the document is stored under the collection name and the _id
+    let pairs = db.debug_scan(b"\x02users\x00").unwrap().collect::<Result<Vec<(Vec<u8>, Vec<u8>)>>>().unwrap();
+    assert_eq!(pairs.len(), 1);
+    assert_eq!(pairs[0].0, b"\x02users\x00\x10\x00\x00\x00\x01".to_vec());
+    let stored: Document = polodb_core::bson::from_slice(&pairs[0].1).unwrap();
+    assert_eq!(stored.get_str("email").unwrap(), "a@x.com");
+
+    // the index entry is the indexed value followed by the _id
+    let index_prefix = b"\x02$I\x00\x02users\x00\x02email_1\x00";
+    let pairs = db.debug_scan(index_prefix).unwrap().collect::<Result<Vec<(Vec<u8>, Vec<u8>)>>>().unwrap();
+    assert_eq!(pairs.len(), 1);
+    let mut expected = index_prefix.to_vec();
+    expected.extend_from_slice(b"\x02a@x.com\x00\x10\x00\x00\x00\x01");
+    assert_eq!(pairs[0].0, expected);
+
+    assert_eq!(db.debug_scan(b"\x02nothing\x00").unwrap().count(), 0);
+}
+
+#[test]
+fn test_materialized_view() {
+    use polodb_core::Error;
+    use polodb_core::bson::Bson;
+
+    let db = prepare_db("test-materialized-view").unwrap();
+    let orders = db.collection::<Document>("orders");
+    orders.insert_many(vec![
+        doc! { "item": "apple", "qty": 1 },
+        doc! { "item": "pear", "qty": 2 },
+        doc! { "item": "apple", "qty": 3 },
+    ]).unwrap();
+
+    db.create_materialized_view("order_counts", "orders", vec![
+        doc! {
+            "$group": {
+                "_id": "$item",
+                "count": { "$sum": 1 },
+            },
+        },
+    ], "order_counts_view").unwrap();
+
+    let view = db.collection::<Document>("order_counts_view");
+    let counts = || -> Vec<(String, i64)> {
+        let mut result: Vec<(String, i64)> = view.find(doc! {})
+            .run()
+            .unwrap()
+            .map(|doc| {
+                let doc = doc.unwrap();
+                let count = match doc.get("count").unwrap() {
+                    Bson::Int32(i) => *i as i64,
+                    Bson::Int64(i) => *i,
+                    value => panic!("unexpected count: {}", value),
+                };
+                (doc.get_str("_id").unwrap().to_string(), count)
+            })
+            .collect();
+        result.sort();
+        result
+    };
+
+    // nothing is written before the refresh
+    assert!(counts().is_empty());
+
+    assert_eq!(db.refresh_view("order_counts").unwrap(), 2);
+    assert_eq!(counts(), vec![("apple".to_string(), 2), ("pear".to_string(), 1)]);
+
+    orders.insert_many(vec![
+        doc! { "item": "pear", "qty": 4 },
+        doc! { "item": "plum", "qty": 5 },
+    ]).unwrap();
+    orders.delete_many(doc! { "item": "apple" }).unwrap();
+
+    // the target is replaced with the new results
+    assert_eq!(counts().len(), 2);
+    assert_eq!(db.refresh_view("order_counts").unwrap(), 2);
+    assert_eq!(counts(), vec![("pear".to_string(), 2), ("plum".to_string(), 1)]);
+
+    db.drop_materialized_view("order_counts").unwrap();
+    assert!(matches!(db.refresh_view("order_counts"), Err(Error::ViewNotFound(_))));
+    assert!(matches!(db.drop_materialized_view("order_counts"), Err(Error::ViewNotFound(_))));
+    // the results are kept
+    assert_eq!(counts().len(), 2);
+
+    let err = db.create_materialized_view("loop", "orders", vec![], "orders").unwrap_err();
+    assert!(matches!(err, Error::ValidationError(_)), "unexpected error: {}", err);
+}
+
+#[test]
+fn test_drop_database() {
+    use polodb_core::{Error, IndexModel};
+
+    let db_path = mk_db_path("test-drop-database");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    {
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        for name in ["books", "authors", "reviews"] {
+            let collection = db.collection::<Document>(name);
+            collection.create_index(IndexModel {
+                keys: doc! { "title": 1 },
+                options: None,
+            }).unwrap();
+            collection.insert_many(vec![
+                doc! { "title": "The Three-Body Problem" },
+                doc! { "title": "The Dark Forest" },
+            ]).unwrap();
+        }
+        db.create_materialized_view("titles", "books", vec![], "book_titles").unwrap();
+        assert_eq!(db.list_collection_names().unwrap().len(), 3);
+
+        db.drop_database().unwrap();
+
+        assert!(db.list_collection_names().unwrap().is_empty());
+        assert!(matches!(db.refresh_view("titles").unwrap_err(), Error::ViewNotFound(_)));
+        let books = db.collection::<Document>("books");
+        assert_eq!(books.count_documents().unwrap(), 0);
+        // the old index entries don't match the new documents
+        books.insert_one(doc! { "title": "Death's End" }).unwrap();
+        assert!(books.find_one(doc! { "title": "The Dark Forest" }).unwrap().is_none());
+        assert!(books.find_one(doc! { "title": "Death's End" }).unwrap().is_some());
+
+        db.close().unwrap();
+    }
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    assert_eq!(db.list_collection_names().unwrap(), vec!["books".to_string()]);
+    let books = db.collection::<Document>("books");
+    assert_eq!(books.count_documents().unwrap(), 1);
+}
+
+#[test]
+fn test_rename_collection() {
+    use polodb_core::{Error, IndexModel};
+
+    let db = prepare_db_with_config("test-rename-collection", ConfigBuilder::new().take()).unwrap();
+    let books = db.collection::<Document>("books");
+    books.create_index(IndexModel {
+        keys: doc! { "title": 1 },
+        options: None,
+    }).unwrap();
+    books.insert_many(vec![
+        doc! { "_id": 1, "title": "The Three-Body Problem" },
+        doc! { "_id": 2, "title": "The Dark Forest" },
+    ]).unwrap();
+    db.collection::<Document>("novels").insert_one(doc! { "_id": 3 }).unwrap();
+
+    let err = db.rename_collection("books", "novels", false).unwrap_err();
+    assert!(matches!(err, Error::CollectionAlreadyExits(_)));
+    let err = db.rename_collection("missing", "other", false).unwrap_err();
+    assert!(matches!(err, Error::CollectionNotFound(_)));
+
+    db.rename_collection("books", "novels", true).unwrap();
+
+    let mut names = db.list_collection_names().unwrap();
+    names.sort();
+    assert_eq!(names, vec!["novels".to_string()]);
+    assert_eq!(books.count_documents().unwrap(), 0);
+
+    let novels = db.collection::<Document>("novels");
+    assert_eq!(novels.count_documents().unwrap(), 2);
+    assert!(novels.find_one(doc! { "_id": 3 }).unwrap().is_none());
+    // found by the moved index
+    let found = novels.find_one(doc! { "title": "The Dark Forest" }).unwrap().unwrap();
+    assert_eq!(found.get_i32("_id").unwrap(), 2);
+    assert_eq!(novels.index_stats().unwrap().get("title_1"), Some(&1));
+}
+
+#[test]
+fn test_slow_query_log() {
+    let mut config = ConfigBuilder::new();
+    config
+        .set_slow_query_log_enabled(true)
+        .set_slow_query_ms(0);
+    let db = prepare_db_with_config("test-slow-query-log", config.take()).unwrap();
+    let collection = db.collection::<Document>("books");
+    collection.insert_many(vec![
+        doc! { "_id": 1, "pages": 100 },
+        doc! { "_id": 2, "pages": 300 },
+    ]).unwrap();
+    assert!(db.slow_queries().is_empty());
+
+    let books = collection
+        .find(doc! { "pages": { "$gt": 200 }, "$comment": "long books" })
+        .run()
+        .unwrap()
+        .collect::<polodb_core::Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(books, vec![doc! { "_id": 2, "pages": 300 }]);
+    let books = collection
+        .find(doc! { "pages": { "$gt": 0 }, "$comment": "thinnest" })
+        .sort(doc! { "pages": 1 })
+        .limit(1)
+        .run()
+        .unwrap()
+        .collect::<polodb_core::Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(books, vec![doc! { "_id": 1, "pages": 100 }]);
+    let count = collection
+        .find(doc! { "pages": { "$gt": 0 }, "$comment": "counted" })
+        .count()
+        .unwrap();
+    assert_eq!(count, 2);
+    collection.update_one(doc! { "_id": 2, "$comment": "failed" }, doc! { "$inc": { "_id": 100 } }).unwrap_err();
+    collection.update_one(doc! { "_id": 1, "$comment": "reprint" }, doc! { "$set": { "pages": 120 } }).unwrap();
+    collection.delete_many(doc! { "pages": { "$lt": 200 } }).unwrap();
+
+    let queries = db.slow_queries();
+    let summary = queries.iter()
+        .map(|query| (query.op, query.ns.as_str(), query.filter.clone(), query.comment.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(summary, vec![
+        ("find", "books", doc! { "pages": { "$gt": 200 } }, Some("long books".into())),
+        ("find", "books", doc! { "pages": { "$gt": 0 } }, Some("thinnest".into())),
+        ("find", "books", doc! { "pages": { "$gt": 0 } }, Some("counted".into())),
+        ("update", "books", doc! { "_id": 2 }, Some("failed".into())),
+        ("update", "books", doc! { "_id": 1 }, Some("reprint".into())),
+        ("delete", "books", doc! { "pages": { "$lt": 200 } }, None),
+    ]);
+
+    // the queries faster than the threshold are not logged
+    let mut config = ConfigBuilder::new();
+    config
+        .set_slow_query_log_enabled(true)
+        .set_slow_query_ms(60_000);
+    let db = prepare_db_with_config("test-slow-query-log-threshold", config.take()).unwrap();
+    let collection = db.collection::<Document>("books");
+    collection.insert_one(doc! { "_id": 1, "pages": 100 }).unwrap();
+    collection.find(doc! { "$comment": "all" }).run().unwrap().for_each(drop);
+    assert!(db.slow_queries().is_empty());
+}
+
+#[test]
+fn test_cloned_handles() {
+    use std::thread;
+
+    const THREAD_COUNT: i64 = 4;
+    const INSERT_COUNT: i64 = 100;
+
+    let db = prepare_db("test-cloned-handles").unwrap();
+    let db_path = mk_db_path("test-cloned-handles");
+    // the file is occupied by the opened database
+    assert!(Database::open_path(db_path.as_path()).is_err());
+
+    let handles = (0..THREAD_COUNT).map(|thread_id| {
+        let db = db.clone();
+        thread::spawn(move || {
+            let collection = db.collection::<Document>("items");
+            for i in 0..INSERT_COUNT {
+                let id = thread_id * INSERT_COUNT + i;
+                collection.insert_one(doc! { "_id": id, "thread": thread_id }).unwrap();
+                let item = collection.find_one(doc! { "_id": id }).unwrap().unwrap();
+                assert_eq!(item.get_i64("thread").unwrap(), thread_id);
+            }
+            collection.count_documents().unwrap()
+        })
+    }).collect::<Vec<_>>();
+
+    for handle in handles {
+        let count = handle.join().unwrap();
+        assert!(count >= INSERT_COUNT as u64);
+    }
+
+    let collection = db.collection::<Document>("items");
+    assert_eq!(collection.count_documents().unwrap(), (THREAD_COUNT * INSERT_COUNT) as u64);
+    for thread_id in 0..THREAD_COUNT {
+        let count = collection.find(doc! { "thread": thread_id }).run().unwrap().count();
+        assert_eq!(count, INSERT_COUNT as usize);
+    }
+
+    // a clone keeps the database open
+    let db2 = db.clone();
+    assert!(matches!(db.close(), Err(polodb_core::Error::Busy)));
+    assert_eq!(db2.collection::<Document>("items").count_documents().unwrap(), (THREAD_COUNT * INSERT_COUNT) as u64);
+    db2.close().unwrap();
+    Database::open_path(db_path.as_path()).unwrap();
+}
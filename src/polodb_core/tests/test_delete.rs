@@ -51,6 +51,63 @@ fn test_delete_one() {
     });
 }
 
+#[test]
+fn test_delete_one_vs_delete_many() {
+    let db = prepare_db("test-delete-one-vs-delete-many").unwrap();
+    let collection = db.collection::<Document>("test");
+
+    let docs: Vec<Document> = (0..5).map(|i| doc! {
+        "_id": i,
+        "group": "a",
+    }).collect();
+    collection.insert_many(docs).unwrap();
+
+    // delete_one stops at the first matched document
+    let result = collection.delete_one(doc! { "group": "a" }).unwrap();
+    assert_eq!(result.deleted_count, 1);
+    assert_eq!(collection.count_documents().unwrap(), 4);
+
+    let result = collection.delete_many(doc! { "group": "a" }).unwrap();
+    assert_eq!(result.deleted_count, 4);
+    assert_eq!(collection.count_documents().unwrap(), 0);
+
+    assert_eq!(collection.delete_one(doc! { "group": "a" }).unwrap().deleted_count, 0);
+    assert_eq!(collection.delete_many(doc! {}).unwrap().deleted_count, 0);
+
+    let missing = db.collection::<Document>("missing");
+    assert_eq!(missing.delete_one(doc! { "group": "a" }).unwrap().deleted_count, 0);
+    assert_eq!(missing.delete_many(doc! {}).unwrap().deleted_count, 0);
+}
+
+#[test]
+fn test_delete_dry_run() {
+    let db = prepare_db("test-delete-dry-run").unwrap();
+    let collection = db.collection::<Document>("test");
+
+    let docs: Vec<Document> = (0..5).map(|i| doc! {
+        "_id": i,
+        "group": if i < 3 { "a" } else { "b" },
+    }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let matched = collection.delete_many_dry_run(doc! { "group": "a" }).unwrap();
+    assert_eq!(matched, vec![
+        doc! { "_id": 0, "group": "a" },
+        doc! { "_id": 1, "group": "a" },
+        doc! { "_id": 2, "group": "a" },
+    ]);
+
+    let preview = collection.delete_one_dry_run(doc! { "group": "b" }).unwrap();
+    assert_eq!(preview, vec![doc! { "_id": 3, "group": "b" }]);
+
+    // nothing is deleted
+    assert_eq!(collection.count_documents().unwrap(), 5);
+
+    let result = collection.delete_many(doc! { "group": "a" }).unwrap();
+    assert_eq!(result.deleted_count, matched.len() as u64);
+    assert_eq!(collection.count_documents().unwrap(), 2);
+}
+
 #[test]
 fn test_one_delete_item() {
     vec![
@@ -248,3 +305,101 @@ fn test_delete_issues_148() {
         assert_eq!(result[2].get("name").unwrap().as_str().unwrap(), "4");
     }
 }
+
+#[test]
+fn test_delete_many_with_limit() {
+    use polodb_core::options::DeleteOptions;
+
+    let db = prepare_db("test-delete-many-with-limit").unwrap();
+    let collection = db.collection::<Document>("test");
+
+    let docs: Vec<Document> = (0..10).map(|i| doc! { "_id": i, "kind": if i < 7 { "old" } else { "new" } }).collect();
+    collection.insert_many(docs).unwrap();
+
+    // more documents match than the limit
+    let result = collection.delete_many_with_options(
+        doc! { "kind": "old" },
+        DeleteOptions::builder().limit(3).build(),
+    ).unwrap();
+    assert_eq!(result.deleted_count, 3);
+    assert_eq!(collection.count_documents().unwrap(), 7);
+
+    // fewer documents match than the limit
+    let result = collection.delete_many_with_options(
+        doc! { "kind": "old" },
+        DeleteOptions::builder().limit(100).build(),
+    ).unwrap();
+    assert_eq!(result.deleted_count, 4);
+    assert_eq!(collection.count_documents().unwrap(), 3);
+
+    // the limit applies to an empty query too
+    let result = collection.delete_many_with_options(
+        doc! {},
+        DeleteOptions::builder().limit(2).build(),
+    ).unwrap();
+    assert_eq!(result.deleted_count, 2);
+    assert_eq!(collection.count_documents().unwrap(), 1);
+}
+
+#[test]
+fn test_delete_by_ids() {
+    use polodb_core::bson::Bson;
+
+    let db = prepare_db("test-delete-by-ids").unwrap();
+
+    let collection = db.collection::<Document>("cache");
+    let docs: Vec<Document> = (0..10).map(|i| doc! { "_id": i, "value": i * 10 }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let ids = vec![Bson::Int32(1), Bson::Int32(4), Bson::Int32(7), Bson::Int32(42), Bson::Int32(4)];
+    let result = collection.delete_by_ids(&ids).unwrap();
+    // the missing and the repeated ids are not counted
+    assert_eq!(result.deleted_count, 3);
+
+    let mut remaining: Vec<i32> = collection.find(doc! {})
+        .run()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+    remaining.sort();
+    assert_eq!(remaining, vec![0, 2, 3, 5, 6, 8, 9]);
+
+    assert_eq!(collection.delete_by_ids(&[]).unwrap().deleted_count, 0);
+
+    // in a transaction, the deletions are rolled back together
+    let txn = db.start_transaction().unwrap();
+    let txn_collection = txn.collection::<Document>("cache");
+    assert_eq!(txn_collection.delete_by_ids(&[Bson::Int32(0), Bson::Int32(2)]).unwrap().deleted_count, 2);
+    txn.rollback().unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 7);
+}
+
+#[test]
+fn test_delete_by_ids_with_index() {
+    use polodb_core::{IndexModel, IndexOptions};
+    use polodb_core::bson::Bson;
+
+    let db = prepare_db("test-delete-by-ids-with-index").unwrap();
+
+    let collection = db.collection::<Document>("users");
+    collection.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+    collection.insert_many(vec![
+        doc! { "_id": 1, "email": "a@x.com" },
+        doc! { "_id": 2, "email": "b@x.com" },
+    ]).unwrap();
+
+    // the numbers are matched by value
+    let result = collection.delete_by_ids(&[Bson::Int64(1)]).unwrap();
+    assert_eq!(result.deleted_count, 1);
+
+    // the index entry is removed with the document
+    assert!(collection.find_one(doc! { "email": "a@x.com" }).unwrap().is_none());
+    collection.insert_one(doc! { "_id": 3, "email": "a@x.com" }).unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 2);
+}
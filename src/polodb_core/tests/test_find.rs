@@ -12,13 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use polodb_core::{Result, CollectionT};
-use polodb_core::bson::{doc, Document};
+use polodb_core::{Result, CollectionT, ConfigBuilder, Error, IndexModel};
+use polodb_core::bson::{doc, Bson, Document};
+use serde::Deserialize;
 
 mod common;
 
 use common::{
     prepare_db,
+    prepare_db_with_config,
     create_file_and_return_db_with_items,
 };
 
@@ -366,3 +368,1070 @@ fn test_find_sort() {
     assert_eq!(result[1].get("name").unwrap().as_str().unwrap(), "banana");
     assert_eq!(result[2].get("name").unwrap().as_str().unwrap(), "orange");
 }
+
+#[test]
+fn test_ne_matches_missing_field() {
+    let db = prepare_db("test-ne-matches-missing-field").unwrap();
+
+    let col = db.collection::<Document>("teacher");
+
+    col.insert_many(vec![
+        doc! {
+            "name": "David",
+            "age": 5,
+        },
+        doc! {
+            "name": "John",
+            "age": 22,
+        },
+        doc! {
+            "name": "Mary",
+        },
+    ]).unwrap();
+
+    let result = col
+        .find(doc! {
+            "age": {
+                "$ne": 5,
+            },
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].get("name").unwrap().as_str().unwrap(), "John");
+    assert_eq!(result[1].get("name").unwrap().as_str().unwrap(), "Mary");
+
+    let result = col
+        .find(doc! {
+            "age": {
+                "$not": {
+                    "$ne": 5,
+                },
+            },
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].get("name").unwrap().as_str().unwrap(), "David");
+}
+
+#[test]
+fn test_find_materialize_cap() {
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_max_materialize_count(10);
+    let db = prepare_db_with_config("test-find-materialize-cap", config_builder.take()).unwrap();
+
+    let col = db.collection::<Document>("test");
+
+    let docs: Vec<Document> = (0..10).map(|i| doc! { "x": i }).collect();
+    col.insert_many(docs).unwrap();
+
+    // exactly at the cap
+    let result = col.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(result.len(), 10);
+
+    col.insert_one(doc! { "x": 10 }).unwrap();
+
+    // beyond the cap
+    let err = col.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap_err();
+    assert!(matches!(err, Error::MaterializeLimitExceeded(10)));
+
+    // the iteration ends after the error
+    let results: Vec<Result<Document>> = col.find(doc! {}).run().unwrap().collect();
+    assert_eq!(results.len(), 11);
+    assert!(matches!(results[10], Err(Error::MaterializeLimitExceeded(10))));
+
+    // an explicit limit bypasses the cap
+    let result = col.find(doc! {}).limit(11).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(result.len(), 11);
+
+    // streaming is not affected
+    let mut cursor = col.find(doc! {}).run().unwrap();
+    let mut count = 0;
+    while cursor.advance().unwrap() {
+        count += 1;
+    }
+    assert_eq!(count, 11);
+}
+
+#[test]
+fn test_find_by_array_index() {
+    let db = prepare_db("test-find-by-array-index").unwrap();
+
+    let col = db.collection::<Document>("orders");
+
+    col.insert_many(vec![
+        doc! {
+            "order": 1,
+            "items": [
+                { "name": "x" },
+                { "name": "y" },
+            ],
+        },
+        doc! {
+            "order": 2,
+            "items": [
+                { "name": "y" },
+            ],
+        },
+    ]).unwrap();
+
+    let result = col
+        .find(doc! {
+            "items.0.name": "x",
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].get_i32("order").unwrap(), 1);
+
+    let result = col
+        .find(doc! {
+            "items.0.name": {
+                "$eq": "y",
+            },
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].get_i32("order").unwrap(), 2);
+
+    // out of bounds
+    let result = col
+        .find(doc! {
+            "items.1.name": "y",
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].get_i32("order").unwrap(), 1);
+
+    let result = col
+        .find(doc! {
+            "items.5.name": {
+                "$eq": "y",
+            },
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result.len(), 0);
+}
+
+#[test]
+fn test_find_fetches_in_batches() {
+    let fetch_first = |name: &str, batch_size: Option<u64>| {
+        let mut config_builder = ConfigBuilder::new();
+        if let Some(batch_size) = batch_size {
+            config_builder.set_cursor_batch_size(batch_size);
+        }
+        let db = prepare_db_with_config(name, config_builder.take()).unwrap();
+        let metrics = db.metrics();
+        metrics.enable();
+
+        let col = db.collection::<Document>("test");
+        let docs: Vec<Document> = (0..TEST_SIZE).map(|i| doc! { "x": i as i64 }).collect();
+        col.insert_many(docs).unwrap();
+
+        metrics.reset();
+
+        let mut cursor = col.find(doc! {}).run().unwrap();
+        assert!(cursor.advance().unwrap());
+        let returned_after_first = metrics.snapshot().returned_count;
+
+        let mut count = 1;
+        while cursor.advance().unwrap() {
+            let doc = cursor.deserialize_current().unwrap();
+            assert_eq!(doc.get_i64("x").unwrap(), count as i64);
+            count += 1;
+        }
+        assert_eq!(count, TEST_SIZE);
+        assert!(!cursor.advance().unwrap());
+
+        (returned_after_first, metrics.snapshot().cursor_fetch_count)
+    };
+
+    // by default, the query only runs as far as the documents advanced to
+    let (returned, fetches) = fetch_first("test-find-fetches-on-demand", None);
+    assert_eq!(returned, 1);
+    assert_eq!(fetches, TEST_SIZE + 1);
+
+    // a batch reads ahead of the cursor
+    let (returned, fetches) = fetch_first("test-find-fetches-in-batches", Some(100));
+    assert_eq!(returned, 100);
+    // 10 full batches and the last empty one
+    assert_eq!(fetches, TEST_SIZE / 100 + 1);
+}
+
+#[test]
+fn test_find_count() {
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_cursor_batch_size(100);
+    let db = prepare_db_with_config("test-find-count", config_builder.take()).unwrap();
+    let metrics = db.metrics();
+    metrics.enable();
+
+    let col = db.collection::<Document>("test");
+
+    let docs: Vec<Document> = (0..TEST_SIZE).map(|i| doc! { "x": i as i64 }).collect();
+    col.insert_many(docs).unwrap();
+
+    let filter = doc! { "x": { "$gte": 100i64 } };
+    let expected = col.find(filter.clone()).run().unwrap().count() as u64;
+    assert_eq!(expected, TEST_SIZE as u64 - 100);
+
+    metrics.reset();
+    assert_eq!(col.find(filter.clone()).count().unwrap(), expected);
+    // only the row of the count is fetched
+    assert_eq!(metrics.snapshot().cursor_fetch_count, 1);
+
+    assert_eq!(col.find(filter.clone()).skip(10).count().unwrap(), expected - 10);
+    assert_eq!(col.find(filter).limit(5).count().unwrap(), 5);
+    assert_eq!(col.find(doc! { "x": -1i64 }).count().unwrap(), 0);
+    assert_eq!(db.collection::<Document>("missing").find(doc! {}).count().unwrap(), 0);
+}
+
+#[test]
+fn test_get_field() {
+    let db = prepare_db("test-get-field").unwrap();
+    let col = db.collection::<Document>("users");
+    col.insert_many(vec![
+        doc! { "_id": 1, "name": "Alice", "address": { "city": "Paris", "geo": { "lat": 48.85 } } },
+        doc! { "_id": 2, "name": "Bob", "tags": ["a", "b"] },
+    ]).unwrap();
+
+    let value = col.get_field(doc! { "_id": 1 }, "address.geo.lat").unwrap().unwrap();
+    let full = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    let expected = full.get_document("address").unwrap()
+        .get_document("geo").unwrap()
+        .get("lat").unwrap();
+    assert_eq!(&value, expected);
+
+    assert_eq!(col.get_field(doc! { "name": "Bob" }, "tags").unwrap(), Some(Bson::Array(vec!["a".into(), "b".into()])));
+    assert_eq!(col.get_field(doc! { "name": "Bob" }, "_id").unwrap(), Some(Bson::Int32(2)));
+    assert_eq!(col.get_field(doc! { "name": "Bob" }, "address.city").unwrap(), None);
+    assert_eq!(col.get_field(doc! { "name": "Carol" }, "name").unwrap(), None);
+}
+
+fn nested_logic_query(op: &str, depth: usize) -> Document {
+    let mut query = doc! { "x": 1 };
+    for _ in 0..depth {
+        query = doc! { op: [query] };
+    }
+    query
+}
+
+#[test]
+fn test_find_nested_logic_depth_limit() {
+    let db = prepare_db("test-find-nested-logic-depth-limit").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! { "x": 1 }).unwrap();
+
+    // the default limit rejects a pathologically nested query
+    let result = col.find(nested_logic_query("$or", 1_000)).run();
+    assert!(matches!(result, Err(Error::InvalidField(_))));
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_max_query_depth(3);
+    let db = prepare_db_with_config("test-find-nested-logic-depth-limit-config", config_builder.take()).unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! { "x": 1 }).unwrap();
+
+    for op in ["$and", "$or"] {
+        let found = col.find(nested_logic_query(op, 3)).run().unwrap().count();
+        assert_eq!(found, 1);
+
+        let err = col.find(nested_logic_query(op, 4)).run().err().unwrap();
+        match err {
+            Error::InvalidField(field) => {
+                assert_eq!(field.path.unwrap(), format!("/{}/[0]/{}/[0]/{}/[0]/{}", op, op, op, op));
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+}
+
+#[test]
+fn test_find_elem_match_projection() {
+    let db = prepare_db("test-find-elem-match-projection").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! {
+            "_id": 1,
+            "name": "a",
+            "items": [
+                { "sku": "x", "qty": 1 },
+                { "sku": "y", "qty": 5 },
+                { "sku": "z", "qty": 8 },
+            ],
+        },
+        doc! {
+            "_id": 2,
+            "name": "b",
+            "items": [
+                { "sku": "x", "qty": 0 },
+            ],
+        },
+    ]).unwrap();
+
+    let result = col
+        .find(doc! {})
+        .sort(doc! { "_id": 1 })
+        .projection(doc! {
+            "items": {
+                "$elemMatch": {
+                    "qty": { "$gt": 1 },
+                },
+            },
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+
+    assert_eq!(result, vec![
+        // only the first matching element is returned
+        doc! {
+            "_id": 1,
+            "items": [
+                { "sku": "y", "qty": 5 },
+            ],
+        },
+        // the field is omitted if no element matches
+        doc! {
+            "_id": 2,
+        },
+    ]);
+
+    let result = col
+        .find(doc! { "_id": 1 })
+        .projection(doc! {
+            "_id": 0,
+            "name": 1,
+            "items": {
+                "$elemMatch": { "sku": "z" },
+            },
+        })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    assert_eq!(result, vec![
+        doc! {
+            "name": "a",
+            "items": [
+                { "sku": "z", "qty": 8 },
+            ],
+        },
+    ]);
+
+    let result = col
+        .find(doc! {})
+        .projection(doc! {
+            "items": {
+                "$elemMatch": { "qty": { "$unknown": 1 } },
+            },
+        })
+        .run();
+    assert!(matches!(result, Err(Error::InvalidField(_))));
+
+    // $elemMatch only projects a top-level array
+    let result = col
+        .find(doc! {})
+        .projection(doc! {
+            "order.items": {
+                "$elemMatch": { "sku": "z" },
+            },
+        })
+        .run();
+    assert!(matches!(result, Err(Error::InvalidField(_))));
+}
+
+#[test]
+fn test_find_sort_breaks_ties_by_id() {
+    let db = prepare_db("test-find-sort-breaks-ties-by-id").unwrap();
+    let col = db.collection::<Document>("test");
+
+    let docs: Vec<Document> = (0..100).map(|i| doc! {
+        "_id": i,
+        "group": i % 3,
+        "rank": (i * 37) % 100,
+    }).collect();
+    col.insert_many(docs).unwrap();
+
+    let ids = |docs: Vec<Document>| -> Vec<i32> {
+        docs.iter().map(|doc| doc.get_i32("_id").unwrap()).collect()
+    };
+
+    // the keys are compared in the order they are specified
+    let result = col.find(doc! {})
+        .sort(doc! { "group": 1, "rank": -1 })
+        .run()
+        .unwrap()
+        .collect::<Result<Vec<Document>>>()
+        .unwrap();
+    let mut expected: Vec<(i32, i32, i32)> = (0..100).map(|i| (i % 3, -((i * 37) % 100), i)).collect();
+    expected.sort();
+    assert_eq!(ids(result), expected.iter().map(|(_, _, id)| *id).collect::<Vec<i32>>());
+
+    // the documents reordered by the previous stage are sorted by _id on ties
+    for _ in 0..3 {
+        let result = col.aggregate(vec![
+            doc! { "$sort": { "rank": 1 } },
+            doc! { "$sort": { "group": -1 } },
+        ])
+            .run()
+            .unwrap()
+            .collect::<Result<Vec<Document>>>()
+            .unwrap();
+        let mut expected: Vec<(i32, i32)> = (0..100).map(|i| (-(i % 3), i)).collect();
+        expected.sort();
+        assert_eq!(ids(result), expected.iter().map(|(_, id)| *id).collect::<Vec<i32>>());
+    }
+}
+
+#[test]
+fn test_find_sort_breaks_ties_by_document_id() {
+    let db = prepare_db("test-find-sort-breaks-ties-by-document-id").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! { "region": "us", "n": 1 },
+        doc! { "region": "eu", "n": 2 },
+        doc! { "region": "eu", "n": 1 },
+        doc! { "region": "ap", "n": 3 },
+        doc! { "region": "ap", "n": 3 },
+    ]).unwrap();
+
+    // the groups have document _ids, the ones with the same count are sorted by them
+    let result = col.aggregate(vec![
+        doc! { "$group": { "_id": { "region": "$region", "n": "$n" }, "count": { "$sum": 1 } } },
+        doc! { "$sort": { "count": -1 } },
+    ])
+        .run()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_document("_id").unwrap().clone())
+        .collect::<Vec<Document>>();
+    assert_eq!(result, vec![
+        doc! { "region": "ap", "n": 3 },
+        doc! { "region": "eu", "n": 1 },
+        doc! { "region": "eu", "n": 2 },
+        doc! { "region": "us", "n": 1 },
+    ]);
+}
+
+#[test]
+fn test_find_reuses_compiled_query() {
+    let db = prepare_db("test-find-reuses-compiled-query").unwrap();
+    let metrics = db.metrics();
+    metrics.enable();
+
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! { "x": 1, "y": 2 },
+        doc! { "x": 1, "y": 3 },
+        doc! { "x": 2, "y": 2 },
+    ]).unwrap();
+
+    metrics.reset();
+
+    let result: Vec<Document> = col.find(doc! { "x": 1, "y": 2 }).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(metrics.snapshot().query_compile_count, 1);
+
+    // the same query with the fields in another order skips the compilation
+    let result: Vec<Document> = col.find(doc! { "y": 2, "x": 1 }).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(metrics.snapshot().query_compile_count, 1);
+
+    // so does the same query with the numbers of other types
+    let result: Vec<Document> = col.find(doc! { "x": 1.0, "y": 2_i64 }).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(metrics.snapshot().query_compile_count, 1);
+
+    // a new index makes the cached program outdated
+    col.create_index(IndexModel {
+        keys: doc! { "x": 1 },
+        options: None,
+    }).unwrap();
+
+    let result: Vec<Document> = col.find(doc! { "x": 1, "y": 2 }).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(metrics.snapshot().query_compile_count, 2);
+    assert_eq!(metrics.snapshot().find_by_index_count, 1);
+}
+
+#[test]
+fn test_find_cached_query_after_rolled_back_index() {
+    let db = prepare_db("test-find-cached-query-after-rolled-back-index").unwrap();
+
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! { "x": 1, "y": 2 },
+        doc! { "x": 2, "y": 2 },
+    ]).unwrap();
+
+    // the program using the index on "x" is cached, then the index is rolled back
+    let txn = db.start_transaction().unwrap();
+    let txn_col = txn.collection::<Document>("test");
+    txn_col.create_index(IndexModel {
+        keys: doc! { "x": 1 },
+        options: None,
+    }).unwrap();
+    let result: Vec<Document> = txn_col.find(doc! { "x": 1 }).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(result.len(), 1);
+    txn.rollback().unwrap();
+
+    // another index takes the same place, the cached program must not be reused
+    col.create_index(IndexModel {
+        keys: doc! { "y": 1 },
+        options: None,
+    }).unwrap();
+
+    let result: Vec<Document> = col.find(doc! { "x": 1 }).run().unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(result.len(), 1);
+}
+
+#[derive(Debug, Deserialize)]
+struct Book {
+    title: String,
+    pages: i32,
+}
+
+#[test]
+fn test_find_typed_skips_bad_documents() {
+    let db = prepare_db("test-find-typed-skips-bad-documents").unwrap();
+    db.collection::<Document>("books").insert_many(vec![
+        doc! { "_id": 1, "title": "The Three-Body Problem", "pages": 400 },
+        doc! { "_id": 2, "title": "The Dark Forest", "pages": "many" },
+        doc! { "_id": 3, "title": "Death's End" },
+        doc! { "_id": 4, "title": "Ball Lightning", "pages": 384 },
+    ]).unwrap();
+
+    let results: Vec<Result<Book>> = db.collection::<Book>("books")
+        .find(doc! {})
+        .run()
+        .unwrap()
+        .collect();
+    assert_eq!(results.len(), 4);
+
+    let titles: Vec<String> = results.iter()
+        .filter_map(|r| r.as_ref().ok())
+        .map(|book| book.title.clone())
+        .collect();
+    assert_eq!(titles, vec!["The Three-Body Problem", "Ball Lightning"]);
+    assert_eq!(results[3].as_ref().unwrap().pages, 384);
+
+    assert!(results[1].is_err());
+    assert!(results[2].is_err());
+}
+
+#[test]
+fn test_find_array_size() {
+    let db = prepare_db("test-find-array-size").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! { "_id": 1, "tags": ["a", "b", "c"] },
+        doc! { "_id": 2, "tags": ["a"] },
+        doc! { "_id": 3, "tags": [] },
+    ]).unwrap();
+
+    let result = col.find(doc! { "tags": { "$size": 3 } }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].get_i32("_id").unwrap(), 1);
+
+    let result = col.find(doc! { "tags": { "$size": 0i64 } }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].get_i32("_id").unwrap(), 3);
+
+    for size in [Bson::Int32(-1), Bson::Double(1.5)] {
+        let result = col.find(doc! { "tags": { "$size": size } }).run();
+        assert!(matches!(result, Err(Error::InvalidField(_))));
+    }
+}
+
+#[test]
+fn test_find_numbers_equal_across_types() {
+    let db = prepare_db("test-find-numbers-equal-across-types").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! { "_id": 10i64, "n": 5i64 },
+        doc! { "_id": 11i64, "n": 6.0 },
+        doc! { "_id": 12.0, "n": 7 },
+    ]).unwrap();
+
+    // the primary keys are looked up by value
+    let doc = col.find_one(doc! { "_id": 10 }).unwrap().unwrap();
+    assert_eq!(doc.get("_id"), Some(&Bson::Int64(10)));
+    let doc = col.find_one(doc! { "_id": 11.0 }).unwrap().unwrap();
+    assert_eq!(doc.get("_id"), Some(&Bson::Int64(11)));
+    let doc = col.find_one(doc! { "_id": 12 }).unwrap().unwrap();
+    assert_eq!(doc.get("_id"), Some(&Bson::Double(12.0)));
+    assert!(col.find_one(doc! { "_id": 10.5 }).unwrap().is_none());
+
+    // the stored type is kept
+    let doc = col.find_one(doc! { "_id": 10, "n": 5 }).unwrap().unwrap();
+    assert_eq!(doc.get("n"), Some(&Bson::Int64(5)));
+
+    let result = col.find(doc! { "n": 6 }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].get("_id"), Some(&Bson::Int64(11)));
+
+    let result = col.find(doc! { "n": 7i64 }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(result.len(), 1);
+
+    // updates find the document the same way
+    let result = col.update_one(doc! { "_id": 10 }, doc! { "$set": { "n": 50 } }).unwrap();
+    assert_eq!(result.modified_count, 1);
+    let doc = col.find_one(doc! { "_id": 10i64 }).unwrap().unwrap();
+    assert_eq!(doc.get("n"), Some(&Bson::Int32(50)));
+}
+
+#[test]
+fn test_find_max_time() {
+    vec![
+        prepare_db("test-find-max-time").unwrap(),
+    ].iter().for_each(|db| {
+        let col = db.collection::<Document>("test");
+        let docs: Vec<Document> = (0..5000).map(|i| doc! {
+            "_id": i,
+            "name": format!("item-{}", i),
+        }).collect();
+        col.insert_many(docs).unwrap();
+
+        // a scan which can't finish within the budget
+        let mut cursor = col.find(doc! {
+            "name": { "$regex": "^nothing" },
+        }).max_time(std::time::Duration::from_micros(1)).run().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let err = cursor.advance().unwrap_err();
+        assert!(matches!(err, Error::MaxTimeExpired(0)));
+
+        let mut cursor = col.aggregate(vec![
+            doc! { "$match": { "name": { "$regex": "^nothing" } } },
+        ]).max_time(std::time::Duration::from_millis(1)).run().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let err = cursor.advance().unwrap_err();
+        assert!(matches!(err, Error::MaxTimeExpired(1)));
+
+        // enough time to finish
+        let result = col.find(doc! {
+            "name": { "$regex": "^item-1" },
+        }).max_time(std::time::Duration::from_secs(60)).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+        assert_eq!(result.len(), 1111);
+    });
+}
+
+#[test]
+fn test_find_cancellation() {
+    use std::sync::{mpsc, Arc};
+    use polodb_core::CancellationToken;
+
+    let db = Arc::new(prepare_db("test-find-cancellation").unwrap());
+    let col = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..20000).map(|i| doc! {
+        "_id": i,
+        "name": format!("item-{}", i),
+    }).collect();
+    col.insert_many(docs).unwrap();
+
+    let token = CancellationToken::new();
+    let (started_tx, started_rx) = mpsc::channel::<()>();
+    let (cancelled_tx, cancelled_rx) = mpsc::channel::<()>();
+
+    let handle = {
+        let db = db.clone();
+        let token = token.clone();
+        std::thread::spawn(move || {
+            let col = db.collection::<Document>("test");
+            let mut cursor = col.find(doc! {})
+                .cancellation_token(token)
+                .run()
+                .unwrap();
+            let mut count = 0;
+            loop {
+                match cursor.advance() {
+                    Ok(true) => {
+                        count += 1;
+                        if count == 10 {
+                            started_tx.send(()).unwrap();
+                            cancelled_rx.recv().unwrap();
+                        }
+                    }
+                    Ok(false) => return (count, None),
+                    Err(err) => return (count, Some(err)),
+                }
+            }
+        })
+    };
+
+    started_rx.recv().unwrap();
+    let start = std::time::Instant::now();
+    token.cancel();
+    cancelled_tx.send(()).unwrap();
+
+    let (count, err) = handle.join().unwrap();
+    assert!(matches!(err, Some(Error::Cancelled)));
+    assert!(count < 20000);
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
+
+// encode coefficient * 10^exponent as a BSON decimal
+fn mk_decimal(coefficient: i64, exponent: i32) -> polodb_core::bson::Decimal128 {
+    let c = coefficient.unsigned_abs();
+    let mut high = ((exponent + 6176) as u64) << 49;
+    if coefficient < 0 {
+        high |= 1 << 63;
+    }
+    let mut bytes = [0u8; 16];
+    bytes[0..8].copy_from_slice(&c.to_le_bytes());
+    bytes[8..16].copy_from_slice(&high.to_le_bytes());
+    polodb_core::bson::Decimal128::from_bytes(bytes)
+}
+
+#[test]
+fn test_find_decimal128() {
+    vec![
+        prepare_db("test-find-decimal128").unwrap(),
+    ].iter().for_each(|db| {
+        let col = db.collection::<Document>("prices");
+        col.insert_many(vec![
+            doc! { "_id": mk_decimal(1, 0), "price": mk_decimal(1999, -2) },
+            doc! { "_id": mk_decimal(2, 0), "price": mk_decimal(5, 0) },
+            doc! { "_id": mk_decimal(3, 0), "price": mk_decimal(-25, -1) },
+            doc! { "_id": mk_decimal(4, 0), "price": mk_decimal(100001, -4) },
+        ]).unwrap();
+
+        // the decimal is stored as it is and usable as the primary key
+        let doc = col.find_one(doc! { "_id": mk_decimal(2, 0) }).unwrap().unwrap();
+        assert_eq!(doc.get("price"), Some(&Bson::Decimal128(mk_decimal(5, 0))));
+
+        let ids = |filter: Document| -> Vec<Bson> {
+            col.find(filter)
+                .sort(doc! { "price": 1 })
+                .run()
+                .unwrap()
+                .map(|doc| doc.unwrap().get("_id").unwrap().clone())
+                .collect()
+        };
+
+        // 10.0001 and 19.99
+        assert_eq!(ids(doc! { "price": { "$gt": mk_decimal(10, 0) } }), vec![
+            Bson::Decimal128(mk_decimal(4, 0)),
+            Bson::Decimal128(mk_decimal(1, 0)),
+        ]);
+
+        // -2.5 and 5
+        assert_eq!(ids(doc! { "price": { "$lt": mk_decimal(500, -2) }, "_id": { "$ne": mk_decimal(2, 0) } }), vec![
+            Bson::Decimal128(mk_decimal(3, 0)),
+        ]);
+        assert_eq!(ids(doc! { "price": { "$lte": mk_decimal(500, -2) } }).len(), 2);
+
+        // compared with the other numeric types
+        assert_eq!(ids(doc! { "price": { "$gt": 0, "$lt": 10.5 } }), vec![
+            Bson::Decimal128(mk_decimal(2, 0)),
+            Bson::Decimal128(mk_decimal(4, 0)),
+        ]);
+        assert_eq!(ids(doc! { "price": 5i64 }).len(), 1);
+    });
+}
+
+#[test]
+fn test_find_natural_order() {
+    vec![
+        prepare_db("test-find-natural-order").unwrap(),
+    ].iter().for_each(|db| {
+        let col = db.collection::<Document>("items");
+        col.create_index(IndexModel {
+            keys: doc! { "group": 1 },
+            options: None,
+        }).unwrap();
+        col.insert_many(vec![
+            doc! { "_id": 3, "group": "a" },
+            doc! { "_id": 1, "group": "b" },
+            doc! { "_id": 4, "group": "a" },
+            doc! { "_id": 2, "group": "a" },
+        ]).unwrap();
+
+        let ids = |filter: Document, direction: i32| -> Vec<Bson> {
+            col.find(filter)
+                .sort(doc! { "$natural": direction })
+                .run()
+                .unwrap()
+                .map(|doc| doc.unwrap().get("_id").unwrap().clone())
+                .collect()
+        };
+
+        // the documents are stored in the order of their primary keys
+        assert_eq!(ids(doc! {}, 1), vec![Bson::Int32(1), Bson::Int32(2), Bson::Int32(3), Bson::Int32(4)]);
+        assert_eq!(ids(doc! {}, -1), vec![Bson::Int32(4), Bson::Int32(3), Bson::Int32(2), Bson::Int32(1)]);
+
+        // the index on "group" is bypassed
+        assert_eq!(ids(doc! { "group": "a" }, 1), vec![Bson::Int32(2), Bson::Int32(3), Bson::Int32(4)]);
+        assert_eq!(ids(doc! { "group": "a" }, -1), vec![Bson::Int32(4), Bson::Int32(3), Bson::Int32(2)]);
+
+        let skipped: Vec<Bson> = col.find(doc! {})
+            .sort(doc! { "$natural": -1 })
+            .skip(1)
+            .limit(2)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get("_id").unwrap().clone())
+            .collect();
+        assert_eq!(skipped, vec![Bson::Int32(3), Bson::Int32(2)]);
+
+        let result = col.find(doc! {})
+            .sort(doc! { "$natural": 1, "group": 1 })
+            .run();
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+
+        let result = col.find(doc! {})
+            .sort(doc! { "$natural": 2 })
+            .run();
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+    });
+}
+
+#[test]
+fn test_find_by_ids() {
+    let db = prepare_db("test-find-by-ids").unwrap();
+
+    let collection = db.collection::<Document>("users");
+    let docs: Vec<Document> = (0..5).map(|i| doc! { "_id": i, "name": format!("user{}", i) }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let ids = vec![Bson::Int32(3), Bson::Int32(42), Bson::Int32(0), Bson::String("3".to_string()), Bson::Int32(3)];
+    let result = collection.find_by_ids(&ids).unwrap();
+    assert_eq!(result.len(), 5);
+    assert_eq!(result[0].as_ref().unwrap().get_str("name").unwrap(), "user3");
+    assert!(result[1].is_none());
+    assert_eq!(result[2].as_ref().unwrap().get_str("name").unwrap(), "user0");
+    assert!(result[3].is_none());
+    assert_eq!(result[4].as_ref().unwrap().get_str("name").unwrap(), "user3");
+
+    assert!(collection.find_by_ids(&[]).unwrap().is_empty());
+
+    let missing = db.collection::<Document>("missing");
+    assert_eq!(missing.find_by_ids(&[Bson::Int32(1)]).unwrap(), vec![None]);
+
+    // the transaction reads its own writes
+    let txn = db.start_transaction().unwrap();
+    let txn_collection = txn.collection::<Document>("users");
+    txn_collection.insert_one(doc! { "_id": 42, "name": "user42" }).unwrap();
+    let result = txn_collection.find_by_ids(&[Bson::Int32(42)]).unwrap();
+    assert_eq!(result[0].as_ref().unwrap().get_str("name").unwrap(), "user42");
+    txn.rollback().unwrap();
+}
+
+#[test]
+fn test_find_multiple_operators_on_field() {
+    let db = prepare_db("test-find-multiple-operators-on-field").unwrap();
+
+    let col = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..12).map(|i| doc! { "_id": i, "age": i }).collect();
+    col.insert_many(docs).unwrap();
+
+    let find_ids = |filter: Document| -> Vec<i32> {
+        col.find(filter)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect()
+    };
+
+    // all the operators must pass
+    assert_eq!(find_ids(doc! { "age": { "$gt": 3, "$lt": 10 } }), vec![4, 5, 6, 7, 8, 9]);
+    assert_eq!(find_ids(doc! { "age": { "$lt": 10, "$gt": 3 } }), vec![4, 5, 6, 7, 8, 9]);
+    assert_eq!(find_ids(doc! { "age": { "$gte": 3, "$lte": 5, "$ne": 4 } }), vec![3, 5]);
+
+    // conflicting ranges
+    assert!(find_ids(doc! { "age": { "$gt": 8, "$lt": 3 } }).is_empty());
+    assert!(find_ids(doc! { "age": { "$gte": 5, "$lte": 5, "$ne": 5 } }).is_empty());
+
+    // mixed with $not
+    assert_eq!(find_ids(doc! { "age": { "$gt": 3, "$not": { "$gte": 6 } } }), vec![4, 5]);
+    assert!(find_ids(doc! { "age": { "$gt": 6, "$not": { "$gte": 6 } } }).is_empty());
+
+    // $not with multiple operators negates them together
+    assert_eq!(find_ids(doc! { "age": { "$not": { "$gt": 3, "$lt": 10 } } }), vec![0, 1, 2, 3, 10, 11]);
+    assert_eq!(find_ids(doc! { "age": { "$not": { "$gt": 8, "$lt": 3 } } }), (0..12).collect::<Vec<i32>>());
+    assert_eq!(
+        find_ids(doc! { "age": { "$lt": 11, "$not": { "$gt": 1, "$lt": 9 } } }),
+        vec![0, 1, 9, 10],
+    );
+    assert_eq!(find_ids(doc! { "age": { "$not": { "$not": { "$gt": 3, "$lt": 6 } } } }), vec![4, 5]);
+}
+
+#[test]
+fn test_find_json_schema() {
+    let db = prepare_db("test-find-json-schema").unwrap();
+
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! { "_id": 1, "name": "David", "age": 30, "info": { "email": "david@example.com" } },
+        doc! { "_id": 2, "name": "John", "age": 5, "info": { "email": "john@example.com" } },
+        doc! { "_id": 3, "name": "Mary", "age": 40, "info": {} },
+        doc! { "_id": 4, "name": 4, "age": 40 },
+    ]).unwrap();
+
+    let find_ids = |filter: Document| -> Vec<i32> {
+        col.find(filter)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect()
+    };
+
+    let schema = doc! {
+        "required": ["info"],
+        "properties": {
+            "name": { "bsonType": "string" },
+            "age": { "minimum": 18 },
+            "info": { "required": ["email"] },
+        },
+    };
+    assert_eq!(find_ids(doc! { "$jsonSchema": schema.clone() }), vec![1]);
+
+    // combined with the other conditions
+    assert_eq!(find_ids(doc! { "age": { "$gt": 1 }, "$jsonSchema": { "properties": { "age": { "maximum": 30 } } } }), vec![1, 2]);
+    assert_eq!(find_ids(doc! { "$or": [{ "$jsonSchema": schema }, { "_id": 4 }] }), vec![1, 4]);
+
+    let err = col.find(doc! { "$jsonSchema": { "bsonType": "str" } }).run().unwrap_err();
+    assert!(matches!(err, Error::ValidationError(_)));
+}
+
+#[test]
+fn test_scan_raw() {
+    let db = prepare_db("test-scan-raw").unwrap();
+
+    let col = db.collection::<Document>("test");
+    let docs: Vec<Document> = (0..TEST_SIZE as i32).rev().map(|i| doc! {
+        "_id": i,
+        "name": format!("name{}", i),
+        "tags": ["a", "b", "c"],
+    }).collect();
+    col.insert_many(docs).unwrap();
+    // a collection sharing the prefix of the name is not scanned
+    db.collection::<Document>("test2").insert_one(doc! { "_id": -1 }).unwrap();
+
+    let raw_ids: Vec<i32> = col.scan_raw()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+
+    let ids: Vec<i32> = col.find(doc! {})
+        .run()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+
+    assert_eq!(raw_ids, ids);
+    assert_eq!(raw_ids, (0..TEST_SIZE as i32).collect::<Vec<i32>>());
+
+    let raw_doc = col.scan_raw().unwrap().next().unwrap().unwrap();
+    let doc: Document = raw_doc.to_document().unwrap();
+    assert_eq!(doc, col.find_one(doc! { "_id": 0 }).unwrap().unwrap());
+
+    assert_eq!(db.collection::<Document>("missing").scan_raw().unwrap().count(), 0);
+}
+
+#[test]
+fn test_find_object_id_string() {
+    let hex = "65a1b2c3d4e5f60718293a4b";
+    let oid = polodb_core::bson::oid::ObjectId::parse_str(hex).unwrap();
+
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_coerce_object_id_strings(true);
+    let db = prepare_db_with_config("test-find-object-id-string", config_builder.take()).unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! { "_id": oid, "name": "typed" }).unwrap();
+    col.insert_one(doc! { "name": "other" }).unwrap();
+
+    let found = col.find_one(doc! { "_id": hex }).unwrap().unwrap();
+    assert_eq!(found.get_str("name").unwrap(), "typed");
+    let found = col.find(doc! { "_id": { "$in": [hex] } }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(found.len(), 1);
+    // the aggregation path goes through $match
+    let found = col.find(doc! { "_id": hex }).limit(1).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(found.len(), 1);
+    let found = col.find(doc! { "_id": { "$ne": hex } }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get_str("name").unwrap(), "other");
+
+    let result = col.update_one(doc! { "_id": hex }, doc! { "$set": { "name": "updated" } }).unwrap();
+    assert_eq!(result.modified_count, 1);
+    let result = col.delete_one(doc! { "_id": hex }).unwrap();
+    assert_eq!(result.deleted_count, 1);
+
+    // without the flag the hex string is compared literally
+    let db = prepare_db("test-find-object-id-string-off").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! { "_id": oid, "name": "typed" }).unwrap();
+    col.insert_one(doc! { "_id": hex, "name": "string" }).unwrap();
+
+    let found = col.find(doc! { "_id": hex }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].get_str("name").unwrap(), "string");
+    let found = col.find_one(doc! { "_id": oid }).unwrap().unwrap();
+    assert_eq!(found.get_str("name").unwrap(), "typed");
+}
+
+#[test]
+fn test_find_geo_within() {
+    let db = prepare_db("test-find-geo-within").unwrap();
+    let places = db.collection::<Document>("places");
+    places.insert_many(vec![
+        doc! { "_id": 1, "loc": [1, 1] },
+        doc! { "_id": 2, "loc": [0, 0] },
+        doc! { "_id": 3, "loc": [2.0, 2.0] },
+        doc! { "_id": 4, "loc": [3, 3] },
+        doc! { "_id": 5, "loc": [4, 0] },
+        doc! { "_id": 6, "loc": { "lng": 1, "lat": 3 } },
+        doc! { "_id": 7, "loc": [-1, 1] },
+        doc! { "_id": 8, "loc": "not a point" },
+    ]).unwrap();
+
+    let find_ids = |filter: Document| -> Vec<i32> {
+        places.find(filter)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect()
+    };
+
+    // the points on the edges are inside
+    let ids = find_ids(doc! {
+        "loc": { "$geoWithin": { "$box": [[0, 0], [2, 2]] } },
+    });
+    assert_eq!(ids, vec![1, 2, 3]);
+
+    let ids = find_ids(doc! {
+        "loc": { "$geoWithin": { "$box": [[4, 3], [1, 0]] } },
+    });
+    assert_eq!(ids, vec![1, 3, 4, 5, 6]);
+
+    let ids = find_ids(doc! {
+        "loc": { "$geoWithin": { "$polygon": [[0, 0], [4, 0], [0, 4]] } },
+    });
+    assert_eq!(ids, vec![1, 2, 3, 5, 6]);
+
+    let ids = find_ids(doc! {
+        "loc": { "$not": { "$geoWithin": { "$polygon": [[0, 0], [4, 0], [0, 4]] } } },
+    });
+    assert_eq!(ids, vec![4, 7, 8]);
+
+    let result = places.find(doc! {
+        "loc": { "$geoWithin": { "$polygon": [[0, 0], [4, 0]] } },
+    }).run();
+    assert!(matches!(result, Err(Error::InvalidField(_))));
+}
@@ -12,9 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use polodb_core::{CollectionT, IndexModel, IndexOptions, Result};
+use polodb_core::{CollectionT, ConfigBuilder, Error, IndexModel, IndexOptions, MetricsSnapshot, Result};
 use bson::{doc, Document};
-use crate::common::prepare_db;
+use crate::common::{prepare_db, prepare_db_with_config};
 
 mod common;
 
@@ -100,6 +100,41 @@ fn test_create_index_with_data() {
     });
 }
 
+#[test]
+fn test_metrics_snapshot_and_reset() {
+    let db = prepare_db("test-metrics-snapshot-and-reset").unwrap();
+    let metrics = db.metrics();
+    metrics.enable();
+
+    let col = db.collection::<Document>("teacher");
+
+    col.create_index(IndexModel {
+        keys: doc! {
+            "age": 1,
+        },
+        options: None,
+    }).unwrap();
+
+    col.insert_one(doc! {
+        "name": "David",
+        "age": 33,
+    }).unwrap();
+
+    col.find_one(doc! { "age": 33 }).unwrap().unwrap();
+    col.find_one(doc! { "age": 33 }).unwrap().unwrap();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.find_by_index_count, 2);
+
+    metrics.reset();
+    assert_eq!(metrics.snapshot(), MetricsSnapshot::default());
+    // the snapshot taken before is not affected
+    assert_eq!(snapshot.find_by_index_count, 2);
+
+    col.find_one(doc! { "age": 33 }).unwrap().unwrap();
+    assert_eq!(metrics.snapshot().find_by_index_count, 1);
+}
+
 #[test]
 fn test_find_by_index() {
     vec![
@@ -363,3 +398,482 @@ fn test_issue_171() {
 
     assert_eq!(docs[0].get_str("name").unwrap(), "David");
 }
+
+#[test]
+fn test_find_regex_prefix_by_index() {
+    use bson::Regex;
+
+    vec![
+        prepare_db("test-find-regex-prefix-by-index").unwrap(),
+    ].iter().for_each(|db| {
+        let metrics = db.metrics();
+        metrics.enable();
+
+        let names = ["alice", "alan", "albert", "bob", "Alfred", "al", "a.b", "carol"];
+        for col_name in ["indexed", "plain"] {
+            let col = db.collection::<Document>(col_name);
+            if col_name == "indexed" {
+                col.create_index(IndexModel {
+                    keys: doc! {
+                        "name": 1,
+                    },
+                    options: None,
+                }).unwrap();
+            }
+            let mut docs: Vec<Document> = names.iter().map(|name| doc! { "name": *name }).collect();
+            docs.push(doc! { "name": 10 });
+            col.insert_many(docs).unwrap();
+        }
+
+        let find_names = |col_name: &str, pattern: &str, options: &str| -> Vec<String> {
+            let col = db.collection::<Document>(col_name);
+            let mut result: Vec<String> = col.find(doc! {
+                "name": {
+                    "$regex": Regex {
+                        pattern: pattern.to_string(),
+                        options: options.to_string(),
+                    },
+                },
+            })
+                .run()
+                .unwrap()
+                .map(|doc| doc.unwrap().get_str("name").unwrap().to_string())
+                .collect();
+            result.sort();
+            result
+        };
+
+        for (pattern, options) in [("^al", ""), ("^al.*t$", ""), ("^a\\.", ""), ("^al", "i"), ("^alx?", ""), ("^z", "")] {
+            metrics.reset();
+            let indexed = find_names("indexed", pattern, options);
+            assert_eq!(indexed, find_names("plain", pattern, options), "pattern: {}", pattern);
+            if options.is_empty() && pattern != "^z" {
+                assert_eq!(metrics.find_by_index_count(), 1, "pattern: {}", pattern);
+            }
+        }
+
+        assert_eq!(find_names("indexed", "^al", ""), vec!["al", "alan", "albert", "alice"]);
+        assert_eq!(find_names("indexed", "^al.*t$", ""), vec!["albert"]);
+    });
+}
+
+#[test]
+fn test_case_insensitive_unique_index() {
+    use polodb_core::Collation;
+
+    vec![
+        prepare_db("test-case-insensitive-unique-index").unwrap(),
+    ].iter().for_each(|db| {
+        let col = db.collection::<Document>("users");
+
+        col.create_index(IndexModel {
+            keys: doc! {
+                "email": 1,
+            },
+            options: Some(IndexOptions {
+                unique: Some(true),
+                collation: Some(Collation {
+                    locale: "en".to_string(),
+                    strength: Some(2),
+                }),
+                ..Default::default()
+            }),
+        }).unwrap();
+
+        col.insert_one(doc! {
+            "email": "A@x.com",
+        }).unwrap();
+
+        let err = col.insert_one(doc! {
+            "email": "a@x.com",
+        }).unwrap_err();
+        assert!(err.to_string().contains("duplicate key error"));
+
+        col.insert_one(doc! {
+            "email": "b@x.com",
+        }).unwrap();
+
+        // the stored value keeps its case and is found by the exact value only
+        let found = col.find(doc! {
+            "email": "A@x.com",
+        }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(col.find_one(doc! { "email": "a@x.com" }).unwrap().is_none());
+
+        // the folded key is removed with the document
+        col.delete_many(doc! {
+            "email": "A@x.com",
+        }).unwrap();
+        col.insert_one(doc! {
+            "email": "a@X.com",
+        }).unwrap();
+    });
+}
+
+#[test]
+fn test_max_index_key_length() {
+    let mut config = ConfigBuilder::new();
+    config.set_max_index_key_length(8);
+    let db = prepare_db_with_config("test-max-index-key-length", config.take()).unwrap();
+    let col = db.collection::<Document>("users");
+    col.create_index(IndexModel {
+        keys: doc! { "name": 1 },
+        options: None,
+    }).unwrap();
+
+    // exactly at the limit
+    col.insert_one(doc! { "name": "abcdefgh" }).unwrap();
+
+    let err = col.insert_one(doc! { "name": "abcdefghi" }).unwrap_err();
+    assert!(matches!(err, Error::DataSizeTooLarge(8, 9)), "unexpected error: {}", err);
+    assert_eq!(col.count_documents().unwrap(), 1);
+
+    // the fields out of the indexes are not limited
+    col.insert_one(doc! { "name": "abc", "bio": "a long story about nothing" }).unwrap();
+
+    // an update can't make the value too long
+    let err = col.update_one(doc! { "name": "abc" }, doc! {
+        "$set": { "name": "abcdefghijk" },
+    }).unwrap_err();
+    assert!(err.to_string().contains("data size too large, expected: 8, actual: 11"), "unexpected error: {}", err);
+    assert!(col.find_one(doc! { "name": "abc" }).unwrap().is_some());
+
+    // neither can an index be created on the existing long values
+    let err = col.create_index(IndexModel {
+        keys: doc! { "bio": 1 },
+        options: None,
+    }).unwrap_err();
+    assert!(matches!(err, Error::DataSizeTooLarge(_)), "unexpected error: {}", err);
+}
+
+#[test]
+fn test_truncate_index_keys() {
+    let mut config = ConfigBuilder::new();
+    config.set_max_index_key_length(8);
+    config.set_truncate_index_keys(true);
+    let db = prepare_db_with_config("test-truncate-index-keys", config.take()).unwrap();
+    let metrics = db.metrics();
+    metrics.enable();
+
+    let col = db.collection::<Document>("users");
+    col.create_index(IndexModel {
+        keys: doc! { "name": 1 },
+        options: None,
+    }).unwrap();
+
+    // "abcdefgh" is at the limit, the others share it as the truncated key
+    col.insert_many(vec![
+        doc! { "_id": 1, "name": "abcdefgh" },
+        doc! { "_id": 2, "name": "abcdefghi" },
+        doc! { "_id": 3, "name": "abcdefghij" },
+        doc! { "_id": 4, "name": "abcdefg" },
+        // cut at the char boundary
+        doc! { "_id": 5, "name": "abcdefg\u{e9}" },
+    ]).unwrap();
+
+    let find_ids = |name: &str| -> Vec<i32> {
+        metrics.reset();
+        let result = col.find(doc! { "name": name })
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect();
+        assert_eq!(metrics.find_by_index_count(), 1, "name: {}", name);
+        result
+    };
+
+    assert_eq!(find_ids("abcdefgh"), vec![1]);
+    assert_eq!(find_ids("abcdefghi"), vec![2]);
+    assert_eq!(find_ids("abcdefghij"), vec![3]);
+    assert_eq!(find_ids("abcdefg"), vec![4]);
+    assert_eq!(find_ids("abcdefg\u{e9}"), vec![5]);
+    assert!(find_ids("abcdefghijk").is_empty());
+
+    // the truncated keys are removed with the documents
+    col.delete_one(doc! { "name": "abcdefghij" }).unwrap();
+    assert!(find_ids("abcdefghij").is_empty());
+    assert_eq!(find_ids("abcdefghi"), vec![2]);
+
+    col.update_one(doc! { "_id": 2 }, doc! {
+        "$set": { "name": "xyz" },
+    }).unwrap();
+    assert!(find_ids("abcdefghi").is_empty());
+    assert_eq!(find_ids("xyz"), vec![2]);
+}
+
+#[test]
+fn test_index_stats() {
+    let db = prepare_db("test-index-stats").unwrap();
+    let col = db.collection::<Document>("users");
+    col.create_index(IndexModel {
+        keys: doc! { "name": 1 },
+        options: None,
+    }).unwrap();
+    col.create_index(IndexModel {
+        keys: doc! { "age": 1 },
+        options: None,
+    }).unwrap();
+    col.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: None,
+    }).unwrap();
+
+    let docs: Vec<Document> = (0..10).map(|i| doc! {
+        "name": format!("user{}", i),
+        "age": i,
+        "email": format!("user{}@example.com", i),
+    }).collect();
+    col.insert_many(docs).unwrap();
+
+    let stats = col.index_stats().unwrap();
+    assert_eq!(stats.len(), 3);
+    assert!(stats.values().all(|count| *count == 0));
+
+    for i in 0..3 {
+        let result = col.find_one(doc! { "name": format!("user{}", i) }).unwrap();
+        assert!(result.is_some());
+    }
+    // a lookup finding nothing still uses the index
+    assert!(col.find_one(doc! { "age": 42 }).unwrap().is_none());
+    // not an indexed field
+    col.find(doc! { "missing": 1 }).run().unwrap().count();
+
+    let stats = col.index_stats().unwrap();
+    assert_eq!(stats.get("name_1"), Some(&3));
+    assert_eq!(stats.get("age_1"), Some(&1));
+    assert_eq!(stats.get("email_1"), Some(&0));
+
+    // the counts of a dropped index are forgotten
+    col.drop_index("name_1").unwrap();
+    col.create_index(IndexModel {
+        keys: doc! { "name": 1 },
+        options: None,
+    }).unwrap();
+    let stats = col.index_stats().unwrap();
+    assert_eq!(stats.get("name_1"), Some(&0));
+
+    assert!(db.collection::<Document>("missing").index_stats().unwrap().is_empty());
+}
+
+#[test]
+fn test_index_mixed_numeric_types() {
+    let db = prepare_db("test-index-mixed-numeric-types").unwrap();
+    let col = db.collection::<Document>("items");
+    col.create_index(IndexModel {
+        keys: doc! { "price": 1 },
+        options: None,
+    }).unwrap();
+
+    col.insert_many(vec![
+        doc! { "_id": 1, "price": 9.5 },
+        doc! { "_id": 2, "price": 10 },
+        doc! { "_id": 3, "price": 10_i64 },
+        doc! { "_id": 4, "price": 10.0 },
+        doc! { "_id": 5, "price": 11_i64 },
+        doc! { "_id": 6, "price": 10.5 },
+    ]).unwrap();
+
+    let find_ids = |filter: Document| -> Vec<i32> {
+        let mut ids: Vec<i32> = col.find(filter)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    // the entries stored with every numeric type are found by the index
+    assert_eq!(find_ids(doc! { "price": 10 }), vec![2, 3, 4]);
+    assert_eq!(find_ids(doc! { "price": 10_i64 }), vec![2, 3, 4]);
+    assert_eq!(find_ids(doc! { "price": 10.0 }), vec![2, 3, 4]);
+    assert_eq!(find_ids(doc! { "price": 10.5 }), vec![6]);
+    assert_eq!(find_ids(doc! { "price": 11 }), vec![5]);
+    assert_eq!(find_ids(doc! { "price": 12 }), Vec::<i32>::new());
+    assert_eq!(col.index_stats().unwrap().get("price_1"), Some(&6));
+
+    // the ranges compare the values across the types
+    assert_eq!(find_ids(doc! { "price": { "$gt": 9.5 } }), vec![2, 3, 4, 5, 6]);
+    assert_eq!(find_ids(doc! { "price": { "$lt": 10_i64 } }), vec![1]);
+    assert_eq!(find_ids(doc! { "price": { "$gte": 10, "$lte": 10.5 } }), vec![2, 3, 4, 6]);
+}
+
+#[test]
+fn test_embedded_field_index() {
+    let db = prepare_db("test-embedded-field-index").unwrap();
+    let metrics = db.metrics();
+    metrics.enable();
+
+    let col = db.collection::<Document>("customers");
+    col.create_index(IndexModel {
+        keys: doc! { "address.zip": 1 },
+        options: None,
+    }).unwrap();
+
+    col.insert_many(vec![
+        doc! { "_id": 1, "address": { "city": "Beverly Hills", "zip": "90210" } },
+        doc! { "_id": 2, "address": { "city": "New York", "zip": "10001" } },
+        doc! { "_id": 3, "address": { "city": "Beverly Hills", "zip": "90210" } },
+        doc! { "_id": 4, "address": { "city": "Unknown" } },
+        doc! { "_id": 5, "name": "no address" },
+    ]).unwrap();
+
+    let find_ids = |filter: Document| -> Vec<i32> {
+        let mut ids: Vec<i32> = col.find(filter)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    metrics.reset();
+    assert_eq!(find_ids(doc! { "address.zip": "90210" }), vec![1, 3]);
+    assert_eq!(find_ids(doc! { "address.zip": { "$eq": "10001" } }), vec![2]);
+    assert_eq!(find_ids(doc! { "address.zip": "00000" }), Vec::<i32>::new());
+    // the lookup finding no entry isn't counted
+    assert_eq!(metrics.find_by_index_count(), 2);
+
+    // the documents are sorted by the embedded field, the missing ones last
+    let ids: Vec<i32> = col.find(doc! {})
+        .sort(doc! { "address.zip": 1 })
+        .run()
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+    assert_eq!(ids, vec![2, 1, 3, 4, 5]);
+
+    // the entries follow the updates of the embedded document
+    col.update_one(doc! { "_id": 2 }, doc! {
+        "$set": { "address": { "city": "Beverly Hills", "zip": "90210" } },
+    }).unwrap();
+    assert_eq!(find_ids(doc! { "address.zip": "90210" }), vec![1, 2, 3]);
+    assert_eq!(find_ids(doc! { "address.zip": "10001" }), Vec::<i32>::new());
+}
+
+#[test]
+fn test_multikey_index() {
+    let db = prepare_db("test-multikey-index").unwrap();
+    let metrics = db.metrics();
+    metrics.enable();
+
+    let col = db.collection::<Document>("posts");
+    col.insert_one(doc! { "_id": 1, "tags": ["rust", "db", "rust"] }).unwrap();
+
+    // the arrays inserted before the index get an entry per element too
+    col.create_index(IndexModel {
+        keys: doc! { "tags": 1 },
+        options: None,
+    }).unwrap();
+
+    col.insert_many(vec![
+        doc! { "_id": 2, "tags": ["go", "db"] },
+        doc! { "_id": 3, "tags": ["rust"] },
+        doc! { "_id": 4, "tags": "rust" },
+        doc! { "_id": 5, "tags": [] },
+        doc! { "_id": 6, "tags": [1, 2.0] },
+    ]).unwrap();
+
+    let find_ids = |filter: Document| -> Vec<i32> {
+        let mut ids: Vec<i32> = col.find(filter)
+            .run()
+            .unwrap()
+            .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    metrics.reset();
+    assert_eq!(find_ids(doc! { "tags": { "$elemMatch": { "$eq": "rust" } } }), vec![1, 3]);
+    // a document is returned once, even if it has more than one of the values
+    assert_eq!(find_ids(doc! { "tags": { "$elemMatch": { "$in": ["rust", "db"] } } }), vec![1, 2, 3]);
+    assert_eq!(find_ids(doc! { "tags": { "$elemMatch": { "$in": [2, 1] } } }), vec![6]);
+    assert_eq!(metrics.find_by_index_count(), 3);
+    assert_eq!(col.index_stats().unwrap().get("tags_1"), Some(&3));
+
+    // `$in` and the plain values are still compared with the whole field
+    metrics.reset();
+    assert_eq!(find_ids(doc! { "tags": { "$in": ["rust", "go"] } }), vec![4]);
+    assert_eq!(find_ids(doc! { "tags": "db" }), Vec::<i32>::new());
+    assert_eq!(metrics.find_by_index_count(), 2);
+
+    // the conditions other than `$eq` and `$in` scan the collection
+    metrics.reset();
+    assert_eq!(find_ids(doc! { "tags": { "$elemMatch": { "$gt": 1 } } }), vec![6]);
+    assert_eq!(metrics.find_by_index_count(), 0);
+
+    // the entries of every element follow the updates and the deletions
+    col.update_one(doc! { "_id": 2 }, doc! {
+        "$set": { "tags": ["go"] },
+    }).unwrap();
+    col.delete_one(doc! { "_id": 3 }).unwrap();
+    assert_eq!(find_ids(doc! { "tags": { "$elemMatch": { "$in": ["rust", "db"] } } }), vec![1]);
+    assert_eq!(find_ids(doc! { "tags": { "$elemMatch": { "$eq": "go" } } }), vec![2]);
+}
+
+#[test]
+fn test_unique_multikey_index() {
+    let db = prepare_db("test-unique-multikey-index").unwrap();
+    let col = db.collection::<Document>("users");
+    col.create_index(IndexModel {
+        keys: doc! { "emails": 1 },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+
+    // the repeated elements of a document don't collide
+    col.insert_one(doc! { "_id": 1, "emails": ["a@example.com", "a@example.com"] }).unwrap();
+    col.insert_one(doc! { "_id": 2, "emails": ["b@example.com"] }).unwrap();
+
+    let err = col.insert_one(doc! { "_id": 3, "emails": ["c@example.com", "b@example.com"] }).unwrap_err();
+    assert!(matches!(err, Error::DuplicateKey(_)));
+}
+
+#[test]
+fn test_profile() {
+    let db = prepare_db("test-profile").unwrap();
+    // the metrics are not enabled, they only count in the profile
+    let metrics = db.metrics();
+
+    let col = db.collection::<Document>("teacher");
+    col.create_index(IndexModel {
+        keys: doc! { "age": 1 },
+        options: None,
+    }).unwrap();
+    col.insert_many(vec![
+        doc! { "name": "Alice", "age": 30 },
+        doc! { "name": "Bob", "age": 31 },
+        doc! { "name": "Carol", "age": 32 },
+        doc! { "name": "David", "age": 33 },
+        doc! { "name": "Eve", "age": 33 },
+    ]).unwrap();
+
+    let profile = db.profile(|| {
+        // by the index
+        let by_age = col.find(doc! { "age": 33 }).run()?.count();
+        assert_eq!(by_age, 2);
+        // by a collection scan
+        let by_name = col.find(doc! { "name": "Bob" }).run()?.count();
+        assert_eq!(by_name, 1);
+        Ok(())
+    }).unwrap();
+
+    assert_eq!(profile.examined_count, 2 + 5);
+    assert_eq!(profile.returned_count, 3);
+    assert_eq!(profile.find_by_index_count, 1);
+
+    // nothing is counted out of the profile
+    let snapshot = metrics.snapshot();
+    col.find(doc! { "age": 33 }).run().unwrap().count();
+    assert_eq!(metrics.snapshot(), snapshot);
+
+    let err = db.profile(|| {
+        col.find(doc! { "age": { "$unknown": 1 } }).run()?;
+        Ok(())
+    }).unwrap_err();
+    assert!(matches!(err, Error::InvalidField(_)));
+}
@@ -18,12 +18,12 @@ use std::path::PathBuf;
 use bson::Document;
 use bson::spec::ElementType;
 use serde::{Deserialize, Serialize};
-use polodb_core::{Database, Result, CollectionT};
+use polodb_core::{ConfigBuilder, Database, Result, CollectionT};
 use polodb_core::bson::{doc, Bson};
 
 mod common;
 
-use common::prepare_db;
+use common::{prepare_db, prepare_db_with_config};
 use polodb_core::test_utils::mk_db_path;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -287,3 +287,545 @@ fn test_insert_persist() {
         assert_eq!(result.len() as u64, i as u64 + 1);
     }
 }
+
+#[test]
+fn test_insert_with_defaults() {
+    use polodb_core::options::CreateCollectionOptions;
+
+    let db = prepare_db("test-insert-with-defaults").unwrap();
+    db.create_collection_with_options("users", CreateCollectionOptions::builder()
+        .defaults(doc! {
+            "role": "guest",
+            "active": true,
+        })
+        .build()
+    ).unwrap();
+
+    let collection = db.collection::<Document>("users");
+    collection.insert_many(vec![
+        doc! {
+            "_id": 1,
+            "name": "Vincent",
+        },
+        doc! {
+            "_id": 2,
+            "name": "Alice",
+            "role": "admin",
+            "active": false,
+        },
+    ]).unwrap();
+
+    let doc = collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("name").unwrap(), "Vincent");
+    assert_eq!(doc.get_str("role").unwrap(), "guest");
+    assert!(doc.get_bool("active").unwrap());
+
+    let doc = collection.find_one(doc! { "_id": 2 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("role").unwrap(), "admin");
+    assert!(!doc.get_bool("active").unwrap());
+}
+
+#[test]
+fn test_insert_one_and_fetch() {
+    let db = prepare_db("test-insert-one-and-fetch").unwrap();
+    let collection = db.collection::<Document>("books");
+
+    let stored = collection.insert_one_and_fetch(doc! {
+        "title": "The Three-Body Problem",
+    }).unwrap();
+    let id = stored.get_object_id("_id").unwrap();
+    assert_eq!(stored.get_str("title").unwrap(), "The Three-Body Problem");
+
+    let found = collection.find_one(doc! { "_id": id }).unwrap().unwrap();
+    assert_eq!(found, stored);
+}
+
+#[test]
+fn test_insert_auto_increment_id() {
+    use polodb_core::options::CreateCollectionOptions;
+
+    let db_path = mk_db_path("test-insert-auto-increment-id");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    {
+        let db = Database::open_path(db_path.as_path()).unwrap();
+        db.create_collection_with_options("logs", CreateCollectionOptions::builder()
+            .auto_increment_id(true)
+            .build()
+        ).unwrap();
+
+        let collection = db.collection::<Document>("logs");
+        let result = collection.insert_one(doc! { "msg": "a" }).unwrap();
+        assert_eq!(result.inserted_id, Bson::Int64(1));
+
+        let result = collection.insert_many(vec![
+            doc! { "msg": "b" },
+            doc! { "msg": "c" },
+        ]).unwrap();
+        assert_eq!(result.inserted_ids[&0], Bson::Int64(2));
+        assert_eq!(result.inserted_ids[&1], Bson::Int64(3));
+
+        // an explicit _id is kept and doesn't consume the sequence
+        let result = collection.insert_one(doc! { "_id": "custom", "msg": "d" }).unwrap();
+        assert_eq!(result.inserted_id, Bson::String("custom".into()));
+    }
+
+    let db = Database::open_path(db_path.as_path()).unwrap();
+    let collection = db.collection::<Document>("logs");
+    let result = collection.insert_one(doc! { "msg": "e" }).unwrap();
+    assert_eq!(result.inserted_id, Bson::Int64(4));
+
+    let doc = collection.find_one(doc! { "_id": 4i64 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("msg").unwrap(), "e");
+    assert_eq!(collection.count_documents().unwrap(), 5);
+}
+
+#[test]
+fn test_insert_auto_increment_id_concurrent() {
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+    use std::thread;
+    use polodb_core::Error;
+    use polodb_core::options::CreateCollectionOptions;
+
+    const THREAD_COUNT: usize = 8;
+    const INSERT_COUNT: usize = 20;
+
+    let db = Arc::new(prepare_db("test-insert-auto-increment-id-concurrent").unwrap());
+    db.create_collection_with_options("logs", CreateCollectionOptions::builder()
+        .auto_increment_id(true)
+        .build()
+    ).unwrap();
+
+    let handles = (0..THREAD_COUNT).map(|_| {
+        let db = db.clone();
+        thread::spawn(move || {
+            let collection = db.collection::<Document>("logs");
+            let mut inserted = 0;
+            while inserted < INSERT_COUNT {
+                match collection.insert_one(doc! { "msg": "a" }) {
+                    Ok(_) => inserted += 1,
+                    Err(Error::Busy) => (),
+                    Err(err) => panic!("unexpected error: {:?}", err),
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // every insert takes its own value of the counter
+    let collection = db.collection::<Document>("logs");
+    let ids = collection.find(doc! {}).run().unwrap()
+        .map(|doc| doc.unwrap().get_i64("_id").unwrap())
+        .collect::<BTreeSet<i64>>();
+    let expected = (1..=(THREAD_COUNT * INSERT_COUNT) as i64).collect::<BTreeSet<i64>>();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+fn test_insert_many_error_identifies_document() {
+    use polodb_core::{Error, IndexModel, IndexOptions};
+
+    let db = prepare_db("test-insert-many-error-identifies-document").unwrap();
+    let collection = db.collection::<Document>("users");
+    collection.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+
+    let err = collection.insert_many(vec![
+        doc! { "_id": 1, "email": "a@example.com" },
+        doc! { "_id": 2, "email": "b@example.com" },
+        doc! { "_id": 3, "email": "a@example.com" },
+        doc! { "_id": 4, "email": "c@example.com" },
+    ]).unwrap_err();
+    match err {
+        Error::WriteDocument(ctx) => {
+            assert_eq!(ctx.index, Some(2));
+            assert_eq!(ctx.id, Some(Bson::Int32(3)));
+            assert!(matches!(ctx.source, Error::DuplicateKey(_)), "unexpected error: {}", ctx.source);
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    // the whole batch is rolled back
+    assert_eq!(collection.count_documents().unwrap(), 0);
+}
+
+#[test]
+fn test_insert_many_duplicate_id_in_batch() {
+    use polodb_core::Error;
+
+    let db = prepare_db("test-insert-many-duplicate-id-in-batch").unwrap();
+    let collection = db.collection::<Document>("users");
+
+    let err = collection.insert_many(vec![
+        doc! { "_id": 1, "name": "Alice" },
+        doc! { "name": "Bob" },
+        doc! { "_id": 2, "name": "Carol" },
+        doc! { "_id": 1, "name": "Dave" },
+        doc! { "_id": 2, "name": "Eve" },
+    ]).unwrap_err();
+    match err {
+        Error::WriteDocument(ctx) => {
+            assert_eq!(ctx.index, Some(3));
+            assert_eq!(ctx.id, Some(Bson::Int32(1)));
+            match &ctx.source {
+                Error::DuplicateKey(dup) => {
+                    assert_eq!(dup.name, "_id_");
+                    assert_eq!(dup.ns, "users");
+                }
+                _ => panic!("unexpected error: {}", ctx.source),
+            }
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+
+    // nothing is written
+    assert_eq!(collection.count_documents().unwrap(), 0);
+
+    // documents without an _id never conflict
+    collection.insert_many(vec![
+        doc! { "name": "Bob" },
+        doc! { "name": "Bob" },
+    ]).unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 2);
+}
+
+#[test]
+fn test_insert_object_id_process_id() {
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_object_id_process_id([0xAB, 0xCD, 0xEF, 0x01, 0x23]);
+    let db = prepare_db_with_config("test-insert-object-id-process-id", config_builder.take()).unwrap();
+
+    let collection = db.collection::<Document>("test");
+    let result = collection.insert_many(vec![doc! { "x": 1 }, doc! { "x": 2 }]).unwrap();
+    assert_eq!(result.inserted_ids.len(), 2);
+
+    for id in result.inserted_ids.values() {
+        let oid = id.as_object_id().unwrap();
+        assert_eq!(&oid.bytes()[4..9], &[0xAB, 0xCD, 0xEF, 0x01, 0x23]);
+    }
+}
+
+fn buffered_config() -> polodb_core::Config {
+    let mut config_builder = ConfigBuilder::new();
+    config_builder
+        .set_write_buffer_size(3)
+        .set_oplog_enabled(true);
+    config_builder.take()
+}
+
+#[test]
+fn test_write_buffer_auto_flush() {
+    let db = prepare_db_with_config("test-write-buffer-auto-flush", buffered_config()).unwrap();
+    let collection = db.collection::<Document>("test");
+
+    // the oplog is read without flushing, so it only shows the written documents
+    // the _id is assigned when the document is buffered
+    let result = collection.insert_one(doc! { "x": 1 }).unwrap();
+    let first_id = result.inserted_id.as_object_id().unwrap();
+    collection.insert_one(doc! { "x": 2 }).unwrap();
+    assert_eq!(db.read_oplog(0).unwrap().len(), 0);
+
+    collection.insert_one(doc! { "x": 3 }).unwrap();
+    let entries = db.read_oplog(0).unwrap();
+    assert_eq!(entries.len(), 3);
+    let xs: Vec<i32> = entries.iter()
+        .map(|entry| entry.get_document("o").unwrap().get_i32("x").unwrap())
+        .collect();
+    assert_eq!(xs, vec![1, 2, 3]);
+    assert_eq!(entries[0].get_document("o").unwrap().get_object_id("_id").unwrap(), first_id);
+
+    collection.insert_one(doc! { "x": 4 }).unwrap();
+    assert_eq!(db.read_oplog(0).unwrap().len(), 3);
+}
+
+#[test]
+fn test_write_buffer_manual_flush() {
+    let db_path = mk_db_path("test-write-buffer-manual-flush");
+    let _ = std::fs::remove_dir_all(db_path.as_path());
+
+    {
+        let db = Database::open_path_with_config(&db_path, buffered_config()).unwrap();
+        let collection = db.collection::<Document>("test");
+        collection.insert_one(doc! { "x": 1 }).unwrap();
+        assert_eq!(db.read_oplog(0).unwrap().len(), 0);
+
+        collection.flush().unwrap();
+        assert_eq!(db.read_oplog(0).unwrap().len(), 1);
+
+        // flushed by closing the database
+        collection.insert_one(doc! { "x": 2 }).unwrap();
+        db.close().unwrap();
+    }
+
+    let db = Database::open_path(&db_path).unwrap();
+    assert_eq!(db.collection::<Document>("test").count_documents().unwrap(), 2);
+}
+
+#[test]
+fn test_write_buffer_read_after_insert() {
+    let db = prepare_db_with_config("test-write-buffer-read-after-insert", buffered_config()).unwrap();
+    let collection = db.collection::<Document>("test");
+
+    collection.insert_one(doc! { "x": 1 }).unwrap();
+    let found = collection.find_one(doc! { "x": 1 }).unwrap().unwrap();
+    assert!(found.get_object_id("_id").is_ok());
+
+    collection.insert_one(doc! { "x": 2 }).unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 2);
+
+    collection.insert_one(doc! { "x": 3 }).unwrap();
+    let txn = db.start_transaction().unwrap();
+    assert_eq!(txn.collection::<Document>("test").count_documents().unwrap(), 3);
+    txn.commit().unwrap();
+
+    // a duplicate key doesn't fail the read writing the buffer,
+    // the valid document is still written
+    collection.insert_one(doc! { "_id": 1, "x": 4 }).unwrap();
+    collection.insert_one(doc! { "_id": 1, "x": 5 }).unwrap();
+    let found = collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(found.get_i32("x").unwrap(), 4);
+    assert_eq!(collection.count_documents().unwrap(), 4);
+
+    // it's reported by the next flush
+    assert_eq!(collection.flush().unwrap_err().code(), 56);
+    collection.flush().unwrap();
+}
+
+#[test]
+fn test_write_buffer_auto_flush_errors() {
+    let db = prepare_db_with_config("test-write-buffer-auto-flush-errors", buffered_config()).unwrap();
+    let collection = db.collection::<Document>("test");
+
+    // the auto-flush by the third insert doesn't fail it by the error of the second one
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+    collection.insert_one(doc! { "_id": 2 }).unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 2);
+
+    // the error of its own document is returned
+    collection.insert_one(doc! { "_id": 3 }).unwrap();
+    collection.insert_one(doc! { "_id": 4 }).unwrap();
+    let err = collection.insert_one(doc! { "_id": 2 }).unwrap_err();
+    assert_eq!(err.code(), 56);
+    assert_eq!(collection.count_documents().unwrap(), 4);
+
+    // only the earlier error is left for the flush
+    assert_eq!(collection.flush().unwrap_err().code(), 56);
+    collection.flush().unwrap();
+}
+
+#[test]
+fn test_write_buffer_auto_increment_id() {
+    use polodb_core::options::CreateCollectionOptions;
+
+    let db = prepare_db_with_config("test-write-buffer-auto-increment-id", buffered_config()).unwrap();
+    db.create_collection_with_options("logs", CreateCollectionOptions::builder()
+        .auto_increment_id(true)
+        .build()
+    ).unwrap();
+
+    // the buffered documents take the ids of the collection
+    let collection = db.collection::<Document>("logs");
+    for i in 0..4 {
+        let result = collection.insert_one(doc! { "seq": i }).unwrap();
+        assert_eq!(result.inserted_id, Bson::Int64(i + 1));
+    }
+    collection.flush().unwrap();
+
+    let ids: Vec<i64> = collection.find(doc! {}).run().unwrap()
+        .map(|doc| doc.unwrap().get_i64("_id").unwrap())
+        .collect();
+    assert_eq!(ids, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_insert_invalid_key_string() {
+    use polodb_core::{Error, IndexModel};
+
+    let db = prepare_db("test-insert-invalid-key-string").unwrap();
+    let collection = db.collection::<Document>("test");
+    collection.create_index(IndexModel {
+        keys: doc! { "name": 1 },
+        options: None,
+    }).unwrap();
+
+    let err = collection.insert_one(doc! { "_id": "a\0b" }).unwrap_err();
+    assert!(matches!(err, Error::InvalidKeyString(_)));
+    let err = collection.insert_one(doc! { "name": "a\0b" }).unwrap_err();
+    assert!(matches!(err, Error::InvalidKeyString(_)));
+    assert_eq!(collection.count_documents().unwrap(), 0);
+
+    // only the values stored in the keys are checked
+    collection.insert_one(doc! { "_id": 1, "comment": "a\0b" }).unwrap();
+    let doc = collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("comment").unwrap(), "a\0b");
+}
+
+#[test]
+fn test_capped_collection_max() {
+    use polodb_core::options::CreateCollectionOptions;
+
+    let db = prepare_db("test-capped-collection-max").unwrap();
+    db.create_collection_with_options("logs", CreateCollectionOptions::builder()
+        .capped(true)
+        .max(3)
+        .build()
+    ).unwrap();
+
+    let collection = db.collection::<Document>("logs");
+    for i in 0..5 {
+        collection.insert_one(doc! { "seq": i }).unwrap();
+    }
+    collection.insert_many(vec![
+        doc! { "seq": 5 },
+        doc! { "seq": 6 },
+    ]).unwrap();
+
+    // the oldest documents are evicted, the others stay in insertion order
+    let docs = collection.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    let seqs: Vec<i32> = docs.iter().map(|doc| doc.get_i32("seq").unwrap()).collect();
+    assert_eq!(seqs, vec![4, 5, 6]);
+    assert_eq!(collection.count_documents().unwrap(), 3);
+
+    // the documents with an explicit _id are evicted by insertion order too
+    collection.insert_one(doc! { "_id": "custom", "seq": 7 }).unwrap();
+    collection.insert_one(doc! { "_id": -1i64, "seq": 8 }).unwrap();
+    let mut seqs: Vec<i32> = collection.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap()
+        .iter()
+        .map(|doc| doc.get_i32("seq").unwrap())
+        .collect();
+    seqs.sort();
+    assert_eq!(seqs, vec![6, 7, 8]);
+
+    // a deleted document leaves room for a new one
+    collection.delete_one(doc! { "seq": 8 }).unwrap();
+    collection.insert_one(doc! { "seq": 9 }).unwrap();
+    let mut seqs: Vec<i32> = collection.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap()
+        .iter()
+        .map(|doc| doc.get_i32("seq").unwrap())
+        .collect();
+    seqs.sort();
+    assert_eq!(seqs, vec![6, 7, 9]);
+}
+
+#[test]
+fn test_capped_collection_size() {
+    use polodb_core::options::CreateCollectionOptions;
+
+    let doc_size = bson::to_vec(&doc! { "_id": 1i64, "msg": "0123456789" }).unwrap().len() as u64;
+
+    let db = prepare_db("test-capped-collection-size").unwrap();
+    db.create_collection_with_options("logs", CreateCollectionOptions::builder()
+        .capped(true)
+        .size(doc_size * 2 + 1)
+        .build()
+    ).unwrap();
+
+    let collection = db.collection::<Document>("logs");
+    for i in 0..4 {
+        collection.insert_one(doc! { "msg": format!("{:010}", i) }).unwrap();
+    }
+
+    let docs = collection.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    let msgs: Vec<&str> = docs.iter().map(|doc| doc.get_str("msg").unwrap()).collect();
+    assert_eq!(msgs, vec!["0000000002", "0000000003"]);
+
+    // the limits are required
+    let result = db.create_collection_with_options("invalid", CreateCollectionOptions::builder()
+        .capped(true)
+        .build()
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_insert_many_unordered() {
+    use polodb_core::{Error, IndexModel, IndexOptions};
+    use polodb_core::options::InsertManyOptions;
+
+    let db = prepare_db("test-insert-many-unordered").unwrap();
+    let collection = db.collection::<Document>("users");
+    collection.create_index(IndexModel {
+        keys: doc! { "email": 1 },
+        options: Some(IndexOptions {
+            unique: Some(true),
+            ..Default::default()
+        }),
+    }).unwrap();
+
+    let result = collection.insert_many_with_options(vec![
+        doc! { "_id": 1, "email": "a@example.com" },
+        doc! { "_id": 2, "email": "b@example.com" },
+        doc! { "_id": 1, "email": "c@example.com" },
+        doc! { "_id": 3, "email": "a@example.com" },
+        doc! { "_id": 4, "email": "d@example.com" },
+    ], InsertManyOptions::builder().ordered(false).build()).unwrap();
+
+    let mut inserted: Vec<usize> = result.inserted_ids.keys().cloned().collect();
+    inserted.sort();
+    assert_eq!(inserted, vec![0, 1, 4]);
+
+    let failed: Vec<(Option<usize>, Option<Bson>)> = result.write_errors.iter()
+        .map(|err| match err {
+            Error::WriteDocument(ctx) => {
+                assert!(matches!(ctx.source, Error::DuplicateKey(_)), "unexpected error: {}", ctx.source);
+                (ctx.index, ctx.id.clone())
+            }
+            _ => panic!("unexpected error: {}", err),
+        })
+        .collect();
+    assert_eq!(failed, vec![
+        (Some(2), Some(Bson::Int32(1))),
+        (Some(3), Some(Bson::Int32(3))),
+    ]);
+
+    // the other documents are committed, the failed ones left nothing behind
+    assert_eq!(collection.count_documents().unwrap(), 3);
+    let doc = collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("email").unwrap(), "a@example.com");
+    assert!(collection.find_one(doc! { "email": "c@example.com" }).unwrap().is_none());
+    assert!(collection.find_one(doc! { "_id": 3 }).unwrap().is_none());
+    assert!(collection.find_one(doc! { "email": "d@example.com" }).unwrap().is_some());
+}
+
+#[test]
+fn test_max_document_depth() {
+    let mut config_builder = ConfigBuilder::new();
+    config_builder.set_max_document_depth(5);
+    let db = prepare_db_with_config("test-max-document-depth", config_builder.take()).unwrap();
+    let col = db.collection::<Document>("test");
+
+    // the embedded documents under the top-level document, the first level
+    let nested = |levels: u32| -> Bson {
+        let mut value = Bson::Int32(1);
+        for _ in 0..levels {
+            value = Bson::Document(doc! { "a": value });
+        }
+        value
+    };
+
+    col.insert_one(doc! { "_id": 1, "a": nested(4) }).unwrap();
+
+    let err = col.insert_one(doc! { "_id": 2, "a": nested(5) }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::ValidationError(_)));
+    // the arrays are levels too
+    let err = col.insert_one(doc! { "_id": 3, "a": [[[[[1]]]]] }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::ValidationError(_)));
+    assert_eq!(col.count_documents().unwrap(), 1);
+
+    let err = col.update_one(doc! { "_id": 1 }, doc! {
+        "$set": { "b": nested(5) },
+    }).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::ValidationError(_)));
+    let doc = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert!(doc.get("b").is_none());
+}
@@ -0,0 +1,257 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use polodb_core::{ConfigBuilder, Database};
+use polodb_core::bson::{doc, Bson, Document};
+use polodb_core::CollectionT;
+
+mod common;
+
+use common::prepare_db_with_config;
+
+fn prepare_db_with_oplog(db_name: &str, max_entries: u64) -> Database {
+    let mut config = ConfigBuilder::new();
+    config
+        .set_oplog_enabled(true)
+        .set_oplog_max_entries(max_entries);
+    prepare_db_with_config(db_name, config.take()).unwrap()
+}
+
+#[test]
+fn test_oplog_entries() {
+    let db = prepare_db_with_oplog("test-oplog-entries", 0);
+    let collection = db.collection::<Document>("users");
+
+    collection.insert_many(vec![
+        doc! { "_id": 1, "name": "Alice", "age": 30 },
+        doc! { "_id": 2, "name": "Bob", "age": 25 },
+    ]).unwrap();
+    collection.update_one(doc! { "_id": 1 }, doc! { "$set": { "age": 31 } }).unwrap();
+    collection.delete_one(doc! { "_id": 2 }).unwrap();
+    db.collection::<Document>("logs").insert_one(doc! { "_id": 1 }).unwrap();
+    db.collection::<Document>("logs").drop().unwrap();
+
+    let entries = db.read_oplog(0).unwrap();
+    let ops = entries.iter()
+        .map(|entry| (
+            entry.get_i64("ts").unwrap(),
+            entry.get_str("op").unwrap(),
+            entry.get_str("ns").unwrap(),
+        ))
+        .collect::<Vec<_>>();
+    assert_eq!(ops, vec![
+        (1, "i", "users"),
+        (2, "i", "users"),
+        (3, "u", "users"),
+        (4, "d", "users"),
+        (5, "i", "logs"),
+        (6, "c", "logs"),
+    ]);
+
+    assert_eq!(entries[0].get_document("o").unwrap(), &doc! { "_id": 1, "name": "Alice", "age": 30 });
+    assert_eq!(entries[2].get_document("o").unwrap(), &doc! { "_id": 1, "name": "Alice", "age": 31 });
+    assert_eq!(entries[2].get_document("o2").unwrap(), &doc! { "_id": 1 });
+    assert_eq!(entries[3].get_document("o").unwrap(), &doc! { "_id": 2 });
+    assert_eq!(entries[5].get_document("o").unwrap(), &doc! { "drop": "logs" });
+
+    let entries = db.read_oplog(4).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].get_i64("ts").unwrap(), 5);
+
+    // the writes of an aborted transaction are not logged
+    let txn = db.start_transaction().unwrap();
+    txn.collection::<Document>("users").insert_one(doc! { "_id": 3 }).unwrap();
+    txn.rollback().unwrap();
+    assert_eq!(db.read_oplog(6).unwrap().len(), 0);
+}
+
+#[test]
+fn test_oplog_max_entries() {
+    let db = prepare_db_with_oplog("test-oplog-max-entries", 10);
+    let collection = db.collection::<Document>("items");
+
+    for i in 0..25 {
+        collection.insert_one(doc! { "_id": i }).unwrap();
+    }
+
+    // only the latest 10 entries are kept
+    let entries = db.read_oplog(0).unwrap();
+    assert_eq!(entries.len(), 10);
+    let ts = entries.iter()
+        .map(|entry| entry.get_i64("ts").unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ts, (16..=25).collect::<Vec<i64>>());
+    assert_eq!(entries[0].get_document("o").unwrap().get("_id"), Some(&Bson::Int32(15)));
+}
+
+#[test]
+fn test_oplog_disabled() {
+    let db = prepare_db_with_config("test-oplog-disabled", ConfigBuilder::new().take()).unwrap();
+    let collection = db.collection::<Document>("items");
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+
+    assert!(db.read_oplog(0).unwrap().is_empty());
+}
+
+#[test]
+fn test_tail_oplog() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let db = Arc::new(prepare_db_with_oplog("test-tail-oplog", 0));
+    let collection = db.collection::<Document>("events");
+    collection.insert_one(doc! { "_id": 1 }).unwrap();
+
+    // start after the existing entry
+    let mut cursor = db.tail_oplog(1);
+    assert!(cursor.try_next().unwrap().is_none());
+    assert!(cursor.next_timeout(Duration::from_millis(50)).unwrap().is_none());
+
+    let handle = {
+        let db = db.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            let collection = db.collection::<Document>("events");
+            collection.insert_one(doc! { "_id": 2 }).unwrap();
+            collection.delete_one(doc! { "_id": 1 }).unwrap();
+        })
+    };
+
+    // blocks until the other thread writes
+    let entry = cursor.next().unwrap().unwrap();
+    assert_eq!(entry.get_i64("ts").unwrap(), 2);
+    assert_eq!(entry.get_str("op").unwrap(), "i");
+    assert_eq!(entry.get_document("o").unwrap(), &doc! { "_id": 2 });
+
+    let entry = cursor.next_timeout(Duration::from_secs(10)).unwrap().unwrap();
+    assert_eq!(entry.get_str("op").unwrap(), "d");
+    assert_eq!(cursor.last_ts(), 3);
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_tail_oplog_open_transaction() {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use polodb_core::Error;
+
+    let db = Arc::new(prepare_db_with_oplog("test-tail-oplog-open-transaction", 0));
+    let mut cursor = db.tail_oplog(0);
+
+    // the transaction takes ts 1 and commits after the other write
+    let txn = db.start_transaction().unwrap();
+    txn.collection::<Document>("events").insert_one(doc! { "_id": 1 }).unwrap();
+
+    let handle = {
+        let db = db.clone();
+        std::thread::spawn(move || {
+            let collection = db.collection::<Document>("events");
+            loop {
+                match collection.insert_one(doc! { "_id": 2 }) {
+                    Ok(_) => break,
+                    Err(Error::Busy) => (),
+                    Err(err) => panic!("unexpected error: {:?}", err),
+                }
+            }
+        })
+    };
+
+    // the other write waits for the transaction, so there is nothing to skip
+    assert!(cursor.next_timeout(Duration::from_millis(100)).unwrap().is_none());
+    txn.commit().unwrap();
+    handle.join().unwrap();
+
+    let entry = cursor.next_timeout(Duration::from_secs(10)).unwrap().unwrap();
+    assert_eq!(entry.get_i64("ts").unwrap(), 1);
+    assert_eq!(entry.get_document("o").unwrap(), &doc! { "_id": 1 });
+    let entry = cursor.next_timeout(Duration::from_secs(10)).unwrap().unwrap();
+    assert_eq!(entry.get_i64("ts").unwrap(), 2);
+    assert_eq!(entry.get_document("o").unwrap(), &doc! { "_id": 2 });
+}
+
+#[test]
+fn test_oplog_comment() {
+    let db = prepare_db_with_oplog("test-oplog-comment", 0);
+    let collection = db.collection::<Document>("users");
+
+    collection.insert_many(vec![
+        doc! { "_id": 1, "age": 30 },
+        doc! { "_id": 2, "age": 25 },
+    ]).unwrap();
+    let result = collection.update_many(
+        doc! { "age": { "$gt": 20 }, "$comment": "birthday" },
+        doc! { "$inc": { "age": 1 } },
+    ).unwrap();
+    assert_eq!(result.modified_count, 2);
+    let result = collection.delete_one(doc! { "$comment": "cleanup", "_id": 2 }).unwrap();
+    assert_eq!(result.deleted_count, 1);
+
+    let entries = db.read_oplog(0).unwrap();
+    let comments = entries.iter()
+        .map(|entry| (entry.get_str("op").unwrap(), entry.get("comment").cloned()))
+        .collect::<Vec<_>>();
+    assert_eq!(comments, vec![
+        ("i", None),
+        ("i", None),
+        ("u", Some(Bson::String("birthday".to_string()))),
+        ("u", Some(Bson::String("birthday".to_string()))),
+        ("d", Some(Bson::String("cleanup".to_string()))),
+    ]);
+    assert_eq!(entries[2].get_document("o").unwrap(), &doc! { "_id": 1, "age": 31 });
+}
+
+#[test]
+fn test_oplog_concurrent_writes() {
+    use std::sync::Arc;
+    use std::thread;
+    use polodb_core::Error;
+
+    const THREAD_COUNT: i32 = 8;
+    const INSERT_COUNT: i32 = 20;
+
+    let db = Arc::new(prepare_db_with_oplog("test-oplog-concurrent-writes", 0));
+
+    let handles = (0..THREAD_COUNT).map(|t| {
+        let db = db.clone();
+        thread::spawn(move || {
+            let collection = db.collection::<Document>("users");
+            let mut i = 0;
+            while i < INSERT_COUNT {
+                match collection.insert_one(doc! { "_id": t * INSERT_COUNT + i }) {
+                    Ok(_) => i += 1,
+                    Err(Error::Busy) => (),
+                    Err(err) => panic!("unexpected error: {:?}", err),
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // every insert has its own entry, numbered without a gap
+    let entries = db.read_oplog(0).unwrap();
+    let ts = entries.iter()
+        .map(|entry| entry.get_i64("ts").unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ts, (1..=(THREAD_COUNT * INSERT_COUNT) as i64).collect::<Vec<_>>());
+
+    let mut ids = entries.iter()
+        .map(|entry| entry.get_document("o").unwrap().get_i32("_id").unwrap())
+        .collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, (0..THREAD_COUNT * INSERT_COUNT).collect::<Vec<_>>());
+}
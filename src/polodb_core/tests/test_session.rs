@@ -12,12 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use polodb_core::{Result, CollectionT};
+use polodb_core::{Result, CollectionT, ConfigBuilder, IsolationLevel};
 use polodb_core::bson::{Document, doc};
 
 mod common;
 
-use common::prepare_db;
+use common::{prepare_db, prepare_db_with_config};
 
 #[test]
 fn test_transaction_commit() {
@@ -108,3 +108,169 @@ fn test_rollback() {
         assert_eq!(collection.count_documents().unwrap(), 0);
     });
 }
+
+#[test]
+fn test_rollback_on_drop() {
+    let db = prepare_db("test-rollback-on-drop").unwrap();
+
+    {
+        let txn = db.start_transaction().unwrap();
+        let collection = txn.collection::<Document>("test");
+        collection.insert_one(doc! { "_id": 1 }).unwrap();
+
+        // a clone keeps the transaction alive
+        let cloned = txn.clone();
+        drop(txn);
+        let collection = cloned.collection::<Document>("test");
+        collection.insert_one(doc! { "_id": 2 }).unwrap();
+        assert_eq!(collection.count_documents().unwrap(), 2);
+    }
+
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), 0);
+
+    // the rolled back transaction doesn't block the next ones
+    let txn = db.start_transaction().unwrap();
+    txn.collection::<Document>("test").insert_one(doc! { "_id": 1 }).unwrap();
+    txn.commit().unwrap();
+    assert_eq!(collection.count_documents().unwrap(), 1);
+}
+
+#[test]
+fn test_commit_before_drop() {
+    let db = prepare_db("test-commit-before-drop").unwrap();
+
+    {
+        let txn = db.start_transaction().unwrap();
+        let collection = txn.collection::<Document>("test");
+        collection.insert_many(vec![doc! { "_id": 1 }, doc! { "_id": 2 }]).unwrap();
+        txn.commit().unwrap();
+    }
+
+    let collection = db.collection::<Document>("test");
+    assert_eq!(collection.count_documents().unwrap(), 2);
+    db.close().unwrap();
+}
+
+#[test]
+fn test_snapshot_ignores_concurrent_writes() {
+    use std::sync::Arc;
+    use std::thread;
+
+    let db = Arc::new(prepare_db("test-snapshot").unwrap());
+    let collection = db.collection::<Document>("test");
+
+    for i in 0..10 {
+        collection.insert_one(doc! {
+            "_id": i,
+            "content": i.to_string(),
+        }).unwrap();
+    }
+
+    let snapshot = db.snapshot().unwrap();
+    let snapshot_collection = snapshot.collection::<Document>("test");
+    let mut cursor = snapshot_collection.find(doc! {}).run().unwrap();
+    assert!(cursor.advance().unwrap());
+
+    let db2 = db.clone();
+    thread::spawn(move || {
+        let collection = db2.collection::<Document>("test");
+        for i in 10..20 {
+            collection.insert_one(doc! {
+                "_id": i,
+                "content": i.to_string(),
+            }).unwrap();
+        }
+    }).join().unwrap();
+
+    let mut count = 1;
+    while cursor.advance().unwrap() {
+        count += 1;
+    }
+    assert_eq!(count, 10);
+    assert_eq!(snapshot_collection.count_documents().unwrap(), 10);
+
+    assert_eq!(collection.count_documents().unwrap(), 20);
+}
+
+fn count_after_concurrent_commit(db_name: &str, isolation_level: IsolationLevel) -> (u64, u64) {
+    use std::sync::Arc;
+    use std::thread;
+
+    let mut config = ConfigBuilder::new();
+    config.set_isolation_level(isolation_level);
+    let db = Arc::new(prepare_db_with_config(db_name, config.take()).unwrap());
+    let collection = db.collection::<Document>("test");
+    for i in 0..10 {
+        collection.insert_one(doc! { "_id": i }).unwrap();
+    }
+
+    let txn = db.start_transaction().unwrap();
+    let txn_collection = txn.collection::<Document>("test");
+    let before = txn_collection.count_documents().unwrap();
+
+    let db2 = db.clone();
+    thread::spawn(move || {
+        let collection = db2.collection::<Document>("test");
+        for i in 10..20 {
+            collection.insert_one(doc! { "_id": i }).unwrap();
+        }
+    }).join().unwrap();
+
+    let after = txn_collection.count_documents().unwrap();
+    txn.commit().unwrap();
+    (before, after)
+}
+
+#[test]
+fn test_isolation_level() {
+    // the commit is visible to the transaction started before it
+    let (before, after) = count_after_concurrent_commit("test-isolation-read-committed", IsolationLevel::ReadCommitted);
+    assert_eq!(before, 10);
+    assert_eq!(after, 20);
+
+    // the transaction keeps reading the data at the time it started
+    let (before, after) = count_after_concurrent_commit("test-isolation-snapshot", IsolationLevel::Snapshot);
+    assert_eq!(before, 10);
+    assert_eq!(after, 10);
+}
+
+#[test]
+fn test_read_your_writes() {
+    for isolation_level in [IsolationLevel::ReadCommitted, IsolationLevel::Snapshot] {
+        let mut config = ConfigBuilder::new();
+        config.set_isolation_level(isolation_level);
+        let db = prepare_db_with_config(&format!("test-read-your-writes-{:?}", isolation_level), config.take()).unwrap();
+
+        let txn = db.start_transaction().unwrap();
+        let collection = txn.collection::<Document>("test");
+        collection.insert_one(doc! { "_id": 1, "x": 1 }).unwrap();
+
+        // every kind of read in the transaction sees the pending insert
+        let found = collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+        assert_eq!(found.get_i32("x").unwrap(), 1);
+        assert_eq!(collection.count_documents().unwrap(), 1);
+        let found = collection.find(doc! { "x": 1 }).limit(1).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+        assert_eq!(found.len(), 1);
+        let aggregated = collection.aggregate(vec![doc! { "$match": { "x": 1 } }])
+            .run()
+            .unwrap()
+            .collect::<Result<Vec<Document>>>()
+            .unwrap();
+        assert_eq!(aggregated.len(), 1);
+
+        // so does a write
+        let result = collection.update_one(doc! { "_id": 1 }, doc! { "$set": { "x": 2 } }).unwrap();
+        assert_eq!(result.matched_count, 1);
+        let found = collection.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+        assert_eq!(found.get_i32("x").unwrap(), 2);
+
+        // but nothing is seen outside of the transaction
+        assert!(db.collection::<Document>("test").find_one(doc! { "_id": 1 }).unwrap().is_none());
+
+        txn.commit().unwrap();
+
+        let found = db.collection::<Document>("test").find_one(doc! { "_id": 1 }).unwrap().unwrap();
+        assert_eq!(found.get_i32("x").unwrap(), 2);
+    }
+}
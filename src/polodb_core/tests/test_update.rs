@@ -13,8 +13,8 @@
 // limitations under the License.
 
 use polodb_core::options::UpdateOptions;
-use polodb_core::{CollectionT, Database, Result};
-use polodb_core::bson::{Document, doc};
+use polodb_core::{CollectionT, Database, Error, Result};
+use polodb_core::bson::{Bson, DateTime, Document, doc};
 
 mod common;
 
@@ -152,6 +152,50 @@ fn test_update_rename() {
     assert_eq!(result.get("num2").unwrap().as_i32().unwrap(), 0);
 }
 
+#[test]
+fn test_update_rename_nested_and_array() {
+    let db = prepare_db("test-update-rename-nested-and-array").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! {
+        "_id": 0,
+        "a": {
+            "x": 1,
+        },
+        "items": [
+            { "x": 1 },
+        ],
+    }).unwrap();
+
+    // the dotted paths are resolved through the embedded documents
+    col.update_one(doc! { "_id": 0 }, doc! {
+        "$rename": {
+            "a.x": "b.y",
+        },
+    }).unwrap();
+    let result = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    assert_eq!(result.get_document("a").unwrap(), &doc! {});
+    assert_eq!(result.get_document("b").unwrap(), &doc! { "y": 1 });
+
+    // renaming the elements of an array is rejected
+    let err = col.update_one(doc! { "_id": 0 }, doc! {
+        "$rename": {
+            "items.0.x": "items.0.y",
+        },
+    }).unwrap_err();
+    assert!(matches!(err, Error::InvalidField(_)), "unexpected error: {}", err);
+
+    let err = col.update_one(doc! { "_id": 0 }, doc! {
+        "$rename": {
+            "items.x": "items.y",
+        },
+    }).unwrap_err();
+    assert!(matches!(err, Error::FieldTypeUnexpected(_)), "unexpected error: {}", err);
+
+    // the document is not changed
+    let result = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    assert_eq!(result.get_array("items").unwrap(), &vec![Bson::Document(doc! { "x": 1 })]);
+}
+
 #[test]
 fn test_update_unset() {
     let db = prepare_db_with_data("test-update-unset");
@@ -234,6 +278,53 @@ fn test_update_min() {
 
 }
 
+#[test]
+fn test_update_min_max_dates_and_strings() {
+    let db = prepare_db("test-update-min-max-dates-and-strings").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! {
+        "_id": 0,
+        "lastSeen": DateTime::from_millis(2000),
+        "name": "m",
+    }).unwrap();
+
+    let update = |update: Document| {
+        col.update_one(doc! { "_id": 0 }, update).unwrap()
+    };
+    let get = || col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+
+    // an earlier date doesn't change the field
+    let result = update(doc! { "$max": { "lastSeen": DateTime::from_millis(1000) } });
+    assert_eq!(result.modified_count, 0);
+    assert_eq!(*get().get_datetime("lastSeen").unwrap(), DateTime::from_millis(2000));
+
+    let result = update(doc! { "$max": { "lastSeen": DateTime::from_millis(3000) } });
+    assert_eq!(result.modified_count, 1);
+    assert_eq!(*get().get_datetime("lastSeen").unwrap(), DateTime::from_millis(3000));
+
+    update(doc! { "$min": { "lastSeen": DateTime::from_millis(500) } });
+    assert_eq!(*get().get_datetime("lastSeen").unwrap(), DateTime::from_millis(500));
+
+    update(doc! { "$min": { "name": "z" } });
+    assert_eq!(get().get_str("name").unwrap(), "m");
+    update(doc! { "$min": { "name": "a" } });
+    assert_eq!(get().get_str("name").unwrap(), "a");
+    update(doc! { "$max": { "name": "b" } });
+    assert_eq!(get().get_str("name").unwrap(), "b");
+
+    // an absent field is set
+    update(doc! { "$max": { "firstSeen": DateTime::from_millis(100) } });
+    assert_eq!(*get().get_datetime("firstSeen").unwrap(), DateTime::from_millis(100));
+    update(doc! { "$min": { "nickname": "x" } });
+    assert_eq!(get().get_str("nickname").unwrap(), "x");
+
+    // values of different types are compared by the order of their types
+    update(doc! { "$max": { "name": 10 } });
+    assert_eq!(get().get_str("name").unwrap(), "b");
+    update(doc! { "$min": { "name": 10 } });
+    assert_eq!(get().get_i32("name").unwrap(), 10);
+}
+
 #[test]
 fn test_update_push() {
     let db = prepare_db("test-update-push").unwrap();
@@ -258,6 +349,57 @@ fn test_update_push() {
     assert_eq!(content.len(), 4);
 }
 
+#[test]
+fn test_update_push_position() {
+    let db = prepare_db("test-update-push-position").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! {
+        "_id": 0,
+        "content": [1, 2, 3],
+    }).unwrap();
+
+    let push = |modifiers: Document| {
+        col.update_one(doc! { "_id": 0 }, doc! {
+            "$push": {
+                "content": modifiers,
+            },
+        })
+    };
+    let content = || {
+        let result = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+        result.get_array("content").unwrap()
+            .iter()
+            .map(|item| item.as_i32().unwrap())
+            .collect::<Vec<i32>>()
+    };
+
+    // insert at the front
+    push(doc! { "$each": [10, 11], "$position": 0 }).unwrap();
+    assert_eq!(content(), vec![10, 11, 1, 2, 3]);
+
+    // insert in the middle
+    push(doc! { "$each": [20], "$position": 2 }).unwrap();
+    assert_eq!(content(), vec![10, 11, 20, 1, 2, 3]);
+
+    // a negative position counts from the end
+    push(doc! { "$each": [30], "$position": -1 }).unwrap();
+    assert_eq!(content(), vec![10, 11, 20, 1, 2, 30, 3]);
+
+    // a position past the end appends
+    push(doc! { "$each": [40], "$position": 100 }).unwrap();
+    assert_eq!(content().last(), Some(&40));
+    assert_eq!(content().len(), 8);
+
+    // $each without $position appends all the elements
+    push(doc! { "$each": [50, 51] }).unwrap();
+    assert_eq!(content().len(), 10);
+    assert_eq!(content()[8..], [50, 51]);
+
+    // $position requires $each
+    assert!(push(doc! { "$position": 0 }).is_err());
+    assert_eq!(content().len(), 10);
+}
+
 #[test]
 fn test_upsert() {
     let db = prepare_db("test-upsert").unwrap();
@@ -300,3 +442,320 @@ fn test_upsert() {
     // assert_eq!(hobbies.len(), 1);
     // assert_eq!(hobbies[0].as_str().unwrap(), "reading");
 }
+
+#[test]
+fn test_update_pop() {
+    let db = prepare_db("test-update-pop").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! {
+            "_id": 0,
+            "content": [1, 2, 3],
+        },
+        doc! {
+            "_id": 1,
+            "content": [],
+        },
+        doc! {
+            "_id": 2,
+        },
+    ]).unwrap();
+
+    // only 1 or -1 is accepted
+    let result = col.update_many(doc! {
+        "_id": 0,
+    }, doc! {
+        "$pop": {
+            "content": 0,
+        },
+    });
+    assert!(result.is_err());
+
+    // 1 removes the last element
+    col.update_many(doc! {
+        "_id": 0,
+    }, doc! {
+        "$pop": {
+            "content": 1,
+        },
+    }).unwrap();
+    let result = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    let content = result.get_array("content").unwrap();
+    assert_eq!(content.len(), 2);
+    assert_eq!(content[0].as_i32().unwrap(), 1);
+    assert_eq!(content[1].as_i32().unwrap(), 2);
+
+    // -1 removes the first element
+    col.update_many(doc! {
+        "_id": 0,
+    }, doc! {
+        "$pop": {
+            "content": -1,
+        },
+    }).unwrap();
+    let result = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    let content = result.get_array("content").unwrap();
+    assert_eq!(content.len(), 1);
+    assert_eq!(content[0].as_i32().unwrap(), 2);
+
+    // empty and absent arrays are untouched
+    let update_result = col.update_many(doc! {
+        "_id": {
+            "$gte": 1,
+        },
+    }, doc! {
+        "$pop": {
+            "content": 1,
+        },
+    }).unwrap();
+    assert_eq!(update_result.matched_count, 2);
+    assert_eq!(update_result.modified_count, 0);
+
+    let result = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert!(result.get_array("content").unwrap().is_empty());
+    let result = col.find_one(doc! { "_id": 2 }).unwrap().unwrap();
+    assert!(result.get("content").is_none());
+}
+
+#[test]
+fn test_update_versioned() {
+    let db = prepare_db("test-update-versioned").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! {
+        "_id": 0,
+        "name": "Vincent",
+        "__v": 0,
+    }).unwrap();
+
+    let options = UpdateOptions::builder().versioned(true).build();
+
+    // both writers have read version 0
+    let result = col.update_one_with_options(doc! {
+        "_id": 0,
+        "__v": 0,
+    }, doc! {
+        "$set": {
+            "name": "Alice",
+        },
+    }, options.clone()).unwrap();
+    assert_eq!(result.modified_count, 1);
+
+    let err = col.update_one_with_options(doc! {
+        "_id": 0,
+        "__v": 0,
+    }, doc! {
+        "$set": {
+            "name": "Bob",
+        },
+    }, options.clone()).unwrap_err();
+    assert!(matches!(err, polodb_core::Error::VersionConflict(_)));
+
+    let doc = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("name").unwrap(), "Alice");
+    assert_eq!(doc.get_i32("__v").unwrap(), 1);
+
+    // retry with the new version
+    col.update_one_with_options(doc! {
+        "_id": 0,
+        "__v": 1,
+    }, doc! {
+        "$set": {
+            "name": "Bob",
+        },
+    }, options.clone()).unwrap();
+    let doc = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    assert_eq!(doc.get_str("name").unwrap(), "Bob");
+    assert_eq!(doc.get_i32("__v").unwrap(), 2);
+
+    // a missing document is not a conflict
+    let result = col.update_one_with_options(doc! {
+        "_id": 1,
+        "__v": 0,
+    }, doc! {
+        "$set": {
+            "name": "Bob",
+        },
+    }, options.clone()).unwrap();
+    assert_eq!(result.matched_count, 0);
+
+    // the version field is managed by the database
+    let result = col.update_one_with_options(doc! {
+        "_id": 0,
+        "__v": 2,
+    }, doc! {
+        "$set": {
+            "__v": 10,
+        },
+    }, options);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_update_versioned_concurrent() {
+    use std::sync::Arc;
+    use std::thread;
+
+    const THREAD_COUNT: usize = 8;
+    const UPDATE_COUNT: usize = 20;
+
+    let db = Arc::new(prepare_db("test-update-versioned-concurrent").unwrap());
+    let col = db.collection::<Document>("test");
+    col.insert_one(doc! {
+        "_id": 0,
+        "count": 0,
+        "__v": 0,
+    }).unwrap();
+
+    let handles = (0..THREAD_COUNT).map(|_| {
+        let db = db.clone();
+        thread::spawn(move || {
+            let col = db.collection::<Document>("test");
+            let options = UpdateOptions::builder().versioned(true).build();
+            let mut updated = 0;
+            while updated < UPDATE_COUNT {
+                // read, then write the incremented count back with the version read
+                let doc = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+                let count = doc.get_i32("count").unwrap();
+                let version = doc.get("__v").unwrap().clone();
+                let result = col.update_one_with_options(doc! {
+                    "_id": 0,
+                    "__v": version,
+                }, doc! {
+                    "$set": {
+                        "count": count + 1,
+                    },
+                }, options.clone());
+                match result {
+                    Ok(_) => updated += 1,
+                    // read again and retry
+                    Err(Error::VersionConflict(_)) | Err(Error::Busy) => (),
+                    Err(err) => panic!("unexpected error: {:?}", err),
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // no update is lost between the version check and the write
+    let doc = col.find_one(doc! { "_id": 0 }).unwrap().unwrap();
+    assert_eq!(doc.get_i32("count").unwrap(), (THREAD_COUNT * UPDATE_COUNT) as i32);
+    assert_eq!(doc.get_i32("__v").unwrap(), (THREAD_COUNT * UPDATE_COUNT) as i32);
+}
+
+#[test]
+fn test_update_dry_run() {
+    let db = prepare_db("test-update-dry-run").unwrap();
+    let col = db.collection::<Document>("test");
+    col.insert_many(vec![
+        doc! { "_id": 0, "group": "a", "count": 1 },
+        doc! { "_id": 1, "group": "a", "count": 2 },
+        doc! { "_id": 2, "group": "b", "count": 3 },
+    ]).unwrap();
+
+    let update = doc! { "$inc": { "count": 10 } };
+
+    let preview = col.update_many_dry_run(doc! { "group": "a" }, update.clone()).unwrap();
+    assert_eq!(preview, vec![
+        doc! { "_id": 0, "group": "a", "count": 11 },
+        doc! { "_id": 1, "group": "a", "count": 12 },
+    ]);
+
+    let preview = col.update_one_dry_run(doc! { "group": "a" }, update.clone()).unwrap();
+    assert_eq!(preview, vec![
+        doc! { "_id": 0, "group": "a", "count": 11 },
+    ]);
+
+    assert!(col.update_many_dry_run(doc! { "group": "c" }, update.clone()).unwrap().is_empty());
+
+    // nothing is written
+    let docs = col.find(doc! {}).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(docs, vec![
+        doc! { "_id": 0, "group": "a", "count": 1 },
+        doc! { "_id": 1, "group": "a", "count": 2 },
+        doc! { "_id": 2, "group": "b", "count": 3 },
+    ]);
+
+    // the preview matches the real update
+    let result = col.update_many(doc! { "group": "a" }, update).unwrap();
+    assert_eq!(result.modified_count, 2);
+    let docs = col.find(doc! { "group": "a" }).run().unwrap().collect::<Result<Vec<Document>>>().unwrap();
+    assert_eq!(docs, vec![
+        doc! { "_id": 0, "group": "a", "count": 11 },
+        doc! { "_id": 1, "group": "a", "count": 12 },
+    ]);
+}
+
+#[test]
+fn test_update_many_with_limit() {
+    let db = prepare_db("test-update-many-with-limit").unwrap();
+    let collection = db.collection::<Document>("test");
+
+    let docs: Vec<Document> = (0..10).map(|i| doc! { "_id": i, "migrated": false }).collect();
+    collection.insert_many(docs).unwrap();
+
+    let result = collection.update_many_with_options(
+        doc! { "migrated": false },
+        doc! { "$set": { "migrated": true } },
+        UpdateOptions::builder().limit(4).build(),
+    ).unwrap();
+    assert_eq!(result.matched_count, 4);
+    assert_eq!(result.modified_count, 4);
+    assert_eq!(collection.find(doc! { "migrated": true }).run().unwrap().count(), 4);
+
+    let result = collection.update_many_with_options(
+        doc! { "migrated": false },
+        doc! { "$set": { "migrated": true } },
+        UpdateOptions::builder().limit(100).build(),
+    ).unwrap();
+    assert_eq!(result.matched_count, 6);
+    assert_eq!(result.modified_count, 6);
+    assert_eq!(collection.find(doc! { "migrated": false }).run().unwrap().count(), 0);
+}
+
+#[test]
+fn test_update_inc_numeric_types() {
+    let db = prepare_db("test-update-inc-numeric-types").unwrap();
+    let col = db.collection::<Document>("test");
+
+    let inc = |value: Bson, by: Bson| -> Result<Bson> {
+        col.delete_many(doc! {})?;
+        col.insert_one(doc! { "_id": 1, "value": value })?;
+        col.update_one(doc! { "_id": 1 }, doc! { "$inc": { "value": by } })?;
+        let doc = col.find_one(doc! { "_id": 1 })?.unwrap();
+        Ok(doc.get("value").unwrap().clone())
+    };
+
+    assert_eq!(inc(Bson::Int32(1), Bson::Int32(2)).unwrap(), Bson::Int32(3));
+    assert_eq!(inc(Bson::Int32(1), Bson::Int64(2)).unwrap(), Bson::Int64(3));
+    assert_eq!(inc(Bson::Int64(1), Bson::Int32(2)).unwrap(), Bson::Int64(3));
+    assert_eq!(inc(Bson::Int64(1), Bson::Int64(2)).unwrap(), Bson::Int64(3));
+
+    // any Double makes a Double
+    assert_eq!(inc(Bson::Int64(10), Bson::Double(0.5)).unwrap(), Bson::Double(10.5));
+    assert_eq!(inc(Bson::Int32(10), Bson::Double(0.5)).unwrap(), Bson::Double(10.5));
+    assert_eq!(inc(Bson::Double(0.5), Bson::Int32(1)).unwrap(), Bson::Double(1.5));
+    assert_eq!(inc(Bson::Double(0.5), Bson::Int64(1)).unwrap(), Bson::Double(1.5));
+    assert_eq!(inc(Bson::Double(0.5), Bson::Double(0.25)).unwrap(), Bson::Double(0.75));
+
+    // an Int32 overflowing is promoted to Int64
+    assert_eq!(
+        inc(Bson::Int32(i32::MAX), Bson::Int32(1)).unwrap(),
+        Bson::Int64(i32::MAX as i64 + 1),
+    );
+    assert_eq!(
+        inc(Bson::Int32(i32::MIN), Bson::Int32(-1)).unwrap(),
+        Bson::Int64(i32::MIN as i64 - 1),
+    );
+
+    // an Int64 can't be promoted
+    let err = inc(Bson::Int64(i64::MAX), Bson::Int32(1)).unwrap_err();
+    assert!(matches!(err, Error::DataOverflow));
+    let doc = col.find_one(doc! { "_id": 1 }).unwrap().unwrap();
+    assert_eq!(doc.get("value").unwrap(), &Bson::Int64(i64::MAX));
+
+    let err = inc(Bson::Null, Bson::Int32(1)).unwrap_err();
+    assert!(matches!(err, Error::IncrementNullField));
+}
@@ -1,6 +1,8 @@
 
 mod transaction;
 mod transaction_inner;
+mod snapshot;
 
 pub(crate) use transaction_inner::TransactionInner;
 pub use transaction::Transaction;
+pub use snapshot::Snapshot;
@@ -0,0 +1,44 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Weak};
+use crate::SnapshotCollection;
+use crate::db::db_inner::DatabaseInner;
+use super::transaction_inner::TransactionInner;
+
+/// A read-only view of the database at a fixed point in time.
+///
+/// Writes committed after the snapshot is taken are not visible
+/// to the queries running on it, which makes it suitable for long scans.
+#[derive(Clone)]
+pub struct Snapshot {
+    db: Weak<DatabaseInner>,
+    inner: Arc<TransactionInner>,
+}
+
+impl Snapshot {
+
+    pub(crate) fn new(db: Weak<DatabaseInner>, inner: TransactionInner) -> Snapshot {
+        Snapshot {
+            db,
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Return a collection reading from this snapshot.
+    pub fn collection<T>(&self, col_name: &str) -> SnapshotCollection<T> {
+        SnapshotCollection::new(self.db.clone(), col_name, self.inner.as_ref().clone())
+    }
+
+}
@@ -13,15 +13,62 @@
 // limitations under the License.
 
 use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
 use serde::Serialize;
 use crate::{TransactionalCollection};
 use crate::db::db_inner::DatabaseInner;
 use super::transaction_inner::TransactionInner;
 
+/// A transaction started by [`Database::start_transaction`](crate::Database::start_transaction).
+///
+/// The operations on the collections returned by [`Transaction::collection`] are only
+/// visible to the transaction until it's committed.
+///
+/// A transaction which is neither committed nor rolled back is rolled back
+/// when it's dropped, with all its clones.
+///
+/// ```rust
+/// use polodb_core::{Database, CollectionT};
+/// use polodb_core::bson::{Document, doc};
+///
+/// # let db_path = polodb_core::test_utils::mk_db_path("doc-test-transaction");
+/// let db = Database::open_path(db_path).unwrap();
+///
+/// {
+///     let txn = db.start_transaction().unwrap();
+///     let collection = txn.collection::<Document>("books");
+///     collection.insert_one(doc! { "title": "1984" }).unwrap();
+///     // dropped without commit
+/// }
+/// assert_eq!(db.collection::<Document>("books").count_documents().unwrap(), 0);
+///
+/// let txn = db.start_transaction().unwrap();
+/// let collection = txn.collection::<Document>("books");
+/// collection.insert_one(doc! { "title": "1984" }).unwrap();
+/// txn.commit().unwrap();
+/// assert_eq!(db.collection::<Document>("books").count_documents().unwrap(), 1);
+/// ```
 #[derive(Clone)]
 pub struct Transaction {
     db: Weak<DatabaseInner>,
-    inner: Arc<TransactionInner>,
+    inner: Arc<TransactionState>,
+}
+
+/// Shared by the clones of a [`Transaction`],
+/// rolls the transaction back when the last of them is dropped before it's finished.
+struct TransactionState {
+    txn: TransactionInner,
+    finished: AtomicBool,
+}
+
+impl Drop for TransactionState {
+
+    fn drop(&mut self) {
+        if !self.finished.load(Ordering::SeqCst) {
+            let _ = self.txn.rollback();
+        }
+    }
+
 }
 
 impl Transaction {
@@ -29,7 +76,10 @@ impl Transaction {
     pub(crate) fn new(db: Weak<DatabaseInner>, inner: TransactionInner) -> Transaction {
         Transaction {
             db,
-            inner: Arc::new(inner),
+            inner: Arc::new(TransactionState {
+                txn: inner,
+                finished: AtomicBool::new(false),
+            }),
         }
     }
 
@@ -40,17 +90,19 @@ impl Transaction {
     /// a new collection will be created.
     ///
     pub fn collection<T: Serialize>(&self, col_name: &str) -> TransactionalCollection<T> {
-        TransactionalCollection::new(self.db.clone(), col_name, self.inner.as_ref().clone())
+        TransactionalCollection::new(self.db.clone(), col_name, self.inner.txn.clone())
     }
 
-    #[inline]
     pub fn commit(&self) -> crate::Result<()> {
-        self.inner.commit()
+        self.inner.txn.commit()?;
+        self.inner.finished.store(true, Ordering::SeqCst);
+        Ok(())
     }
 
-    #[inline]
     pub fn rollback(&self) -> crate::Result<()> {
-        self.inner.rollback()
+        self.inner.txn.rollback()?;
+        self.inner.finished.store(true, Ordering::SeqCst);
+        Ok(())
     }
 
 }
@@ -39,6 +39,17 @@ impl TransactionInner {
         self.auto_commit
     }
 
+    #[inline]
+    pub fn get(&self, key: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+        self.rocksdb_txn.get(key)
+    }
+
+    /// Read the key and lock it until the transaction ends.
+    #[inline]
+    pub fn get_for_update(&self, key: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+        self.rocksdb_txn.get_for_update(key)
+    }
+
     #[inline]
     pub fn put(&self, key: &[u8], value: &[u8]) -> crate::Result<()> {
         self.rocksdb_txn.set(key, value)
@@ -67,4 +78,15 @@ impl TransactionInner {
         self.rocksdb_txn.rollback()
     }
 
+    /// Mark the point [`TransactionInner::rollback_to_savepoint`] goes back to.
+    #[inline]
+    pub fn set_savepoint(&self) {
+        self.rocksdb_txn.set_savepoint()
+    }
+
+    #[inline]
+    pub fn rollback_to_savepoint(&self) -> crate::Result<()> {
+        self.rocksdb_txn.rollback_to_savepoint()
+    }
+
 }
@@ -13,14 +13,16 @@
 // limitations under the License.
 
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::io::{BufRead, Read, Write};
-use bson::{Bson, DateTime, Decimal128, Document, Timestamp};
+use bson::{Array, Bson, DateTime, Decimal128, Document, Timestamp};
 use bson::oid::ObjectId;
 use bson::spec::ElementType;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use bson::ser::Error as BsonErr;
 use bson::ser::Result as BsonResult;
 use crate::{Error, Result};
+use crate::utils::decimal128::{decimal128_cmp, decimal128_cmp_i64, decimal128_to_f64};
 
 pub fn stacked_key<'a, T: IntoIterator<Item = &'a Bson>>(keys: T) -> Result<Vec<u8>> {
     let mut result = Vec::<u8>::new();
@@ -39,6 +41,7 @@ pub fn stacked_key_bytes<W: Write>(writer: &mut W, key: &Bson) -> Result<()> {
             writer.write_f64::<BigEndian>(*dbl)?;
         }
         Bson::String(str) => {
+            check_key_string(str)?;
             writer.write_u8(ElementType::String as u8)?;
 
             writer.write_all(str.as_bytes())?;
@@ -84,6 +87,7 @@ pub fn stacked_key_bytes<W: Write>(writer: &mut W, key: &Bson) -> Result<()> {
             writer.write_i64::<BigEndian>(t)?;
         }
         Bson::Symbol(str) => {
+            check_key_string(str)?;
             writer.write_u8(ElementType::Symbol as u8)?;
 
             writer.write_all(str.as_bytes())?;
@@ -110,6 +114,26 @@ pub fn stacked_key_bytes<W: Write>(writer: &mut W, key: &Bson) -> Result<()> {
     Ok(())
 }
 
+/// The strings in a key are terminated by a zero byte,
+/// a string containing one would be split when the key is read.
+fn check_key_string(s: &str) -> Result<()> {
+    if s.as_bytes().contains(&0) {
+        return Err(Error::InvalidKeyString(format!("{:?} contains a zero byte", s)));
+    }
+    Ok(())
+}
+
+fn read_key_string<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut bytes = Vec::<u8>::new();
+    reader.read_until(0, &mut bytes)?;
+    // remove the terminating zero
+    if bytes.pop() != Some(0) {
+        return Err(Error::InvalidKeyString("the string is not terminated".to_string()));
+    }
+    String::from_utf8(bytes)
+        .map_err(|err| Error::InvalidKeyString(err.utf8_error().to_string()))
+}
+
 pub fn split_stacked_keys(buffer: &[u8]) -> Result<Vec<Bson>> {
     let mut result = Vec::<Bson>::new();
     let mut reader = buffer;
@@ -124,11 +148,7 @@ pub fn split_stacked_keys(buffer: &[u8]) -> Result<Vec<Bson>> {
             let val = reader.read_f64::<BigEndian>()?;
             result.push(Bson::Double(val));
         } else if ch == ElementType::String as u8 {
-            let mut bytes = Vec::<u8>::new();
-            reader.read_until(0, &mut bytes)?;
-            // remove last byte of bytes
-            bytes.pop();
-            result.push(Bson::String(String::from_utf8(bytes)?));
+            result.push(Bson::String(read_key_string(&mut reader)?));
         } else if ch == ElementType::Boolean as u8 {
             let val = reader.read_u8()?;
             result.push(Bson::Boolean(val != 0));
@@ -156,10 +176,7 @@ pub fn split_stacked_keys(buffer: &[u8]) -> Result<Vec<Bson>> {
             let datetime = DateTime::from_millis(val);
             result.push(Bson::DateTime(datetime));
         } else if ch == ElementType::Symbol as u8 {
-            let mut bytes = Vec::<u8>::new();
-            reader.read_until(0, &mut bytes)?;
-            bytes.pop();
-            result.push(Bson::Symbol(String::from_utf8(bytes)?));
+            result.push(Bson::Symbol(read_key_string(&mut reader)?));
         } else if ch == ElementType::Decimal128 as u8 {
             let mut bytes = [0u8; 16];
             reader.read_exact(&mut bytes)?;
@@ -207,6 +224,13 @@ pub fn value_cmp(a: &Bson, b: &Bson) -> BsonResult<Ordering> {
             let f = *i1 as f64;
             Ok(f.total_cmp(d2))
         }
+        (Bson::Decimal128(d1), Bson::Decimal128(d2)) => Ok(decimal128_cmp(d1, d2)),
+        (Bson::Decimal128(d1), Bson::Int32(i2)) => Ok(decimal128_cmp_i64(d1, *i2 as i64)),
+        (Bson::Decimal128(d1), Bson::Int64(i2)) => Ok(decimal128_cmp_i64(d1, *i2)),
+        (Bson::Int32(i1), Bson::Decimal128(d2)) => Ok(decimal128_cmp_i64(d2, *i1 as i64).reverse()),
+        (Bson::Int64(i1), Bson::Decimal128(d2)) => Ok(decimal128_cmp_i64(d2, *i1).reverse()),
+        (Bson::Decimal128(d1), Bson::Double(d2)) => Ok(decimal128_to_f64(d1).total_cmp(d2)),
+        (Bson::Double(d1), Bson::Decimal128(d2)) => Ok(d1.total_cmp(&decimal128_to_f64(d2))),
         (Bson::Binary(b1), Bson::Binary(b2)) => Ok(b1.bytes.cmp(&b2.bytes)),
         (Bson::String(str1), Bson::String(str2)) => Ok(str1.cmp(str2)),
         (Bson::ObjectId(oid1), Bson::ObjectId(oid2)) => Ok(oid1.cmp(oid2)),
@@ -223,43 +247,175 @@ pub fn value_cmp(a: &Bson, b: &Bson) -> BsonResult<Ordering> {
     }
 }
 
+/// The rank of a value in the comparison order across types used by MongoDB:
+/// null < numbers < strings < objects < arrays < binary < ObjectId < booleans < dates
+/// < timestamps < regular expressions.
+fn canonical_type_rank(value: &Bson) -> u8 {
+    match value {
+        Bson::MinKey => 0,
+        Bson::Null | Bson::Undefined => 1,
+        Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) => 2,
+        Bson::Symbol(_) | Bson::String(_) => 3,
+        Bson::Document(_) => 4,
+        Bson::Array(_) => 5,
+        Bson::Binary(_) => 6,
+        Bson::ObjectId(_) => 7,
+        Bson::Boolean(_) => 8,
+        Bson::DateTime(_) => 9,
+        Bson::Timestamp(_) => 10,
+        Bson::RegularExpression(_) => 11,
+        Bson::MaxKey => 13,
+        _ => 12,
+    }
+}
+
+/// Compare two values of any types with a total ordering.
+///
+/// Values of different types are ordered by the canonical order of their types,
+/// the values of the same type which can't be compared are treated as equal.
+/// Arrays are compared element by element, documents field by field,
+/// and a prefix is less than the longer value.
+pub fn value_total_cmp(a: &Bson, b: &Bson) -> Ordering {
+    let a_rank = canonical_type_rank(a);
+    let b_rank = canonical_type_rank(b);
+    if a_rank != b_rank {
+        return a_rank.cmp(&b_rank);
+    }
+    match (a, b) {
+        (Bson::Array(arr1), Bson::Array(arr2)) => {
+            for (v1, v2) in arr1.iter().zip(arr2.iter()) {
+                let ord = value_total_cmp(v1, v2);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            arr1.len().cmp(&arr2.len())
+        }
+        (Bson::Document(doc1), Bson::Document(doc2)) => {
+            // like MongoDB, the type of the value goes before the field name
+            for ((k1, v1), (k2, v2)) in doc1.iter().zip(doc2.iter()) {
+                let ord = canonical_type_rank(v1).cmp(&canonical_type_rank(v2))
+                    .then_with(|| k1.cmp(k2))
+                    .then_with(|| value_total_cmp(v1, v2));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            doc1.len().cmp(&doc2.len())
+        }
+        _ => value_cmp(a, b).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Return the values of the other numeric types equal to `value`,
+/// e.g. `Int64(10)` and `Double(10.0)` for `Int32(10)`.
+/// Non-numeric values have no equivalents.
+pub fn numeric_equivalents(value: &Bson) -> Vec<Bson> {
+    let mut result = Vec::new();
+    match value {
+        Bson::Int32(i) => {
+            result.push(Bson::Int64(*i as i64));
+            result.push(Bson::Double(*i as f64));
+        }
+        Bson::Int64(i) => {
+            if let Ok(small) = i32::try_from(*i) {
+                result.push(Bson::Int32(small));
+            }
+            let f = *i as f64;
+            if f as i64 == *i {
+                result.push(Bson::Double(f));
+            }
+        }
+        Bson::Double(d) if d.is_finite() && d.fract() == 0.0 => {
+            if *d >= i32::MIN as f64 && *d <= i32::MAX as f64 {
+                result.push(Bson::Int32(*d as i32));
+            }
+            if *d >= i64::MIN as f64 && *d < i64::MAX as f64 {
+                result.push(Bson::Int64(*d as i64));
+            }
+        }
+        _ => (),
+    }
+    result
+}
+
 pub fn try_get_document_value(doc: &Document, key: &str) -> Option<Bson> {
     let keys = key.split('.').collect::<Vec<&str>>();
     let keys_slice = keys.as_slice();
     try_get_document_by_slices(doc, keys_slice)
 }
 
+/// Get the value of an array by a path like "0.name",
+/// the first segment of the path must be a numeric index.
+pub fn try_get_array_value(arr: &Array, key: &str) -> Option<Bson> {
+    let keys = key.split('.').collect::<Vec<&str>>();
+    let keys_slice = keys.as_slice();
+    try_get_array_by_slices(arr, keys_slice)
+}
+
 fn try_get_document_by_slices(doc: &Document, keys: &[&str]) -> Option<Bson> {
-    let first = keys.first();
-    first
-        .and_then(|first_str| {
-            let remains = &keys[1..];
-            let value = doc.get(first_str);
-            match value {
-                Some(Bson::Document(doc)) => {
-                    try_get_document_by_slices(doc, remains)
-                }
-                Some(v) => {
-                    if remains.is_empty() {
-                        return Some(v.clone());
-                    }
-                    None
-                }
-                _ => None,
-            }
-        })
+    let first = keys.first()?;
+    let value = doc.get(first)?;
+    try_get_value_by_slices(value, &keys[1..])
+}
+
+fn try_get_array_by_slices(arr: &Array, keys: &[&str]) -> Option<Bson> {
+    let first = keys.first()?;
+    let index = first.parse::<usize>().ok()?;
+    let value = arr.get(index)?;
+    try_get_value_by_slices(value, &keys[1..])
+}
+
+fn try_get_value_by_slices(value: &Bson, remains: &[&str]) -> Option<Bson> {
+    if remains.is_empty() {
+        return Some(value.clone());
+    }
+    match value {
+        Bson::Document(doc) => try_get_document_by_slices(doc, remains),
+        Bson::Array(arr) => try_get_array_by_slices(arr, remains),
+        _ => None,
+    }
 }
 
 pub fn bson_datetime_now() -> DateTime {
     DateTime::now()
 }
 
+/// Check the documents and the arrays are nested at most `max_depth` levels,
+/// the top-level document being the first level. `0` for no limit.
+pub fn check_document_depth(doc: &Document, max_depth: u32) -> Result<()> {
+    if max_depth == 0 || !values_exceed_depth(doc.values(), 1, max_depth) {
+        return Ok(());
+    }
+    Err(Error::ValidationError(format!(
+        "the document is nested deeper than the maximum depth {}",
+        max_depth,
+    )))
+}
+
+// the nested values are only visited down to the limit
+fn values_exceed_depth<'a, I: Iterator<Item = &'a Bson>>(values: I, depth: u32, max_depth: u32) -> bool {
+    for value in values {
+        let exceeded = match value {
+            Bson::Document(doc) => depth >= max_depth || values_exceed_depth(doc.values(), depth + 1, max_depth),
+            Bson::Array(arr) => depth >= max_depth || values_exceed_depth(arr.iter(), depth + 1, max_depth),
+            _ => false,
+        };
+        if exceeded {
+            return true;
+        }
+    }
+    false
+}
+
 #[cfg(test)]
 mod tests {
     use std::cmp::Ordering;
     use bson::{Bson, doc, Timestamp};
     use bson::oid::ObjectId;
-    use crate::utils::bson::{split_stacked_keys, stacked_key, value_cmp};
+    use bson::spec::ElementType;
+    use crate::Error;
+    use crate::utils::bson::{check_document_depth, numeric_equivalents, split_stacked_keys, stacked_key, value_cmp, value_total_cmp};
 
     #[test]
     fn test_value_cmp() {
@@ -271,6 +427,46 @@ mod tests {
         assert_eq!(value_cmp(&Bson::Int64(1), &Bson::Int32(1)).unwrap(), Ordering::Equal);
     }
 
+    #[test]
+    fn test_value_total_cmp() {
+        let now = super::bson_datetime_now();
+        assert_eq!(value_total_cmp(&Bson::String("a".into()), &Bson::String("b".into())), Ordering::Less);
+        assert_eq!(value_total_cmp(&Bson::DateTime(now), &Bson::DateTime(now)), Ordering::Equal);
+        assert_eq!(value_total_cmp(&Bson::Null, &Bson::DateTime(now)), Ordering::Less);
+        assert_eq!(value_total_cmp(&Bson::Int32(100), &Bson::String("1".into())), Ordering::Less);
+        assert_eq!(value_total_cmp(&Bson::Boolean(true), &Bson::DateTime(now)), Ordering::Less);
+        assert_eq!(value_total_cmp(&Bson::Double(1.5), &Bson::Int64(1)), Ordering::Greater);
+        assert_eq!(value_total_cmp(&bson::bson!([1, 2]), &bson::bson!([1, 3])), Ordering::Less);
+        assert_eq!(value_total_cmp(&bson::bson!([1, 2]), &bson::bson!([1])), Ordering::Greater);
+        assert_eq!(value_total_cmp(&bson::bson!([1, 2]), &bson::bson!([1.0, 2])), Ordering::Equal);
+        assert_eq!(value_total_cmp(&Bson::Document(doc! { "a": 1 }), &Bson::Document(doc! { "a": 2 })), Ordering::Less);
+        assert_eq!(value_total_cmp(&Bson::Document(doc! { "a": 1 }), &Bson::Document(doc! { "b": 1 })), Ordering::Less);
+        assert_eq!(value_total_cmp(&Bson::Document(doc! { "b": 1 }), &Bson::Document(doc! { "a": "x" })), Ordering::Less);
+        assert_eq!(value_total_cmp(&Bson::Document(doc! { "a": 1, "b": 2 }), &Bson::Document(doc! { "a": 1 })), Ordering::Greater);
+        assert_eq!(value_total_cmp(&Bson::Document(doc! { "a": { "b": 1 } }), &Bson::Document(doc! { "a": { "b": 1 } })), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_numeric_equivalents() {
+        assert_eq!(numeric_equivalents(&Bson::Int32(10)), vec![Bson::Int64(10), Bson::Double(10.0)]);
+        assert_eq!(numeric_equivalents(&Bson::Int64(10)), vec![Bson::Int32(10), Bson::Double(10.0)]);
+        assert_eq!(numeric_equivalents(&Bson::Int64(1 << 40)), vec![Bson::Double((1u64 << 40) as f64)]);
+        assert_eq!(numeric_equivalents(&Bson::Double(10.0)), vec![Bson::Int32(10), Bson::Int64(10)]);
+        assert!(numeric_equivalents(&Bson::Double(10.5)).is_empty());
+        assert!(numeric_equivalents(&Bson::String("10".into())).is_empty());
+    }
+
+    #[test]
+    fn test_check_document_depth() {
+        let doc = doc! { "a": { "b": [1, { "c": 1 }] }, "d": 1 };
+        assert!(check_document_depth(&doc, 4).is_ok());
+        assert!(check_document_depth(&doc, 0).is_ok());
+        assert!(matches!(check_document_depth(&doc, 3), Err(Error::ValidationError(_))));
+        assert!(check_document_depth(&doc! { "a": [] }, 2).is_ok());
+        assert!(check_document_depth(&doc! { "a": 1 }, 1).is_ok());
+        assert!(check_document_depth(&doc! { "a": {} }, 1).is_err());
+    }
+
     #[test]
     fn test_try_get_document_value() {
         assert_eq!(super::try_get_document_value(&doc!{}, "a"), None);
@@ -280,6 +476,25 @@ mod tests {
         assert_eq!(super::try_get_document_value(&doc!{"a": { "b": 1 }}, "a.c"), None);
         assert_eq!(super::try_get_document_value(&doc!{"a": { "b": { "c": 1 }}}, "a.b.c"), Some(Bson::Int32(1)));
         assert_eq!(super::try_get_document_value(&doc!{"a": { "b": { "c": 1 }}}, "a.b.d"), None);
+        assert_eq!(super::try_get_document_value(&doc!{"a": { "b": 1 }}, "a"), Some(Bson::Document(doc!{ "b": 1 })));
+    }
+
+    #[test]
+    fn test_try_get_value_by_array_index() {
+        let doc = doc! {
+            "items": [
+                { "name": "x" },
+                { "name": "y" },
+            ],
+        };
+        assert_eq!(super::try_get_document_value(&doc, "items.0.name"), Some(Bson::String("x".into())));
+        assert_eq!(super::try_get_document_value(&doc, "items.1.name"), Some(Bson::String("y".into())));
+        assert_eq!(super::try_get_document_value(&doc, "items.2.name"), None);
+        assert_eq!(super::try_get_document_value(&doc, "items.a.name"), None);
+
+        let arr = doc.get_array("items").unwrap();
+        assert_eq!(super::try_get_array_value(arr, "1"), Some(Bson::Document(doc! { "name": "y" })));
+        assert_eq!(super::try_get_array_value(arr, "-1"), None);
     }
 
     #[test]
@@ -306,4 +521,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_invalid_key_strings() {
+        let err = stacked_key(&[Bson::String("a\0b".to_string())]).unwrap_err();
+        assert!(matches!(err, Error::InvalidKeyString(_)));
+
+        // an invalid UTF-8 sequence
+        let bytes = [ElementType::String as u8, b'a', 0xC3, 0x28, 0];
+        let err = split_stacked_keys(&bytes).unwrap_err();
+        assert!(matches!(err, Error::InvalidKeyString(_)));
+
+        let bytes = [ElementType::String as u8, b'a', b'b'];
+        let err = split_stacked_keys(&bytes).unwrap_err();
+        assert!(matches!(err, Error::InvalidKeyString(_)));
+    }
+
 }
@@ -0,0 +1,214 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Comparison of the IEEE 754-2008 128-bit decimals (BID encoding) stored in BSON.
+
+use std::cmp::Ordering;
+use bson::Decimal128;
+
+const EXPONENT_BIAS: i32 = 6176;
+const MAX_COEFFICIENT: u128 = 9_999_999_999_999_999_999_999_999_999_999;
+
+enum DecodedDecimal {
+    NaN,
+    Infinity(bool),
+    Finite {
+        negative: bool,
+        coefficient: u128,
+        exponent: i32,
+    },
+}
+
+impl DecodedDecimal {
+
+    fn decode(value: &Decimal128) -> DecodedDecimal {
+        let bytes = value.bytes();
+        let mut low_bytes = [0u8; 8];
+        let mut high_bytes = [0u8; 8];
+        low_bytes.copy_from_slice(&bytes[0..8]);
+        high_bytes.copy_from_slice(&bytes[8..16]);
+        let low = u64::from_le_bytes(low_bytes);
+        let high = u64::from_le_bytes(high_bytes);
+
+        let negative = (high >> 63) != 0;
+        let combination = (high >> 58) & 0x1f;
+        if combination == 0x1f {
+            return DecodedDecimal::NaN;
+        }
+        if combination == 0x1e {
+            return DecodedDecimal::Infinity(negative);
+        }
+
+        let (exponent, coefficient) = if (high >> 61) & 0x3 == 0x3 {
+            // the implicit coefficient is larger than the maximum,
+            // which is a non-canonical zero
+            (((high >> 47) & 0x3fff) as i32, 0)
+        } else {
+            let coefficient = (((high & 0x1_ffff_ffff_ffff) as u128) << 64) | low as u128;
+            let coefficient = if coefficient > MAX_COEFFICIENT { 0 } else { coefficient };
+            (((high >> 49) & 0x3fff) as i32, coefficient)
+        };
+
+        DecodedDecimal::Finite {
+            negative,
+            coefficient,
+            exponent: exponent - EXPONENT_BIAS,
+        }
+    }
+
+    fn from_i64(value: i64) -> DecodedDecimal {
+        DecodedDecimal::Finite {
+            negative: value < 0,
+            coefficient: value.unsigned_abs() as u128,
+            exponent: 0,
+        }
+    }
+
+    // -1, 0 or 1 for the finite values, NaN is the smallest one
+    fn rank(&self) -> i8 {
+        match self {
+            DecodedDecimal::NaN => -3,
+            DecodedDecimal::Infinity(true) => -2,
+            DecodedDecimal::Finite { coefficient: 0, .. } => 0,
+            DecodedDecimal::Finite { negative: true, .. } => -1,
+            DecodedDecimal::Finite { negative: false, .. } => 1,
+            DecodedDecimal::Infinity(false) => 2,
+        }
+    }
+
+    fn cmp(&self, other: &DecodedDecimal) -> Ordering {
+        let rank = self.rank();
+        let other_rank = other.rank();
+        if rank != other_rank || (rank != -1 && rank != 1) {
+            return rank.cmp(&other_rank);
+        }
+        match (self, other) {
+            (
+                DecodedDecimal::Finite { coefficient: c1, exponent: e1, .. },
+                DecodedDecimal::Finite { coefficient: c2, exponent: e2, .. },
+            ) => {
+                let ord = magnitude_cmp(*c1, *e1, *c2, *e2);
+                if rank < 0 {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            }
+            _ => Ordering::Equal,
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            DecodedDecimal::NaN => f64::NAN,
+            DecodedDecimal::Infinity(true) => f64::NEG_INFINITY,
+            DecodedDecimal::Infinity(false) => f64::INFINITY,
+            DecodedDecimal::Finite { coefficient: 0, negative, .. } => {
+                if *negative { -0.0 } else { 0.0 }
+            }
+            DecodedDecimal::Finite { negative, coefficient, exponent } => {
+                let value = (*coefficient as f64) * 10f64.powi(*exponent);
+                if *negative { -value } else { value }
+            }
+        }
+    }
+
+}
+
+fn digit_count(mut value: u128) -> i32 {
+    let mut count = 0;
+    while value > 0 {
+        value /= 10;
+        count += 1;
+    }
+    count
+}
+
+// compare c1 * 10^e1 with c2 * 10^e2, both coefficients are non-zero
+fn magnitude_cmp(c1: u128, e1: i32, c2: u128, e2: i32) -> Ordering {
+    let adjusted1 = digit_count(c1) + e1;
+    let adjusted2 = digit_count(c2) + e2;
+    if adjusted1 != adjusted2 {
+        return adjusted1.cmp(&adjusted2);
+    }
+    // the same count of integral digits, the scaled coefficients
+    // have at most 34 digits and fit in u128
+    if e1 > e2 {
+        (c1 * 10u128.pow((e1 - e2) as u32)).cmp(&c2)
+    } else {
+        c1.cmp(&(c2 * 10u128.pow((e2 - e1) as u32)))
+    }
+}
+
+/// Compare two decimals by their values, e.g. `1.0` equals `1.00`.
+/// NaN is smaller than all the other values.
+pub(crate) fn decimal128_cmp(a: &Decimal128, b: &Decimal128) -> Ordering {
+    DecodedDecimal::decode(a).cmp(&DecodedDecimal::decode(b))
+}
+
+/// Compare a decimal with an integer exactly.
+pub(crate) fn decimal128_cmp_i64(a: &Decimal128, b: i64) -> Ordering {
+    DecodedDecimal::decode(a).cmp(&DecodedDecimal::from_i64(b))
+}
+
+/// The nearest double of a decimal, used to compare it with doubles.
+pub(crate) fn decimal128_to_f64(value: &Decimal128) -> f64 {
+    DecodedDecimal::decode(value).to_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use bson::Decimal128;
+    use super::{decimal128_cmp, decimal128_cmp_i64, decimal128_to_f64};
+
+    fn dec(coefficient: i64, exponent: i32) -> Decimal128 {
+        let c = coefficient.unsigned_abs() as u128;
+        let mut high = (((exponent + 6176) as u64) << 49) | (c >> 64) as u64;
+        if coefficient < 0 {
+            high |= 1 << 63;
+        }
+        let low = c as u64;
+        let mut bytes = [0u8; 16];
+        bytes[0..8].copy_from_slice(&low.to_le_bytes());
+        bytes[8..16].copy_from_slice(&high.to_le_bytes());
+        Decimal128::from_bytes(bytes)
+    }
+
+    #[test]
+    fn test_decimal128_cmp() {
+        // 1.5 < 2.25
+        assert_eq!(decimal128_cmp(&dec(15, -1), &dec(225, -2)), Ordering::Less);
+        // 1.0 == 1.00
+        assert_eq!(decimal128_cmp(&dec(10, -1), &dec(100, -2)), Ordering::Equal);
+        // -3 < -2.5
+        assert_eq!(decimal128_cmp(&dec(-3, 0), &dec(-25, -1)), Ordering::Less);
+        // 0 == -0
+        assert_eq!(decimal128_cmp(&dec(0, 0), &dec(0, 5)), Ordering::Equal);
+        // 1E+3 > 999.9
+        assert_eq!(decimal128_cmp(&dec(1, 3), &dec(9999, -1)), Ordering::Greater);
+
+        let nan = Decimal128::from_bytes([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x7c]);
+        assert_eq!(decimal128_cmp(&nan, &dec(-1, 0)), Ordering::Less);
+    }
+
+    #[test]
+    fn test_decimal128_cmp_numbers() {
+        assert_eq!(decimal128_cmp_i64(&dec(100, -2), 1), Ordering::Equal);
+        assert_eq!(decimal128_cmp_i64(&dec(101, -2), 1), Ordering::Greater);
+        assert_eq!(decimal128_cmp_i64(&dec(-5, -1), 0), Ordering::Less);
+        assert_eq!(decimal128_to_f64(&dec(125, -2)), 1.25);
+    }
+
+}
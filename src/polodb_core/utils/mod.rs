@@ -15,4 +15,7 @@
 pub(crate) mod file_lock;
 
 pub(crate) mod bson;
+pub(crate) mod decimal128;
+pub(crate) mod object_id;
+pub(crate) mod rand;
 pub mod str;
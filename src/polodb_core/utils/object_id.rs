@@ -0,0 +1,201 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bson::{Bson, Document};
+use bson::oid::ObjectId;
+use crate::utils::rand::SmallRng;
+
+const MAX_COUNTER: u32 = 0xFF_FFFF;
+
+/// Generates the ObjectIds of the inserted documents.
+///
+/// An ObjectId is made of a 4-byte timestamp, a 5-byte process id and a 3-byte counter.
+/// The process id is random unless it's set in the config, so the processes writing
+/// separate files that are merged later can be given distinct ones.
+/// The random bytes are taken from `rng`, which is seeded when the config has a random seed.
+pub(crate) struct ObjectIdMaker {
+    process_id: [u8; 5],
+    counter:    AtomicU32,
+}
+
+impl ObjectIdMaker {
+
+    pub fn new(process_id: Option<[u8; 5]>, rng: &mut SmallRng) -> ObjectIdMaker {
+        let process_id = process_id.unwrap_or_else(|| {
+            let mut bytes = [0; 5];
+            bytes.copy_from_slice(&rng.next_u64().to_be_bytes()[0..5]);
+            bytes
+        });
+        let counter = rng.next_u64() as u32 & MAX_COUNTER;
+
+        ObjectIdMaker {
+            process_id,
+            counter: AtomicU32::new(counter),
+        }
+    }
+
+    pub fn make(&self) -> ObjectId {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed) & MAX_COUNTER;
+
+        let mut bytes = [0; 12];
+        bytes[0..4].copy_from_slice(&timestamp.to_be_bytes());
+        bytes[4..9].copy_from_slice(&self.process_id);
+        bytes[9..12].copy_from_slice(&counter.to_be_bytes()[1..4]);
+        ObjectId::from_bytes(bytes)
+    }
+
+}
+
+/// Replace the 24-char hex strings compared with `_id` in a filter by ObjectIds.
+///
+/// Covers `{ _id: <str> }`, the `$eq`, `$ne`, `$in` and `$nin` operators on `_id`,
+/// and the filters nested in `$and`, `$or` and `$nor`. Other strings are left as is.
+pub(crate) fn coerce_object_id_strings(filter: Document) -> Document {
+    filter.into_iter()
+        .map(|(key, value)| {
+            let value = match key.as_str() {
+                "_id" => coerce_id_condition(value),
+                "$and" | "$or" | "$nor" => coerce_sub_filters(value),
+                _ => value,
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+fn coerce_id_condition(value: Bson) -> Bson {
+    match value {
+        Bson::Document(doc) if doc.keys().any(|k| k.starts_with('$')) => {
+            let doc: Document = doc.into_iter()
+                .map(|(op, operand)| {
+                    let operand = match op.as_str() {
+                        "$eq" | "$ne" => coerce_hex_string(operand),
+                        "$in" | "$nin" => match operand {
+                            Bson::Array(arr) => Bson::Array(arr.into_iter().map(coerce_hex_string).collect()),
+                            _ => operand,
+                        },
+                        _ => operand,
+                    };
+                    (op, operand)
+                })
+                .collect();
+            Bson::Document(doc)
+        }
+        _ => coerce_hex_string(value),
+    }
+}
+
+fn coerce_sub_filters(value: Bson) -> Bson {
+    match value {
+        Bson::Array(arr) => {
+            let arr = arr.into_iter()
+                .map(|item| match item {
+                    Bson::Document(doc) => Bson::Document(coerce_object_id_strings(doc)),
+                    _ => item,
+                })
+                .collect();
+            Bson::Array(arr)
+        }
+        _ => value,
+    }
+}
+
+fn coerce_hex_string(value: Bson) -> Bson {
+    match value {
+        Bson::String(s) if s.len() == 24 => match ObjectId::parse_str(&s) {
+            Ok(oid) => Bson::ObjectId(oid),
+            Err(_) => Bson::String(s),
+        },
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use bson::doc;
+    use bson::oid::ObjectId;
+    use crate::utils::rand::SmallRng;
+    use super::{ObjectIdMaker, coerce_object_id_strings};
+
+    #[test]
+    fn test_process_id() {
+        let maker = ObjectIdMaker::new(Some([1, 2, 3, 4, 5]), &mut SmallRng::from_entropy());
+        let oid = maker.make();
+        assert_eq!(&oid.bytes()[4..9], &[1, 2, 3, 4, 5]);
+
+        let next = maker.make();
+        assert_ne!(oid, next);
+    }
+
+    #[test]
+    fn test_seeded_maker() {
+        let maker1 = ObjectIdMaker::new(None, &mut SmallRng::new(7));
+        let maker2 = ObjectIdMaker::new(None, &mut SmallRng::new(7));
+        // only the timestamps may differ
+        assert_eq!(&maker1.make().bytes()[4..], &maker2.make().bytes()[4..]);
+    }
+
+    #[test]
+    fn test_no_collisions_across_makers() {
+        // the same counters in both makers, only the process ids tell the ids apart
+        let maker1 = ObjectIdMaker::new(Some([0, 0, 0, 0, 1]), &mut SmallRng::from_entropy());
+        let maker2 = ObjectIdMaker::new(Some([0, 0, 0, 0, 2]), &mut SmallRng::from_entropy());
+        maker2.counter.store(maker1.counter.load(std::sync::atomic::Ordering::Relaxed), std::sync::atomic::Ordering::Relaxed);
+
+        let mut ids = HashSet::new();
+        for _ in 0..10_000 {
+            assert!(ids.insert(maker1.make()));
+            assert!(ids.insert(maker2.make()));
+        }
+        assert_eq!(ids.len(), 20_000);
+    }
+
+    #[test]
+    fn test_coerce_object_id_strings() {
+        let oid = ObjectId::parse_str("65a1b2c3d4e5f60718293a4b").unwrap();
+        let filter = coerce_object_id_strings(doc! {
+            "_id": "65a1b2c3d4e5f60718293a4b",
+            "name": "65a1b2c3d4e5f60718293a4b",
+        });
+        assert_eq!(filter, doc! {
+            "_id": oid,
+            "name": "65a1b2c3d4e5f60718293a4b",
+        });
+
+        let filter = coerce_object_id_strings(doc! {
+            "$or": [
+                { "_id": { "$in": ["65a1b2c3d4e5f60718293a4b", "not-an-id", 1] } },
+                { "_id": { "$ne": "65a1b2c3d4e5f60718293a4b" } },
+            ],
+        });
+        assert_eq!(filter, doc! {
+            "$or": [
+                { "_id": { "$in": [oid, "not-an-id", 1] } },
+                { "_id": { "$ne": oid } },
+            ],
+        });
+
+        // 24 chars but not hex
+        let filter = coerce_object_id_strings(doc! { "_id": "zzzzzzzzzzzzzzzzzzzzzzzz" });
+        assert_eq!(filter, doc! { "_id": "zzzzzzzzzzzzzzzzzzzzzzzz" });
+    }
+
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// A small SplitMix64 generator, good enough for sampling,
+/// not for anything that has to be unpredictable.
+pub(crate) struct SmallRng {
+    state: u64,
+}
+
+impl SmallRng {
+
+    pub(crate) fn new(seed: u64) -> SmallRng {
+        SmallRng { state: seed }
+    }
+
+    pub(crate) fn from_entropy() -> SmallRng {
+        let mut bytes = [0u8; 8];
+        getrandom::getrandom(&mut bytes).unwrap();
+        SmallRng::new(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, `bound` must not be 0.
+    pub(crate) fn gen_below(&mut self, bound: u64) -> u64 {
+        // the bias of the modulo is negligible for the bounds used here
+        self.next_u64() % bound
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmallRng;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = SmallRng::new(42);
+        let mut b = SmallRng::new(42);
+        for _ in 0..16 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        let mut c = SmallRng::new(43);
+        assert_ne!(SmallRng::new(42).next_u64(), c.next_u64());
+
+        for _ in 0..100 {
+            assert!(a.gen_below(7) < 7);
+        }
+    }
+
+}
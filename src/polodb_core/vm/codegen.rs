@@ -15,10 +15,14 @@
 
 use super::label::{JumpTableRecord, Label, LabelSlot};
 use crate::coll::collection_info::CollectionSpecification;
+use crate::coll::json_schema;
+use crate::config::MAX_QUERY_DEPTH;
 use crate::errors::{mk_invalid_query_field};
 use crate::index::INDEX_PREFIX;
+use crate::vm::elem_match::is_valid_condition_entry;
+use crate::vm::geo::Shape;
 use crate::vm::op::DbOp;
-use crate::vm::subprogram::SubProgramIndexItem;
+use crate::vm::subprogram::{AggregateCompileOptions, SubProgramIndexItem};
 use crate::vm::SubProgram;
 use crate::{Error, Result};
 use bson::spec::{BinarySubtype, ElementType};
@@ -32,6 +36,8 @@ use crate::vm::vm_count::VmFuncCount;
 use crate::vm::vm_external_func::VmExternalFunc;
 use crate::vm::vm_group::VmFuncGroup;
 use crate::vm::vm_limit::VmFuncLimit;
+use crate::vm::vm_project::VmFuncProject;
+use crate::vm::vm_sample::VmFuncSample;
 use crate::vm::vm_skip::VmFuncSkip;
 use crate::vm::vm_sort::VmFuncSort;
 use crate::vm::vm_unset::VmFuncUnset;
@@ -46,6 +52,17 @@ pub(super) struct Codegen {
     is_write: bool,
     paths: Vec<String>,
     op_registry: OpRegistry,
+    // the current nesting depth of $and/$or
+    logic_depth: u32,
+    max_query_depth: u32,
+    // $sort writes to temporary files beyond this many documents, see VmFuncSort
+    sort_spill_threshold: Option<u64>,
+    // $group writes to temporary files beyond this many groups, see VmFuncGroup
+    group_spill_threshold: Option<u64>,
+    // a $natural sort forces a collection scan, true for the reverse order
+    natural_reverse: Option<bool>,
+    // seeds the $sample stages, see VmFuncSample
+    random_seed: Option<u64>,
 }
 
 impl Codegen {
@@ -57,9 +74,50 @@ impl Codegen {
             is_write,
             paths: Vec::with_capacity(PATH_DEFAULT_SIZE),
             op_registry: OpRegistry,
+            logic_depth: 0,
+            max_query_depth: MAX_QUERY_DEPTH,
+            sort_spill_threshold: None,
+            group_spill_threshold: None,
+            natural_reverse: None,
+            random_seed: None,
         }
     }
 
+    #[inline]
+    pub(super) fn set_max_query_depth(&mut self, max_query_depth: u32) {
+        self.max_query_depth = max_query_depth;
+    }
+
+    pub(super) fn apply_aggregate_options(&mut self, options: &AggregateCompileOptions) {
+        self.max_query_depth = options.max_query_depth;
+        self.sort_spill_threshold = options.sort_spill_threshold;
+        self.group_spill_threshold = options.group_spill_threshold;
+        self.natural_reverse = options.natural_reverse;
+        self.random_seed = options.random_seed;
+    }
+
+    /// Emit the op moving the cursor to the first document in the scan order,
+    /// jumping to `label` when the collection is empty.
+    pub(super) fn emit_rewind(&mut self, label: Label) {
+        let op = if self.natural_reverse == Some(true) {
+            DbOp::RewindLast
+        } else {
+            DbOp::Rewind
+        };
+        self.emit_goto(op, label);
+    }
+
+    /// Emit the op moving the cursor to the next document in the scan order,
+    /// jumping to `label` when there is one.
+    pub(super) fn emit_advance(&mut self, label: Label) {
+        let op = if self.natural_reverse == Some(true) {
+            DbOp::Prev
+        } else {
+            DbOp::Next
+        };
+        self.emit_goto(op, label);
+    }
+
     fn unify_labels(&mut self) {
         for record in &self.jump_table {
             let pos = (record.begin_loc + record.offset) as usize;
@@ -76,7 +134,6 @@ impl Codegen {
     }
 
     #[inline]
-    #[allow(dead_code)]
     pub(super) fn new_global_variable(&mut self, init_value: Bson) -> Result<GlobalVariable> {
         self.new_global_variable_impl(init_value, None)
     }
@@ -113,13 +170,11 @@ impl Codegen {
         self.program.label_slots[label.u_pos()] = LabelSlot::UnnamedLabel(current_loc);
     }
 
-    #[allow(dead_code)]
     fn emit_load_global(&mut self, global: GlobalVariable) {
         self.emit(DbOp::LoadGlobal);
         self.emit_u32(global.pos());
     }
 
-    #[allow(dead_code)]
     fn emit_store_global(&mut self, global: GlobalVariable) {
         self.emit(DbOp::StoreGlobal);
         self.emit_u32(global.pos());
@@ -202,21 +257,26 @@ impl Codegen {
     where
         F: FnOnce(&mut Codegen) -> Result<()>,
     {
-        let try_pkey_result = self.try_query_by_pkey(col_spec, query, result_callback)?;
-        if try_pkey_result.is_none() {
-            return Ok(());
-        }
+        // $natural bypasses the primary key and the indexes
+        let result_callback: F = if self.natural_reverse.is_some() {
+            result_callback
+        } else {
+            let try_pkey_result = self.try_query_by_pkey(col_spec, query, result_callback)?;
+            if try_pkey_result.is_none() {
+                return Ok(());
+            }
 
-        let result_callback: F = try_pkey_result.unwrap();
+            let result_callback: F = try_pkey_result.unwrap();
 
-        let try_index_result = self.try_query_by_index(col_spec, query, result_callback)?;
-        if try_index_result.is_none() {
-            return Ok(());
-        }
+            let try_index_result = self.try_query_by_index(col_spec, query, result_callback)?;
+            if try_index_result.is_none() {
+                return Ok(());
+            }
 
-        self.emit_open(col_spec._id.clone().into());
+            try_index_result.unwrap()
+        };
 
-        let result_callback: F = try_index_result.unwrap();
+        self.emit_open(col_spec._id.clone().into());
 
         let compare_fun = self.new_label();
         let compare_fun_clean = self.new_label();
@@ -226,12 +286,12 @@ impl Codegen {
         let not_found_label = self.new_label();
         let close_label = self.new_label();
 
-        self.emit_goto(DbOp::Rewind, close_label);
+        self.emit_rewind(close_label);
 
         self.emit_goto(DbOp::Goto, compare_label);
 
         self.emit_label(next_label);
-        self.emit_goto(DbOp::Next, compare_label);
+        self.emit_advance(compare_label);
 
         // <==== close cursor
         self.emit_label_with_name(close_label, "close");
@@ -317,21 +377,37 @@ impl Codegen {
 
         let index_meta = &col_spec.indexes;
         for (index_name, index_info) in index_meta {
+            // the keys of the index are folded, they can't match the values exactly
+            if index_info.collation().map_or(false, |collation| collation.is_case_insensitive()) {
+                continue;
+            }
             let (key, _order) = index_info.keys.iter().next().unwrap();
             // the key is ellipse representation, such as "a.b.c"
             // the query is supposed to be ellipse too, such as
-            // { "a.b.c": 1 }
-            let test_result = query.get(key);
-            if let Some(query_doc) = test_result {
-                if query_doc.element_type() != ElementType::EmbeddedDocument {
-                    let mut remain_query = query.clone();
-                    remain_query.remove(key);
-
-                    self.indeed_emit_query_by_index(
+            // { "a.b.c": 1 } or { "a.b.c": { "$eq": 1 } }
+            if let Some(condition) = query.get(key) {
+                // the long strings may be truncated in the index keys, and the arrays
+                // are indexed by their elements, so the documents found are tested
+                // against the whole query
+                if let Some(value) = index_lookup_value(condition) {
+                    self.emit_query_by_index_checked(
                         col_spec._id.as_str(),
                         index_name.as_str(),
-                        query_doc,
-                        &remain_query,
+                        DbOp::FindByIndex,
+                        value,
+                        query,
+                        result_callback,
+                    )?;
+                    return Ok(None);
+                }
+
+                if let Some(prefix) = regex_literal_prefix(condition) {
+                    self.emit_query_by_index_checked(
+                        col_spec._id.as_str(),
+                        index_name.as_str(),
+                        DbOp::FindByIndexPrefix,
+                        Bson::String(prefix),
+                        query,
                         result_callback,
                     )?;
                     return Ok(None);
@@ -342,12 +418,17 @@ impl Codegen {
         Ok(Some(result_callback))
     }
 
-    fn indeed_emit_query_by_index<F>(
+    /// Scan the index entries found by `find_op` with `value`, which is
+    /// [`DbOp::FindByIndex`] for the value, or the values of an array,
+    /// or [`DbOp::FindByIndexPrefix`] for the strings starting with it. The whole query is still tested against
+    /// every document found.
+    fn emit_query_by_index_checked<F>(
         &mut self,
         col_name: &str,
         index_name: &str,
-        query_value: &Bson,
-        remain_query: &Document,
+        find_op: DbOp,
+        value: Bson,
+        query: &Document,
         result_callback: F,
     ) -> Result<()>
     where
@@ -367,50 +448,56 @@ impl Codegen {
             bytes: prefix_bytes,
         }));
 
-        let close_label = self.new_label();
-        let result_label = self.new_label();
+        let compare_fun = self.new_label();
+        let compare_fun_clean = self.new_label();
+        let compare_label = self.new_label();
         let next_label = self.new_label();
+        let result_label = self.new_label();
+        let not_found_label = self.new_label();
+        let close_label = self.new_label();
 
-        let value_id = self.push_static(query_value.clone());
+        let value_id = self.push_static(value);
         self.emit_push_value(value_id);
 
         let col_name_id = self.push_static(Bson::String(col_name.to_string()));
         self.emit_push_value(col_name_id);
 
-        self.emit_goto(DbOp::FindByIndex, close_label);
+        self.emit_goto(find_op, close_label);
 
-        self.emit_goto(DbOp::Goto, result_label);
+        self.emit_goto(DbOp::Goto, compare_label);
 
         self.emit_label(next_label);
-        self.emit_goto(DbOp::NextIndexValue, result_label);
+        self.emit_goto(DbOp::NextIndexValue, compare_label);
 
-        self.emit_label(close_label);
+        self.emit_label_with_name(close_label, "close");
 
         self.emit(DbOp::Pop); // pop the collection name
-        self.emit(DbOp::Pop); // pop the query value
+        self.emit(DbOp::Pop); // pop the value
 
         self.emit(DbOp::Close);
         self.emit(DbOp::Halt);
 
-        self.emit_label(result_label);
-        for (key, value) in remain_query.iter() {
-            let key_static_id = self.push_static(Bson::String(key.clone()));
-            let value_static_id = self.push_static(value.clone());
+        self.emit_label_with_name(not_found_label, "not_this_item");
+        self.emit(DbOp::Pop); // pop the current value;
+        self.emit_goto(DbOp::Goto, next_label);
 
-            self.emit_goto2(DbOp::GetField, key_static_id, close_label); // push a value1
-            self.emit_push_value(value_static_id); // push a value2
+        self.emit_label_with_name(result_label, "result");
+        result_callback(self)?;
+        self.emit_goto(DbOp::Goto, next_label);
 
-            self.emit(DbOp::Equal);
-            // if not equal，go to next
-            self.emit_goto(DbOp::IfFalse, close_label);
+        self.emit_label_with_name(compare_label, "compare");
+        self.emit(DbOp::Dup);
+        self.emit_goto(DbOp::Call, compare_fun);
+        self.emit_u32(1);
+        self.emit_goto(DbOp::IfFalse, not_found_label);
+        self.emit_goto(DbOp::Goto, result_label);
 
-            self.emit(DbOp::Pop); // pop a value2
-            self.emit(DbOp::Pop); // pop a value1
-        }
+        self.emit_label_with_name(compare_fun, "compare_function");
 
-        result_callback(self)?;
+        self.emit_standard_query_doc(query, result_label, compare_fun_clean)?;
 
-        self.emit_goto(DbOp::Goto, next_label);
+        self.emit_label_with_name(compare_fun_clean, "compare_function_clean");
+        self.emit_ret(0);
 
         Ok(())
     }
@@ -421,7 +508,7 @@ impl Codegen {
         result_label: Label,
         not_found_label: Label,
     ) -> Result<()> {
-        if query_doc.is_empty() {
+        if query_doc.keys().all(|key| key == "$comment") {
             self.emit(DbOp::StoreR0_2);
             self.emit_u8(1);
             return Ok(())
@@ -519,6 +606,17 @@ impl Codegen {
         Ok(())
     }
 
+    fn enter_logic(&mut self) -> Result<()> {
+        if self.logic_depth >= self.max_query_depth {
+            return Err(Error::InvalidField(mk_invalid_query_field(
+                self.last_key().into(),
+                self.gen_path(),
+            )));
+        }
+        self.logic_depth += 1;
+        Ok(())
+    }
+
     // case1: "$and" | "$or" -> [ Document ]
     // case3: "_id" -> Document
     fn emit_query_tuple(
@@ -532,21 +630,41 @@ impl Codegen {
             match key {
                 "$and" => {
                     let sub_arr = crate::try_unwrap_array!("$and", value);
+                    self.enter_logic()?;
                     self.emit_logic_and(
                         sub_arr.as_ref(),
                         result_label,
                         not_found_label,
                     )?;
+                    self.logic_depth -= 1;
                 }
 
                 "$or" => {
                     let sub_arr = crate::try_unwrap_array!("$or", value);
+                    self.enter_logic()?;
                     self.emit_logic_or(
                         sub_arr.as_ref(),
                         not_found_label,
                     )?;
+                    self.logic_depth -= 1;
+                }
+
+                "$jsonSchema" => {
+                    let schema = crate::try_unwrap_document!("$jsonSchema", value);
+                    json_schema::check_schema(schema)?;
+
+                    let schema_static_id = self.push_static(value.clone());
+                    self.emit_push_value(schema_static_id);
+
+                    self.emit(DbOp::JsonSchema);
+                    self.emit_goto(DbOp::IfFalse, not_found_label);
+
+                    self.emit(DbOp::Pop);
                 }
 
+                // only recorded by the logs, it matches every document
+                "$comment" => (),
+
                 _ => {
                     return Err(Error::InvalidField(mk_invalid_query_field(
                         self.last_key().into(),
@@ -681,6 +799,49 @@ impl Codegen {
                 self.emit_u32((field_size + 1) as u32);
             }
 
+            "$elemMatch" => {
+                match sub_value {
+                    Bson::Document(cond) if cond.iter().all(|(k, v)| is_valid_condition_entry(k, v)) => (),
+                    _ => {
+                        return Err(Error::InvalidField(mk_invalid_query_field(
+                            self.last_key().into(),
+                            self.gen_path(),
+                        )))
+                    }
+                }
+
+                let field_size = self.recursively_get_field(key, not_found_label);
+
+                let stat_val_id = self.push_static(sub_value.clone());
+                self.emit_push_value(stat_val_id);
+                self.emit_logical(DbOp::ElemMatch, is_in_not);
+
+                self.emit_goto(DbOp::IfFalse, not_found_label);
+
+                self.emit(DbOp::Pop2);
+                self.emit_u32((field_size + 1) as u32);
+            }
+
+            "$geoWithin" => {
+                if Shape::parse(sub_value).is_none() {
+                    return Err(Error::InvalidField(mk_invalid_query_field(
+                        self.last_key().into(),
+                        self.gen_path(),
+                    )))
+                }
+
+                let field_size = self.recursively_get_field(key, not_found_label);
+
+                let stat_val_id = self.push_static(sub_value.clone());
+                self.emit_push_value(stat_val_id);
+                self.emit_logical(DbOp::GeoWithin, is_in_not);
+
+                self.emit_goto(DbOp::IfFalse, not_found_label);
+
+                self.emit(DbOp::Pop2);
+                self.emit_u32((field_size + 1) as u32);
+            }
+
             "$lt" => {
                 let field_size = self.recursively_get_field(key, not_found_label);
 
@@ -709,17 +870,37 @@ impl Codegen {
             }
 
             "$ne" => {
-                let field_size = self.recursively_get_field(key, not_found_label);
+                // a missing field is not equal to anything,
+                // so it passes `$ne` but fails `$not: { $ne }`
+                let missing_label = if is_in_not {
+                    not_found_label
+                } else {
+                    self.new_label()
+                };
+                let next_label = self.new_label();
+
+                let key_static_id = self.push_static(key.into());
+                self.emit_goto2(DbOp::GetField, key_static_id, missing_label);
 
                 let stat_val_id = self.push_static(sub_value.clone());
                 self.emit_push_value(stat_val_id);
-                self.emit_logical(DbOp::Equal, is_in_not);
+                self.emit_logical(DbOp::Equal, !is_in_not);
 
                 // if equal，go to next
                 self.emit_goto(DbOp::IfFalse, not_found_label);
 
                 self.emit(DbOp::Pop2);
-                self.emit_u32((field_size + 1) as u32);
+                self.emit_u32(2);
+
+                if !is_in_not {
+                    self.emit_goto(DbOp::Goto, next_label);
+
+                    self.emit_label(missing_label);
+                    self.emit(DbOp::StoreR0_2);
+                    self.emit_u8(1);
+                }
+
+                self.emit_label(next_label);
             }
 
             "$nin" => {
@@ -747,7 +928,8 @@ impl Codegen {
 
             "$size" => {
                 let expected_size = match sub_value {
-                    Bson::Int64(i) => *i,
+                    Bson::Int32(i) if *i >= 0 => *i as i64,
+                    Bson::Int64(i) if *i >= 0 => *i,
                     _ => {
                         return Err(Error::InvalidField(mk_invalid_query_field(
                             self.last_key().into(),
@@ -807,7 +989,11 @@ impl Codegen {
                 };
 
                 crate::path_hint!(self, "$not".to_string(), {
-                    self.emit_query_tuple_document(key, doc, !is_in_not, not_found_label)?;
+                    if !is_in_not && doc.len() > 1 {
+                        self.emit_negated_query_tuple_document(key, doc, not_found_label)?;
+                    } else {
+                        self.emit_query_tuple_document(key, doc, !is_in_not, not_found_label)?;
+                    }
                 });
             }
 
@@ -843,6 +1029,53 @@ impl Codegen {
         Ok(())
     }
 
+    // `{ $not: { $gt: 3, $lt: 10 } }` is `NOT (a AND b)`, i.e. `(NOT a) OR (NOT b)`,
+    // every negated operator is a function like the branches of `$or`
+    fn emit_negated_query_tuple_document(
+        &mut self,
+        key: &str,
+        value: &Document,
+        not_found_label: Label,
+    ) -> Result<()> {
+        let cmp_label = self.new_label();
+        let matched_label = self.new_label();
+        self.emit_goto(DbOp::Goto, cmp_label);
+
+        let mut functions = Vec::<Label>::new();
+        for (sub_key, sub_value) in value.iter() {
+            crate::path_hint!(self, sub_key.clone(), {
+                let query_label = self.new_label();
+                let ret_label = self.new_label();
+
+                self.emit_label(query_label);
+                self.emit_query_tuple_document_kv(
+                    key,
+                    true,
+                    ret_label,
+                    sub_key.as_ref(),
+                    sub_value,
+                )?;
+
+                self.emit_label(ret_label);
+                self.emit_ret(0);
+
+                functions.push(query_label);
+            });
+        }
+
+        self.emit_label(cmp_label);
+        for fun in functions {
+            self.emit_goto(DbOp::Call, fun);
+            self.emit_u32(0);
+            self.emit_goto(DbOp::IfTrue, matched_label);
+        }
+        self.emit_goto(DbOp::Goto, not_found_label);
+
+        self.emit_label(matched_label);
+
+        Ok(())
+    }
+
     // There are two stage of compiling pipeline
     // 1. Generate the layout code of the pipeline
     // 2. Generate the implementation code of the pipeline
@@ -931,6 +1164,7 @@ impl Codegen {
                             &mut self.paths,
                             self.op_registry.clone(),
                             value,
+                            self.group_spill_threshold,
                         )?;
                         self.emit_external_func(external_func, stage_ctx_item, next_fun);
                     }
@@ -944,9 +1178,18 @@ impl Codegen {
                         let external_func: Box<dyn VmExternalFunc> = VmFuncLimit::compile(&mut self.paths, value)?;
                         self.emit_external_func(external_func, stage_ctx_item, next_fun);
                     }
+                    "$sample" => {
+                        let next_fun = ctx.items[index + 1].next_label;
+                        let external_func = VmFuncSample::compile(&mut self.paths, value, self.random_seed)?;
+                        self.emit_external_func(external_func, stage_ctx_item, next_fun);
+                    }
                     "$sort" => {
                         let next_fun = ctx.items[index + 1].next_label;
-                        let external_func: Box<dyn VmExternalFunc> = VmFuncSort::compile(&mut self.paths, value)?;
+                        let external_func = VmFuncSort::compile(
+                            &mut self.paths,
+                            value,
+                            self.sort_spill_threshold,
+                        )?;
                         self.emit_external_func(external_func, stage_ctx_item, next_fun);
                     }
                     "$addFields" => {
@@ -963,6 +1206,15 @@ impl Codegen {
                         let external_func: Box<dyn VmExternalFunc> = VmFuncUnset::compile(&mut self.paths, value)?;
                         self.emit_external_func(external_func, stage_ctx_item, next_fun);
                     }
+                    "$project" => {
+                        let next_fun = ctx.items[index + 1].next_label;
+                        let external_func = VmFuncProject::compile(
+                            &mut self.paths,
+                            self.op_registry.clone(),
+                            value,
+                        )?;
+                        self.emit_external_func(external_func, stage_ctx_item, next_fun);
+                    }
                     _ => {
                         return Err(Error::UnknownAggregationOperation(key.clone()));
                     }
@@ -1034,6 +1286,30 @@ impl Codegen {
         Ok(())
     }
 
+    /// Count the runs of the result callback with the `counter`,
+    /// close the cursor and halt when it reaches the `limit`.
+    pub(super) fn emit_limit_check(&mut self, counter: GlobalVariable, limit: u64) {
+        let continue_label = self.new_label();
+
+        self.emit_load_global(counter);
+        self.emit(DbOp::Inc);
+        self.emit_store_global(counter);
+
+        let limit_id = self.push_static(Bson::Int64(limit as i64));
+        self.emit_push_value(limit_id);
+
+        self.emit(DbOp::Less);
+        self.emit(DbOp::Pop2);
+        self.emit_u32(2);
+
+        self.emit_goto(DbOp::IfTrue, continue_label);
+
+        self.emit(DbOp::Close);
+        self.emit(DbOp::Halt);
+
+        self.emit_label(continue_label);
+    }
+
     pub(super) fn emit_delete_operation(&mut self) {
         self.emit(DbOp::DeleteCurrent);
     }
@@ -1043,14 +1319,21 @@ impl Codegen {
         self.emit(DbOp::StoreR0_2);
         self.emit_u8(0);
 
+        self.emit_update_operators(update)?;
+
+        self.emit(DbOp::UpdateCurrent);
+
+        Ok(())
+    }
+
+    /// Apply the update operators to the document on the top of the stack.
+    pub(super) fn emit_update_operators(&mut self, update: &Document) -> Result<()> {
         for (key, value) in update.iter() {
             crate::path_hint!(self, key.clone(), {
                 self.emit_update_operation_kv(key, value)?;
             });
         }
 
-        self.emit(DbOp::UpdateCurrent);
-
         Ok(())
     }
 
@@ -1104,9 +1387,9 @@ impl Codegen {
             }
 
             "$rename" => {
-                let doc = crate::try_unwrap_document!("$set", value);
+                let doc = crate::try_unwrap_document!("$rename", value);
 
-                let op = RenameOperator::compile(doc.clone())?;
+                let op = RenameOperator::compile(doc.clone(), self.gen_path())?;
                 self.emit_update_operator(Box::new(op));
             }
 
@@ -1120,7 +1403,11 @@ impl Codegen {
             "$push" => {
                 let doc = crate::try_unwrap_document!("$push", value);
 
-                let op = PushOperator::compile(doc.clone())?;
+                let op = PushOperator::compile(
+                    doc.clone(),
+                    self.last_key().to_string(),
+                    self.gen_path(),
+                )?;
                 self.emit_update_operator(Box::new(op));
             }
 
@@ -1239,3 +1526,98 @@ impl Codegen {
             .push(JumpTableRecord::new(record_loc, 5, label.pos()));
     }
 }
+
+/// The value of a `{ "$eq": <value> }` condition, which is looked up
+/// in the index like the plain value. Other conditions are returned as is.
+fn unwrap_eq_condition(condition: &Bson) -> &Bson {
+    match condition {
+        Bson::Document(doc) if doc.len() == 1 => match doc.get("$eq") {
+            Some(value) if value.element_type() != ElementType::EmbeddedDocument => value,
+            _ => condition,
+        },
+        _ => condition,
+    }
+}
+
+/// The value looked up in the index for the condition on the indexed field,
+/// or an array of the values when any of them is matched, i.e. `$in` and
+/// `$elemMatch` with `$eq` or `$in`.
+///
+/// `None` if the condition can't be looked up in the index.
+fn index_lookup_value(condition: &Bson) -> Option<Bson> {
+    let condition = unwrap_eq_condition(condition);
+    let doc = match condition {
+        Bson::Document(doc) => doc,
+        // an array is indexed by its elements, it can't be looked up as a whole
+        Bson::Array(_) => return None,
+        _ => return index_lookup_values(std::slice::from_ref(condition)).map(|_| condition.clone()),
+    };
+    if doc.len() != 1 {
+        return None;
+    }
+    match doc.iter().next()? {
+        (op, Bson::Array(values)) if op == "$in" => index_lookup_values(values),
+        (op, Bson::Document(cond)) if op == "$elemMatch" && cond.len() == 1 => {
+            match cond.iter().next()? {
+                (op, Bson::Array(values)) if op == "$in" => index_lookup_values(values),
+                (op, value) if op == "$eq" => index_lookup_values(std::slice::from_ref(value)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The array of the values, if all of them can be stored in the index keys.
+fn index_lookup_values(values: &[Bson]) -> Option<Bson> {
+    let is_key = |value: &Bson| {
+        !matches!(value, Bson::Array(_)) && crate::utils::bson::stacked_key([value]).is_ok()
+    };
+    if values.iter().all(is_key) {
+        Some(Bson::Array(values.to_vec()))
+    } else {
+        None
+    }
+}
+
+/// The text the strings matching `{ "$regex": /^prefix.../ }` must start with.
+///
+/// `None` if the regex is not anchored to the start of the string,
+/// or has options changing how the prefix is matched.
+fn regex_literal_prefix(value: &Bson) -> Option<String> {
+    let doc = value.as_document()?;
+    if doc.len() != 1 {
+        return None;
+    }
+    let regex = match doc.get("$regex")? {
+        Bson::RegularExpression(regex) => regex,
+        _ => return None,
+    };
+    if regex.options.contains(['i', 'm', 'x']) || regex.pattern.contains('|') {
+        return None;
+    }
+
+    let mut chars = regex.pattern.strip_prefix('^')?.chars().peekable();
+    let mut prefix = String::new();
+    while let Some(ch) = chars.next() {
+        let literal = match ch {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped.is_ascii_punctuation() => escaped,
+                _ => break,
+            },
+            '.' | '^' | '$' | '*' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '\0' => break,
+            _ => ch,
+        };
+        // the character is optional
+        if matches!(chars.peek(), Some('*') | Some('?') | Some('{')) {
+            break;
+        }
+        prefix.push(literal);
+    }
+
+    if prefix.is_empty() {
+        None
+    } else {
+        Some(prefix)
+    }
+}
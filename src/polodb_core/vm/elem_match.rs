@@ -0,0 +1,94 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The conditions of `$elemMatch`, shared by the queries and the projections.
+
+use std::cmp::Ordering;
+use bson::{Bson, Document};
+use crate::utils::bson::{try_get_document_value, value_cmp};
+
+fn is_comparison_operator(key: &str) -> bool {
+    matches!(key, "$eq" | "$ne" | "$gt" | "$gte" | "$lt" | "$lte" | "$in" | "$nin")
+}
+
+/// Test if the entry of a condition is supported, either an operator applied
+/// to the element itself or a field of an embedded element.
+pub(super) fn is_valid_condition_entry(key: &str, value: &Bson) -> bool {
+    if key.starts_with('$') {
+        return is_valid_operator(key, value);
+    }
+    match value {
+        Bson::Document(ops) if ops.keys().any(|op| op.starts_with('$')) => {
+            ops.iter().all(|(op, value)| is_valid_operator(op, value))
+        }
+        _ => true,
+    }
+}
+
+fn is_valid_operator(op: &str, value: &Bson) -> bool {
+    match op {
+        "$in" | "$nin" => matches!(value, Bson::Array(_)),
+        _ => is_comparison_operator(op),
+    }
+}
+
+/// Test if an array element matches the condition of `$elemMatch`.
+///
+/// The keys of the condition are either the fields of an embedded document,
+/// or comparison operators applied to the element itself.
+pub(super) fn matches_condition(item: &Bson, cond: &Document) -> bool {
+    cond.iter().all(|(k, v)| {
+        if k.starts_with('$') {
+            return matches_operator(item, k, v);
+        }
+        let field_value = match item {
+            Bson::Document(doc) => try_get_document_value(doc, k),
+            _ => None,
+        };
+        match v {
+            Bson::Document(ops) if ops.keys().any(|op| op.starts_with('$')) => {
+                let field_value = field_value.unwrap_or(Bson::Null);
+                ops.iter().all(|(op, value)| matches_operator(&field_value, op, value))
+            }
+            _ => field_value.map_or(false, |field_value| compare(&field_value, v) == Some(Ordering::Equal)),
+        }
+    })
+}
+
+fn matches_operator(value: &Bson, op: &str, target: &Bson) -> bool {
+    let ord = compare(value, target);
+    match op {
+        "$eq" => ord == Some(Ordering::Equal),
+        "$ne" => ord != Some(Ordering::Equal),
+        "$gt" => ord == Some(Ordering::Greater),
+        "$gte" => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+        "$lt" => ord == Some(Ordering::Less),
+        "$lte" => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+        "$in" | "$nin" => {
+            let found = target.as_array()
+                .map_or(false, |arr| arr.iter().any(|t| compare(value, t) == Some(Ordering::Equal)));
+            (op == "$in") == found
+        }
+        _ => false,
+    }
+}
+
+// values of different types never match, except the numbers
+fn compare(a: &Bson, b: &Bson) -> Option<Ordering> {
+    let is_number = |v: &Bson| matches!(v, Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_));
+    if a.element_type() != b.element_type() && !(is_number(a) && is_number(b)) {
+        return None;
+    }
+    value_cmp(a, b).ok()
+}
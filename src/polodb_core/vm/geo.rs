@@ -0,0 +1,140 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The shapes of `$geoWithin` on the flat plane. A point is an array `[x, y]`
+//! or a document of two numbers, such as `{ lng: x, lat: y }`.
+
+use bson::Bson;
+
+pub(super) enum Shape {
+    // the bottom left and the top right corners
+    Box((f64, f64), (f64, f64)),
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl Shape {
+
+    /// Parse the value of `$geoWithin`, `{ $box: [<corner>, <corner>] }`
+    /// or `{ $polygon: [<point>, <point>, <point>, ...] }`.
+    pub(super) fn parse(value: &Bson) -> Option<Shape> {
+        let doc = value.as_document()?;
+        if doc.len() != 1 {
+            return None;
+        }
+        let (key, value) = doc.iter().next()?;
+        let points = value.as_array()?
+            .iter()
+            .map(point_of)
+            .collect::<Option<Vec<_>>>()?;
+        match key.as_str() {
+            "$box" if points.len() == 2 => {
+                let (a, b) = (points[0], points[1]);
+                Some(Shape::Box((a.0.min(b.0), a.1.min(b.1)), (a.0.max(b.0), a.1.max(b.1))))
+            }
+            "$polygon" if points.len() >= 3 => Some(Shape::Polygon(points)),
+            _ => None,
+        }
+    }
+
+    /// Test if the point is inside the shape, the points on the edges are inside.
+    pub(super) fn contains(&self, point: (f64, f64)) -> bool {
+        let (x, y) = point;
+        match self {
+            Shape::Box(min, max) => {
+                x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1
+            }
+            Shape::Polygon(vertices) => {
+                let edges = vertices.iter()
+                    .zip(vertices.iter().cycle().skip(1))
+                    .map(|(a, b)| (*a, *b));
+                let mut inside = false;
+                for (a, b) in edges {
+                    if is_on_segment(point, a, b) {
+                        return true;
+                    }
+                    // cast a ray to the right, count the edges it crosses
+                    if (a.1 > y) != (b.1 > y) {
+                        let cross_x = a.0 + (y - a.1) * (b.0 - a.0) / (b.1 - a.1);
+                        if x < cross_x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+}
+
+/// The coordinates of a point, `None` if the value is not a point.
+pub(super) fn point_of(value: &Bson) -> Option<(f64, f64)> {
+    let (x, y) = match value {
+        Bson::Array(arr) if arr.len() == 2 => (&arr[0], &arr[1]),
+        Bson::Document(doc) if doc.len() == 2 => {
+            let mut values = doc.values();
+            (values.next()?, values.next()?)
+        }
+        _ => return None,
+    };
+    Some((number_of(x)?, number_of(y)?))
+}
+
+fn number_of(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(i) => Some(*i as f64),
+        Bson::Int64(i) => Some(*i as f64),
+        Bson::Double(d) if d.is_finite() => Some(*d),
+        _ => None,
+    }
+}
+
+fn is_on_segment(point: (f64, f64), a: (f64, f64), b: (f64, f64)) -> bool {
+    let cross = (b.0 - a.0) * (point.1 - a.1) - (b.1 - a.1) * (point.0 - a.0);
+    if cross.abs() > f64::EPSILON * (1.0 + (b.0 - a.0).abs() + (b.1 - a.1).abs()) {
+        return false;
+    }
+    point.0 >= a.0.min(b.0) && point.0 <= a.0.max(b.0)
+        && point.1 >= a.1.min(b.1) && point.1 <= a.1.max(b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{bson, doc};
+    use super::{point_of, Shape};
+
+    #[test]
+    fn test_polygon_contains() {
+        let triangle = Shape::parse(&bson!({ "$polygon": [[0, 0], [4, 0], [0, 4]] })).unwrap();
+        assert!(triangle.contains((1.0, 1.0)));
+        assert!(triangle.contains((0.0, 0.0)));
+        assert!(triangle.contains((2.0, 2.0)));
+        assert!(triangle.contains((0.0, 3.0)));
+        assert!(!triangle.contains((3.0, 3.0)));
+        assert!(!triangle.contains((-1.0, 1.0)));
+
+        assert!(Shape::parse(&bson!({ "$polygon": [[0, 0], [4, 0]] })).is_none());
+        assert!(Shape::parse(&bson!({ "$circle": [[0, 0], 1] })).is_none());
+    }
+
+    #[test]
+    fn test_point_of() {
+        assert_eq!(point_of(&bson!([1, 2.5])), Some((1.0, 2.5)));
+        assert_eq!(point_of(&bson!({ "lng": 1_i64, "lat": 2 })), Some((1.0, 2.0)));
+        assert_eq!(point_of(&bson!([1, "2"])), None);
+        assert_eq!(point_of(&bson!([1, 2, 3])), None);
+        assert_eq!(point_of(&doc! { "x": 1 }.into()), None);
+    }
+
+}
@@ -25,10 +25,15 @@ mod vm_group;
 mod operators;
 mod vm_skip;
 mod vm_sort;
+mod spill_run;
 mod vm_limit;
+mod vm_sample;
 mod vm_unset;
 mod vm_add_fields;
+mod vm_project;
+mod elem_match;
+mod geo;
 mod update_operators;
 
-pub(crate) use subprogram::SubProgram;
+pub(crate) use subprogram::{SubProgram, PlainSubProgram, AggregateCompileOptions};
 pub(crate) use vm::{VM, VmState};
@@ -57,6 +57,13 @@ pub enum DbOp {
     // op1. location: 4 bytes
     Rewind,
 
+    // reset the cursor to the last element, to scan backwards with Prev
+    // if empty, jump to location
+    //
+    // 5 bytes
+    // op1. location: 4 bytes
+    RewindLast,
+
     // reset the cursor pointer to the element
     // in btree by the primary key on the top of the stack
     // if the item can not be found, jump to the location
@@ -67,10 +74,21 @@ pub enum DbOp {
 
     // reset the cursor pointer to the element
     //
+    // the value on the top - 1 of the stack is looked up,
+    // or every element of it if it's an array
+    //
     // 5 bytes
     // op1. location: 4 bytes
     FindByIndex,
 
+    // reset the cursor pointer to the first element of the index
+    // whose string value starts with the prefix on the top - 1 of the stack
+    // if the item can not be found, jump to the location
+    //
+    // 5 bytes
+    // op1. location: 4 bytes
+    FindByIndexPrefix,
+
     // next element of the cursor
     // if no next element, pass
     // otherwise, jump to location
@@ -81,6 +99,16 @@ pub enum DbOp {
     // op1. location: 4bytes
     Next,
 
+    // previous element of the cursor
+    // if no previous element, pass
+    // otherwise, jump to location
+    //
+    // push current value to the stack
+    //
+    // 5 bytes
+    // op1. location: 4bytes
+    Prev,
+
     // next index value
     // advance the cursor to next index
     // push the value of the index on the top of the stack
@@ -215,12 +243,24 @@ pub enum DbOp {
     LessEqual,
     Regex,
 
+    // check if top1 matches the `$jsonSchema` on top0
+    // the result is stored in r0
+    JsonSchema,
+
     Not,
 
     // check if top0 is in top2
     // the result is stored in r0
     In,
 
+    // check if any element of the array on top1 matches the `$elemMatch` condition on top0
+    // the result is stored in r0
+    ElemMatch,
+
+    // check if the point on top1 is inside the `$geoWithin` shape on top0
+    // the result is stored in r0
+    GeoWithin,
+
     EqualNull,
 
     // open a cursor with op0 as root_pid
@@ -77,7 +77,7 @@ impl VmOperator for AbsOperator {
             OperatorExpr::Constant(ref v) => v.clone(),
             OperatorExpr::Expr(ref op) =>
                 Self::bson_abs(op.initial_value()),
-            OperatorExpr::Alias(_) => Bson::Null,
+            OperatorExpr::Alias(_) | OperatorExpr::Root | OperatorExpr::Remove => Bson::Null,
         }
     }
 
@@ -93,6 +93,8 @@ impl VmOperator for AbsOperator {
                 }.unwrap_or(Bson::Null);
                 Self::bson_abs(unwrap)
             }
+            OperatorExpr::Root => Self::bson_abs(input.clone()),
+            OperatorExpr::Remove => Bson::Null,
         }
     }
 
@@ -101,7 +103,7 @@ impl VmOperator for AbsOperator {
             OperatorExpr::Constant(ref v) => v.clone(),
             OperatorExpr::Expr(ref op) =>
                 Self::bson_abs(op.complete()),
-            OperatorExpr::Alias(_) => Bson::Null,
+            OperatorExpr::Alias(_) | OperatorExpr::Root | OperatorExpr::Remove => Bson::Null,
         }
     }
 }
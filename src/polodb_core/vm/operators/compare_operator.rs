@@ -0,0 +1,94 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use bson::Bson;
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
+use crate::{Result, Error};
+use crate::errors::mk_invalid_aggregate_field;
+use crate::utils::bson::value_total_cmp;
+
+#[derive(Clone, Copy)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+// { $gt: [ <expression1>, <expression2> ] }
+pub(crate) struct CompareOperator {
+    op: CompareOp,
+    left: OperatorExpr,
+    right: OperatorExpr,
+}
+
+impl CompareOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: OpRegistry, op_name: &str, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        let op = match op_name {
+            "$eq" => CompareOp::Eq,
+            "$ne" => CompareOp::Ne,
+            "$gt" => CompareOp::Gt,
+            "$gte" => CompareOp::Gte,
+            "$lt" => CompareOp::Lt,
+            "$lte" => CompareOp::Lte,
+            _ => return Err(Error::UnknownAggregationOperation(op_name.to_string())),
+        };
+        let (left, right) = match v {
+            Bson::Array(arr) if arr.len() == 2 => {
+                (registry.compile_expr(paths, &arr[0])?, registry.compile_expr(paths, &arr[1])?)
+            }
+            _ => {
+                let invalid_err = mk_invalid_aggregate_field(paths);
+                return Err(Error::InvalidField(invalid_err));
+            }
+        };
+        Ok(Box::new(CompareOperator {
+            op,
+            left,
+            right,
+        }))
+    }
+
+}
+
+impl VmOperator for CompareOperator {
+    fn initial_value(&self) -> Bson {
+        Bson::Null
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        // a missing value compares as null
+        let left = self.left.eval(input).unwrap_or(Bson::Null);
+        let right = self.right.eval(input).unwrap_or(Bson::Null);
+        // values of different types are compared by the order of their types
+        let ord = value_total_cmp(&left, &right);
+        let result = match self.op {
+            CompareOp::Eq => ord == Ordering::Equal,
+            CompareOp::Ne => ord != Ordering::Equal,
+            CompareOp::Gt => ord == Ordering::Greater,
+            CompareOp::Gte => ord != Ordering::Less,
+            CompareOp::Lt => ord == Ordering::Less,
+            CompareOp::Lte => ord != Ordering::Greater,
+        };
+        Bson::Boolean(result)
+    }
+
+    fn complete(&self) -> Bson {
+        Bson::Null
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::Bson;
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
+use crate::{Result, Error};
+use crate::errors::mk_invalid_aggregate_field;
+
+// { $cond: [ <if>, <then>, <else> ] }
+// { $cond: { if: <if>, then: <then>, else: <else> } }
+pub(crate) struct CondOperator {
+    if_expr: OperatorExpr,
+    then_expr: OperatorExpr,
+    else_expr: OperatorExpr,
+}
+
+impl CondOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: OpRegistry, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        let (if_value, then_value, else_value) = match v {
+            Bson::Array(arr) if arr.len() == 3 => (&arr[0], &arr[1], &arr[2]),
+            Bson::Document(doc) if doc.len() == 3 => {
+                match (doc.get("if"), doc.get("then"), doc.get("else")) {
+                    (Some(if_value), Some(then_value), Some(else_value)) => (if_value, then_value, else_value),
+                    _ => {
+                        let invalid_err = mk_invalid_aggregate_field(paths);
+                        return Err(Error::InvalidField(invalid_err));
+                    }
+                }
+            }
+            _ => {
+                let invalid_err = mk_invalid_aggregate_field(paths);
+                return Err(Error::InvalidField(invalid_err));
+            }
+        };
+        Ok(Box::new(CondOperator {
+            if_expr: registry.compile_expr(paths, if_value)?,
+            then_expr: registry.compile_expr(paths, then_value)?,
+            else_expr: registry.compile_expr(paths, else_value)?,
+        }))
+    }
+
+    fn is_truthy(value: Option<Bson>) -> bool {
+        match value {
+            None | Some(Bson::Null) | Some(Bson::Undefined) | Some(Bson::Boolean(false)) => false,
+            Some(Bson::Int32(v)) => v != 0,
+            Some(Bson::Int64(v)) => v != 0,
+            Some(Bson::Double(v)) => v != 0.0,
+            Some(_) => true,
+        }
+    }
+
+}
+
+impl VmOperator for CondOperator {
+    fn initial_value(&self) -> Bson {
+        Bson::Null
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        self.eval(input).unwrap_or(Bson::Null)
+    }
+
+    fn complete(&self) -> Bson {
+        Bson::Null
+    }
+
+    fn eval(&self, input: &Bson) -> Option<Bson> {
+        if Self::is_truthy(self.if_expr.eval(input)) {
+            self.then_expr.eval(input)
+        } else {
+            self.else_expr.eval(input)
+        }
+    }
+}
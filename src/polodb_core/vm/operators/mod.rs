@@ -1,6 +1,9 @@
 mod sum_operator;
 mod op_registry;
 mod abs_operator;
+mod cond_operator;
+mod compare_operator;
+mod std_dev_operator;
 
 use bson::Bson;
 
@@ -12,14 +15,46 @@ pub(crate) trait VmOperator {
 
     fn complete(&self) -> Bson;
 
+    /// Evaluate the operator as an expression of a single document.
+    /// `None` means the value is missing, e.g. `$$REMOVE` was chosen.
+    fn eval(&self, input: &Bson) -> Option<Bson> {
+        Some(self.next(input))
+    }
 }
 
 pub(crate) enum OperatorExpr {
     Constant(Bson),
     Expr(Box<dyn VmOperator>),
     Alias(String),
+    // $$ROOT, the document being processed
+    Root,
+    // $$REMOVE, the field is omitted from the output
+    Remove,
+}
+
+impl OperatorExpr {
+
+    /// Evaluate the expression against the input document,
+    /// `None` means the value is missing.
+    pub(crate) fn eval(&self, input: &Bson) -> Option<Bson> {
+        match self {
+            OperatorExpr::Constant(v) => Some(v.clone()),
+            OperatorExpr::Expr(op) => op.eval(input),
+            // the field name can be the path of an embedded field, such as "address.zip"
+            OperatorExpr::Alias(field_name) => match input {
+                Bson::Document(doc) => crate::utils::bson::try_get_document_value(doc, field_name),
+                _ => None,
+            },
+            OperatorExpr::Root => Some(input.clone()),
+            OperatorExpr::Remove => None,
+        }
+    }
+
 }
 
 pub(crate) use sum_operator::SumOperator;
 pub(crate) use abs_operator::AbsOperator;
+pub(crate) use cond_operator::CondOperator;
+pub(crate) use compare_operator::CompareOperator;
+pub(crate) use std_dev_operator::{StdDevPopOperator, StdDevSampOperator};
 pub(crate) use op_registry::OpRegistry;
@@ -15,7 +15,7 @@
 use bson::{Bson, Document};
 use crate::{Error, Result};
 use crate::errors::mk_invalid_aggregate_field;
-use crate::vm::operators::{AbsOperator, SumOperator, VmOperator};
+use crate::vm::operators::{AbsOperator, CompareOperator, CondOperator, OperatorExpr, StdDevPopOperator, StdDevSampOperator, SumOperator, VmOperator};
 
 // Reference: https://www.mongodb.com/docs/manual/reference/operator/aggregation/
 #[derive(Clone)]
@@ -41,6 +41,12 @@ impl OpRegistry {
             match op_name.as_str() {
                 "$sum" => SumOperator::compile(op_value),
                 "$abs" => AbsOperator::compile(paths, self.clone(), op_value)?,
+                "$cond" => CondOperator::compile(paths, self.clone(), op_value)?,
+                "$stdDevPop" => StdDevPopOperator::compile(paths, self.clone(), op_value)?,
+                "$stdDevSamp" => StdDevSampOperator::compile(paths, self.clone(), op_value)?,
+                "$eq" | "$ne" | "$gt" | "$gte" | "$lt" | "$lte" => {
+                    CompareOperator::compile(paths, self.clone(), op_name, op_value)?
+                }
                 _ => {
                     let invalid_err = mk_invalid_aggregate_field(paths);
                     return Err(Error::InvalidField(invalid_err))
@@ -50,4 +56,26 @@ impl OpRegistry {
         Ok(op)
     }
 
+    /// Compile an expression argument: a field path, a system variable,
+    /// an operator document or a constant.
+    pub(crate) fn compile_expr(&self, paths: &mut Vec<String>, v: &Bson) -> Result<OperatorExpr> {
+        let expr = match v {
+            Bson::String(s) => match s.strip_prefix('$') {
+                Some("$ROOT") => OperatorExpr::Root,
+                Some("$REMOVE") => OperatorExpr::Remove,
+                Some(var) if var.starts_with('$') => {
+                    let invalid_err = mk_invalid_aggregate_field(paths);
+                    return Err(Error::InvalidField(invalid_err));
+                }
+                Some(field_name) => OperatorExpr::Alias(field_name.to_string()),
+                None => OperatorExpr::Constant(v.clone()),
+            },
+            Bson::Document(doc) if doc.keys().next().map_or(false, |k| k.starts_with('$')) => {
+                OperatorExpr::Expr(self.compile_doc(paths, doc)?)
+            }
+            _ => OperatorExpr::Constant(v.clone()),
+        };
+        Ok(expr)
+    }
+
 }
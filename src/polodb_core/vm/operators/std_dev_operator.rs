@@ -0,0 +1,173 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::Cell;
+use bson::Bson;
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
+use crate::Result;
+
+// The running count, mean and sum of squared differences from the mean,
+// updated with Welford's algorithm to stay accurate over many values.
+#[derive(Clone, Copy, Default)]
+struct RunningVariance {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningVariance {
+
+    fn of_values<'a>(values: impl IntoIterator<Item = &'a Bson>) -> RunningVariance {
+        let mut result = RunningVariance::default();
+        for value in values {
+            result.add(value);
+        }
+        result
+    }
+
+    // the non-numeric values are ignored
+    fn add(&mut self, value: &Bson) {
+        let x = match value {
+            Bson::Int32(i) => *i as f64,
+            Bson::Int64(i) => *i as f64,
+            Bson::Double(d) => *d,
+            Bson::Decimal128(d) => crate::utils::decimal128::decimal128_to_f64(d),
+            _ => return,
+        };
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn std_dev(&self, sample: bool) -> Bson {
+        let divisor = if sample {
+            if self.count < 2 {
+                return Bson::Null;
+            }
+            self.count - 1
+        } else {
+            if self.count == 0 {
+                return Bson::Null;
+            }
+            self.count
+        };
+        Bson::Double((self.m2 / divisor as f64).sqrt())
+    }
+
+}
+
+// The standard deviation of the numeric values of the expression over the documents
+// as an accumulator, or over the elements when it's evaluated to an array in an expression.
+struct StdDev {
+    inner: OperatorExpr,
+    sample: bool,
+    state: Cell<RunningVariance>,
+}
+
+impl StdDev {
+
+    fn compile(paths: &mut Vec<String>, registry: OpRegistry, v: &Bson, sample: bool) -> Result<StdDev> {
+        Ok(StdDev {
+            inner: registry.compile_expr(paths, v)?,
+            sample,
+            state: Cell::new(RunningVariance::default()),
+        })
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        let mut state = self.state.get();
+        if let Some(value) = self.inner.eval(input) {
+            state.add(&value);
+        }
+        self.state.set(state);
+        state.std_dev(self.sample)
+    }
+
+    fn eval(&self, input: &Bson) -> Option<Bson> {
+        let state = match self.inner.eval(input) {
+            Some(Bson::Array(arr)) => RunningVariance::of_values(arr.iter()),
+            Some(value) => RunningVariance::of_values(std::iter::once(&value)),
+            None => RunningVariance::default(),
+        };
+        Some(state.std_dev(self.sample))
+    }
+
+}
+
+// { $stdDevPop: <expression> }
+pub(crate) struct StdDevPopOperator {
+    inner: StdDev,
+}
+
+impl StdDevPopOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: OpRegistry, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        Ok(Box::new(StdDevPopOperator {
+            inner: StdDev::compile(paths, registry, v, false)?,
+        }))
+    }
+
+}
+
+impl VmOperator for StdDevPopOperator {
+    fn initial_value(&self) -> Bson {
+        Bson::Null
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        self.inner.next(input)
+    }
+
+    fn complete(&self) -> Bson {
+        self.inner.state.get().std_dev(false)
+    }
+
+    fn eval(&self, input: &Bson) -> Option<Bson> {
+        self.inner.eval(input)
+    }
+}
+
+// { $stdDevSamp: <expression> }
+pub(crate) struct StdDevSampOperator {
+    inner: StdDev,
+}
+
+impl StdDevSampOperator {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: OpRegistry, v: &Bson) -> Result<Box<dyn VmOperator>> {
+        Ok(Box::new(StdDevSampOperator {
+            inner: StdDev::compile(paths, registry, v, true)?,
+        }))
+    }
+
+}
+
+impl VmOperator for StdDevSampOperator {
+    fn initial_value(&self) -> Bson {
+        Bson::Null
+    }
+
+    fn next(&self, input: &Bson) -> Bson {
+        self.inner.next(input)
+    }
+
+    fn complete(&self) -> Bson {
+        self.inner.state.get().std_dev(true)
+    }
+
+    fn eval(&self, input: &Bson) -> Option<Bson> {
+        self.inner.eval(input)
+    }
+}
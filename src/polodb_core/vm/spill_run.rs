@@ -0,0 +1,89 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use bson::Document;
+use uuid::Uuid;
+use crate::Result;
+
+// Documents written to a temporary file by a stage to be read back in order,
+// the file is removed when the run is dropped.
+pub(super) struct SpillRun {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    reader: Option<BufReader<File>>,
+    // the next document of the run
+    pub(super) head: Option<Document>,
+}
+
+impl SpillRun {
+
+    /// Create an empty run in the temporary directory, `stage` names the file.
+    pub(super) fn create(stage: &str) -> Result<SpillRun> {
+        let path = std::env::temp_dir().join(format!("polodb-{}-{}.bson", stage, Uuid::new_v4()));
+        let writer = BufWriter::new(File::create(&path)?);
+        Ok(SpillRun {
+            path,
+            writer: Some(writer),
+            reader: None,
+            head: None,
+        })
+    }
+
+    pub(super) fn write(stage: &str, docs: &[Document]) -> Result<SpillRun> {
+        let mut run = SpillRun::create(stage)?;
+        for doc in docs {
+            run.append(doc)?;
+        }
+        run.finish()?;
+        Ok(run)
+    }
+
+    pub(super) fn append(&mut self, doc: &Document) -> Result<()> {
+        let writer = self.writer.as_mut().expect("the run is finished");
+        doc.to_writer(writer)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Start reading the run from its first document.
+    pub(super) fn open(&mut self) -> Result<()> {
+        self.finish()?;
+        self.reader = Some(BufReader::new(File::open(&self.path)?));
+        self.advance()
+    }
+
+    pub(super) fn advance(&mut self) -> Result<()> {
+        self.head = match self.reader.as_mut() {
+            Some(reader) if !reader.fill_buf()?.is_empty() => Some(Document::from_reader(reader)?),
+            _ => None,
+        };
+        Ok(())
+    }
+
+}
+
+impl Drop for SpillRun {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
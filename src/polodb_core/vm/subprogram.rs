@@ -18,6 +18,7 @@ use super::op::DbOp;
 use crate::coll::collection_info::{CollectionSpecification, IndexInfo};
 use crate::utils::str::escape_binary_to_string;
 use crate::vm::codegen::Codegen;
+use crate::config::MAX_QUERY_DEPTH;
 use crate::{Result};
 use bson::{Bson, Document};
 use indexmap::IndexMap;
@@ -29,11 +30,38 @@ use crate::vm::global_variable::GlobalVariableSlot;
 use crate::vm::update_operators::UpdateOperator;
 use crate::vm::vm_external_func::VmExternalFunc;
 
+#[derive(Clone)]
 pub(crate) struct SubProgramIndexItem {
     pub col_name: String,
     pub indexes: IndexMap<String, IndexInfo>,
 }
 
+/// Options of [`SubProgram::compile_aggregate`].
+pub(crate) struct AggregateCompileOptions {
+    pub max_query_depth: u32,
+    /// `$sort` spills to temporary files beyond this many documents
+    pub sort_spill_threshold: Option<u64>,
+    /// `$group` spills to temporary files beyond this many groups
+    pub group_spill_threshold: Option<u64>,
+    /// Scan the collection in storage order, bypassing the indexes,
+    /// in reverse when `Some(true)`. Set by a `$natural` sort.
+    pub natural_reverse: Option<bool>,
+    /// The seed of the `$sample` stages, `None` to seed them from entropy
+    pub random_seed: Option<u64>,
+}
+
+impl Default for AggregateCompileOptions {
+    fn default() -> Self {
+        AggregateCompileOptions {
+            max_query_depth: MAX_QUERY_DEPTH,
+            sort_spill_threshold: None,
+            group_spill_threshold: None,
+            natural_reverse: None,
+            random_seed: None,
+        }
+    }
+}
+
 pub(crate) struct SubProgram {
     pub(super) static_values: Vec<Bson>,
     pub(super) instructions: Vec<u8>,
@@ -42,6 +70,34 @@ pub(crate) struct SubProgram {
     pub(super) index_infos: Vec<SubProgramIndexItem>,
     pub(crate) external_funcs: Vec<Box<dyn VmExternalFunc>>,
     pub(crate) update_operators: Vec<Box<dyn UpdateOperator>>,
+    /// The validator of the collection, checked before updated documents are written
+    pub(super) validator: Option<Document>,
+}
+
+/// A [`SubProgram`] without external functions and update operators.
+/// It's plain data, so it can be shared between the threads.
+pub(crate) struct PlainSubProgram {
+    static_values: Vec<Bson>,
+    instructions: Vec<u8>,
+    global_variables: Vec<GlobalVariableSlot>,
+    label_slots: Vec<LabelSlot>,
+    index_infos: Vec<SubProgramIndexItem>,
+    validator: Option<Document>,
+}
+
+impl PlainSubProgram {
+    pub(crate) fn to_program(&self) -> SubProgram {
+        SubProgram {
+            static_values: self.static_values.clone(),
+            instructions: self.instructions.clone(),
+            global_variables: self.global_variables.clone(),
+            label_slots: self.label_slots.clone(),
+            index_infos: self.index_infos.clone(),
+            external_funcs: Vec::new(),
+            update_operators: Vec::new(),
+            validator: self.validator.clone(),
+        }
+    }
 }
 
 impl SubProgram {
@@ -54,7 +110,25 @@ impl SubProgram {
             index_infos: Vec::new(),
             external_funcs: Vec::new(),
             update_operators: Vec::new(),
+            validator: None,
+        }
+    }
+
+    /// Copy the program if it doesn't own any external functions or update operators,
+    /// which carry the state of a single execution.
+    pub(crate) fn to_plain(&self) -> Option<PlainSubProgram> {
+        if !self.external_funcs.is_empty() || !self.update_operators.is_empty() {
+            return None;
         }
+
+        Some(PlainSubProgram {
+            static_values: self.static_values.clone(),
+            instructions: self.instructions.clone(),
+            global_variables: self.global_variables.clone(),
+            label_slots: self.label_slots.clone(),
+            index_infos: self.index_infos.clone(),
+            validator: self.validator.clone(),
+        })
     }
 
     pub(crate) fn compile_empty_query() -> SubProgram {
@@ -69,12 +143,14 @@ impl SubProgram {
         col_spec: &CollectionSpecification,
         query: &Document,
         skip_annotation: bool,
+        max_query_depth: u32,
     ) -> Result<SubProgram> {
         if query.is_empty() {
             return SubProgram::compile_query_all(col_spec, skip_annotation);
         }
 
         let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.set_max_query_depth(max_query_depth);
 
         codegen.emit_query_layout(
             col_spec,
@@ -91,14 +167,23 @@ impl SubProgram {
         Ok(codegen.take())
     }
 
+    /// When `limit` is set, the scan stops after updating `limit` documents.
     pub(crate) fn compile_update(
         col_spec: &CollectionSpecification,
         query: &Document,
         update: &Document,
         skip_annotation: bool,
         is_many: bool,
+        limit: Option<u64>,
+        max_query_depth: u32,
     ) -> Result<SubProgram> {
         let mut codegen = Codegen::new(skip_annotation, true);
+        codegen.set_max_query_depth(max_query_depth);
+
+        let limit_counter = match limit {
+            Some(limit) => Some((codegen.new_global_variable(Bson::Int64(0))?, limit)),
+            None => None,
+        };
 
         let has_indexes = !col_spec.indexes.is_empty();
         let index_item_id: u32 = if has_indexes {
@@ -126,6 +211,44 @@ impl SubProgram {
                     codegen.emit_u32(index_item_id);
                 }
 
+                codegen.emit(DbOp::Pop);
+
+                if let Some((counter, limit)) = limit_counter {
+                    codegen.emit_limit_check(counter, limit);
+                }
+                Ok(())
+            },
+            None,
+            is_many,
+        )?;
+
+        let mut program = codegen.take();
+        program.validator = col_spec.validator.clone();
+        Ok(program)
+    }
+
+    /// Compile the query of an update or a delete without writing anything.
+    ///
+    /// The matched documents are returned, with the `update` applied if it's provided.
+    pub(crate) fn compile_dry_run(
+        col_spec: &CollectionSpecification,
+        query: &Document,
+        update: Option<&Document>,
+        skip_annotation: bool,
+        is_many: bool,
+        max_query_depth: u32,
+    ) -> Result<SubProgram> {
+        let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.set_max_query_depth(max_query_depth);
+
+        codegen.emit_query_layout(
+            col_spec,
+            query,
+            |codegen| -> Result<()> {
+                if let Some(update) = update {
+                    codegen.emit_update_operators(update)?;
+                }
+                codegen.emit(DbOp::ResultRow);
                 codegen.emit(DbOp::Pop);
                 Ok(())
             },
@@ -136,14 +259,23 @@ impl SubProgram {
         Ok(codegen.take())
     }
 
+    /// When `limit` is set, the scan stops after deleting `limit` documents.
     pub(crate) fn compile_delete(
         col_spec: &CollectionSpecification,
         col_name: &str,
         query: Option<&Document>,
         skip_annotation: bool,
         is_many: bool,
+        limit: Option<u64>,
+        max_query_depth: u32,
     ) -> Result<SubProgram> {
         let mut codegen = Codegen::new(skip_annotation, true);
+        codegen.set_max_query_depth(max_query_depth);
+
+        let limit_counter = match limit {
+            Some(limit) => Some((codegen.new_global_variable(Bson::Int64(0))?, limit)),
+            None => None,
+        };
 
         let has_indexes = !col_spec.indexes.is_empty();
         let index_item_id: u32 = if has_indexes {
@@ -168,6 +300,10 @@ impl SubProgram {
 
                 codegen.emit_delete_operation();
                 codegen.emit(DbOp::Pop);
+
+                if let Some((counter, limit)) = limit_counter {
+                    codegen.emit_limit_check(counter, limit);
+                }
                 Ok(())
             },
             None,
@@ -201,12 +337,12 @@ impl SubProgram {
 
         codegen.emit_open(col_name.into());
 
-        codegen.emit_goto(DbOp::Rewind, close_label);
+        codegen.emit_rewind(close_label);
 
         codegen.emit_goto(DbOp::Goto, result_label);
 
         codegen.emit_label(next_label);
-        codegen.emit_goto(DbOp::Next, result_label);
+        codegen.emit_advance(result_label);
 
         codegen.emit_label(close_label);
         codegen.emit(DbOp::Close);
@@ -268,6 +404,7 @@ impl SubProgram {
         col_spec: &CollectionSpecification,
         pipeline: impl IntoIterator<Item = Document>,
         skip_annotation: bool,
+        options: &AggregateCompileOptions,
     ) -> Result<SubProgram> {
         let pipeline_vec: Vec<Document> = pipeline.into_iter().collect();
         if pipeline_vec.is_empty() {
@@ -276,10 +413,16 @@ impl SubProgram {
 
         let first = pipeline_vec.first().unwrap();
         if first.len() == 1 && first.contains_key("$match") {
-            return SubProgram::compile_aggregate_with_match(col_spec, pipeline_vec, skip_annotation);
+            return SubProgram::compile_aggregate_with_match(
+                col_spec,
+                pipeline_vec,
+                skip_annotation,
+                options,
+            );
         }
 
         let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.apply_aggregate_options(options);
         let result_label = codegen.new_label();
         let next_label = codegen.new_label();
         let close_label = codegen.new_label();
@@ -317,8 +460,10 @@ impl SubProgram {
         col_spec: &CollectionSpecification,
         pipeline_vec: Vec<Document>,
         skip_annotation: bool,
+        options: &AggregateCompileOptions,
     ) -> Result<SubProgram> {
         let mut codegen = Codegen::new(skip_annotation, false);
+        codegen.apply_aggregate_options(options);
         let first_doc = pipeline_vec.first().unwrap();
         let query_doc_value = first_doc.get("$match").unwrap();
         let query_doc = match query_doc_value {
@@ -445,6 +590,12 @@ impl fmt::Display for SubProgram {
                         pc += 5;
                     }
 
+                    DbOp::RewindLast => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: RewindLast({})", pc, location)?;
+                        pc += 5;
+                    }
+
                     DbOp::FindByPrimaryKey => {
                         let location = begin.add(pc + 1).cast::<u32>().read();
                         writeln!(f, "{}: FindByPrimaryKey({})", pc, location)?;
@@ -457,12 +608,24 @@ impl fmt::Display for SubProgram {
                         pc += 5;
                     }
 
+                    DbOp::FindByIndexPrefix => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: FindByIndexPrefix({})", pc, location)?;
+                        pc += 5;
+                    }
+
                     DbOp::Next => {
                         let location = begin.add(pc + 1).cast::<u32>().read();
                         writeln!(f, "{}: Next({})", pc, location)?;
                         pc += 5;
                     }
 
+                    DbOp::Prev => {
+                        let location = begin.add(pc + 1).cast::<u32>().read();
+                        writeln!(f, "{}: Prev({})", pc, location)?;
+                        pc += 5;
+                    }
+
                     DbOp::NextIndexValue => {
                         let location = begin.add(pc + 1).cast::<u32>().read();
                         writeln!(f, "{}: NextIndexValue({})", pc, location)?;
@@ -582,6 +745,11 @@ impl fmt::Display for SubProgram {
                         pc += 1;
                     }
 
+                    DbOp::JsonSchema => {
+                        writeln!(f, "{}: JsonSchema", pc)?;
+                        pc += 1;
+                    }
+
                     DbOp::Not => {
                         writeln!(f, "{}: Not", pc)?;
                         pc += 1;
@@ -592,6 +760,16 @@ impl fmt::Display for SubProgram {
                         pc += 1;
                     }
 
+                    DbOp::ElemMatch => {
+                        writeln!(f, "{}: ElemMatch", pc)?;
+                        pc += 1;
+                    }
+
+                    DbOp::GeoWithin => {
+                        writeln!(f, "{}: GeoWithin", pc)?;
+                        pc += 1;
+                    }
+
                     DbOp::EqualNull => {
                         writeln!(f, "{}: EqualNull", pc)?;
                         pc += 1;
@@ -742,7 +920,9 @@ impl fmt::Display for SubProgram {
 #[cfg(test)]
 mod tests {
     use crate::coll::collection_info::{CollectionSpecification, IndexInfo};
+    use crate::config::MAX_QUERY_DEPTH;
     use crate::vm::SubProgram;
+    use crate::vm::subprogram::AggregateCompileOptions;
     use bson::{doc, Regex};
     use indexmap::indexmap;
     use polodb_line_diff::assert_eq;
@@ -789,7 +969,7 @@ mod tests {
             "age": 32,
         };
         let col_spec = new_spec("test");
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -846,7 +1026,7 @@ mod tests {
             "info.color": "yellow",
         };
         let col_spec = new_spec("test");
-        let program = SubProgram::compile_query(&col_spec, &query_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &query_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -898,7 +1078,7 @@ mod tests {
             "_id": 6,
             "age": 32,
         };
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -946,7 +1126,7 @@ mod tests {
             "name": "Vincent Chan",
         };
 
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -955,27 +1135,185 @@ mod tests {
 5: PushValue(32)
 10: PushValue("test")
 15: FindByIndex(35)
-20: Goto(44)
+20: Goto(67)
 
-25: Label(2)
-30: NextIndexValue(44)
+25: Label(3)
+30: NextIndexValue(67)
 
-35: Label(0)
+35: Label(6, "close")
 40: Pop
 41: Pop
 42: Close
 43: Halt
 
-44: Label(1)
-49: GetField("name", 35)
-58: PushValue("Vincent Chan")
-63: Equal
-64: FalseJump(35)
-69: Pop
-70: Pop
-71: ResultRow
-72: Pop
-73: Goto(25)
+44: Label(5, "not_this_item")
+49: Pop
+50: Goto(25)
+
+55: Label(4, "result")
+60: ResultRow
+61: Pop
+62: Goto(25)
+
+67: Label(2, "compare")
+72: Dup
+73: Call(92, 1)
+82: FalseJump(44)
+87: Goto(55)
+
+92: Label(0, "compare_function")
+97: GetField("age", 141)
+106: PushValue(32)
+111: Equal
+112: FalseJump(141)
+117: Pop
+118: Pop
+119: GetField("name", 141)
+128: PushValue("Vincent Chan")
+133: Equal
+134: FalseJump(141)
+139: Pop
+140: Pop
+
+141: Label(1, "compare_function_clean")
+146: Ret0
+"#;
+        assert_eq!(expect, actual);
+    }
+
+    #[test]
+    fn print_query_by_embedded_field_index() {
+        let mut col_spec = new_spec("test");
+
+        col_spec.indexes.insert(
+            "address_zip_1".into(),
+            IndexInfo {
+                keys: indexmap! {
+                    "address.zip".into() => 1,
+                },
+                options: None,
+            },
+        );
+
+        let test_doc = doc! {
+            "address.zip": {
+                "$eq": 90210,
+            },
+        };
+
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
+        let actual = format!("Program:\n\n{}", program);
+
+        let expect = r#"Program:
+
+0: OpenRead(b"\x02$I\x00\x02test\x00\x02address_zip_1\x00")
+5: PushValue(90210)
+10: PushValue("test")
+15: FindByIndex(35)
+20: Goto(67)
+
+25: Label(3)
+30: NextIndexValue(67)
+
+35: Label(6, "close")
+40: Pop
+41: Pop
+42: Close
+43: Halt
+
+44: Label(5, "not_this_item")
+49: Pop
+50: Goto(25)
+
+55: Label(4, "result")
+60: ResultRow
+61: Pop
+62: Goto(25)
+
+67: Label(2, "compare")
+72: Dup
+73: Call(92, 1)
+82: FalseJump(44)
+87: Goto(55)
+
+92: Label(0, "compare_function")
+97: GetField("address", 131)
+106: GetField("zip", 131)
+115: PushValue(90210)
+120: Equal
+121: FalseJump(131)
+126: Pop2(3)
+
+131: Label(1, "compare_function_clean")
+136: Ret0
+"#;
+        assert_eq!(expect, actual);
+    }
+
+    #[test]
+    fn print_query_in_by_multikey_index() {
+        let mut col_spec = new_spec("test");
+
+        col_spec.indexes.insert(
+            "tags_1".into(),
+            IndexInfo {
+                keys: indexmap! {
+                    "tags".into() => 1,
+                },
+                options: None,
+            },
+        );
+
+        let test_doc = doc! {
+            "tags": {
+                "$in": ["a", "b"],
+            },
+        };
+
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
+        let actual = format!("Program:\n\n{}", program);
+
+        let expect = r#"Program:
+
+0: OpenRead(b"\x02$I\x00\x02test\x00\x02tags_1\x00")
+5: PushValue(["a", "b"])
+10: PushValue("test")
+15: FindByIndex(35)
+20: Goto(67)
+
+25: Label(3)
+30: NextIndexValue(67)
+
+35: Label(6, "close")
+40: Pop
+41: Pop
+42: Close
+43: Halt
+
+44: Label(5, "not_this_item")
+49: Pop
+50: Goto(25)
+
+55: Label(4, "result")
+60: ResultRow
+61: Pop
+62: Goto(25)
+
+67: Label(2, "compare")
+72: Dup
+73: Call(92, 1)
+82: FalseJump(44)
+87: Goto(55)
+
+92: Label(0, "compare_function")
+97: GetField("tags", 122)
+106: PushValue(["a", "b"])
+111: In
+112: FalseJump(122)
+117: Pop2(2)
+
+122: Label(1, "compare_function_clean")
+127: Ret0
 "#;
         assert_eq!(expect, actual);
     }
@@ -993,7 +1331,7 @@ mod tests {
                 },
             ],
         };
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -1057,7 +1395,7 @@ mod tests {
                 },
             ],
         };
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -1135,7 +1473,7 @@ mod tests {
                 },
             }
         };
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
         let expect = r#"Program:
 
@@ -1190,7 +1528,7 @@ mod tests {
                 "$in": [ 1, 2 ],
             },
         };
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -1251,7 +1589,7 @@ mod tests {
                 },
             },
         };
-        let program = SubProgram::compile_query(&col_spec, &test_doc, false).unwrap();
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -1295,6 +1633,89 @@ mod tests {
         assert_eq!(expect, actual);
     }
 
+    #[test]
+    fn print_regex_prefix_by_index() {
+        let mut col_spec = new_spec("test");
+
+        col_spec.indexes.insert(
+            "name_1".into(),
+            IndexInfo {
+                keys: indexmap! {
+                    "name".into() => 1,
+                },
+                options: None,
+            },
+        );
+
+        let test_doc = doc! {
+            "name": doc! {
+                "$regex": Regex {
+                    options: String::new(),
+                    pattern: "^Vin".into(),
+                },
+            },
+        };
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
+        let actual = format!("Program:\n\n{}", program);
+
+        let expect = r#"Program:
+
+0: OpenRead(b"\x02$I\x00\x02test\x00\x02name_1\x00")
+5: PushValue("Vin")
+10: PushValue("test")
+15: FindByIndexPrefix(35)
+20: Goto(67)
+
+25: Label(3)
+30: NextIndexValue(67)
+
+35: Label(6, "close")
+40: Pop
+41: Pop
+42: Close
+43: Halt
+
+44: Label(5, "not_this_item")
+49: Pop
+50: Goto(25)
+
+55: Label(4, "result")
+60: ResultRow
+61: Pop
+62: Goto(25)
+
+67: Label(2, "compare")
+72: Dup
+73: Call(92, 1)
+82: FalseJump(44)
+87: Goto(55)
+
+92: Label(0, "compare_function")
+97: GetField("name", 122)
+106: PushValue(/^Vin/)
+111: Regex
+112: FalseJump(122)
+117: Pop2(2)
+
+122: Label(1, "compare_function_clean")
+127: Ret0
+"#;
+        assert_eq!(expect, actual);
+
+        // not anchored, scan the collection
+        let test_doc = doc! {
+            "name": doc! {
+                "$regex": Regex {
+                    options: String::new(),
+                    pattern: "Vin".into(),
+                },
+            },
+        };
+        let program = SubProgram::compile_query(&col_spec, &test_doc, false, MAX_QUERY_DEPTH).unwrap();
+        let actual = format!("{}", program);
+        assert!(actual.starts_with("0: OpenRead(\"test\")"));
+    }
+
     #[test]
     fn print_update() {
         let col_spec = new_spec("test");
@@ -1324,7 +1745,7 @@ mod tests {
             },
         };
         let program =
-            SubProgram::compile_update(&col_spec, &query_doc, &update_doc, false, true)
+            SubProgram::compile_update(&col_spec, &query_doc, &update_doc, false, true, None, MAX_QUERY_DEPTH)
                 .unwrap();
         let actual = format!("Program:\n\n{}", program);
 
@@ -1402,7 +1823,7 @@ mod tests {
             },
         };
         let program =
-            SubProgram::compile_update(&col_spec, &query_doc, &update_doc, false, true)
+            SubProgram::compile_update(&col_spec, &query_doc, &update_doc, false, true, None, MAX_QUERY_DEPTH)
                 .unwrap();
         let actual = format!("Program:\n\n{}", program);
 
@@ -1463,7 +1884,7 @@ mod tests {
                     },
                 },
             },
-        ], false).unwrap();
+        ], false, &AggregateCompileOptions::default()).unwrap();
         let actual = format!("Program:\n\n{}", program);
         let expect = r#"Program:
 
@@ -1520,7 +1941,7 @@ mod tests {
             doc! {
                 "$count": "total",
             },
-        ], false).unwrap();
+        ], false, &AggregateCompileOptions::default()).unwrap();
         let actual = format!("Program:\n\n{}", program);
 
         let expect = r#"Program:
@@ -1600,7 +2021,7 @@ mod tests {
             doc! {
                 "$count": "total",
             },
-        ], false).unwrap();
+        ], false, &AggregateCompileOptions::default()).unwrap();
         let actual = format!("Program:\n\n{}", program);
         let expect = r#"Program:
 
@@ -1663,7 +2084,7 @@ mod tests {
                     },
                 },
             },
-        ], false);
+        ], false, &AggregateCompileOptions::default());
         assert!(program.is_err());
         match program {
             Err(Error::InvalidField(i)) => {
@@ -16,13 +16,18 @@ impl IncOperator {
         })
     }
 
+    // the result is a Double if any operand is, otherwise an Int64 if any is,
+    // an Int32 sum overflowing is promoted to Int64
     fn inc_numeric(key: &str, a: &Bson, b: &Bson) -> Result<Bson> {
         let val = match (a, b) {
-            (Bson::Int32(a), Bson::Int32(b)) => Bson::Int32(*a + *b),
-            (Bson::Int32(a), Bson::Int64(b)) => Bson::Int64(*a as i64 + *b),
+            (Bson::Int32(a), Bson::Int32(b)) => match a.checked_add(*b) {
+                Some(sum) => Bson::Int32(sum),
+                None => Bson::Int64(*a as i64 + *b as i64),
+            },
+            (Bson::Int32(a), Bson::Int64(b)) => IncOperator::add_i64(*a as i64, *b)?,
             (Bson::Int32(a), Bson::Double(b)) => Bson::Double(*a as f64 + *b),
-            (Bson::Int64(a), Bson::Int64(b)) => Bson::Int64(*a + *b),
-            (Bson::Int64(a), Bson::Int32(b)) => Bson::Int64(*a + *b as i64),
+            (Bson::Int64(a), Bson::Int64(b)) => IncOperator::add_i64(*a, *b)?,
+            (Bson::Int64(a), Bson::Int32(b)) => IncOperator::add_i64(*a, *b as i64)?,
             (Bson::Int64(a), Bson::Double(b)) => Bson::Double(*a as f64 + *b),
             (Bson::Double(a), Bson::Double(b)) => Bson::Double(*a + *b),
             (Bson::Double(a), Bson::Int32(b)) => Bson::Double(*a + *b as f64),
@@ -41,6 +46,10 @@ impl IncOperator {
         Ok(val)
     }
 
+    fn add_i64(a: i64, b: i64) -> Result<Bson> {
+        a.checked_add(b).map(Bson::Int64).ok_or(Error::DataOverflow)
+    }
+
     fn inc_field(doc: &mut Document, key: &str, value: Bson) -> Result<()> {
         match doc.get(key) {
             Some(Bson::Null) => {
@@ -1,6 +1,7 @@
+use std::cmp::Ordering;
 use bson::{Bson, Document};
 use crate::Result;
-use crate::vm::op::{generic_cmp, DbOp};
+use crate::utils::bson::value_total_cmp;
 use crate::vm::update_operators::{UpdateOperator, UpdateResult};
 
 pub(crate) struct MinOperator {
@@ -28,9 +29,12 @@ impl UpdateOperator for MinOperator {
 
         let mut updated = false;
         for (k, v) in self.doc.iter() {
-            let current_val = doc.get(k).unwrap_or(&Bson::Null);
-            let cmp = generic_cmp(DbOp::Less, v, current_val)?;
-            if cmp {
+            // an absent field is always set to the value
+            let should_set = match doc.get(k) {
+                Some(current_val) => value_total_cmp(v, current_val) == Ordering::Less,
+                None => true,
+            };
+            if should_set {
                 doc.insert(k.clone(), v.clone());
                 updated = true;
             }
@@ -23,9 +23,10 @@ impl PopOperator {
                     )))
                 }
             };
-            let val = match num {
-                -1 => false,
-                1 => true,
+            // -1 removes the first element, 1 removes the last one
+            let is_first = match num {
+                -1 => true,
+                1 => false,
                 _ => {
                     return Err(Error::InvalidField(mk_invalid_query_field(
                         name,
@@ -33,7 +34,7 @@ impl PopOperator {
                     )))
                 }
             };
-            pop_map.insert(key.clone(), val);
+            pop_map.insert(key.clone(), is_first);
         }
         Ok(PopOperator {
             pop_map,
@@ -53,9 +54,8 @@ impl UpdateOperator for PopOperator {
 
         let mut updated = false;
         for (k, is_first) in self.pop_map.iter() {
-            let target = doc.get(k).unwrap_or(&Bson::Null);
-            let result = match target.clone() {
-                Bson::Array(mut arr) => {
+            let result = match doc.get(k).cloned() {
+                Some(Bson::Array(mut arr)) => {
                     if arr.is_empty() {
                         continue;
                     }
@@ -66,9 +66,8 @@ impl UpdateOperator for PopOperator {
                     }
                     Bson::Array(arr)
                 }
-                Bson::Null => {
-                    Bson::Array(Vec::new())
-                }
+                // popping an absent array is a no-op
+                None => continue,
                 _ => {
                     return Err(Error::InvalidField(mk_invalid_query_field(
                         self.name().to_string(),
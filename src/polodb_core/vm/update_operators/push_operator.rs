@@ -1,21 +1,87 @@
 use bson::{Bson, Document};
+use indexmap::IndexMap;
 use crate::vm::update_operators::{UpdateOperator, UpdateResult};
-use crate::Result;
-use crate::errors::CannotApplyOperationForTypes;
+use crate::{Error, Result};
+use crate::errors::{mk_invalid_query_field, CannotApplyOperationForTypes};
+
+struct PushItem {
+    values: Vec<Bson>,
+    // None appends the values to the end of the array
+    position: Option<i64>,
+}
 
 pub(crate) struct PushOperator {
-    doc: Document,
+    push_map: IndexMap<String, PushItem>,
 }
 
 impl PushOperator {
 
-    pub fn compile(doc: Document) -> Result<PushOperator> {
+    pub fn compile(doc: Document, name: String, path: String) -> Result<PushOperator> {
         <dyn UpdateOperator>::validate_key(&doc)?;
+        let mut push_map = IndexMap::new();
+        for (key, value) in doc.iter() {
+            let item = match value {
+                Bson::Document(modifiers) if PushOperator::has_modifiers(modifiers) => {
+                    PushOperator::compile_modifiers(modifiers, &name, &path)?
+                }
+                _ => PushItem {
+                    values: vec![value.clone()],
+                    position: None,
+                },
+            };
+            push_map.insert(key.clone(), item);
+        }
         Ok(PushOperator {
-            doc
+            push_map,
         })
     }
 
+    fn has_modifiers(doc: &Document) -> bool {
+        doc.contains_key("$each") || doc.contains_key("$position")
+    }
+
+    fn compile_modifiers(doc: &Document, name: &str, path: &str) -> Result<PushItem> {
+        let mk_err = || Error::InvalidField(mk_invalid_query_field(
+            name.to_string(),
+            path.to_string(),
+        ));
+
+        let values = match doc.get("$each") {
+            Some(Bson::Array(arr)) => arr.clone(),
+            // $position requires $each
+            _ => return Err(mk_err()),
+        };
+
+        let mut position = None;
+        for (key, value) in doc.iter() {
+            match key.as_str() {
+                "$each" => (),
+                "$position" => {
+                    position = Some(match value {
+                        Bson::Int32(i) => *i as i64,
+                        Bson::Int64(i) => *i,
+                        _ => return Err(mk_err()),
+                    });
+                }
+                _ => return Err(mk_err()),
+            }
+        }
+
+        Ok(PushItem {
+            values,
+            position,
+        })
+    }
+
+    /// Resolve the insert offset, negative positions count from the end.
+    fn insert_offset(position: Option<i64>, len: usize) -> usize {
+        match position {
+            None => len,
+            Some(pos) if pos >= 0 => (pos as usize).min(len),
+            Some(pos) => len.saturating_sub(pos.unsigned_abs() as usize),
+        }
+    }
+
 }
 
 impl UpdateOperator for PushOperator {
@@ -28,27 +94,24 @@ impl UpdateOperator for PushOperator {
         let doc = value.as_document_mut().unwrap();
 
         let mut updated = false;
-        for (k, v) in self.doc.iter() {
+        for (k, item) in self.push_map.iter() {
             let target = doc.get(k).unwrap_or(&Bson::Null);
-            let result = match target.clone() {
-                Bson::Array(mut arr) => {
-                    arr.push(v.clone());
-                    Bson::Array(arr)
-                }
-                Bson::Null => {
-                    Bson::Array(vec![v.clone()])
-                }
+            let mut arr = match target.clone() {
+                Bson::Array(arr) => arr,
+                Bson::Null => Vec::with_capacity(item.values.len()),
                 _ => {
                     return Err(CannotApplyOperationForTypes {
                         op_name: "$push".into(),
                         field_name: k.into(),
                         field_type: target.to_string(),
-                        target_type: v.to_string(),
+                        target_type: Bson::Array(item.values.clone()).to_string(),
                     }
                         .into());
                 }
             };
-            doc.insert(k.clone(), result);
+            let offset = PushOperator::insert_offset(item.position, arr.len());
+            arr.splice(offset..offset, item.values.iter().cloned());
+            doc.insert(k.clone(), Bson::Array(arr));
             updated = true;
         }
 
@@ -57,3 +120,4 @@ impl UpdateOperator for PushOperator {
         })
     }
 }
+
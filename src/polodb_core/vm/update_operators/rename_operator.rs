@@ -1,17 +1,23 @@
 use bson::{Bson, Document};
-use crate::errors::FieldTypeUnexpectedStruct;
+use crate::errors::{mk_invalid_query_field, FieldTypeUnexpectedStruct};
 use crate::vm::update_operators::{UpdateOperator, UpdateResult};
-use crate::Result;
+use crate::{Error, Result};
 
+/// Renames the fields, the dotted paths are resolved through the embedded documents.
+///
+/// Renaming the elements of arrays is not supported: a path containing a numeric
+/// segment such as `a.0.x` is rejected with [`Error::InvalidField`] when the update
+/// is compiled, and a path going through an array is rejected with
+/// [`Error::FieldTypeUnexpected`] when it's applied.
 pub(crate) struct RenameOperator {
     doc: Document
 }
 
 impl RenameOperator {
 
-    pub fn compile(doc: Document) -> Result<RenameOperator> {
+    pub fn compile(doc: Document, path: String) -> Result<RenameOperator> {
         for (key, value) in doc.iter() {
-            let _new_name = match value {
+            let new_name = match value {
                 Bson::String(new_name) => new_name.as_str(),
                 t => {
                     let name = format!("{}", t);
@@ -23,23 +29,65 @@ impl RenameOperator {
                         .into());
                 }
             };
+            for field in [key.as_str(), new_name] {
+                if field.split('.').any(RenameOperator::is_array_index) {
+                    return Err(Error::InvalidField(mk_invalid_query_field(
+                        field.to_string(),
+                        format!("{}/{}", path, key),
+                    )));
+                }
+            }
         }
         Ok(RenameOperator {
             doc
         })
     }
 
-    fn rename_field(doc: &mut Document, key: &str, new_key: &str) -> Result<()> {
-        match doc.remove(key) {
-            Some(value) => {
-                doc.insert(new_key, value);
-            }
+    fn is_array_index(segment: &str) -> bool {
+        !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit())
+    }
 
+    fn not_a_document(field_name: &str, value: &Bson) -> Error {
+        FieldTypeUnexpectedStruct {
+            field_name: field_name.into(),
+            expected_ty: "Document".into(),
+            actual_ty: format!("{:?}", value.element_type()),
+        }
+            .into()
+    }
+
+    fn take_field(doc: &mut Document, path: &str) -> Result<Option<Bson>> {
+        match path.split_once('.') {
+            None => Ok(doc.remove(path)),
+            Some((head, rest)) => match doc.get_mut(head) {
+                Some(Bson::Document(sub_doc)) => RenameOperator::take_field(sub_doc, rest),
+                Some(value @ Bson::Array(_)) => Err(RenameOperator::not_a_document(head, value)),
+                _ => Ok(None),
+            },
+        }
+    }
+
+    fn put_field(doc: &mut Document, path: &str, value: Bson) -> Result<()> {
+        match path.split_once('.') {
             None => {
-                doc.insert(new_key, Bson::Null);
+                doc.insert(path, value);
+                Ok(())
+            }
+            Some((head, rest)) => {
+                if !doc.contains_key(head) {
+                    doc.insert(head, Document::new());
+                }
+                match doc.get_mut(head).unwrap() {
+                    Bson::Document(sub_doc) => RenameOperator::put_field(sub_doc, rest, value),
+                    other => Err(RenameOperator::not_a_document(head, other)),
+                }
             }
         }
-        Ok(())
+    }
+
+    fn rename_field(doc: &mut Document, key: &str, new_key: &str) -> Result<()> {
+        let value = RenameOperator::take_field(doc, key)?.unwrap_or(Bson::Null);
+        RenameOperator::put_field(doc, new_key, value)
     }
 
 }
@@ -12,19 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::coll::validator;
+use crate::coll::json_schema;
 use crate::cursor::Cursor;
+use crate::db::capped_helper;
+use crate::db::oplog::{self, OplogTarget};
 use crate::errors::{
     FieldTypeUnexpectedStruct, RegexError, UnexpectedTypeForOpStruct,
 };
-use crate::index::{IndexHelper, IndexHelperOperation, make_index_key_with_query_key};
+use crate::index::{IndexHelper, IndexHelperOperation, IndexKeyLimit, IndexStats, make_index_key_with_query_key};
 use crate::transaction::TransactionInner;
+use crate::vm::elem_match;
+use crate::vm::geo;
 use crate::vm::op::{generic_cmp, DbOp};
 use crate::vm::SubProgram;
-use crate::{Error, Metrics, Result};
-use bson::{Bson, Document};
+use crate::{CancellationToken, Error, Metrics, Result};
+use bson::{doc, Bson, Document};
+use bson::spec::ElementType;
 use regex::RegexBuilder;
 use std::cell::Cell;
 use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 use crate::vm::vm_external_func::VmExternalFuncStatus;
 
 macro_rules! try_vm {
@@ -41,6 +50,9 @@ macro_rules! try_vm {
 
 const STACK_SIZE: usize = 256;
 
+// the clock is read once every N rows scanned
+const DEADLINE_CHECK_INTERVAL: u32 = 64;
+
 #[repr(i8)]
 #[derive(PartialEq, Copy, Clone)]
 pub enum VmState {
@@ -77,8 +89,27 @@ pub(crate) struct VM {
     frames: Vec<VMFrame>,
     pub(crate) program: SubProgram,
     global_vars: Vec<Bson>,
-    index_value: Option<Bson>,
-    metrics: Metrics,
+    // the keys of the index entries matching the current index scan start with it
+    index_key_prefix: Option<Vec<u8>>,
+    // the prefixes scanned after the current one, the next is at the end
+    pending_index_key_prefixes: Vec<Vec<u8>>,
+    // the primary keys of the documents read by the index scan, when a document
+    // can have entries under more than one prefix, e.g. an entry per array element
+    seen_index_pkeys: Option<HashSet<Vec<u8>>>,
+    pub(crate) metrics: Metrics,
+    deadline: Option<(Instant, Duration)>,
+    deadline_ticks: u32,
+    cancellation_token: Option<CancellationToken>,
+    // the updated and deleted documents are logged to it
+    oplog_target: Option<OplogTarget>,
+    // the name of the capped collection whose insertion order tracks the deleted documents
+    capped_collection: Option<String>,
+    // the long values are truncated or rejected in the index keys
+    index_key_limit: IndexKeyLimit,
+    // the updated documents nested deeper are rejected, 0 for no limit
+    max_document_depth: u32,
+    // the lookups by the indexes are counted to it
+    index_stats: Option<IndexStats>,
 }
 
 unsafe impl Send for VM {}
@@ -107,9 +138,68 @@ impl VM {
             frames: vec![VMFrame::default()],
             program,
             global_vars,
-            index_value: None,
+            index_key_prefix: None,
+            pending_index_key_prefixes: Vec::new(),
+            seen_index_pkeys: None,
             metrics,
+            deadline: None,
+            deadline_ticks: 0,
+            cancellation_token: None,
+            oplog_target: None,
+            capped_collection: None,
+            index_key_limit: IndexKeyLimit::default(),
+            max_document_depth: 0,
+            index_stats: None,
+        }
+    }
+
+    /// Abort the execution with [`Error::MaxTimeExpired`] once `max_time`
+    /// has elapsed from now.
+    pub(crate) fn set_max_time(&mut self, max_time: Duration) {
+        self.deadline = Some((Instant::now() + max_time, max_time));
+        self.deadline_ticks = 0;
+    }
+
+    pub(crate) fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation_token = Some(token);
+    }
+
+    pub(crate) fn set_oplog_target(&mut self, target: OplogTarget) {
+        self.oplog_target = Some(target);
+    }
+
+    pub(crate) fn set_capped_collection(&mut self, col_name: &str) {
+        self.capped_collection = Some(col_name.to_string());
+    }
+
+    pub(crate) fn set_index_key_limit(&mut self, limit: IndexKeyLimit) {
+        self.index_key_limit = limit;
+    }
+
+    pub(crate) fn set_max_document_depth(&mut self, max_depth: u32) {
+        self.max_document_depth = max_depth;
+    }
+
+    pub(crate) fn set_index_stats(&mut self, index_stats: IndexStats) {
+        self.index_stats = Some(index_stats);
+    }
+
+    fn check_interrupt(&mut self) -> Result<()> {
+        if let Some(token) = &self.cancellation_token {
+            if token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+        }
+        let (deadline, max_time) = match self.deadline {
+            Some(deadline) => deadline,
+            None => return Ok(()),
+        };
+        let tick = self.deadline_ticks;
+        self.deadline_ticks = tick.wrapping_add(1);
+        if tick % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+            return Err(Error::MaxTimeExpired(max_time.as_millis() as u64));
         }
+        Ok(())
     }
 
     fn prefix_bytes_from_bson(val: Bson) -> Result<Vec<u8>> {
@@ -153,6 +243,22 @@ impl VM {
             let item = cursor.copy_data()?;
             let doc = bson::from_slice(item.as_ref())?;
             self.stack.push(Bson::Document(doc));
+            self.metrics.add_examined_count();
+            is_empty.set(false);
+        } else {
+            is_empty.set(true);
+        }
+        Ok(())
+    }
+
+    fn reset_cursor_to_last(&mut self, is_empty: &Cell<bool>) -> Result<()> {
+        let cursor = self.r1.as_mut().unwrap();
+        cursor.reset_to_last()?;
+        if cursor.has_next() {
+            let item = cursor.copy_data()?;
+            let doc = bson::from_slice(item.as_ref())?;
+            self.stack.push(Bson::Document(doc));
+            self.metrics.add_examined_count();
             is_empty.set(false);
         } else {
             is_empty.set(true);
@@ -166,7 +272,16 @@ impl VM {
         let top_index = self.stack.len() - 1;
         let op = &self.stack[top_index];
 
-        let result = cursor.reset_by_pkey(op)?;
+        let mut result = cursor.reset_by_pkey(op)?;
+        if !result {
+            // the numbers are equal by value, whatever type the primary key is stored with
+            for equivalent in crate::utils::bson::numeric_equivalents(op) {
+                if cursor.reset_by_pkey(&equivalent)? {
+                    result = true;
+                    break;
+                }
+            }
+        }
         if !result {
             return Ok(false);
         }
@@ -174,25 +289,69 @@ impl VM {
         let buf = cursor.copy_data()?;
         let doc = bson::from_slice(buf.as_ref())?;
         self.stack.push(Bson::Document(doc));
+        self.metrics.add_examined_count();
         Ok(true)
     }
 
     fn find_by_index(&mut self) -> Result<bool> {
         let stack_len = self.stack.len();
         // let col_name = self.stack[stack_len - 1].as_str().expect("col_name must be string").to_string();
-        let query_value = &self.stack[stack_len - 2];
+        // the values of `$in` are looked up one after another
+        let query_values = match &self.stack[stack_len - 2] {
+            Bson::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
 
-        let cursor = self.r1.as_mut().unwrap();
-        let result = cursor.reset_by_index_value(query_value)?;
-        self.index_value = Some(query_value.clone());
+        let cursor = self.r1.as_ref().unwrap();
+        let mut key_prefixes = Vec::with_capacity(query_values.len());
+        for query_value in query_values {
+            // the long values are looked up by the truncated keys
+            let query_value = self.index_key_limit.truncate_value(query_value);
+            // the numbers are equal by value, whatever type they are stored with in the index,
+            // so the entries of every equivalent are scanned
+            let equivalents = crate::utils::bson::numeric_equivalents(&query_value);
+            for value in std::iter::once(&query_value).chain(equivalents.iter()) {
+                key_prefixes.push(make_index_key_with_query_key(cursor.prefix_bytes.as_slice(), value)?);
+            }
+        }
+        key_prefixes.sort();
+        key_prefixes.dedup();
 
-        if !result {
+        // the entries of an array are under the prefix of every element
+        let dedup = key_prefixes.len() > 1;
+        self.find_by_index_key_prefixes(key_prefixes, dedup)
+    }
+
+    /// Scan the index entries of the strings starting with the prefix.
+    fn find_by_index_prefix(&mut self) -> Result<bool> {
+        let stack_len = self.stack.len();
+        let prefix = self.stack[stack_len - 2].as_str().expect("prefix must be string");
+        let prefix = self.index_key_limit.truncate_str(prefix);
+
+        let cursor = self.r1.as_ref().unwrap();
+        // the string keys are stacked without the terminating zero
+        let mut key_prefix = cursor.prefix_bytes.clone();
+        key_prefix.push(ElementType::String as u8);
+        key_prefix.extend_from_slice(prefix.as_bytes());
+
+        // more than one element of an array can start with the prefix
+        self.find_by_index_key_prefixes(vec![key_prefix], true)
+    }
+
+    /// Scan the index entries starting with any of the prefixes, one prefix after another.
+    ///
+    /// With `dedup`, a document is only read by its first entry.
+    fn find_by_index_key_prefixes(&mut self, mut key_prefixes: Vec<Vec<u8>>, dedup: bool) -> Result<bool> {
+        self.add_index_usage()?;
+
+        key_prefixes.reverse();
+        self.pending_index_key_prefixes = key_prefixes;
+        self.seen_index_pkeys = if dedup { Some(HashSet::new()) } else { None };
+        if !self.seek_next_index_key_prefix()? {
             return Ok(false);
         }
 
-        let key = cursor.peek_key().expect("key must exist");
-
-        let index_value = self.read_index_value_by_index_key(key.as_ref())?;
+        let index_value = self.read_unseen_index_value()?;
 
         if index_value.is_none() {
             return Ok(false);
@@ -205,6 +364,68 @@ impl VM {
         Ok(true)
     }
 
+    /// Move the cursor to the next index entry, in the current prefix or the pending ones.
+    fn advance_index_cursor(&mut self) -> Result<bool> {
+        let cursor = self.r1.as_mut().unwrap();
+        cursor.next()?;
+
+        let key_prefix = self.index_key_prefix.as_ref().expect("index_key_prefix must exist");
+        let in_prefix = cursor.peek_key().map_or(false, |key| key.starts_with(key_prefix.as_slice()));
+        if in_prefix {
+            return Ok(true);
+        }
+        self.seek_next_index_key_prefix()
+    }
+
+    /// Read the document of the index entry under the cursor,
+    /// skipping the entries of the documents already read.
+    fn read_unseen_index_value(&mut self) -> Result<Option<Bson>> {
+        loop {
+            let key = self.r1.as_ref().unwrap().peek_key().expect("key must exist");
+
+            if let Some(seen_pkeys) = self.seen_index_pkeys.as_mut() {
+                let slices = crate::utils::bson::split_stacked_keys(key.as_ref())?;
+                let pkey = crate::utils::bson::stacked_key(slices.last())?;
+                if !seen_pkeys.insert(pkey) {
+                    if !self.advance_index_cursor()? {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+            }
+
+            return self.read_index_value_by_index_key(key.as_ref());
+        }
+    }
+
+    /// Move the cursor to the first entry of the next pending prefix having any.
+    fn seek_next_index_key_prefix(&mut self) -> Result<bool> {
+        while let Some(key_prefix) = self.pending_index_key_prefixes.pop() {
+            let cursor = self.r1.as_mut().unwrap();
+            let found = cursor.reset_by_key_prefix(key_prefix.as_slice())?;
+            self.index_key_prefix = Some(key_prefix);
+            if found {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Count a lookup by the index opened in the cursor,
+    /// whose prefix is stacked from the index prefix, the collection name and the index name.
+    fn add_index_usage(&self) -> Result<()> {
+        let index_stats = match &self.index_stats {
+            Some(index_stats) => index_stats,
+            None => return Ok(()),
+        };
+        let cursor = self.r1.as_ref().unwrap();
+        let slices = crate::utils::bson::split_stacked_keys(cursor.prefix_bytes.as_slice())?;
+        if let (Some(Bson::String(col_name)), Some(Bson::String(index_name))) = (slices.get(1), slices.get(2)) {
+            index_stats.add_usage(col_name, index_name);
+        }
+        Ok(())
+    }
+
     fn read_index_value_by_index_key(
         &mut self,
         index_key: &[u8],
@@ -232,6 +453,7 @@ impl VM {
 
         let buf = db_iter.copy_data()?;
         let doc = bson::from_slice(buf.as_ref())?;
+        self.metrics.add_examined_count();
 
         Ok(Some(Bson::Document(doc)))
     }
@@ -244,6 +466,7 @@ impl VM {
             let bytes = cursor.copy_data()?;
             let doc = bson::from_slice(bytes.as_ref())?;
             self.stack.push(Bson::Document(doc));
+            self.metrics.add_examined_count();
 
             debug_assert!(
                 self.stack.len() <= 64,
@@ -259,26 +482,30 @@ impl VM {
         Ok(())
     }
 
-    fn next_index_value(&mut self) -> Result<()> {
+    fn prev(&mut self) -> Result<()> {
         let cursor = self.r1.as_mut().unwrap();
-        cursor.next()?;
-        let current_key = cursor.peek_key();
-        if current_key.is_none() {
-            self.r0 = 0;
-            return Ok(());
-        }
+        cursor.prev()?;
 
-        let index_value = self.index_value.as_ref().expect("index_value must exist");
+        if cursor.has_next() {
+            let bytes = cursor.copy_data()?;
+            let doc = bson::from_slice(bytes.as_ref())?;
+            self.stack.push(Bson::Document(doc));
+            self.metrics.add_examined_count();
+            self.r0 = 1;
+            return Ok(())
+        }
 
-        let key_buffer = make_index_key_with_query_key(cursor.prefix_bytes.as_slice(), index_value)?;
+        self.r0 = 0;
+        Ok(())
+    }
 
-        let current_key = current_key.unwrap();
-        if !current_key.starts_with(key_buffer.as_slice()) {
+    fn next_index_value(&mut self) -> Result<()> {
+        if !self.advance_index_cursor()? {
             self.r0 = 0;
             return Ok(());
         }
 
-        let value_opt = self.read_index_value_by_index_key(current_key.as_ref())?;
+        let value_opt = self.read_unseen_index_value()?;
         if value_opt.is_none() {
             self.r0 = 0;
             return Ok(());
@@ -295,6 +522,14 @@ impl VM {
         &self.stack[self.stack.len() - 1]
     }
 
+    /// The `_id` of the document on the top of the stack
+    fn current_id(&self) -> Option<Bson> {
+        self.stack.last()
+            .and_then(|value| value.as_document())
+            .and_then(|doc| doc.get("_id"))
+            .cloned()
+    }
+
     #[inline]
     fn reset_location(&mut self, location: u32) {
         unsafe {
@@ -392,6 +627,10 @@ impl VM {
 
         let txn = &self.txn;
         let doc = top_value.as_document().unwrap();
+        crate::utils::bson::check_document_depth(doc, self.max_document_depth)?;
+        if let Some(validator) = &self.program.validator {
+            validator::validate_document(validator, doc)?;
+        }
         let doc_buf = bson::to_vec(doc)?;
 
         let updated = {
@@ -400,6 +639,10 @@ impl VM {
         };
 
         if updated {
+            if let Some(target) = &self.oplog_target {
+                let pkey = doc.get("_id").cloned().unwrap_or(Bson::Null);
+                oplog::append(txn, target, oplog::OP_UPDATE, doc.clone(), Some(doc! { "_id": pkey }))?;
+            }
             self.r4 += 1;
         }
 
@@ -423,6 +666,7 @@ impl VM {
                 pkey,
                 index_name.as_str(),
                 index_info,
+                self.index_key_limit,
                 txn,
             )?;
         }
@@ -447,6 +691,7 @@ impl VM {
                 pkey,
                 index_name.as_str(),
                 index_info,
+                self.index_key_limit,
                 txn,
             )?;
         }
@@ -543,6 +788,19 @@ impl VM {
                         }
                     }
 
+                    DbOp::RewindLast => {
+                        let location = self.pc.add(1).cast::<u32>().read();
+
+                        let is_empty = Cell::new(false);
+                        try_vm!(self, self.reset_cursor_to_last(&is_empty));
+
+                        if is_empty.get() {
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
                     DbOp::FindByPrimaryKey => {
                         let location = self.pc.add(1).cast::<u32>().read();
 
@@ -567,7 +825,20 @@ impl VM {
                         }
                     }
 
+                    DbOp::FindByIndexPrefix => {
+                        let location = self.pc.add(1).cast::<u32>().read();
+
+                        let found = try_vm!(self, self.find_by_index_prefix());
+
+                        if !found {
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
                     DbOp::Next => {
+                        try_vm!(self, self.check_interrupt());
                         try_vm!(self, self.next());
                         if self.r0 != 0 {
                             let location = self.pc.add(1).cast::<u32>().read();
@@ -577,7 +848,19 @@ impl VM {
                         }
                     }
 
+                    DbOp::Prev => {
+                        try_vm!(self, self.check_interrupt());
+                        try_vm!(self, self.prev());
+                        if self.r0 != 0 {
+                            let location = self.pc.add(1).cast::<u32>().read();
+                            self.reset_location(location);
+                        } else {
+                            self.pc = self.pc.add(5);
+                        }
+                    }
+
                     DbOp::NextIndexValue => {
+                        try_vm!(self, self.check_interrupt());
                         try_vm!(self, self.next_index_value());
                         if self.r0 != 0 {
                             let location = self.pc.add(1).cast::<u32>().read();
@@ -648,8 +931,10 @@ impl VM {
                         let key = self.borrow_static(key_stat_id as usize);
                         let key_name = key.as_str().unwrap();
                         let top = &self.stack[self.stack.len() - 1];
-                        let doc = match top {
-                            Bson::Document(doc) => doc,
+                        let value = match top {
+                            Bson::Document(doc) => crate::utils::bson::try_get_document_value(doc, key_name),
+                            // numeric path segment, such as "items.0.name"
+                            Bson::Array(arr) => crate::utils::bson::try_get_array_value(arr, key_name),
                             _ => {
                                 let name = format!("{}", top);
                                 let err = FieldTypeUnexpectedStruct {
@@ -662,7 +947,7 @@ impl VM {
                             }
                         };
 
-                        match crate::utils::bson::try_get_document_value(doc, key_name) {
+                        match value {
                             Some(val) => {
                                 self.r0 = 1;
                                 self.stack.push(val);
@@ -730,7 +1015,8 @@ impl VM {
                     }
 
                     DbOp::UpdateCurrent => {
-                        try_vm!(self, self.update_current());
+                        let result = self.update_current();
+                        try_vm!(self, result.map_err(|err| err.with_document(None, self.current_id())));
 
                         self.pc = self.pc.add(1);
                     }
@@ -748,6 +1034,12 @@ impl VM {
                             }
                         };
                         if deleted {
+                            if let (Some(col_name), Some(pkey)) = (&self.capped_collection, self.current_id()) {
+                                capped_helper::remove(txn, col_name, &pkey)?;
+                            }
+                            if let (Some(target), Some(pkey)) = (&self.oplog_target, self.current_id()) {
+                                oplog::append(txn, target, oplog::OP_DELETE, doc! { "_id": pkey }, None)?;
+                            }
                             self.r2 += 1;
                         }
 
@@ -757,7 +1049,8 @@ impl VM {
                     DbOp::InsertIndex => {
                         let index_info_id = self.pc.add(1).cast::<u32>().read();
 
-                        self.insert_index(index_info_id)?;
+                        let result = self.insert_index(index_info_id);
+                        try_vm!(self, result.map_err(|err| err.with_document(None, self.current_id())));
 
                         self.pc = self.pc.add(5);
                     }
@@ -826,12 +1119,46 @@ impl VM {
                         self.pc = self.pc.add(1);
                     }
 
+                    DbOp::ElemMatch => {
+                        let value = &self.stack[self.stack.len() - 2];
+                        let cond = self.stack[self.stack.len() - 1].as_document().unwrap();
+
+                        let matched = value.as_array()
+                            .map_or(false, |arr| arr.iter().any(|item| elem_match::matches_condition(item, cond)));
+                        self.r0 = if matched { 1 } else { 0 };
+
+                        self.pc = self.pc.add(1);
+                    }
+
+                    DbOp::GeoWithin => {
+                        let value = &self.stack[self.stack.len() - 2];
+                        let shape = &self.stack[self.stack.len() - 1];
+
+                        let matched = match (geo::point_of(value), geo::Shape::parse(shape)) {
+                            (Some(point), Some(shape)) => shape.contains(point),
+                            _ => false,
+                        };
+                        self.r0 = if matched { 1 } else { 0 };
+
+                        self.pc = self.pc.add(1);
+                    }
+
                     DbOp::EqualNull => {
                         let val = &self.stack[self.stack.len() - 1];
                         self.r0 = if val == &Bson::Null { 1 } else { 0 };
                         self.pc = self.pc.add(1);
                     }
 
+                    DbOp::JsonSchema => {
+                        let value = &self.stack[self.stack.len() - 2];
+                        let schema = self.stack[self.stack.len() - 1].as_document().unwrap();
+
+                        let result = json_schema::matches(schema, value);
+                        self.r0 = if try_vm!(self, result) { 1 } else { 0 };
+
+                        self.pc = self.pc.add(1);
+                    }
+
                     DbOp::Regex => {
                         let val1 = &self.stack[self.stack.len() - 2];
                         let val2 = &self.stack[self.stack.len() - 1];
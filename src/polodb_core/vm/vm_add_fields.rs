@@ -31,20 +31,7 @@ impl VmFuncAddFields {
                 let mut fields = IndexMap::new();
                 for (k, v) in doc.iter() {
                     let op = crate::path_hint_3!(paths, k.clone(), {
-                        match v {
-                            Bson::Document(v) => {
-                                let op = registry.compile_doc(paths, v)?;
-                                OperatorExpr::Expr(op)
-                            }
-                            Bson::String(field_name) => {
-                                if let Some(stripped_field_name) = field_name.strip_prefix("$") {
-                                    OperatorExpr::Alias(stripped_field_name.to_string())
-                                } else {
-                                    OperatorExpr::Constant(Bson::String(field_name.clone()))
-                                }
-                            }
-                            _ => OperatorExpr::Constant(v.clone()),
-                        }
+                        registry.compile_expr(paths, v)?
                     });
                     fields.insert(k.clone(), op);
                 }
@@ -78,14 +65,21 @@ impl VmExternalFunc for VmFuncAddFields {
         };
         for (k, v) in &self.fields {
             let value = match v {
-                OperatorExpr::Expr(op) => op.next(arg0),
-                OperatorExpr::Constant(v) => v.clone(),
                 OperatorExpr::Alias(alias) => {
                     let alias = alias.as_str();
-                    doc.get(alias).cloned().unwrap_or(Bson::Null)
+                    Some(doc.get(alias).cloned().unwrap_or(Bson::Null))
                 }
+                _ => v.eval(arg0),
             };
-            doc.insert(k.clone(), value);
+            match value {
+                Some(value) => {
+                    doc.insert(k.clone(), value);
+                }
+                // $$REMOVE
+                None => {
+                    doc.remove(k);
+                }
+            }
         }
         Ok(VmExternalFuncStatus::Next(Bson::Document(doc)))
     }
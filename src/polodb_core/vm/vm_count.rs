@@ -45,7 +45,11 @@ impl VmExternalFunc for VmFuncCount {
     fn call(&self, args: &[Bson]) -> Result<VmExternalFuncStatus> {
         let arg0 = &args[0];
         if arg0.as_null().is_some() {  // complete
-            self.is_completed.store(true, Ordering::Relaxed);
+            // the end may be signaled more than once, e.g. by an exhausted $limit
+            // and when the scan is closed, the count is only emitted once
+            if self.is_completed.swap(true, Ordering::Relaxed) {
+                return Ok(VmExternalFuncStatus::Next(Bson::Null));
+            }
             let mut doc = Document::new();
             doc.insert(self.count_name.clone(), self.count.load(Ordering::Relaxed) as i64);
             return Ok(VmExternalFuncStatus::Next(doc.into()));
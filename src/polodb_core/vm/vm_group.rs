@@ -12,88 +12,302 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-use bson::Bson;
+use bson::{doc, Bson, Document};
+use crate::vm::spill_run::SpillRun;
 use crate::vm::vm_external_func::{VmExternalFunc, VmExternalFuncStatus};
 use crate::{Result, Error};
 use indexmap::IndexMap;
-use crate::vm::operators::{OpRegistry, VmOperator};
+use crate::vm::operators::{OpRegistry, OperatorExpr, VmOperator};
 
 const NAME: &str = "group";
+// how many files the documents of the spilled groups are distributed to,
+// a partition with more groups than the threshold is distributed again
+const SPILL_PARTITIONS: u64 = 16;
 
 // Reference: https://www.mongodb.com/docs/manual/reference/operator/aggregation/group/
 pub(crate) struct VmFuncGroup {
+    registry: OpRegistry,
+    id: GroupId,
+    // the accumulators of the stage in order, compiled again for every group
+    accumulators: Vec<(String, Bson)>,
+    // the documents of the new groups are written to temporary files
+    // when there are this many groups, `None` keeps all of them in memory
+    spill_threshold: Option<u64>,
     is_completed: AtomicBool,
     inner: Mutex<VmFuncGroupInner>,
 }
 
+// the expression of `_id`, a document of expressions is evaluated field by field
+enum GroupId {
+    Expr(OperatorExpr),
+    Fields(Vec<(String, OperatorExpr)>),
+}
+
 struct VmFuncGroupInner {
-    group_values: IndexMap<String, Bson>,
-    operators: HashMap<String, Box<dyn VmOperator>>,
+    // by the serialized `_id`, in the order of the first document of every group
+    groups: IndexMap<Vec<u8>, Group>,
+    // how many groups are output after the input is completed
+    output_count: usize,
+    // the number of the next input document
+    input_count: i64,
+    // the input documents `{ s: <number>, d: <document> }` of the groups which are not in memory,
+    // distributed by the hash of their `_id`, so every group is in one partition
+    partitions: Vec<SpillRun>,
+    // the output `{ s: <number of the first document>, d: <result> }` of every partition
+    output_runs: Vec<SpillRun>,
+    merging: bool,
+}
+
+struct Group {
+    id: Bson,
+    operators: Vec<Box<dyn VmOperator>>,
 }
 
 impl VmFuncGroup {
 
-    fn compile_command(
-        paths: &mut Vec<String>,
-        registry: OpRegistry,
-        key: &str,
-        value: &Bson,
-        group_values: &mut IndexMap<String, Bson>,
-        operators: &mut HashMap<String, Box<dyn VmOperator>>,
-    ) -> Result<()> {
-        let op = registry.compile(paths, value)?;
-        group_values.insert(key.into(), op.initial_value());
-        operators.insert(key.into(), op);
-        Ok(())
+    fn compile_id(paths: &mut Vec<String>, registry: &OpRegistry, value: &Bson) -> Result<GroupId> {
+        if let Bson::Document(doc) = value {
+            if !doc.keys().any(|k| k.starts_with('$')) {
+                let mut fields = Vec::with_capacity(doc.len());
+                for (k, v) in doc {
+                    crate::path_hint_2!(paths, k.clone(), {
+                        fields.push((k.clone(), registry.compile_expr(paths, v)?));
+                    });
+                }
+                return Ok(GroupId::Fields(fields));
+            }
+        }
+        Ok(GroupId::Expr(registry.compile_expr(paths, value)?))
     }
 
     pub(crate) fn compile(
         paths: &mut Vec<String>,
         registry: OpRegistry,
         value: &Bson,
+        spill_threshold: Option<u64>,
     ) -> Result<Box<dyn VmExternalFunc>> {
         let doc = crate::try_unwrap_document!("$group", value);
-        let mut group_values = IndexMap::new();
-        let mut operators = HashMap::new();
+        let mut id = None;
+        let mut accumulators = Vec::new();
 
-        let mut found_id = false;
         for (k, v) in doc.iter() {
             crate::path_hint_2!(paths, k.clone(), {
-                group_values.insert(k.clone(), v.clone());
-                let k_str = k.as_str();
-                if k_str == "_id" {
-                    found_id = true;
-                    paths.pop();
-                    continue;
+                if k == "_id" {
+                    id = Some(VmFuncGroup::compile_id(paths, &registry, v)?);
+                } else {
+                    // check the accumulator when the stage is compiled
+                    registry.compile(paths, v)?;
+                    accumulators.push((k.clone(), v.clone()));
                 }
-
-                VmFuncGroup::compile_command(
-                    paths,
-                    registry.clone(),
-                    k_str,
-                    v,
-                    &mut group_values,
-                    &mut operators,
-                )?;
             });
         }
-        if !found_id {
-            let err_msg = "Field '_id' is required for $group".to_string();
-            return Err(Error::ValidationError(err_msg));
-        }
+        let id = match id {
+            Some(id) => id,
+            None => {
+                let err_msg = "Field '_id' is required for $group".to_string();
+                return Err(Error::ValidationError(err_msg));
+            }
+        };
 
         let result = VmFuncGroup {
+            registry,
+            id,
+            accumulators,
+            spill_threshold,
             is_completed: AtomicBool::new(false),
             inner: Mutex::new(VmFuncGroupInner {
-                group_values,
-                operators,
+                groups: IndexMap::new(),
+                output_count: 0,
+                input_count: 0,
+                partitions: Vec::new(),
+                output_runs: Vec::new(),
+                merging: false,
             }),
         };
         Ok(Box::new(result))
     }
+
+    fn eval_id(&self, doc: &Bson) -> Bson {
+        match &self.id {
+            GroupId::Expr(expr) => expr.eval(doc).unwrap_or(Bson::Null),
+            GroupId::Fields(fields) => {
+                let mut result = Document::new();
+                for (k, expr) in fields {
+                    if let Some(value) = expr.eval(doc) {
+                        result.insert(k.clone(), value);
+                    }
+                }
+                Bson::Document(result)
+            }
+        }
+    }
+
+    fn group_key(&self, doc: &Bson) -> Result<(Bson, Vec<u8>)> {
+        let id = self.eval_id(doc);
+        let key = bson::to_vec(&doc! { "_id": id.clone() })?;
+        Ok((id, key))
+    }
+
+    fn new_group(&self, id: Bson) -> Result<Group> {
+        let mut paths = Vec::new();
+        let mut operators = Vec::with_capacity(self.accumulators.len());
+        for (_, v) in &self.accumulators {
+            operators.push(self.registry.compile(&mut paths, v)?);
+        }
+        Ok(Group {
+            id,
+            operators,
+        })
+    }
+
+    fn output(&self, group: &Group) -> Bson {
+        let mut result = Document::new();
+        result.insert("_id", group.id.clone());
+        for ((k, _), op) in self.accumulators.iter().zip(group.operators.iter()) {
+            result.insert(k.clone(), op.complete());
+        }
+        result.into()
+    }
+}
+
+// The groups beyond the spill threshold are not held in memory. The documents of the
+// new groups are written to partitions instead, which are grouped one by one when
+// the input is completed. A partition is grouped under the same threshold, the
+// documents of its groups beyond it are distributed to new partitions by another hash,
+// so there are never more groups in memory than the threshold, however many spilled.
+// All the groups in memory start before the spilled ones, so they are output first,
+// then the results of the partitions are merged by their first documents to keep
+// the order of the groups.
+impl VmFuncGroup {
+
+    fn should_spill(&self, group_count: usize) -> bool {
+        match self.spill_threshold {
+            Some(threshold) => group_count as u64 >= threshold.max(1),
+            None => false,
+        }
+    }
+
+    /// Append the document to its partition, `level` is how many times the document
+    /// has been spilled before, it's hashed with the key so the groups of a partition
+    /// are spread across the partitions of the next level.
+    fn spill(partitions: &mut Vec<SpillRun>, level: u32, key: &[u8], seq: i64, doc: &Bson) -> Result<()> {
+        if partitions.is_empty() {
+            for _ in 0..SPILL_PARTITIONS {
+                partitions.push(SpillRun::create(NAME)?);
+            }
+        }
+        let mut hasher = DefaultHasher::new();
+        level.hash(&mut hasher);
+        key.hash(&mut hasher);
+        let partition = (hasher.finish() % SPILL_PARTITIONS) as usize;
+        partitions[partition].append(&doc! {
+            "s": seq,
+            "d": doc.clone(),
+        })
+    }
+
+    fn next_output(&self, inner: &mut VmFuncGroupInner) -> Result<VmExternalFuncStatus> {
+        if !inner.merging {
+            let index = inner.output_count;
+            if let Some((_, group)) = inner.groups.get_index(index) {
+                let result = self.output(group);
+                inner.output_count += 1;
+                if inner.output_count >= inner.groups.len() && inner.partitions.is_empty() {
+                    self.is_completed.store(true, Ordering::Relaxed);
+                }
+                return Ok(VmExternalFuncStatus::Next(result));
+            }
+            if inner.partitions.is_empty() {
+                self.is_completed.store(true, Ordering::Relaxed);
+                return Ok(VmExternalFuncStatus::Continue);
+            }
+            inner.groups.clear();
+            self.group_partitions(inner)?;
+        }
+        self.next_merged(inner)
+    }
+
+    /// Group the documents of every partition in turn,
+    /// the results are written in the order of their first documents.
+    fn group_partitions(&self, inner: &mut VmFuncGroupInner) -> Result<()> {
+        let mut pending: Vec<(u32, SpillRun)> = std::mem::take(&mut inner.partitions)
+            .into_iter()
+            .map(|partition| (1, partition))
+            .collect();
+        while let Some((level, mut partition)) = pending.pop() {
+            partition.open()?;
+            let mut groups: IndexMap<Vec<u8>, (i64, Group)> = IndexMap::new();
+            let mut next_partitions = Vec::new();
+            while let Some(mut record) = partition.head.take() {
+                let seq = record.get_i64("s").unwrap_or(0);
+                let doc = record.remove("d").unwrap_or(Bson::Null);
+                let (id, key) = self.group_key(&doc)?;
+                if !groups.contains_key(&key) {
+                    if self.should_spill(groups.len()) {
+                        VmFuncGroup::spill(&mut next_partitions, level, &key, seq, &doc)?;
+                        partition.advance()?;
+                        continue;
+                    }
+                    let group = self.new_group(id)?;
+                    groups.insert(key.clone(), (seq, group));
+                }
+                let (_, group) = groups.get(&key).unwrap();
+                for op in &group.operators {
+                    op.next(&doc);
+                }
+                partition.advance()?;
+            }
+
+            let mut run = SpillRun::create(NAME)?;
+            for (seq, group) in groups.values() {
+                run.append(&doc! {
+                    "s": *seq,
+                    "d": self.output(group),
+                })?;
+            }
+            run.open()?;
+            inner.output_runs.push(run);
+            pending.extend(next_partitions.into_iter().map(|partition| (level + 1, partition)));
+        }
+        inner.merging = true;
+        Ok(())
+    }
+
+    /// Return the result of the spilled group whose first document is the earliest.
+    fn next_merged(&self, inner: &mut VmFuncGroupInner) -> Result<VmExternalFuncStatus> {
+        let runs = &mut inner.output_runs;
+        let min_idx = runs.iter()
+            .enumerate()
+            .filter_map(|(idx, run)| {
+                let head = run.head.as_ref()?;
+                Some((idx, head.get_i64("s").unwrap_or(0)))
+            })
+            .min_by_key(|(_, seq)| *seq)
+            .map(|(idx, _)| idx);
+
+        let result = match min_idx {
+            Some(idx) => {
+                let run = &mut runs[idx];
+                let mut record = run.head.take().unwrap();
+                run.advance()?;
+                record.remove("d")
+            }
+            None => None,
+        };
+        if runs.iter().all(|run| run.head.is_none()) {
+            self.is_completed.store(true, Ordering::Relaxed);
+        }
+
+        Ok(match result {
+            Some(result) => VmExternalFuncStatus::Next(result),
+            None => VmExternalFuncStatus::Continue,
+        })
+    }
+
 }
 
 impl VmExternalFunc for VmFuncGroup {
@@ -105,36 +319,28 @@ impl VmExternalFunc for VmFuncGroup {
         let arg0 = &args[0];
         let mut inner = self.inner.lock().unwrap();
         if arg0.as_null().is_some() {  // complete
-            self.is_completed.store(true, Ordering::Relaxed);
-            let mut result = bson::Document::new();
-            for (k, value) in inner.group_values.iter() {
-                if k == "_id" {
-                    result.insert(k.clone(), value.clone());
-                    continue;
-                }
-                let op = inner.operators.get(k).expect("Operator not found");
-                let final_value = op.complete();
-                result.insert(k.clone(), final_value);
-            }
-            return Ok(VmExternalFuncStatus::Next(result.into()));
+            return self.next_output(&mut inner);
         }
 
-        let next_map = inner.group_values
-            .iter()
-            .map(|(k, v)| {
-                if k == "_id" {
-                    return (k.clone(), v.clone());
-                }
-                let op = inner.operators.get(k).expect("Operator not found");
-                let next = op.next(v);
-                (k.clone(), next)
-            })
-            .collect::<IndexMap<String, Bson>>();
-        inner.group_values = next_map;
+        let seq = inner.input_count;
+        inner.input_count += 1;
+        let (id, key) = self.group_key(arg0)?;
+        if !inner.groups.contains_key(&key) {
+            if self.should_spill(inner.groups.len()) {
+                VmFuncGroup::spill(&mut inner.partitions, 0, &key, seq, arg0)?;
+                return Ok(VmExternalFuncStatus::Continue);
+            }
+            let group = self.new_group(id)?;
+            inner.groups.insert(key.clone(), group);
+        }
+        let group = inner.groups.get(&key).unwrap();
+        for op in &group.operators {
+            op.next(arg0);
+        }
         Ok(VmExternalFuncStatus::Continue)
     }
 
     fn is_completed(&self) -> bool {
-        true
+        self.is_completed.load(Ordering::Relaxed)
     }
 }
@@ -0,0 +1,186 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bson::{Bson, Document};
+use indexmap::IndexMap;
+use crate::{Error, Result};
+use crate::errors::mk_invalid_aggregate_field;
+use crate::vm::elem_match::{is_valid_condition_entry, matches_condition};
+use crate::vm::operators::{OpRegistry, OperatorExpr};
+use crate::vm::vm_external_func::{VmExternalFunc, VmExternalFuncStatus};
+
+enum ProjectField {
+    Include,
+    Exclude,
+    // keep the first element of the array matching the condition
+    ElemMatch(Document),
+    // a computed value, e.g. "$field", "$$ROOT" or { $cond: ... }
+    Expr(OperatorExpr),
+}
+
+pub(crate) struct VmFuncProject {
+    fields: IndexMap<String, ProjectField>,
+    is_inclusion: bool,
+    include_id: bool,
+}
+
+impl VmFuncProject {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, registry: OpRegistry, val: &Bson) -> Result<Box<dyn VmExternalFunc>> {
+        let doc = match val {
+            Bson::Document(doc) => doc,
+            _ => {
+                let invalid_err = mk_invalid_aggregate_field(paths);
+                return Err(Error::InvalidField(invalid_err));
+            }
+        };
+
+        let mut fields = IndexMap::new();
+        for (k, v) in doc.iter() {
+            let field = crate::path_hint_3!(paths, k.clone(), {
+                let field = VmFuncProject::compile_field(paths, &registry, v)?;
+                // like MongoDB, $elemMatch can't project a nested field
+                if matches!(field, ProjectField::ElemMatch(_)) && k.contains('.') {
+                    let invalid_err = mk_invalid_aggregate_field(paths);
+                    return Err(Error::InvalidField(invalid_err));
+                }
+                field
+            });
+            fields.insert(k.clone(), field);
+        }
+
+        let include_id = !matches!(fields.get("_id"), Some(ProjectField::Exclude));
+        let is_inclusion = fields.iter()
+            .any(|(k, f)| matches!(f, ProjectField::Expr(_)) || (k != "_id" && !matches!(f, ProjectField::Exclude)));
+
+        // inclusion and exclusion can't be mixed except for _id
+        if is_inclusion && fields.iter().any(|(k, f)| k != "_id" && matches!(f, ProjectField::Exclude)) {
+            let invalid_err = mk_invalid_aggregate_field(paths);
+            return Err(Error::InvalidField(invalid_err));
+        }
+
+        Ok(Box::new(VmFuncProject {
+            fields,
+            is_inclusion,
+            include_id,
+        }))
+    }
+
+    fn compile_field(paths: &mut Vec<String>, registry: &OpRegistry, val: &Bson) -> Result<ProjectField> {
+        let field = match val {
+            Bson::Boolean(b) => if *b { ProjectField::Include } else { ProjectField::Exclude },
+            Bson::Int32(i) => if *i != 0 { ProjectField::Include } else { ProjectField::Exclude },
+            Bson::Int64(i) => if *i != 0 { ProjectField::Include } else { ProjectField::Exclude },
+            Bson::Double(d) => if *d != 0.0 { ProjectField::Include } else { ProjectField::Exclude },
+            Bson::String(_) => ProjectField::Expr(registry.compile_expr(paths, val)?),
+            Bson::Document(doc) if doc.len() == 1 => {
+                match doc.get("$elemMatch") {
+                    Some(Bson::Document(cond)) => {
+                        crate::path_hint_2!(paths, "$elemMatch".to_string(), {
+                            validate_condition(paths, cond)?;
+                        });
+                        ProjectField::ElemMatch(cond.clone())
+                    }
+                    None if doc.keys().all(|k| k.starts_with('$')) => {
+                        ProjectField::Expr(registry.compile_expr(paths, val)?)
+                    }
+                    _ => {
+                        let invalid_err = mk_invalid_aggregate_field(paths);
+                        return Err(Error::InvalidField(invalid_err));
+                    }
+                }
+            }
+            _ => {
+                let invalid_err = mk_invalid_aggregate_field(paths);
+                return Err(Error::InvalidField(invalid_err));
+            }
+        };
+        Ok(field)
+    }
+
+}
+
+impl VmExternalFunc for VmFuncProject {
+    fn name(&self) -> &str {
+        "project"
+    }
+
+    fn call(&self, args: &[Bson]) -> Result<VmExternalFuncStatus> {
+        let arg0 = &args[0];
+        if arg0.as_null().is_some() {
+            return Ok(VmExternalFuncStatus::Next(Bson::Null));
+        }
+        let doc = match arg0 {
+            Bson::Document(doc) => doc,
+            _ => return Err(Error::UnknownAggregationOperation("Invalid argument for $project".to_string())),
+        };
+
+        if !self.is_inclusion {
+            let mut result = doc.clone();
+            for k in self.fields.keys() {
+                result.remove(k);
+            }
+            return Ok(VmExternalFuncStatus::Next(Bson::Document(result)));
+        }
+
+        let mut result = Document::new();
+        if self.include_id {
+            if let Some(id) = doc.get("_id") {
+                result.insert("_id", id.clone());
+            }
+        }
+        for (k, field) in &self.fields {
+            let value = match (field, doc.get(k)) {
+                (ProjectField::Include, Some(value)) => value.clone(),
+                (ProjectField::ElemMatch(cond), Some(Bson::Array(arr))) => {
+                    // the field is omitted if no element is matched
+                    match arr.iter().find(|item| matches_condition(item, cond)) {
+                        Some(item) => Bson::Array(vec![item.clone()]),
+                        None => continue,
+                    }
+                }
+                (ProjectField::Expr(expr), _) => {
+                    match expr.eval(arg0) {
+                        Some(value) => value,
+                        // $$REMOVE
+                        None => {
+                            result.remove(k);
+                            continue;
+                        }
+                    }
+                }
+                _ => continue,
+            };
+            result.insert(k.clone(), value);
+        }
+
+        Ok(VmExternalFuncStatus::Next(Bson::Document(result)))
+    }
+
+    fn is_completed(&self) -> bool {
+        true
+    }
+}
+
+fn validate_condition(paths: &mut Vec<String>, cond: &Document) -> Result<()> {
+    for (k, v) in cond.iter() {
+        crate::path_hint_2!(paths, k.clone(), {
+            if !is_valid_condition_entry(k, v) {
+                let invalid_err = mk_invalid_aggregate_field(paths);
+                return Err(Error::InvalidField(invalid_err));
+            }
+        });
+    }
+    Ok(())
+}
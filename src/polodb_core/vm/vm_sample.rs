@@ -0,0 +1,165 @@
+// Copyright 2024 Vincent Chan
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::{Cell, RefCell};
+use bson::{Bson, Document};
+use crate::vm::vm_external_func::{VmExternalFunc, VmExternalFuncStatus};
+use crate::{Error, Result};
+use crate::errors::mk_invalid_aggregate_field;
+use crate::utils::rand::SmallRng;
+
+// Reference: https://www.mongodb.com/docs/manual/reference/operator/aggregation/sample/
+// Reservoir sampling, only `size` documents are kept in memory.
+pub(crate) struct VmFuncSample {
+    size: usize,
+    rng: RefCell<SmallRng>,
+    reservoir: RefCell<Vec<Document>>,
+    // the count of the documents seen
+    seen: Cell<u64>,
+    idx: Cell<usize>,
+    shuffled: Cell<bool>,
+}
+
+impl VmFuncSample {
+
+    pub(crate) fn compile(paths: &mut Vec<String>, val: &Bson, seed: Option<u64>) -> Result<Box<dyn VmExternalFunc>> {
+        let size = VmFuncSample::parse_size(val)
+            .ok_or_else(|| Error::InvalidField(mk_invalid_aggregate_field(paths)))?;
+        let rng = match seed {
+            Some(seed) => SmallRng::new(seed),
+            None => SmallRng::from_entropy(),
+        };
+        Ok(Box::new(VmFuncSample::new(size, rng)))
+    }
+
+    fn parse_size(val: &Bson) -> Option<usize> {
+        let size = match val {
+            Bson::Document(doc) if doc.len() == 1 => doc.get("size")?,
+            _ => return None,
+        };
+        match size {
+            Bson::Int32(size) if *size >= 0 => Some(*size as usize),
+            Bson::Int64(size) if *size >= 0 => Some(*size as usize),
+            _ => None,
+        }
+    }
+
+    fn new(size: usize, rng: SmallRng) -> VmFuncSample {
+        VmFuncSample {
+            size,
+            rng: RefCell::new(rng),
+            reservoir: RefCell::new(Vec::new()),
+            seen: Cell::new(0),
+            idx: Cell::new(0),
+            shuffled: Cell::new(false),
+        }
+    }
+
+    fn add(&self, doc: &Document) {
+        let seen = self.seen.get() + 1;
+        self.seen.set(seen);
+
+        let mut reservoir = self.reservoir.borrow_mut();
+        if reservoir.len() < self.size {
+            reservoir.push(doc.clone());
+            return;
+        }
+        // keep the n-th document with the probability size / n
+        let slot = self.rng.borrow_mut().gen_below(seen) as usize;
+        if slot < self.size {
+            reservoir[slot] = doc.clone();
+        }
+    }
+
+    /// Shuffle the kept documents, the first ones of the input
+    /// would be in the input order otherwise.
+    fn shuffle(&self) {
+        let mut reservoir = self.reservoir.borrow_mut();
+        let mut rng = self.rng.borrow_mut();
+        for i in (1..reservoir.len()).rev() {
+            let j = rng.gen_below(i as u64 + 1) as usize;
+            reservoir.swap(i, j);
+        }
+    }
+
+}
+
+impl VmExternalFunc for VmFuncSample {
+    fn name(&self) -> &str {
+        "sample"
+    }
+
+    fn call(&self, args: &[Bson]) -> Result<VmExternalFuncStatus> {
+        match &args[0] {
+            Bson::Document(doc) => {
+                self.add(doc);
+                Ok(VmExternalFuncStatus::Continue)
+            }
+            Bson::Null => {
+                if !self.shuffled.replace(true) {
+                    self.shuffle();
+                }
+                let idx = self.idx.get();
+                let reservoir = self.reservoir.borrow();
+                if idx >= reservoir.len() {
+                    return Ok(VmExternalFuncStatus::Next(Bson::Null));
+                }
+                self.idx.set(idx + 1);
+                Ok(VmExternalFuncStatus::Next(reservoir[idx].clone().into()))
+            }
+            _ => Err(Error::ValidationError("Invalid sample value".into())),
+        }
+    }
+
+    fn is_completed(&self) -> bool {
+        self.idx.get() >= self.reservoir.borrow().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Bson};
+    use crate::utils::rand::SmallRng;
+    use crate::vm::vm_external_func::{VmExternalFunc, VmExternalFuncStatus};
+    use super::VmFuncSample;
+
+    fn run_sample(seed: u64) -> Vec<i32> {
+        let sample = VmFuncSample::new(5, SmallRng::new(seed));
+        for i in 0..100 {
+            sample.call(&[doc! { "_id": i }.into()]).unwrap();
+        }
+        let mut result = Vec::new();
+        loop {
+            match sample.call(&[Bson::Null]).unwrap() {
+                VmExternalFuncStatus::Next(Bson::Document(doc)) => {
+                    result.push(doc.get_i32("_id").unwrap());
+                }
+                _ => break,
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_sample_size_and_seeds() {
+        let a = run_sample(1);
+        assert_eq!(a.len(), 5);
+        assert_eq!(a, run_sample(1));
+
+        // a collision of all the seeds is practically impossible
+        let differs = (2..10).any(|seed| run_sample(seed) != a);
+        assert!(differs);
+    }
+
+}
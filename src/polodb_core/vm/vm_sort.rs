@@ -12,25 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::sync::atomic::AtomicUsize;
 use bson::{Bson, Document};
+use indexmap::IndexMap;
+use crate::vm::spill_run::SpillRun;
 use crate::vm::vm_external_func::{VmExternalFunc, VmExternalFuncStatus};
 use crate::{Result, Error};
 use crate::errors::mk_invalid_aggregate_field;
 
 pub(crate) struct VmFuncSort {
-    order_map: HashMap<String, i8>,
+    // the keys are compared in the order they are specified
+    order_map: IndexMap<String, i8>,
     buffer: RefCell<Vec<Document>>,
     idx: AtomicUsize,
+    // the buffered documents are sorted and written to a temporary file
+    // when there are this many of them, `None` keeps all of them in memory
+    spill_threshold: Option<u64>,
+    runs: RefCell<Vec<SpillRun>>,
+    merging: Cell<bool>,
 }
 
 impl VmFuncSort {
-    pub(crate) fn compile(paths: &mut Vec<String>, val: &Bson) -> Result<Box<dyn VmExternalFunc>> {
+    pub(crate) fn compile(paths: &mut Vec<String>, val: &Bson, spill_threshold: Option<u64>) -> Result<Box<dyn VmExternalFunc>> {
         let order_map = match val {
             Bson::Document(doc) => {
-                let mut result = HashMap::default();
+                let mut result = IndexMap::default();
                 for (k, v) in doc.iter() {
                     let order = match v {
                         Bson::Int32(val) => *val as i8,
@@ -39,6 +47,10 @@ impl VmFuncSort {
                     };
                     result.insert(k.clone(), order);
                 }
+                // break the ties by _id to make the sort stable
+                if !result.contains_key("_id") {
+                    result.insert("_id".to_string(), 1);
+                }
                 result
             }
             _ => {
@@ -50,40 +62,100 @@ impl VmFuncSort {
             order_map,
             buffer: RefCell::new(Vec::default()),
             idx: AtomicUsize::new(0),
+            spill_threshold,
+            runs: RefCell::new(Vec::new()),
+            merging: Cell::new(false),
         };
         Ok(Box::new(result))
     }
 
-    fn i8_to_ordering(i: i8) -> std::cmp::Ordering {
+    fn i8_to_ordering(i: i8) -> Ordering {
         match i {
-            1 => std::cmp::Ordering::Less,
-            -1 => std::cmp::Ordering::Greater,
-            _ => std::cmp::Ordering::Equal,
+            1 => Ordering::Less,
+            -1 => Ordering::Greater,
+            _ => Ordering::Equal,
+        }
+    }
+
+    fn compare_documents(&self, a: &Document, b: &Document) -> Ordering {
+        for (k, v) in self.order_map.iter() {
+            // the key can be the path of an embedded field, such as "address.zip"
+            let a_val = crate::utils::bson::try_get_document_value(a, k);
+            let b_val = crate::utils::bson::try_get_document_value(b, k);
+            match (a_val, b_val) {
+                (Some(a_val), Some(b_val)) => {
+                    // the values of any types are compared, such as the document `_id`s of the tie-break
+                    let result = crate::utils::bson::value_total_cmp(&a_val, &b_val);
+                    match result {
+                        Ordering::Equal => continue,
+                        Ordering::Less => return Self::i8_to_ordering(*v),
+                        Ordering::Greater => return Self::i8_to_ordering(-*v),
+                    }
+                }
+                (Some(_), None) => return Self::i8_to_ordering(*v),
+                (None, Some(_)) => return Self::i8_to_ordering(-*v),
+                (None, None) => continue,
+            }
         }
+        Ordering::Equal
     }
 
     fn sort_array(&self) {
         let mut array = self.buffer.borrow_mut();
-        array.sort_by(|a, b| {
-            for (k, v) in self.order_map.iter() {
-                let a_val = a.get(k);
-                let b_val = b.get(k);
-                match (a_val, b_val) {
-                    (Some(a_val), Some(b_val)) => {
-                        let result =  crate::utils::bson::value_cmp(a_val, b_val).expect("Invalid sort value");
-                        match result {
-                            std::cmp::Ordering::Equal => continue,
-                            std::cmp::Ordering::Less => return Self::i8_to_ordering(*v),
-                            std::cmp::Ordering::Greater => return Self::i8_to_ordering(-*v),
-                        }
-                    }
-                    (Some(_), None) => return Self::i8_to_ordering(*v),
-                    (None, Some(_)) => return Self::i8_to_ordering(-*v),
-                    (None, None) => continue,
+        array.sort_by(|a, b| self.compare_documents(a, b));
+    }
+
+    /// Write the buffered documents to a new sorted run.
+    fn spill(&self) -> Result<()> {
+        self.sort_array();
+        let mut buffer = self.buffer.borrow_mut();
+        let run = SpillRun::write("sort", &buffer)?;
+        buffer.clear();
+        self.runs.borrow_mut().push(run);
+        Ok(())
+    }
+
+    /// Return the smallest of the heads of the sorted runs.
+    fn next_merged(&self) -> Result<Bson> {
+        if !self.merging.get() {
+            // the rest of the documents make the last run
+            if !self.buffer.borrow().is_empty() {
+                self.spill()?;
+            }
+            for run in self.runs.borrow_mut().iter_mut() {
+                run.open()?;
+            }
+            self.merging.set(true);
+        }
+
+        let mut runs = self.runs.borrow_mut();
+        let mut min_idx: Option<usize> = None;
+        for (idx, run) in runs.iter().enumerate() {
+            let head = match &run.head {
+                Some(head) => head,
+                None => continue,
+            };
+            let is_smaller = match min_idx {
+                Some(min_idx) => {
+                    let min_head = runs[min_idx].head.as_ref().unwrap();
+                    self.compare_documents(head, min_head) == Ordering::Less
                 }
+                None => true,
+            };
+            if is_smaller {
+                min_idx = Some(idx);
             }
-            std::cmp::Ordering::Equal
-        });
+        }
+
+        match min_idx {
+            Some(idx) => {
+                let run = &mut runs[idx];
+                let doc = run.head.take().unwrap();
+                run.advance()?;
+                Ok(doc.into())
+            }
+            None => Ok(Bson::Null),
+        }
     }
 }
 
@@ -96,10 +168,22 @@ impl VmExternalFunc for VmFuncSort {
         let arg0 = &args[0];
         match arg0 {
             Bson::Document(doc) => {
-                let mut buffer = self.buffer.borrow_mut();
-                buffer.push(doc.clone());
+                let buffer_len = {
+                    let mut buffer = self.buffer.borrow_mut();
+                    buffer.push(doc.clone());
+                    buffer.len() as u64
+                };
+                if let Some(threshold) = self.spill_threshold {
+                    if buffer_len >= threshold.max(1) {
+                        self.spill()?;
+                    }
+                }
                 Ok(VmExternalFuncStatus::Continue)
             }
+            Bson::Null if !self.runs.borrow().is_empty() => {
+                let next = self.next_merged()?;
+                Ok(VmExternalFuncStatus::Next(next))
+            }
             Bson::Null => {
                 self.sort_array();
                 let next = {
@@ -120,6 +204,9 @@ impl VmExternalFunc for VmFuncSort {
     }
 
     fn is_completed(&self) -> bool {
+        if self.merging.get() {
+            return self.runs.borrow().iter().all(|run| run.head.is_none());
+        }
         let idx = self.idx.load(std::sync::atomic::Ordering::Relaxed);
         let buffer = self.buffer.borrow();
         idx >= buffer.len()